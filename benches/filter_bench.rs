@@ -37,5 +37,231 @@ fn bench_parse_compile_execute(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_parse_compile_execute);
+// Running one filter over many contexts: a fresh stack per call versus a reused one.
+fn bench_batch(c: &mut Criterion) {
+    let schema = Arc::new(
+        FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("bar", FieldType::Bytes)
+            .build(),
+    );
+    let expr = FilterParser::parse("foo == 42 && bar == \"baz\"", &schema).unwrap();
+    let filter =
+        CompiledFilter::new(expr, schema.clone(), Arc::new(FunctionRegistry::new())).unwrap();
+    let contexts: Vec<FilterContext> = (0..1_000)
+        .map(|i| {
+            FilterContextBuilder::new(&schema)
+                .set_int("foo", i % 100)
+                .unwrap()
+                .set_bytes("bar", b"baz")
+                .unwrap()
+                .build()
+        })
+        .collect();
+
+    c.bench_function("execute_1k_individually", |b| {
+        b.iter(|| {
+            for ctx in &contexts {
+                let _ = black_box(filter.execute(ctx));
+            }
+        })
+    });
+    c.bench_function("execute_batch_1k", |b| {
+        b.iter(|| {
+            let _ = black_box(filter.execute_batch(&contexts));
+        })
+    });
+}
+
+// Membership in literal integer sets, which the compiler sorts for binary search, against
+// the same values held in an array field (scanned linearly) and a set field (hashed).
+fn bench_in_set(c: &mut Criterion) {
+    let engine = WirerustEngineBuilder::new()
+        .field("port", FieldType::Int)
+        .field("allowed", FieldType::Array(Box::new(FieldType::Int)))
+        .field("allowed_set", FieldType::Set(Box::new(FieldType::Int)))
+        .build();
+    for size in [64_i64, 10_000] {
+        let values: Vec<i64> = (0..size).collect();
+        let set = values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ctx = FilterContextBuilder::new(engine.schema())
+            .set_int("port", size - 1)
+            .unwrap()
+            .set_array(
+                "allowed",
+                values.iter().map(|v| LiteralValue::Int(*v)).collect(),
+            )
+            .unwrap()
+            .set_set("allowed_set", values.iter().map(|v| LiteralValue::Int(*v)))
+            .unwrap()
+            .build();
+        let sorted = engine
+            .parse_and_compile(&format!("port in {{{set}}}"))
+            .unwrap();
+        let linear = engine.parse_and_compile("port in allowed").unwrap();
+        let hashed = engine.parse_and_compile("port in allowed_set").unwrap();
+
+        for (name, filter) in [
+            ("linear", &linear),
+            ("hashed", &hashed),
+            ("sorted", &sorted),
+        ] {
+            c.bench_function(&format!("in_set_{size}_{name}"), |b| {
+                b.iter(|| {
+                    let _ = engine.execute(black_box(filter), &ctx);
+                })
+            });
+        }
+    }
+}
+
+// A 50k-entry literal CIDR list, matched with a prefix trie, against a linear scan of the
+// same number of addresses held in an array field.
+fn bench_ip_trie(c: &mut Criterion) {
+    let engine = WirerustEngineBuilder::new()
+        .field("src", FieldType::Ip)
+        .field("blocked", FieldType::Array(Box::new(FieldType::Ip)))
+        .build();
+    let size = 50_000u32;
+    let network = |i: u32| std::net::Ipv4Addr::from((10 << 24) | (i << 8));
+    let cidrs = (0..size)
+        .map(|i| format!("\"{}/24\"", network(i)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let last: std::net::IpAddr = network(size - 1).into();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_ip("src", last)
+        .unwrap()
+        .set_array(
+            "blocked",
+            (0..size)
+                .map(|i| LiteralValue::Ip(network(i).into()))
+                .collect(),
+        )
+        .unwrap()
+        .build();
+    let trie = engine
+        .parse_and_compile(&format!("src in {{{cidrs}}}"))
+        .unwrap();
+    let linear = engine.parse_and_compile("src in blocked").unwrap();
+    for (name, filter) in [("trie", &trie), ("linear", &linear)] {
+        c.bench_function(&format!("ip_in_{size}_{name}"), |b| {
+            b.iter(|| {
+                let _ = engine.execute(black_box(filter), &ctx);
+            })
+        });
+    }
+}
+
+// Compares a literal `matches` pattern (compiled once by the compiler) against the same
+// pattern supplied through a field, which is compiled on every execution.
+#[cfg(feature = "regex")]
+fn bench_matches(c: &mut Criterion) {
+    let engine = WirerustEngineBuilder::new()
+        .field("ua", FieldType::Bytes)
+        .field("pattern", FieldType::Bytes)
+        .build();
+    let pattern = r"Mozilla/\d+\.\d+ .*Firefox/\d+";
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes(
+            "ua",
+            b"Mozilla/5.0 (X11; Linux x86_64; rv:91.0) Firefox/91.0",
+        )
+        .unwrap()
+        .set_bytes("pattern", pattern)
+        .unwrap()
+        .build();
+    let literal = engine
+        .parse_and_compile(&format!("ua matches \"{pattern}\""))
+        .unwrap();
+    let dynamic = engine.parse_and_compile("ua matches pattern").unwrap();
+
+    c.bench_function("matches_runtime_compiled", |b| {
+        b.iter(|| {
+            let _ = engine.execute(black_box(&dynamic), &ctx);
+        })
+    });
+    c.bench_function("matches_precompiled", |b| {
+        b.iter(|| {
+            let _ = engine.execute(black_box(&literal), &ctx);
+        })
+    });
+}
+
+#[cfg(not(feature = "regex"))]
+fn bench_matches(_c: &mut Criterion) {}
+
+// The bytecode interpreter against the closure-tree backend, on a tiny filter where
+// dispatch dominates and a larger one with arithmetic and a function call.
+fn bench_backends(c: &mut Criterion) {
+    let engine = WirerustEngineBuilder::new()
+        .field("foo", FieldType::Int)
+        .field("bar", FieldType::Bytes)
+        .build();
+    let closures = WirerustEngineBuilder::new()
+        .field("foo", FieldType::Int)
+        .field("bar", FieldType::Bytes)
+        .compiler(ClosureCompiler)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("foo", 42)
+        .unwrap()
+        .set_bytes("bar", b"baz")
+        .unwrap()
+        .build();
+    for (name, src) in [
+        ("small", "foo == 42"),
+        (
+            "large",
+            r#"(foo * 2 + 1) % 5 == 0 || foo in {1 2 3 42} && upper(bar) == "BAZ" && not bar contains "x""#,
+        ),
+    ] {
+        let ir = engine.parse_and_compile(src).unwrap();
+        let tree = closures.parse_and_compile(src).unwrap();
+        c.bench_function(&format!("execute_{name}_bytecode"), |b| {
+            b.iter(|| black_box(ir.execute(&ctx)))
+        });
+        c.bench_function(&format!("execute_{name}_closures"), |b| {
+            b.iter(|| black_box(tree.execute(&ctx)))
+        });
+    }
+}
+
+// The single `field == literal` comparison, the most common filter shape.
+fn bench_eq_field_literal(c: &mut Criterion) {
+    let engine = WirerustEngineBuilder::new()
+        .field("foo", FieldType::Int)
+        .field("bar", FieldType::Bytes)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("foo", 42)
+        .unwrap()
+        .set_bytes("bar", b"application/json")
+        .unwrap()
+        .build();
+    for (name, src) in [
+        ("int", "foo == 42"),
+        ("bytes", r#"bar == "application/json""#),
+    ] {
+        let filter = engine.parse_and_compile(src).unwrap();
+        c.bench_function(&format!("eq_field_literal_{name}"), |b| {
+            b.iter(|| black_box(filter.execute(black_box(&ctx))))
+        });
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_parse_compile_execute,
+    bench_batch,
+    bench_in_set,
+    bench_ip_trie,
+    bench_matches,
+    bench_backends,
+    bench_eq_field_literal
+);
 criterion_main!(benches);
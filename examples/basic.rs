@@ -19,25 +19,18 @@ fn main() -> Result<(), WirerustError> {
     println!("Parsed AST: {:#?}", expr);
 
     // 4. Compile filter
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions))?;
 
     // 5. Create context and set values
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )?;
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)?;
     ctx.set("port", LiteralValue::Int(80), &schema)?;
     ctx.set(
         "tags",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Bytes(Arc::new(b"foo".to_vec()).into()),
-                LiteralValue::Bytes(Arc::new(b"bar".to_vec()).into()),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::bytes("foo"),
+            LiteralValue::bytes("bar"),
+        ])),
         &schema,
     )?;
 
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use wirerust::*;
+
+// Compile many filters against one schema and function registry. Every filter holds a
+// clone of the same `Arc`s, so the schema and registry maps exist only once.
+fn main() -> Result<(), WirerustError> {
+    let schema = Arc::new(
+        FilterSchemaBuilder::new()
+            .field("http.method", FieldType::Bytes)
+            .field("port", FieldType::Int)
+            .build(),
+    );
+    let mut functions = FunctionRegistry::new();
+    register_builtins(&mut functions);
+    let functions = Arc::new(functions);
+
+    let rules = [
+        r#"http.method == "GET" && port == 80"#,
+        r#"lower(http.method) == "post""#,
+        "port in {1..1023}",
+    ];
+    let filters = rules
+        .iter()
+        .map(|src| {
+            let expr = FilterParser::parse(src, &schema)?;
+            CompiledFilter::new_shared(expr, Arc::clone(&schema), Arc::clone(&functions))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let ctx = FilterContextBuilder::new(&schema)
+        .set_bytes("http.method", "GET")?
+        .set_int("port", 80)?
+        .build();
+    for (rule, filter) in rules.iter().zip(&filters) {
+        println!("{rule}: {}", filter.execute(&ctx)?);
+    }
+
+    // An engine does the same in one call, sharing its own schema and registry.
+    let engine = WirerustEngineBuilder::new()
+        .field("http.method", FieldType::Bytes)
+        .field("port", FieldType::Int)
+        .build();
+    let filters = engine.compile_many(&rules)?;
+    println!("Compiled {} filters with the engine", filters.len());
+    Ok(())
+}
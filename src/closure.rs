@@ -0,0 +1,570 @@
+//! Closure module: an alternative backend that turns filters into a tree of closures.
+//!
+//! This module provides the ClosureCompiler and ClosureFilter types.
+
+use crate::compiler::{
+    call_function, check_orderable, cmp_between, cmp_contains, cmp_eq, cmp_eq_ci, cmp_in,
+    cmp_in_ranges, cmp_in_sorted_ints, cmp_ord_checked, cmp_wildcard, eq_field_literal, eval_arith,
+    index_value, kleene, load_field, negate_int, peek_field, set_contains, slice_value, to_bool,
+    trie_contains, Compiler, DefaultCompiler, EngineOptions, ExecutableFilter, ExecutionLimits,
+};
+#[cfg(feature = "regex")]
+use crate::compiler::{capture_compiled, matches_any_compiled};
+use crate::compiler::{cmp_matches, cmp_matches_compiled};
+use crate::context::FilterContext;
+use crate::expr::FilterExpr;
+use crate::functions::FunctionRegistry;
+use crate::ir::{FieldId, Instruction};
+use crate::schema::FilterSchema;
+use crate::types::LiteralValue;
+use crate::WirerustError;
+use std::any::Any;
+use std::sync::Arc;
+
+// One node of the tree: evaluates its children and combines their values.
+type Node = Box<dyn Fn(&mut Env) -> Result<LiteralValue, WirerustError> + Send + Sync>;
+
+// Per-execution state shared by every node.
+struct Env<'a> {
+    ctx: &'a FilterContext,
+    data: &'a dyn Any,
+    limits: &'a ExecutionLimits,
+    executed: usize,
+    elements: Vec<LiteralValue>,
+}
+
+impl Env<'_> {
+    // Count one node against the instruction budget.
+    fn tick(&mut self) -> Result<(), WirerustError> {
+        self.executed += 1;
+        if self.executed > self.limits.max_instructions {
+            return Err(WirerustError::ExecutionError(format!(
+                "Instruction budget of {} exceeded",
+                self.limits.max_instructions
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A backend that compiles filters into nested closures instead of bytecode, avoiding
+/// the interpreter's per-instruction dispatch. It type-checks and optimizes exactly like
+/// [`DefaultCompiler`] and evaluates to the same results. Each node counts against
+/// [`ExecutionLimits::max_instructions`]; there is no value stack, so
+/// `max_stack_depth` does not apply.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClosureCompiler;
+
+impl Compiler for ClosureCompiler {
+    fn compile(
+        &self,
+        expr: FilterExpr,
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+        options: EngineOptions,
+    ) -> Result<Box<dyn ExecutableFilter>, WirerustError> {
+        let ir = DefaultCompiler::compile_with_options(
+            expr,
+            Arc::clone(&schema),
+            Arc::clone(&functions),
+            options,
+        )?;
+        let builder = TreeBuilder {
+            schema,
+            functions,
+            options,
+        };
+        Ok(Box::new(ClosureFilter {
+            root: builder.build(&ir.bytecode)?,
+            fields: ir.loaded_fields(),
+            referenced_fields: ir.referenced_fields().into_iter().map(Into::into).collect(),
+            referenced_functions: ir
+                .referenced_functions()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }))
+    }
+}
+
+/// A filter compiled by [`ClosureCompiler`].
+pub struct ClosureFilter {
+    root: Node,
+    // Fields the tree loads, and the fields and functions it references, kept from the
+    // bytecode it was built from
+    fields: Vec<FieldId>,
+    referenced_fields: Vec<String>,
+    referenced_functions: Vec<String>,
+}
+
+impl ExecutableFilter for ClosureFilter {
+    fn evaluate(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        let mut env = Env {
+            ctx,
+            data,
+            limits,
+            executed: 0,
+            elements: Vec::new(),
+        };
+        (self.root)(&mut env)
+    }
+    fn loaded_fields(&self) -> Vec<FieldId> {
+        self.fields.clone()
+    }
+    fn referenced_fields(&self) -> Option<Vec<&str>> {
+        Some(self.referenced_fields.iter().map(String::as_str).collect())
+    }
+    fn referenced_functions(&self) -> Option<Vec<&str>> {
+        Some(
+            self.referenced_functions
+                .iter()
+                .map(String::as_str)
+                .collect(),
+        )
+    }
+}
+
+// Rebuilds the bytecode's postfix instruction stream as a tree, one node per instruction.
+struct TreeBuilder {
+    schema: Arc<FilterSchema>,
+    functions: Arc<FunctionRegistry>,
+    options: EngineOptions,
+}
+
+impl TreeBuilder {
+    fn build(&self, code: &[Instruction]) -> Result<Node, WirerustError> {
+        let mut nodes: Vec<Node> = Vec::new();
+        for instr in code {
+            let node = self.node(instr, &mut nodes)?;
+            nodes.push(node);
+        }
+        match (nodes.pop(), nodes.is_empty()) {
+            (Some(root), true) => Ok(root),
+            _ => Err(WirerustError::Other(
+                "Bytecode does not reduce to a single expression".into(),
+            )),
+        }
+    }
+
+    fn node(&self, instr: &Instruction, nodes: &mut Vec<Node>) -> Result<Node, WirerustError> {
+        let nulls = self.options.three_valued_logic && instr.null_propagating_operands().is_some();
+        let mut pop = || {
+            nodes
+                .pop()
+                .ok_or_else(|| WirerustError::Other(format!("Missing operand for {instr:?}")))
+        };
+        let node: Node = match instr {
+            Instruction::LoadField(fid) => {
+                let (fid, schema, options) = (*fid, Arc::clone(&self.schema), self.options);
+                Box::new(move |env| {
+                    env.tick()?;
+                    load_field(env.ctx, &schema, &options, fid)
+                })
+            }
+            Instruction::EqFieldLiteral(fid, lit) => {
+                let (fid, schema, options) = (*fid, Arc::clone(&self.schema), self.options);
+                let lit = lit.clone();
+                Box::new(move |env| {
+                    env.tick()?;
+                    let val = peek_field(env.ctx, &schema, &options, fid)?;
+                    Ok(eq_field_literal(val, &lit, &options))
+                })
+            }
+            Instruction::FieldExists(fid) => {
+                let fid = *fid;
+                Box::new(move |env| {
+                    env.tick()?;
+                    let exists = env
+                        .ctx
+                        .get_by_id(fid)
+                        .is_some_and(|v| *v != LiteralValue::Null);
+                    Ok(LiteralValue::Bool(exists))
+                })
+            }
+            Instruction::LoadLiteral(lit) => {
+                let lit = lit.clone();
+                Box::new(move |env| {
+                    env.tick()?;
+                    Ok(lit.clone())
+                })
+            }
+            Instruction::LoadElement => Box::new(|env| {
+                env.tick()?;
+                env.elements.last().cloned().ok_or_else(|| {
+                    WirerustError::ExecutionError("Placeholder '_' used outside of any/all".into())
+                })
+            }),
+            Instruction::CallFunction(fid, argc) => {
+                let mut args = (0..*argc).map(|_| pop()).collect::<Result<Vec<_>, _>>()?;
+                args.reverse();
+                let (fid, functions) = (*fid, Arc::clone(&self.functions));
+                Box::new(move |env| {
+                    env.tick()?;
+                    let values = args
+                        .iter()
+                        .map(|arg| arg(env))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    call_function(&functions, fid, &values, env.data)
+                })
+            }
+            Instruction::CallOperator(id) => {
+                let op = self.functions.get_operator(*id).cloned().ok_or_else(|| {
+                    WirerustError::FunctionError(format!("Operator ID {id} not found"))
+                })?;
+                compare(pop()?, pop()?, nulls, move |a, b| op(a, b))
+            }
+            Instruction::CompareEq => {
+                let options = self.options;
+                compare(pop()?, pop()?, nulls, move |a, b| cmp_eq(a, b, &options))
+            }
+            Instruction::CompareEqCi => compare(pop()?, pop()?, nulls, cmp_eq_ci),
+            Instruction::CompareNeq => {
+                let options = self.options;
+                compare(pop()?, pop()?, nulls, move |a, b| !cmp_eq(a, b, &options))
+            }
+            Instruction::CompareLt => self.ordered(pop()?, pop()?, nulls, "<", |a, b| a < b),
+            Instruction::CompareLte => self.ordered(pop()?, pop()?, nulls, "<=", |a, b| a <= b),
+            Instruction::CompareGt => self.ordered(pop()?, pop()?, nulls, ">", |a, b| a > b),
+            Instruction::CompareGte => self.ordered(pop()?, pop()?, nulls, ">=", |a, b| a >= b),
+            Instruction::CompareIn => {
+                let options = self.options;
+                compare(pop()?, pop()?, nulls, move |a, b| cmp_in(a, b, &options))
+            }
+            Instruction::CompareNotIn => {
+                let options = self.options;
+                compare(pop()?, pop()?, nulls, move |a, b| !cmp_in(a, b, &options))
+            }
+            Instruction::CompareContains => compare(pop()?, pop()?, nulls, cmp_contains),
+            Instruction::CompareWildcard { strict } => {
+                let strict = *strict;
+                compare(pop()?, pop()?, nulls, move |a, b| {
+                    cmp_wildcard(a, b, strict)
+                })
+            }
+            Instruction::CompareMatches => {
+                let anchored = self.options.anchored_matches;
+                compare(pop()?, pop()?, nulls, move |a, b| {
+                    cmp_matches(a, b, anchored)
+                })
+            }
+            Instruction::CompareInSet { set, negate } => {
+                let (set, negate, options) = (set.clone(), *negate, self.options);
+                unary(pop()?, nulls, move |v| {
+                    Ok(LiteralValue::Bool(
+                        set_contains(&set.0, &v, &options) != negate,
+                    ))
+                })
+            }
+            Instruction::CompareInSortedInts { values, negate } => {
+                let (values, negate) = (values.clone(), *negate);
+                unary(pop()?, nulls, move |v| {
+                    Ok(LiteralValue::Bool(
+                        cmp_in_sorted_ints(&v, &values.0) != negate,
+                    ))
+                })
+            }
+            Instruction::CompareInRanges { ranges, negate } => {
+                let (ranges, negate) = (ranges.clone(), *negate);
+                unary(pop()?, nulls, move |v| {
+                    Ok(LiteralValue::Bool(cmp_in_ranges(&v, &ranges) != negate))
+                })
+            }
+            Instruction::CompareIpInTrie { trie, negate } => {
+                let (trie, negate, options) = (trie.clone(), *negate, self.options);
+                unary(pop()?, nulls, move |v| {
+                    Ok(LiteralValue::Bool(
+                        trie_contains(&trie.0, &v, &options) != negate,
+                    ))
+                })
+            }
+            Instruction::CompareBetween { low, high, strict } => {
+                let (low, high, strict) = (low.clone(), high.clone(), *strict);
+                let options = self.options;
+                unary(pop()?, nulls, move |v| {
+                    check_orderable(&v, &low, "between", &options)?;
+                    Ok(LiteralValue::Bool(cmp_between(&v, &low, &high, strict)))
+                })
+            }
+            Instruction::CompareMatchesCompiled(re) => {
+                let re = re.clone();
+                unary(pop()?, nulls, move |v| {
+                    Ok(LiteralValue::Bool(cmp_matches_compiled(&re, &v)))
+                })
+            }
+            #[cfg(feature = "regex")]
+            Instruction::CaptureCompiled(re) => {
+                let re = re.clone();
+                binary(pop()?, pop()?, nulls, move |subject, group| {
+                    capture_compiled(&re, &subject, &group)
+                })
+            }
+            #[cfg(feature = "regex")]
+            Instruction::MatchesAnyCompiled(set) => {
+                let set = set.clone();
+                unary(pop()?, nulls, move |subject| {
+                    matches_any_compiled(&set, &subject)
+                })
+            }
+            Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Mod
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::Shl
+            | Instruction::Shr => {
+                let op = instr.clone();
+                binary(pop()?, pop()?, nulls, move |a, b| eval_arith(&op, &a, &b))
+            }
+            Instruction::Negate => unary(pop()?, nulls, |v| negate_int(&v)),
+            Instruction::Index => binary(pop()?, pop()?, nulls, |target, key| {
+                Ok(index_value(&target, &key).unwrap_or(LiteralValue::Bool(false)))
+            }),
+            Instruction::Slice => {
+                let (end, start, target) = (pop()?, pop()?, pop()?);
+                Box::new(move |env| {
+                    env.tick()?;
+                    let (target, start, end) = (target(env)?, start(env)?, end(env)?);
+                    Ok(slice_value(&target, &start, &end).unwrap_or(LiteralValue::Bool(false)))
+                })
+            }
+            Instruction::LogicalAnd | Instruction::LogicalOr => {
+                let or = matches!(instr, Instruction::LogicalOr);
+                if self.options.three_valued_logic {
+                    binary(pop()?, pop()?, false, move |a, b| Ok(kleene(&a, &b, or)))
+                } else {
+                    // Both sides are evaluated, as in the interpreter, so errors surface alike
+                    binary(pop()?, pop()?, false, move |a, b| {
+                        Ok(LiteralValue::Bool(if or {
+                            to_bool(&a) || to_bool(&b)
+                        } else {
+                            to_bool(&a) && to_bool(&b)
+                        }))
+                    })
+                }
+            }
+            Instruction::LogicalNot => {
+                let three_valued = self.options.three_valued_logic;
+                unary(pop()?, false, move |v| {
+                    Ok(match v {
+                        LiteralValue::Null if three_valued => v,
+                        _ => LiteralValue::Bool(!to_bool(&v)),
+                    })
+                })
+            }
+            Instruction::Quantify { all, body } => {
+                let (all, body) = (*all, self.build(body)?);
+                let items = pop()?;
+                Box::new(move |env| {
+                    env.tick()?;
+                    let items = match items(env)? {
+                        LiteralValue::Array(items) => items,
+                        LiteralValue::Set(set) => Arc::new(set.iter().cloned().collect()),
+                        _ => return Ok(LiteralValue::Bool(false)),
+                    };
+                    // any stops at the first match, all at the first miss
+                    for item in items.iter() {
+                        env.elements.push(item.clone());
+                        let outcome = body(env);
+                        env.elements.pop();
+                        if to_bool(&outcome?) != all {
+                            return Ok(LiteralValue::Bool(!all));
+                        }
+                    }
+                    Ok(LiteralValue::Bool(all))
+                })
+            }
+        };
+        Ok(node)
+    }
+
+    // An ordered comparison, failing on unorderable operands under `strict_comparisons`
+    fn ordered(
+        &self,
+        right: Node,
+        left: Node,
+        nulls: bool,
+        op: &'static str,
+        cmp: fn(&i64, &i64) -> bool,
+    ) -> Node {
+        let options = self.options;
+        binary(right, left, nulls, move |a, b| {
+            cmp_ord_checked(&a, &b, op, &options, cmp).map(LiteralValue::Bool)
+        })
+    }
+}
+
+// A node applying `f` to one operand; with `nulls`, a null operand yields null.
+fn unary(
+    operand: Node,
+    nulls: bool,
+    f: impl Fn(LiteralValue) -> Result<LiteralValue, WirerustError> + Send + Sync + 'static,
+) -> Node {
+    Box::new(move |env| {
+        env.tick()?;
+        let v = operand(env)?;
+        if nulls && v == LiteralValue::Null {
+            return Ok(LiteralValue::Null);
+        }
+        f(v)
+    })
+}
+
+// A node applying `f` to two operands. Operands arrive in pop order (right first), as
+// the bytecode pushed them.
+fn binary(
+    right: Node,
+    left: Node,
+    nulls: bool,
+    f: impl Fn(LiteralValue, LiteralValue) -> Result<LiteralValue, WirerustError>
+        + Send
+        + Sync
+        + 'static,
+) -> Node {
+    Box::new(move |env| {
+        env.tick()?;
+        let a = left(env)?;
+        let b = right(env)?;
+        if nulls && (a == LiteralValue::Null || b == LiteralValue::Null) {
+            return Ok(LiteralValue::Null);
+        }
+        f(a, b)
+    })
+}
+
+fn compare(
+    right: Node,
+    left: Node,
+    nulls: bool,
+    f: impl Fn(&LiteralValue, &LiteralValue) -> bool + Send + Sync + 'static,
+) -> Node {
+    binary(right, left, nulls, move |a, b| {
+        Ok(LiteralValue::Bool(f(&a, &b)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::FilterParser;
+    use crate::functions::register_builtins;
+    use crate::schema::FilterSchemaBuilder;
+    use crate::types::FieldType;
+
+    // Both backends must agree on every filter, including the errors they raise.
+    #[test]
+    fn test_closure_backend_matches_interpreter() {
+        let schema = Arc::new(
+            FilterSchemaBuilder::new()
+                .field("port", FieldType::Int)
+                .field("method", FieldType::Bytes)
+                .field("tags", FieldType::Array(Box::new(FieldType::Bytes)))
+                .field("missing", FieldType::Int)
+                .build(),
+        );
+        let mut functions = FunctionRegistry::new();
+        register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let mut ctx = FilterContext::new();
+        ctx.set_int("port", 8080, &schema)
+            .set_bytes("method", "GET", &schema)
+            .set_array(
+                "tags",
+                vec![
+                    LiteralValue::Bytes(Arc::new(b"edge".to_vec())),
+                    LiteralValue::Bytes(Arc::new(b"api".to_vec())),
+                ],
+                &schema,
+            );
+        for src in [
+            r#"port == 8080 && method == "GET""#,
+            "port in {80 443} or port between {8000 8999}",
+            r#"lower(method) == "get" && len(tags) == 2"#,
+            r#"any(tags, _ == "api") && not all(tags, _ contains "e")"#,
+            r#"method wildcard "g*" && tags[0] == "edge""#,
+            r#"tags[-1] == "api" && len(tags[:1]) == 1"#,
+            "(port * 2) % 7 == 0 || -port < 0",
+            "missing == 0",
+            "port / missing > 0",
+        ] {
+            let expr = FilterParser::parse(src, &schema).unwrap();
+            let compile = |compiler: &dyn Compiler| {
+                compiler
+                    .compile(
+                        expr.clone(),
+                        Arc::clone(&schema),
+                        Arc::clone(&functions),
+                        EngineOptions::default(),
+                    )
+                    .unwrap()
+                    .evaluate(&ctx, &ExecutionLimits::default(), &())
+                    .map_err(|e| e.to_string())
+            };
+            assert_eq!(
+                compile(&ClosureCompiler),
+                compile(&DefaultCompiler),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_closure_backend_reports_references() {
+        let schema = Arc::new(
+            FilterSchemaBuilder::new()
+                .field("port", FieldType::Int)
+                .field("method", FieldType::Bytes)
+                .build(),
+        );
+        let mut functions = FunctionRegistry::new();
+        register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let expr =
+            FilterParser::parse(r#"exists port or lower(method) == "get""#, &schema).unwrap();
+        let filter = ClosureCompiler
+            .compile(expr, schema, functions, EngineOptions::default())
+            .unwrap();
+        assert_eq!(filter.referenced_fields(), Some(vec!["port", "method"]));
+        assert_eq!(filter.referenced_functions(), Some(vec!["lower"]));
+        assert_eq!(filter.loaded_fields().len(), 1);
+    }
+
+    #[test]
+    fn test_closure_backend_limits_and_nulls() {
+        let schema = Arc::new(
+            FilterSchemaBuilder::new()
+                .field("a", FieldType::Int)
+                .build(),
+        );
+        let functions = Arc::new(FunctionRegistry::new());
+        let compile = |src: &str, options: EngineOptions| {
+            let expr = FilterParser::parse(src, &schema).unwrap();
+            ClosureCompiler
+                .compile(expr, Arc::clone(&schema), Arc::clone(&functions), options)
+                .unwrap()
+        };
+        let ctx = FilterContext::new();
+        let filter = compile("a + 1 == 2 || a == 0", EngineOptions::default());
+        assert!(filter
+            .evaluate(&ctx, &ExecutionLimits::new(3, 16), &())
+            .is_err());
+        let options = EngineOptions {
+            three_valued_logic: true,
+            ..Default::default()
+        };
+        let filter = compile("a + 1 == 2", options);
+        assert_eq!(
+            filter
+                .evaluate(&ctx, &ExecutionLimits::default(), &())
+                .unwrap(),
+            LiteralValue::Null
+        );
+    }
+}
@@ -3,12 +3,31 @@
 //! This module provides traits and implementations for compiling filter expressions.
 
 use crate::context::FilterContext;
-use crate::expr::{ComparisonOp, FilterExpr, LogicalOp};
-use crate::functions::{call_builtin, BuiltinFunctionId, FunctionRegistry};
-use crate::ir::{Instruction, IrStack};
+use crate::expr::{ArithOp, ComparisonOp, ExprVisitor, FilterExpr, LogicalOp, QuantifierKind};
+use crate::functions::{
+    call_builtin, fold_case, BuiltinFunctionId, FunctionRegistry, FunctionSignature,
+};
+#[cfg(feature = "regex")]
+use crate::functions::{capture_group, matches_any_set};
+#[cfg(feature = "regex")]
+use crate::ir::CompiledRegexSet;
+use crate::ir::{
+    all_instructions, checked_stack_depth, merge_ranges, FieldId, Instruction, IpPrefixSet,
+    IrStack, LiteralSet, SortedInts,
+};
+use crate::ir::{CompiledRegex, RegexEngine};
+#[cfg(feature = "json")]
+use crate::ir::{FunctionId, OperatorId};
 use crate::schema::FilterSchema;
-use crate::types::LiteralValue;
+use crate::trie::{parse_prefix, IpTrie};
+use crate::types::{FieldType, LiteralValue};
 use crate::WirerustError;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
 
 /// A compiled filter in IR form.
@@ -16,283 +35,2378 @@ pub struct IrCompiledFilter {
     pub bytecode: Vec<Instruction>,
     pub schema: Arc<FilterSchema>,
     pub functions: Arc<FunctionRegistry>,
+    pub options: EngineOptions,
+    max_stack_depth: usize,
+}
+
+/// Resource caps enforced while executing a filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExecutionLimits {
+    /// Maximum number of instructions executed before giving up.
+    pub max_instructions: usize,
+    /// Maximum number of values on the evaluation stack at any point.
+    pub max_stack_depth: usize,
+}
+
+impl ExecutionLimits {
+    /// Create limits with the given instruction budget and stack depth.
+    pub fn new(max_instructions: usize, max_stack_depth: usize) -> Self {
+        Self {
+            max_instructions,
+            max_stack_depth,
+        }
+    }
+}
+
+impl Default for ExecutionLimits {
+    /// Generous limits that no reasonable hand-written filter comes close to.
+    fn default() -> Self {
+        Self::new(1_000_000, 4096)
+    }
 }
 
 impl IrCompiledFilter {
     /// Execute the IR filter against a context.
     pub fn execute(&self, ctx: &FilterContext) -> Result<bool, WirerustError> {
-        let mut stack: IrStack = Vec::with_capacity(16);
-        let bytecode = &self.bytecode;
+        self.execute_with_limits(ctx, &ExecutionLimits::default())
+    }
+
+    /// Evaluate the expression against a context and return its final value rather than
+    /// its truthiness, e.g. the bytes produced by `upper(http.method)`.
+    pub fn evaluate(&self, ctx: &FilterContext) -> Result<LiteralValue, WirerustError> {
+        self.evaluate_full(ctx, &ExecutionLimits::default(), &())
+    }
+
+    /// Execute the IR filter, failing with an execution error if it runs more instructions
+    /// or grows the stack deeper than `limits` allows.
+    pub fn execute_with_limits(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+    ) -> Result<bool, WirerustError> {
+        self.evaluate_full(ctx, limits, &()).map(|v| to_bool(&v))
+    }
+
+    /// Execute the IR filter, passing `data` to every custom function call through
+    /// [`FilterFunction::call_with_data`](crate::FilterFunction::call_with_data).
+    pub fn execute_with_data(
+        &self,
+        ctx: &FilterContext,
+        data: &dyn Any,
+    ) -> Result<bool, WirerustError> {
+        self.evaluate_full(ctx, &ExecutionLimits::default(), data)
+            .map(|v| to_bool(&v))
+    }
+
+    /// Execute the IR filter on a caller-owned evaluation stack, which is cleared first and
+    /// left holding unspecified values afterwards. Reusing one buffer across calls avoids an
+    /// allocation per evaluation; since it is borrowed mutably for the whole call, each
+    /// thread (or worker) needs its own.
+    pub fn execute_with_stack(
+        &self,
+        ctx: &FilterContext,
+        stack: &mut IrStack,
+    ) -> Result<bool, WirerustError> {
+        self.evaluate_reusing(ctx, &ExecutionLimits::default(), &(), stack)
+            .map(|v| to_bool(&v))
+    }
+
+    // Runs on a per-thread scratch stack, or a fresh one when that is already in use by an
+    // outer evaluation (a custom function executing another filter).
+    fn evaluate_full(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        thread_local! {
+            static SCRATCH: std::cell::RefCell<IrStack> = const { std::cell::RefCell::new(Vec::new()) };
+        }
+        SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+            Ok(mut stack) => {
+                let result = self.evaluate_reusing(ctx, limits, data, &mut stack);
+                // Don't keep context values alive between calls
+                stack.clear();
+                result
+            }
+            Err(_) => self.evaluate_reusing(
+                ctx,
+                limits,
+                data,
+                &mut Vec::with_capacity(self.max_stack_depth),
+            ),
+        })
+    }
+
+    pub(crate) fn evaluate_reusing(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn Any,
+        stack: &mut IrStack,
+    ) -> Result<LiteralValue, WirerustError> {
+        stack.clear();
+        stack.reserve(self.max_stack_depth);
+        let mut state = ExecState {
+            ctx,
+            limits,
+            data,
+            stack,
+            executed: 0,
+            elements: Vec::new(),
+        };
+        self.run(&self.bytecode, &mut state)?;
+        state
+            .stack
+            .pop()
+            .ok_or_else(|| WirerustError::ExecutionError("Empty stack after execution".into()))
+    }
+
+    /// The most values the evaluation stack holds at once while running this filter,
+    /// computed at compile time, so a caller can preallocate exactly.
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    /// IDs of the fields the filter loads, excluding those only checked with `exists`, in
+    /// order of first use.
+    pub fn loaded_fields(&self) -> Vec<FieldId> {
+        let mut ids = Vec::new();
+        for instr in all_instructions(&self.bytecode) {
+            if let Instruction::LoadField(fid) | Instruction::EqFieldLiteral(fid, _) = instr {
+                if !ids.contains(fid) {
+                    ids.push(*fid);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Names of the fields the filter reads (including `exists` checks), in order of first use.
+    pub fn referenced_fields(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for instr in all_instructions(&self.bytecode) {
+            if let Instruction::LoadField(fid)
+            | Instruction::FieldExists(fid)
+            | Instruction::EqFieldLiteral(fid, _) = instr
+            {
+                if let Some(name) = self.schema.field_name(*fid) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Names of the registered functions the filter calls, in order of first use.
+    pub fn referenced_functions(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for instr in all_instructions(&self.bytecode) {
+            let name = match instr {
+                Instruction::CallFunction(fid, _) => self.functions.function_name(*fid),
+                #[cfg(feature = "regex")]
+                Instruction::CaptureCompiled(_) => Some("regex_capture"),
+                #[cfg(feature = "regex")]
+                Instruction::MatchesAnyCompiled(_) => Some("matches_any"),
+                _ => None,
+            };
+            if let Some(name) = name {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// Whether a custom (non-builtin) function call or custom operator sits where
+    /// evaluation might skip it: in the right operand of `and`/`or`, or in a quantifier
+    /// predicate, which runs once per element and not at all for an empty array. Both
+    /// operands of `and`/`or` are evaluated today, so this is conservative, but it flags
+    /// the filters in which a function with side effects such as logging can't be relied
+    /// on to run every time.
+    pub fn has_conditional_function_calls(&self) -> bool {
+        self.conditional_calls(&self.bytecode)
+    }
+
+    // Replays the stack effects of `code`, tracking for each value whether computing it
+    // called a custom function
+    fn conditional_calls(&self, code: &[Instruction]) -> bool {
+        let mut stack: Vec<bool> = Vec::new();
+        for instr in code {
+            let (pops, _) = instr.stack_effect();
+            let operands = stack.split_off(stack.len().saturating_sub(pops));
+            let calls = match instr {
+                Instruction::LogicalAnd | Instruction::LogicalOr
+                    if operands.last() == Some(&true) =>
+                {
+                    return true
+                }
+                Instruction::Quantify { body, .. } => {
+                    if all_instructions(body)
+                        .into_iter()
+                        .any(|i| self.is_custom_call(i))
+                    {
+                        return true;
+                    }
+                    operands.contains(&true)
+                }
+                _ => self.is_custom_call(instr) || operands.contains(&true),
+            };
+            stack.push(calls);
+        }
+        false
+    }
+
+    fn is_custom_call(&self, instr: &Instruction) -> bool {
+        let fid = match instr {
+            Instruction::CallFunction(fid, _) => fid,
+            Instruction::CallOperator(_) => return true,
+            _ => return false,
+        };
+        match self.functions.function_name(*fid) {
+            Some(name) => {
+                BuiltinFunctionId::from_name(name).is_none()
+                    || self.functions.is_builtin_overridden(name)
+            }
+            None => true,
+        }
+    }
+
+    /// Render the bytecode one instruction per line, with field and function names in place
+    /// of their IDs. Quantifier bodies are indented under their `Quantify`.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        self.disassemble_into(&self.bytecode, 0, &mut out);
+        out
+    }
+
+    fn disassemble_into(&self, code: &[Instruction], depth: usize, out: &mut String) {
+        use std::fmt::Write;
+        let field = |fid: &usize| self.schema.field_name(*fid).unwrap_or("<unknown>");
+        for (i, instr) in code.iter().enumerate() {
+            let text = match instr {
+                Instruction::LoadField(fid) => format!("LoadField {}", field(fid)),
+                Instruction::FieldExists(fid) => format!("FieldExists {}", field(fid)),
+                Instruction::EqFieldLiteral(fid, lit) => {
+                    format!("EqFieldLiteral {} {}", field(fid), literal_text(lit))
+                }
+                Instruction::LoadLiteral(lit) => format!("LoadLiteral {}", literal_text(lit)),
+                Instruction::CallFunction(fid, argc) => format!(
+                    "CallFunction {}/{argc}",
+                    self.functions.function_name(*fid).unwrap_or("<unknown>")
+                ),
+                Instruction::CallOperator(id) => format!(
+                    "CallOperator {}",
+                    self.functions.operator_name(*id).unwrap_or("<unknown>")
+                ),
+                Instruction::CompareMatchesCompiled(re) => {
+                    format!("CompareMatchesCompiled {:?}", re.0.as_str())
+                }
+                #[cfg(feature = "regex")]
+                Instruction::CaptureCompiled(re) => {
+                    format!("CaptureCompiled {:?}", re.0.as_str())
+                }
+                #[cfg(feature = "regex")]
+                Instruction::MatchesAnyCompiled(set) => {
+                    format!("MatchesAnyCompiled ({} patterns)", set.0.len())
+                }
+                Instruction::CompareInSet { set, negate } => {
+                    let op = if *negate { "not in" } else { "in" };
+                    format!("CompareInSet {op} ({} elements)", set.0.len())
+                }
+                Instruction::CompareInSortedInts { values, negate } => {
+                    let op = if *negate { "not in" } else { "in" };
+                    format!("CompareInSortedInts {op} ({} elements)", values.0.len())
+                }
+                Instruction::CompareInRanges { ranges, negate } => {
+                    let op = if *negate { "not in" } else { "in" };
+                    format!("CompareInRanges {op} ({} ranges)", ranges.len())
+                }
+                Instruction::CompareIpInTrie { trie, negate } => {
+                    let op = if *negate { "not in" } else { "in" };
+                    format!("CompareIpInTrie {op} ({} prefixes)", trie.0.len())
+                }
+                Instruction::CompareBetween { low, high, strict } => format!(
+                    "CompareBetween{} {} {}",
+                    if *strict { " strict" } else { "" },
+                    literal_text(low),
+                    literal_text(high)
+                ),
+                Instruction::Quantify { all, .. } => {
+                    format!("Quantify {}", if *all { "all" } else { "any" })
+                }
+                other => format!("{other:?}"),
+            };
+            let _ = writeln!(out, "{:indent$}{i:04} {text}", "", indent = depth * 2);
+            if let Instruction::Quantify { body, .. } = instr {
+                self.disassemble_into(body, depth + 1, out);
+            }
+        }
+    }
+
+    // Run a block of bytecode on the shared stack; quantifier bodies re-enter here once
+    // per element with that element bound for `LoadElement`.
+    fn run(&self, bytecode: &[Instruction], state: &mut ExecState) -> Result<(), WirerustError> {
         let mut pc = 0;
         while pc < bytecode.len() {
+            state.executed += 1;
+            if state.executed > state.limits.max_instructions {
+                return Err(WirerustError::ExecutionError(format!(
+                    "Instruction budget of {} exceeded",
+                    state.limits.max_instructions
+                )));
+            }
+            // Checked before each step so the builtin fast path below is covered too.
+            if state.stack.len() > state.limits.max_stack_depth {
+                return Err(WirerustError::ExecutionError(format!(
+                    "Stack depth limit of {} exceeded",
+                    state.limits.max_stack_depth
+                )));
+            }
+            if self.options.three_valued_logic {
+                if let Some(n) = bytecode[pc].null_propagating_operands() {
+                    let operands = state.stack.len() - n;
+                    if state.stack[operands..].contains(&LiteralValue::Null) {
+                        state.stack.truncate(operands);
+                        state.stack.push(LiteralValue::Null);
+                        pc += 1;
+                        continue;
+                    }
+                }
+            }
             match &bytecode[pc] {
                 Instruction::LoadField(fid) => {
-                    let val = ctx
+                    let val = load_field(state.ctx, &self.schema, &self.options, *fid)?;
+                    state.stack.push(val);
+                }
+                Instruction::FieldExists(fid) => {
+                    let exists = state
+                        .ctx
                         .get_by_id(*fid)
-                        .cloned()
-                        .unwrap_or(LiteralValue::Bool(false));
-                    stack.push(val);
+                        .is_some_and(|v| *v != LiteralValue::Null);
+                    state.stack.push(LiteralValue::Bool(exists));
+                }
+                Instruction::EqFieldLiteral(fid, lit) => {
+                    let val = peek_field(state.ctx, &self.schema, &self.options, *fid)?;
+                    state.stack.push(eq_field_literal(val, lit, &self.options));
                 }
                 Instruction::LoadLiteral(lit) => {
-                    stack.push(lit.clone());
+                    state.stack.push(lit.clone());
                 }
                 Instruction::CallFunction(fid, argc) => {
                     let argc = *argc as usize;
-                    let args: Vec<_> = stack.split_off(stack.len() - argc);
-                    // Fast-path for built-in functions
-                    if let Some(name) = self.functions.function_name(*fid) {
-                        if let Some(builtin_id) = BuiltinFunctionId::from_name(name) {
-                            let result = call_builtin(builtin_id, &args).ok_or_else(|| {
-                                WirerustError::FunctionError(format!(
-                                    "Builtin function call failed for {name}"
-                                ))
-                            })?;
-                            stack.push(result);
-                            pc += 1;
-                            continue;
-                        }
-                    }
-                    let func = self.functions.get_by_id(*fid).ok_or_else(|| {
-                        WirerustError::FunctionError(format!("Function ID {fid} not found"))
-                    })?;
-                    let result = func.call(&args).ok_or_else(|| {
-                        WirerustError::FunctionError(format!("Function call failed for ID {fid}"))
-                    })?;
-                    stack.push(result);
+                    let args: Vec<_> = state.stack.split_off(state.stack.len() - argc);
+                    let result = call_function(&self.functions, *fid, &args, state.data)?;
+                    state.stack.push(result);
+                }
+                Instruction::CallOperator(id) => {
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let result = call_operator(&self.functions, *id, &left, &right)?;
+                    state.stack.push(LiteralValue::Bool(result));
                 }
                 Instruction::CompareEq => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(left == right));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let equal = cmp_eq(&left, &right, &self.options);
+                    state.stack.push(LiteralValue::Bool(equal));
+                }
+                Instruction::CompareEqCi => {
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state
+                        .stack
+                        .push(LiteralValue::Bool(cmp_eq_ci(&left, &right)));
                 }
                 Instruction::CompareNeq => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(left != right));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let equal = cmp_eq(&left, &right, &self.options);
+                    state.stack.push(LiteralValue::Bool(!equal));
                 }
                 Instruction::CompareLt => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_ord(&left, &right, |a, b| a < b)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let result = cmp_ord_checked(&left, &right, "<", &self.options, |a, b| a < b)?;
+                    state.stack.push(LiteralValue::Bool(result));
                 }
                 Instruction::CompareLte => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_ord(&left, &right, |a, b| a <= b)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let result =
+                        cmp_ord_checked(&left, &right, "<=", &self.options, |a, b| a <= b)?;
+                    state.stack.push(LiteralValue::Bool(result));
                 }
                 Instruction::CompareGt => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_ord(&left, &right, |a, b| a > b)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let result = cmp_ord_checked(&left, &right, ">", &self.options, |a, b| a > b)?;
+                    state.stack.push(LiteralValue::Bool(result));
                 }
                 Instruction::CompareGte => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_ord(&left, &right, |a, b| a >= b)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let result =
+                        cmp_ord_checked(&left, &right, ">=", &self.options, |a, b| a >= b)?;
+                    state.stack.push(LiteralValue::Bool(result));
                 }
                 Instruction::CompareIn => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_in(&left, &right)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let found = cmp_in(&left, &right, &self.options);
+                    state.stack.push(LiteralValue::Bool(found));
                 }
                 Instruction::CompareNotIn => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(!cmp_in(&left, &right)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    let found = cmp_in(&left, &right, &self.options);
+                    state.stack.push(LiteralValue::Bool(!found));
+                }
+                Instruction::CompareInSet { set, negate } => {
+                    let val = state.stack.pop().unwrap();
+                    let found = set_contains(&set.0, &val, &self.options);
+                    state.stack.push(LiteralValue::Bool(found != *negate));
+                }
+                Instruction::CompareInSortedInts { values, negate } => {
+                    let val = state.stack.pop().unwrap();
+                    let found = cmp_in_sorted_ints(&val, &values.0);
+                    state.stack.push(LiteralValue::Bool(found != *negate));
+                }
+                Instruction::CompareInRanges { ranges, negate } => {
+                    let val = state.stack.pop().unwrap();
+                    let found = cmp_in_ranges(&val, ranges);
+                    state.stack.push(LiteralValue::Bool(found != *negate));
+                }
+                Instruction::CompareIpInTrie { trie, negate } => {
+                    let val = state.stack.pop().unwrap();
+                    let found = trie_contains(&trie.0, &val, &self.options);
+                    state.stack.push(LiteralValue::Bool(found != *negate));
+                }
+                Instruction::CompareBetween { low, high, strict } => {
+                    let val = state.stack.pop().unwrap();
+                    check_orderable(&val, low, "between", &self.options)?;
+                    state
+                        .stack
+                        .push(LiteralValue::Bool(cmp_between(&val, low, high, *strict)));
                 }
                 Instruction::CompareMatches => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_matches(&left, &right)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state.stack.push(LiteralValue::Bool(cmp_matches(
+                        &left,
+                        &right,
+                        self.options.anchored_matches,
+                    )));
+                }
+                Instruction::CompareMatchesCompiled(re) => {
+                    let left = state.stack.pop().unwrap();
+                    state
+                        .stack
+                        .push(LiteralValue::Bool(cmp_matches_compiled(re, &left)));
+                }
+                #[cfg(feature = "regex")]
+                Instruction::CaptureCompiled(re) => {
+                    let group = state.stack.pop().unwrap();
+                    let subject = state.stack.pop().unwrap();
+                    state.stack.push(capture_compiled(re, &subject, &group)?);
+                }
+                #[cfg(feature = "regex")]
+                Instruction::MatchesAnyCompiled(set) => {
+                    let subject = state.stack.pop().unwrap();
+                    state.stack.push(matches_any_compiled(set, &subject)?);
                 }
                 Instruction::CompareWildcard { strict } => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_wildcard(&left, &right, *strict)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state
+                        .stack
+                        .push(LiteralValue::Bool(cmp_wildcard(&left, &right, *strict)));
                 }
                 Instruction::CompareContains => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(cmp_contains(&left, &right)));
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state
+                        .stack
+                        .push(LiteralValue::Bool(cmp_contains(&left, &right)));
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Mod
+                | Instruction::BitAnd
+                | Instruction::BitOr
+                | Instruction::BitXor
+                | Instruction::Shl
+                | Instruction::Shr => {
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state.stack.push(eval_arith(&bytecode[pc], &left, &right)?);
+                }
+                Instruction::Negate => {
+                    let val = state.stack.pop().unwrap();
+                    state.stack.push(negate_int(&val)?);
+                }
+                Instruction::Index => {
+                    let key = state.stack.pop().unwrap();
+                    let target = state.stack.pop().unwrap();
+                    state
+                        .stack
+                        .push(index_value(&target, &key).unwrap_or(LiteralValue::Bool(false)));
+                }
+                Instruction::Slice => {
+                    let end = state.stack.pop().unwrap();
+                    let start = state.stack.pop().unwrap();
+                    let target = state.stack.pop().unwrap();
+                    state.stack.push(
+                        slice_value(&target, &start, &end).unwrap_or(LiteralValue::Bool(false)),
+                    );
+                }
+                Instruction::LogicalAnd if self.options.three_valued_logic => {
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state.stack.push(kleene(&left, &right, false));
+                }
+                Instruction::LogicalOr if self.options.three_valued_logic => {
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
+                    state.stack.push(kleene(&left, &right, true));
                 }
                 Instruction::LogicalAnd => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
                     // Short-circuit: if left is false, skip right
                     if !to_bool(&left) {
-                        stack.push(LiteralValue::Bool(false));
+                        state.stack.push(LiteralValue::Bool(false));
                         // skip right (already popped)
                     } else {
-                        stack.push(LiteralValue::Bool(to_bool(&right)));
+                        state.stack.push(LiteralValue::Bool(to_bool(&right)));
                     }
                 }
                 Instruction::LogicalOr => {
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
+                    let right = state.stack.pop().unwrap();
+                    let left = state.stack.pop().unwrap();
                     // Short-circuit: if left is true, skip right
                     if to_bool(&left) {
-                        stack.push(LiteralValue::Bool(true));
+                        state.stack.push(LiteralValue::Bool(true));
                         // skip right (already popped)
                     } else {
-                        stack.push(LiteralValue::Bool(to_bool(&right)));
+                        state.stack.push(LiteralValue::Bool(to_bool(&right)));
                     }
                 }
                 Instruction::LogicalNot => {
-                    let a = stack.pop().unwrap();
-                    stack.push(LiteralValue::Bool(!to_bool(&a)));
+                    let a = state.stack.pop().unwrap();
+                    let result = match a {
+                        LiteralValue::Null if self.options.three_valued_logic => a,
+                        _ => LiteralValue::Bool(!to_bool(&a)),
+                    };
+                    state.stack.push(result);
+                }
+                Instruction::LoadElement => {
+                    let element = state.elements.last().cloned().ok_or_else(|| {
+                        WirerustError::ExecutionError(
+                            "Placeholder '_' used outside of any/all".into(),
+                        )
+                    })?;
+                    state.stack.push(element);
+                }
+                Instruction::Quantify { all, body } => {
+                    let all = *all;
+                    let items = match state.stack.pop().unwrap() {
+                        LiteralValue::Array(items) => items,
+                        LiteralValue::Set(set) => Arc::new(set.iter().cloned().collect()),
+                        _ => {
+                            state.stack.push(LiteralValue::Bool(false));
+                            pc += 1;
+                            continue;
+                        }
+                    };
+                    // any stops at the first match, all at the first miss
+                    let mut result = all;
+                    for item in items.iter() {
+                        state.elements.push(item.clone());
+                        let outcome = self.run(body, state);
+                        state.elements.pop();
+                        outcome?;
+                        if to_bool(&state.stack.pop().unwrap()) != all {
+                            result = !all;
+                            break;
+                        }
+                    }
+                    state.stack.push(LiteralValue::Bool(result));
+                }
+            }
+            pc += 1;
+        }
+        Ok(())
+    }
+}
+
+// Mutable state threaded through nested bytecode blocks during one execution.
+struct ExecState<'a> {
+    ctx: &'a FilterContext,
+    limits: &'a ExecutionLimits,
+    data: &'a dyn Any,
+    stack: &'a mut IrStack,
+    executed: usize,
+    elements: Vec<LiteralValue>,
+}
+
+/// On-disk form of an IR filter: the bytecode plus the names behind every field and
+/// function ID it references (custom operators included), so a load against a different
+/// schema or registry is caught.
+#[cfg(feature = "json")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedIr {
+    bytecode: Vec<Instruction>,
+    fields: std::collections::BTreeMap<FieldId, String>,
+    functions: std::collections::BTreeMap<FunctionId, String>,
+    #[serde(default)]
+    operators: std::collections::BTreeMap<OperatorId, String>,
+    #[serde(default)]
+    options: EngineOptions,
+}
+
+#[cfg(feature = "json")]
+impl IrCompiledFilter {
+    /// Serialize the bytecode (JSON-encoded) for caching across process restarts.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WirerustError> {
+        let mut fields = std::collections::BTreeMap::new();
+        let mut functions = std::collections::BTreeMap::new();
+        let mut operators = std::collections::BTreeMap::new();
+        for instr in all_instructions(&self.bytecode) {
+            match instr {
+                Instruction::LoadField(fid)
+                | Instruction::FieldExists(fid)
+                | Instruction::EqFieldLiteral(fid, _) => {
+                    if let Some(name) = self.schema.field_name(*fid) {
+                        fields.insert(*fid, name.to_string());
+                    }
+                }
+                Instruction::CallFunction(fid, _) => {
+                    if let Some(name) = self.functions.function_name(*fid) {
+                        functions.insert(*fid, name.to_string());
+                    }
+                }
+                Instruction::CallOperator(id) => {
+                    if let Some(name) = self.functions.operator_name(*id) {
+                        operators.insert(*id, name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        let serialized = SerializedIr {
+            bytecode: self.bytecode.clone(),
+            fields,
+            functions,
+            operators,
+            options: self.options,
+        };
+        serde_json::to_vec(&serialized)
+            .map_err(|e| WirerustError::Other(format!("Failed to serialize filter: {e}")))
+    }
+
+    /// Load bytecode produced by [`IrCompiledFilter::to_bytes`], checking that every
+    /// referenced field and function ID still resolves to the same name.
+    pub fn from_bytes(
+        bytes: &[u8],
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+    ) -> Result<Self, WirerustError> {
+        let serialized: SerializedIr = serde_json::from_slice(bytes)
+            .map_err(|e| WirerustError::Other(format!("Failed to deserialize filter: {e}")))?;
+        for instr in all_instructions(&serialized.bytecode) {
+            match instr {
+                Instruction::LoadField(fid)
+                | Instruction::FieldExists(fid)
+                | Instruction::EqFieldLiteral(fid, _) => {
+                    let expected = serialized.fields.get(fid).map(String::as_str);
+                    if expected.is_none() || schema.field_name(*fid) != expected {
+                        return Err(WirerustError::FieldNotFound(format!(
+                            "Field ID {fid} ({}) does not match the schema",
+                            expected.unwrap_or("unnamed")
+                        )));
+                    }
+                }
+                // usize::MAX marks a function that was already unknown at compile time
+                Instruction::CallFunction(fid, _) if *fid != usize::MAX => {
+                    let expected = serialized.functions.get(fid).map(String::as_str);
+                    if expected.is_none() || functions.function_name(*fid) != expected {
+                        return Err(WirerustError::FunctionError(format!(
+                            "Function ID {fid} ({}) does not match the registry",
+                            expected.unwrap_or("unnamed")
+                        )));
+                    }
+                }
+                Instruction::CallOperator(id) => {
+                    let expected = serialized.operators.get(id).map(String::as_str);
+                    if expected.is_none() || functions.operator_name(*id) != expected {
+                        return Err(WirerustError::FunctionError(format!(
+                            "Operator ID {id} ({}) does not match the registry",
+                            expected.unwrap_or("unnamed")
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(IrCompiledFilter {
+            max_stack_depth: checked_stack_depth(&serialized.bytecode)?,
+            bytecode: serialized.bytecode,
+            schema,
+            functions,
+            options: serialized.options,
+        })
+    }
+}
+
+// Helper for disassembly: literals in filter syntax rather than their Debug form
+fn literal_text(val: &LiteralValue) -> String {
+    match val {
+        LiteralValue::Bytes(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+        LiteralValue::Int(i) => i.to_string(),
+        LiteralValue::Bool(b) => b.to_string(),
+        LiteralValue::Ip(ip) => ip.to_string(),
+        LiteralValue::Null => "null".into(),
+        LiteralValue::Array(items) => {
+            let items: Vec<_> = items.iter().map(literal_text).collect();
+            format!("{{{}}}", items.join(" "))
+        }
+        other => format!("{other:?}"),
+    }
+}
+
+pub(crate) fn to_bool(val: &LiteralValue) -> bool {
+    match val {
+        LiteralValue::Bool(b) => *b,
+        LiteralValue::Int(i) => *i != 0,
+        LiteralValue::Bytes(_) => true,
+        LiteralValue::Array(arr) => !arr.is_empty(),
+        LiteralValue::Ip(_) => true,
+        LiteralValue::DateTime(_) => true,
+        LiteralValue::Map(map) => !map.is_empty(),
+        LiteralValue::Set(set) => !set.is_empty(),
+        LiteralValue::Null => false,
+    }
+}
+
+// Helper for three-valued `and` (`dominant` false) and `or` (`dominant` true): the dominant
+// value wins outright, otherwise any null operand makes the result null.
+pub(crate) fn kleene(a: &LiteralValue, b: &LiteralValue, dominant: bool) -> LiteralValue {
+    let known = |v: &LiteralValue| (*v != LiteralValue::Null).then(|| to_bool(v));
+    match (known(a), known(b)) {
+        (Some(x), _) | (_, Some(x)) if x == dominant => LiteralValue::Bool(dominant),
+        (Some(_), Some(_)) => LiteralValue::Bool(!dominant),
+        _ => LiteralValue::Null,
+    }
+}
+
+/// Behavioral switches applied when compiling and executing filters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct EngineOptions {
+    /// Without the `regex` feature, compile `matches` as a plain substring test instead of
+    /// using the built-in pattern engine. Off by default since it silently changes the
+    /// semantics of patterns such as `^GET$`. Has no effect when the `regex` feature is
+    /// enabled.
+    pub substring_matches_fallback: bool,
+    /// Fail execution with an error when a filter reads a field that is not set in the
+    /// context and has no schema default, instead of treating it as `false`. `exists`
+    /// checks are unaffected.
+    pub strict_missing_fields: bool,
+    /// Treat `null` as unknown, SQL-style: comparisons and arithmetic involving it yield
+    /// null, `and`/`or`/`not` follow Kleene logic, and missing fields read as null. An
+    /// unknown result does not match. Off by default, where `null == null` is true.
+    pub three_valued_logic: bool,
+    /// Fail execution with a `TypeError` naming the operator and both runtime types when
+    /// `<`, `<=`, `>`, `>=` or `between` meets operands that can't be ordered, e.g. Bytes
+    /// against Int, instead of evaluating to `false`. Missing fields read as `false` (a
+    /// Bool) unless three-valued logic is on, so the two are best enabled together.
+    pub strict_comparisons: bool,
+    /// Treat IPv4-mapped IPv6 addresses such as `::ffff:192.168.1.1` as their IPv4 form
+    /// in `==`, `!=`, `in` and `not in`, so the two spellings match each other. Off by
+    /// default, where they are distinct addresses.
+    pub normalize_ips: bool,
+    /// Make `matches` require the pattern to match the whole value, as if written
+    /// `^(?:pattern)$`, so `method matches "GET"` no longer matches `"GETX"`. Off by
+    /// default, where `matches` succeeds if the pattern matches anywhere in the value. With
+    /// the substring fallback, anchored `matches` is plain equality.
+    pub anchored_matches: bool,
+    /// Also compile each leaf condition of a filter on its own, so
+    /// [`CompiledFilter::execute_explain`](crate::CompiledFilter::execute_explain) can report
+    /// what each one evaluated to. Off by default since it compiles every condition twice.
+    pub explain: bool,
+}
+
+/// A compilation backend: turns a parsed filter into something executable. Engines use
+/// [`DefaultCompiler`] unless given another with
+/// [`WirerustEngineBuilder::compiler`](crate::WirerustEngineBuilder::compiler).
+pub trait Compiler: Send + Sync {
+    /// Compile `expr` against the schema and functions, honouring `options`.
+    fn compile(
+        &self,
+        expr: FilterExpr,
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+        options: EngineOptions,
+    ) -> Result<Box<dyn ExecutableFilter>, WirerustError>;
+}
+
+/// A filter in a backend's executable form, as produced by a [`Compiler`].
+pub trait ExecutableFilter: Send + Sync {
+    /// Run the filter against `ctx` within `limits` and return its final value, passing
+    /// `data` through to custom functions.
+    fn evaluate(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError>;
+    /// The bytecode behind this filter, for backends that produce it. Serialization,
+    /// disassembly and stack reuse are only available through it.
+    fn as_ir(&self) -> Option<&IrCompiledFilter> {
+        None
+    }
+    /// IDs of the fields the filter reads (not counting `exists` checks), for
+    /// [`CompiledFilter::execute_checked`](crate::CompiledFilter::execute_checked). Taken
+    /// from the bytecode by default; empty when there is none.
+    fn loaded_fields(&self) -> Vec<FieldId> {
+        self.as_ir()
+            .map(|ir| ir.loaded_fields())
+            .unwrap_or_default()
+    }
+    /// Names of the fields the filter reads, `exists` checks included, in order of first
+    /// use. Taken from the bytecode by default; `None` when the backend can't tell, which
+    /// callers must not read as "no fields".
+    fn referenced_fields(&self) -> Option<Vec<&str>> {
+        self.as_ir().map(|ir| ir.referenced_fields())
+    }
+    /// Names of the registered functions the filter calls, in order of first use. Taken
+    /// from the bytecode by default; `None` when the backend can't tell.
+    fn referenced_functions(&self) -> Option<Vec<&str>> {
+        self.as_ir().map(|ir| ir.referenced_functions())
+    }
+}
+
+impl ExecutableFilter for IrCompiledFilter {
+    fn evaluate(
+        &self,
+        ctx: &FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        self.evaluate_full(ctx, limits, data)
+    }
+    fn as_ir(&self) -> Option<&IrCompiledFilter> {
+        Some(self)
+    }
+}
+
+/// The bytecode backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultCompiler;
+
+impl Compiler for DefaultCompiler {
+    fn compile(
+        &self,
+        expr: FilterExpr,
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+        options: EngineOptions,
+    ) -> Result<Box<dyn ExecutableFilter>, WirerustError> {
+        Ok(Box::new(Self::compile_with_options(
+            expr, schema, functions, options,
+        )?))
+    }
+}
+
+impl DefaultCompiler {
+    /// Compile a filter expression into IR bytecode.
+    pub fn compile_ir(
+        expr: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        // Each arm hands off to a helper, keeping this frame (paid once per nesting level)
+        // small enough for the deepest tree the parser accepts.
+        match expr {
+            FilterExpr::LogicalOp { op, left, right } => {
+                let then = match op {
+                    LogicalOp::And => Instruction::LogicalAnd,
+                    LogicalOp::Or => Instruction::LogicalOr,
+                };
+                Self::compile_operands(&[left, right], true, then, schema, functions, options, code)
+            }
+            FilterExpr::Comparison { left, op, right } => {
+                Self::compile_comparison(left, *op, right, schema, functions, options, code)
+            }
+            FilterExpr::Not(inner) => Self::compile_operands(
+                &[inner],
+                true,
+                Instruction::LogicalNot,
+                schema,
+                functions,
+                options,
+                code,
+            ),
+            FilterExpr::Neg(inner) => Self::compile_operands(
+                &[inner],
+                false,
+                Instruction::Negate,
+                schema,
+                functions,
+                options,
+                code,
+            ),
+            FilterExpr::Arithmetic { left, op, right } => {
+                let then = match op {
+                    ArithOp::Add => Instruction::Add,
+                    ArithOp::Sub => Instruction::Sub,
+                    ArithOp::Mul => Instruction::Mul,
+                    ArithOp::Div => Instruction::Div,
+                    ArithOp::Mod => Instruction::Mod,
+                    ArithOp::BitAnd => Instruction::BitAnd,
+                    ArithOp::BitOr => Instruction::BitOr,
+                    ArithOp::BitXor => Instruction::BitXor,
+                    ArithOp::Shl => Instruction::Shl,
+                    ArithOp::Shr => Instruction::Shr,
+                };
+                Self::compile_operands(
+                    &[left, right],
+                    false,
+                    then,
+                    schema,
+                    functions,
+                    options,
+                    code,
+                )
+            }
+            FilterExpr::Value(val) => {
+                code.push(Instruction::LoadLiteral(val.clone()));
+                Ok(())
+            }
+            FilterExpr::Field(name) => {
+                let fid = schema
+                    .field_id(name)
+                    .ok_or_else(|| WirerustError::FieldNotFound(name.clone()))?;
+                code.push(Instruction::LoadField(fid));
+                Ok(())
+            }
+            FilterExpr::FunctionCall { name, args } => {
+                Self::compile_call(name, args, schema, functions, options, code)
+            }
+            FilterExpr::Quantifier {
+                kind,
+                array,
+                predicate,
+            } => {
+                Self::compile_quantifier(*kind, array, predicate, schema, functions, options, code)
+            }
+            FilterExpr::Placeholder => {
+                code.push(Instruction::LoadElement);
+                Ok(())
+            }
+            FilterExpr::Exists(field) => {
+                let fid = schema
+                    .field_id(field)
+                    .ok_or_else(|| WirerustError::FieldNotFound(field.clone()))?;
+                code.push(Instruction::FieldExists(fid));
+                Ok(())
+            }
+            FilterExpr::Index { target, key } => Self::compile_operands(
+                &[target, key],
+                false,
+                Instruction::Index,
+                schema,
+                functions,
+                options,
+                code,
+            ),
+            FilterExpr::Slice { target, start, end } => {
+                Self::compile_slice(target, [start, end], schema, functions, options, code)
+            }
+            FilterExpr::CustomComparison { left, op, right } => {
+                let id = functions.operator_id(op).ok_or_else(|| {
+                    WirerustError::FunctionError(format!("Unknown operator '{op}'"))
+                })?;
+                Self::compile_operands(
+                    &[left, right],
+                    false,
+                    Instruction::CallOperator(id),
+                    schema,
+                    functions,
+                    options,
+                    code,
+                )
+            }
+            FilterExpr::List(vals) => {
+                code.push(Instruction::LoadLiteral(LiteralValue::Array(Arc::new(
+                    vals.clone(),
+                ))));
+                Ok(())
+            }
+            FilterExpr::IntRanges(_) => Err(WirerustError::TypeError(
+                "Integer ranges are only allowed in 'in' sets".into(),
+            )),
+        }
+    }
+
+    // Compile `operands` in order, as conditions or plain values, then push `then`.
+    #[allow(clippy::too_many_arguments)]
+    fn compile_operands(
+        operands: &[&FilterExpr],
+        condition: bool,
+        then: Instruction,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        for operand in operands {
+            if condition {
+                Self::compile_condition(operand, schema, functions, options, code)?;
+            } else {
+                Self::compile_ir(operand, schema, functions, options, code)?;
+            }
+        }
+        code.push(then);
+        Ok(())
+    }
+
+    fn compile_quantifier(
+        kind: QuantifierKind,
+        array: &FilterExpr,
+        predicate: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        Self::compile_ir(array, schema, functions, options, code)?;
+        let mut body = Vec::new();
+        Self::compile_condition(predicate, schema, functions, options, &mut body)?;
+        code.push(Instruction::Quantify {
+            all: kind == QuantifierKind::All,
+            body,
+        });
+        Ok(())
+    }
+
+    fn compile_slice(
+        target: &FilterExpr,
+        bounds: [&Option<Box<FilterExpr>>; 2],
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        Self::compile_ir(target, schema, functions, options, code)?;
+        for bound in bounds {
+            match bound {
+                Some(bound) => Self::compile_ir(bound, schema, functions, options, code)?,
+                None => code.push(Instruction::LoadLiteral(LiteralValue::Null)),
+            }
+        }
+        code.push(Instruction::Slice);
+        Ok(())
+    }
+
+    fn compile_comparison(
+        left: &FilterExpr,
+        op: ComparisonOp,
+        right: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        if let Some(each) = Self::expand_multi_value(left, op, right, schema)? {
+            return Self::compile_ir(&each, schema, functions, options, code);
+        }
+        let substring_fallback = cfg!(not(feature = "regex")) && options.substring_matches_fallback;
+        if op == ComparisonOp::Matches && !substring_fallback {
+            if let Some(re) = Self::literal_regex(right, options.anchored_matches)? {
+                Self::compile_ir(left, schema, functions, options, code)?;
+                code.push(Instruction::CompareMatchesCompiled(CompiledRegex(re)));
+                return Ok(());
+            }
+        }
+        Self::compile_ir(left, schema, functions, options, code)?;
+        if let ComparisonOp::Between | ComparisonOp::StrictBetween = op {
+            let (low, high) = Self::check_range(left, right, schema, functions)?;
+            code.push(Instruction::CompareBetween {
+                low,
+                high,
+                strict: op == ComparisonOp::StrictBetween,
+            });
+            return Ok(());
+        }
+        Self::check_variant(&Self::operand_type(left, schema, functions), op, right)?;
+        match (op, right) {
+            (
+                ComparisonOp::In | ComparisonOp::NotIn,
+                FilterExpr::Value(LiteralValue::Array(items)),
+            ) => {
+                let left_type = Self::static_type(left, schema, functions);
+                return Self::compile_literal_set(
+                    &left_type,
+                    items,
+                    op == ComparisonOp::NotIn,
+                    code,
+                );
+            }
+            (ComparisonOp::In | ComparisonOp::NotIn, FilterExpr::IntRanges(ranges)) => {
+                let left_type = Self::static_type(left, schema, functions);
+                if !FunctionSignature::accepts(&FieldType::Int, &left_type) {
+                    return Err(WirerustError::TypeError(format!(
+                        "Integer ranges cannot contain {left_type:?} values"
+                    )));
+                }
+                code.push(Instruction::CompareInRanges {
+                    ranges: merge_ranges(ranges),
+                    negate: op == ComparisonOp::NotIn,
+                });
+                return Ok(());
+            }
+            _ => Self::compile_ir(right, schema, functions, options, code)?,
+        }
+        match op {
+            ComparisonOp::Eq => code.push(Instruction::CompareEq),
+            ComparisonOp::EqCaseInsensitive => code.push(Instruction::CompareEqCi),
+            ComparisonOp::Neq => code.push(Instruction::CompareNeq),
+            ComparisonOp::Lt => code.push(Instruction::CompareLt),
+            ComparisonOp::Lte => code.push(Instruction::CompareLte),
+            ComparisonOp::Gt => code.push(Instruction::CompareGt),
+            ComparisonOp::Gte => code.push(Instruction::CompareGte),
+            ComparisonOp::In => code.push(Instruction::CompareIn),
+            ComparisonOp::NotIn => code.push(Instruction::CompareNotIn),
+            ComparisonOp::Matches if substring_fallback => code.push(if options.anchored_matches {
+                Instruction::CompareEq
+            } else {
+                Instruction::CompareContains
+            }),
+            ComparisonOp::Matches => code.push(Instruction::CompareMatches),
+            ComparisonOp::Wildcard => code.push(Instruction::CompareWildcard { strict: false }),
+            ComparisonOp::StrictWildcard => {
+                code.push(Instruction::CompareWildcard { strict: true })
+            }
+            ComparisonOp::Contains => code.push(Instruction::CompareContains),
+            ComparisonOp::Between | ComparisonOp::StrictBetween => {
+                unreachable!("compiled above")
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_call(
+        name: &str,
+        args: &[FilterExpr],
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        if let Some(sig) = functions.get(name).and_then(|f| f.signature()) {
+            Self::check_call(name, &sig, args, schema, functions)?;
+        }
+        // A builtin `regex_capture` with a constant pattern compiles it once
+        #[cfg(feature = "regex")]
+        if let (Some(BuiltinFunctionId::RegexCapture), [subject, pattern, group]) =
+            (BuiltinFunctionId::from_name(name), args)
+        {
+            if let Some(re) = Self::literal_regex(pattern, false)? {
+                if functions.function_id(name).is_some() && !functions.is_builtin_overridden(name) {
+                    Self::compile_ir(subject, schema, functions, options, code)?;
+                    Self::compile_ir(group, schema, functions, options, code)?;
+                    code.push(Instruction::CaptureCompiled(CompiledRegex(re)));
+                    return Ok(());
+                }
+            }
+        }
+        // As does `matches_any` with a constant pattern list
+        #[cfg(feature = "regex")]
+        if let (Some(BuiltinFunctionId::MatchesAny), [subject, patterns]) =
+            (BuiltinFunctionId::from_name(name), args)
+        {
+            if let Some(set) = Self::literal_regex_set(patterns) {
+                if functions.function_id(name).is_some() && !functions.is_builtin_overridden(name) {
+                    Self::compile_ir(subject, schema, functions, options, code)?;
+                    code.push(Instruction::MatchesAnyCompiled(CompiledRegexSet(set)));
+                    return Ok(());
+                }
+            }
+        }
+        let argc = u8::try_from(args.len()).map_err(|_| {
+            WirerustError::FunctionError(format!(
+                "Function '{name}' is called with {} arguments, more than the {} allowed",
+                args.len(),
+                u8::MAX
+            ))
+        })?;
+        for arg in args {
+            Self::compile_ir(arg, schema, functions, options, code)?;
+        }
+        // usize::MAX marks an unknown function, an error at runtime
+        let fid = functions.function_id(name).unwrap_or(usize::MAX);
+        code.push(Instruction::CallFunction(fid, argc));
+        Ok(())
+    }
+
+    /// Compile an expression whose truthiness is taken: the whole filter, operands of
+    /// `and`/`or`/`not`, and quantifier predicates. A bare identifier the schema doesn't
+    /// declare is `false` there (a compile error with `strict_missing_fields`), rather than
+    /// an always-true bytes literal.
+    fn compile_condition(
+        expr: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        options: &EngineOptions,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        match expr {
+            FilterExpr::Value(LiteralValue::Bytes(name)) => {
+                if options.strict_missing_fields {
+                    return Err(WirerustError::FieldNotFound(
+                        String::from_utf8_lossy(name).into_owned(),
+                    ));
+                }
+                code.push(Instruction::LoadLiteral(LiteralValue::Bool(false)));
+                Ok(())
+            }
+            _ => Self::compile_ir(expr, schema, functions, options, code),
+        }
+    }
+
+    /// Validate a call against the function's declared signature.
+    fn check_call(
+        name: &str,
+        sig: &FunctionSignature,
+        args: &[FilterExpr],
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> Result<(), WirerustError> {
+        let arity_ok = match sig.variadic {
+            Some(_) => args.len() >= sig.args.len(),
+            None => args.len() == sig.args.len(),
+        };
+        if !arity_ok {
+            let at_least = if sig.variadic.is_some() {
+                "at least "
+            } else {
+                ""
+            };
+            return Err(WirerustError::FunctionError(format!(
+                "Function '{name}' expects {at_least}{} argument(s), got {}",
+                sig.args.len(),
+                args.len()
+            )));
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let Some(expected) = sig.args.get(i).or(sig.variadic.as_ref()) else {
+                break;
+            };
+            let actual = Self::static_type(arg, schema, functions);
+            if !FunctionSignature::accepts(expected, &actual) {
+                return Err(WirerustError::TypeError(format!(
+                    "Argument {} of function '{name}' expects {expected:?}, got {actual:?}",
+                    i + 1
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the membership test of an `in` / `not in` comparison against a literal set,
+    /// choosing a prefix trie, sorted integers, a hashed set or a linear scan by its
+    /// contents. Kept out of `compile_ir` so that recursive function's frame stays small.
+    fn compile_literal_set(
+        left_type: &FieldType,
+        items: &[LiteralValue],
+        negate: bool,
+        code: &mut Vec<Instruction>,
+    ) -> Result<(), WirerustError> {
+        if let Some(trie) = Self::ip_prefix_set(left_type, items)? {
+            code.push(Instruction::CompareIpInTrie {
+                trie: IpPrefixSet(Arc::new(trie)),
+                negate,
+            });
+            return Ok(());
+        }
+        let set = Self::check_set(left_type, items)?;
+        let ints: Option<Vec<i64>> = set
+            .iter()
+            .map(|item| match item {
+                LiteralValue::Int(n) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        if let (Some(ints), true) = (ints, set.len() >= SORTED_INTS_THRESHOLD) {
+            code.push(Instruction::CompareInSortedInts {
+                values: SortedInts::new(ints),
+                negate,
+            });
+        } else if set.len() >= HASH_SET_THRESHOLD {
+            code.push(Instruction::CompareInSet {
+                set: LiteralSet(Arc::new(set.into_iter().collect())),
+                negate,
+            });
+        } else {
+            code.push(Instruction::LoadLiteral(LiteralValue::Array(Arc::new(set))));
+            code.push(if negate {
+                Instruction::CompareNotIn
+            } else {
+                Instruction::CompareIn
+            });
+        }
+        Ok(())
+    }
+
+    /// A set compared with an IP operand that holds a CIDR prefix such as `"10.0.0.0/8"`,
+    /// built into a prefix trie. Sets of plain addresses give `None` and stay hashed.
+    fn ip_prefix_set(
+        left: &FieldType,
+        items: &[LiteralValue],
+    ) -> Result<Option<IpTrie>, WirerustError> {
+        let is_prefix =
+            |item: &LiteralValue| matches!(item, LiteralValue::Bytes(b) if b.contains(&b'/'));
+        if *left != FieldType::Ip || !items.iter().any(is_prefix) {
+            return Ok(None);
+        }
+        let mut prefixes = Vec::with_capacity(items.len());
+        for item in items {
+            match item {
+                LiteralValue::Ip(ip) => prefixes.push((*ip, if ip.is_ipv4() { 32 } else { 128 })),
+                LiteralValue::Bytes(bytes) => {
+                    let prefix = parse_prefix(&String::from_utf8_lossy(bytes))
+                        .map_err(|e| WirerustError::TypeError(format!("Set element {e}")))?;
+                    prefixes.push(prefix);
+                }
+                // A null operand gives null before the set is consulted
+                LiteralValue::Null => {}
+                other => {
+                    return Err(WirerustError::TypeError(format!(
+                        "Set of {:?} elements does not match the Ip operand",
+                        other.get_type()
+                    )))
+                }
+            }
+        }
+        Ok(Some(IpTrie::new(prefixes)))
+    }
+
+    /// Validate the literal set of an `in`/`not in` comparison: every element must share
+    /// one type, matching the left operand when that is known. Quoted addresses are parsed
+    /// when the left operand is an IP, and duplicates are dropped.
+    fn check_set(
+        left: &FieldType,
+        items: &[LiteralValue],
+    ) -> Result<Vec<LiteralValue>, WirerustError> {
+        let mut set: Vec<LiteralValue> = Vec::with_capacity(items.len());
+        let mut seen = HashSet::with_capacity(items.len());
+        let mut elem_type: Option<FieldType> = None;
+        for item in items {
+            let item = match (left, item) {
+                (FieldType::Ip, LiteralValue::Bytes(bytes)) => std::str::from_utf8(bytes)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .map(LiteralValue::Ip)
+                    .ok_or_else(|| {
+                        WirerustError::TypeError(format!(
+                            "Set element \"{}\" is not a valid IP address",
+                            String::from_utf8_lossy(bytes)
+                        ))
+                    })?,
+                (FieldType::Enum(_), LiteralValue::Bytes(bytes)) if !left.has_variant(bytes) => {
+                    return Err(WirerustError::TypeError(format!(
+                        "Set element \"{}\" is not a variant of {left:?}",
+                        String::from_utf8_lossy(bytes)
+                    )));
+                }
+                _ => item.clone(),
+            };
+            let ty = item.get_type();
+            match &elem_type {
+                _ if item == LiteralValue::Null => {}
+                Some(first) if *first != ty => {
+                    return Err(WirerustError::TypeError(format!(
+                        "Set mixes {first:?} and {ty:?} elements"
+                    )))
+                }
+                Some(_) => {}
+                None => {
+                    if !FunctionSignature::accepts(left, &ty) {
+                        return Err(WirerustError::TypeError(format!(
+                            "Set of {ty:?} elements does not match the {left:?} operand"
+                        )));
+                    }
+                    elem_type = Some(ty);
+                }
+            }
+            if seen.insert(item.clone()) {
+                set.push(item);
+            }
+        }
+        Ok(set)
+    }
+
+    /// Check a filter against the schema and function registry without compiling it,
+    /// collecting every problem instead of stopping at the first: undeclared fields and
+    /// bare identifiers used as conditions, unknown functions, bad arity and argument
+    /// types, and comparisons or arithmetic whose operand types can never match. This is
+    /// stricter than compilation, which leaves some of these to runtime. Comparisons with
+    /// a fixed result, like `x == x` or `x in {}`, are reported as
+    /// [`WirerustError::Warning`]s, which don't stop compilation.
+    pub fn validate(
+        expr: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> Vec<WirerustError> {
+        let mut errors = Vec::new();
+        Self::validate_into(expr, true, schema, functions, &mut errors);
+        errors
+    }
+
+    fn validate_into(
+        expr: &FilterExpr,
+        condition: bool,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        errors: &mut Vec<WirerustError>,
+    ) {
+        let walk = |e: &FilterExpr, condition: bool, errors: &mut Vec<WirerustError>| {
+            Self::validate_into(e, condition, schema, functions, errors)
+        };
+        match expr {
+            FilterExpr::LogicalOp { left, right, .. } => {
+                walk(left, true, errors);
+                walk(right, true, errors);
+            }
+            FilterExpr::Not(inner) => walk(inner, true, errors),
+            FilterExpr::Comparison { left, op, right } => {
+                walk(left, false, errors);
+                walk(right, false, errors);
+                let checked = Self::expand_multi_value(left, *op, right, schema)
+                    .and_then(|_| Self::check_comparison(left, *op, right, schema, functions));
+                if let Err(e) = checked {
+                    errors.push(e);
+                }
+                errors.extend(Self::check_constant(left, *op, right));
+            }
+            FilterExpr::Neg(inner) => {
+                walk(inner, false, errors);
+                Self::check_int_operand(inner, "Negation", schema, functions, errors);
+            }
+            FilterExpr::Arithmetic { left, right, .. } => {
+                walk(left, false, errors);
+                walk(right, false, errors);
+                for operand in [left, right] {
+                    Self::check_int_operand(operand, "Arithmetic", schema, functions, errors);
+                }
+            }
+            FilterExpr::Value(LiteralValue::Bytes(name)) if condition => {
+                errors.push(WirerustError::FieldNotFound(
+                    String::from_utf8_lossy(name).into_owned(),
+                ));
+            }
+            FilterExpr::Field(name) | FilterExpr::Exists(name) => {
+                if schema.field_id(name).is_none() {
+                    errors.push(WirerustError::FieldNotFound(name.clone()));
+                }
+            }
+            FilterExpr::FunctionCall { name, args } => {
+                for arg in args {
+                    walk(arg, false, errors);
+                }
+                match functions.get(name) {
+                    None => errors.push(WirerustError::FunctionError(format!(
+                        "Unknown function '{name}'"
+                    ))),
+                    Some(f) => {
+                        if let Some(sig) = f.signature() {
+                            if let Err(e) = Self::check_call(name, &sig, args, schema, functions) {
+                                errors.push(e);
+                            }
+                        }
+                    }
+                }
+            }
+            FilterExpr::Quantifier {
+                array, predicate, ..
+            } => {
+                walk(array, false, errors);
+                walk(predicate, true, errors);
+            }
+            FilterExpr::Index { target, key } => {
+                walk(target, false, errors);
+                walk(key, false, errors);
+            }
+            FilterExpr::Slice { target, start, end } => {
+                walk(target, false, errors);
+                for bound in [start, end].into_iter().flatten() {
+                    walk(bound, false, errors);
+                }
+            }
+            FilterExpr::CustomComparison { left, op, right } => {
+                walk(left, false, errors);
+                walk(right, false, errors);
+                if functions.operator_id(op).is_none() {
+                    errors.push(WirerustError::FunctionError(format!(
+                        "Unknown operator '{op}'"
+                    )));
+                }
+            }
+            FilterExpr::Value(_)
+            | FilterExpr::List(_)
+            | FilterExpr::IntRanges(_)
+            | FilterExpr::Placeholder => {}
+        }
+    }
+
+    /// Whether the operand types of a comparison can ever satisfy it. Operands whose type
+    /// isn't known statically always pass.
+    fn check_comparison(
+        left: &FilterExpr,
+        op: ComparisonOp,
+        right: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> Result<(), WirerustError> {
+        let left_type = Self::operand_type(left, schema, functions);
+        let compatible = |a: &FieldType, b: &FieldType| FunctionSignature::accepts(a, b);
+        if let ComparisonOp::Between | ComparisonOp::StrictBetween = op {
+            return Self::check_range(left, right, schema, functions).map(|_| ());
+        }
+        Self::check_variant(&left_type, op, right)?;
+        let ok = match (op, right) {
+            (
+                ComparisonOp::In | ComparisonOp::NotIn,
+                FilterExpr::Value(LiteralValue::Array(items)),
+            ) => {
+                if Self::ip_prefix_set(&left_type, items)?.is_some() {
+                    return Ok(());
                 }
+                return Self::check_set(&left_type, items).map(|_| ());
+            }
+            _ => {
+                let right_type = Self::static_type(right, schema, functions);
+                match op {
+                    ComparisonOp::In | ComparisonOp::NotIn => match &right_type {
+                        FieldType::Array(elem) | FieldType::Set(elem) => {
+                            compatible(elem, &left_type)
+                        }
+                        other => *other == FieldType::Unknown,
+                    },
+                    ComparisonOp::Contains => match &left_type {
+                        FieldType::Array(elem) | FieldType::Set(elem) => {
+                            compatible(elem, &right_type)
+                                || matches!(&right_type, FieldType::Array(sub) | FieldType::Set(sub) if compatible(elem, sub))
+                        }
+                        FieldType::Unknown => true,
+                        other => {
+                            matches!(other, FieldType::Bytes | FieldType::Enum(_))
+                                && compatible(&FieldType::Bytes, &right_type)
+                        }
+                    },
+                    ComparisonOp::Matches
+                    | ComparisonOp::Wildcard
+                    | ComparisonOp::StrictWildcard
+                    | ComparisonOp::EqCaseInsensitive => {
+                        compatible(&FieldType::Bytes, &left_type)
+                            && compatible(&FieldType::Bytes, &right_type)
+                    }
+                    _ => compatible(&left_type, &right_type),
+                }
+            }
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(WirerustError::TypeError(format!(
+                "Cannot compare {left_type:?} with {:?} using {op:?}",
+                Self::static_type(right, schema, functions)
+            )))
+        }
+    }
+
+    /// Reject a bytes literal compared with an `Enum` operand by `==`, `!=` or `eq_ci`
+    /// when it isn't one of the variants (ignoring case for `eq_ci`).
+    fn check_variant(
+        left: &FieldType,
+        op: ComparisonOp,
+        right: &FilterExpr,
+    ) -> Result<(), WirerustError> {
+        let (FieldType::Enum(variants), FilterExpr::Value(LiteralValue::Bytes(value))) =
+            (left, right)
+        else {
+            return Ok(());
+        };
+        let known = match op {
+            ComparisonOp::Eq | ComparisonOp::Neq => left.has_variant(value),
+            ComparisonOp::EqCaseInsensitive => variants
+                .iter()
+                .any(|v| v.as_bytes().eq_ignore_ascii_case(value)),
+            _ => true,
+        };
+        if known {
+            Ok(())
+        } else {
+            Err(WirerustError::TypeError(format!(
+                "\"{}\" is not a variant of {left:?}",
+                String::from_utf8_lossy(value)
+            )))
+        }
+    }
+
+    /// A warning for a comparison whose result is the same for every input: both sides
+    /// are one expression (`port > port`), both are literals (`1 == 2`), the `in` set
+    /// is empty, or the pattern matches everything (`matches ".*"`, `wildcard "*"`).
+    /// `wildcard ""`, which only matches an empty value, is flagged too. Sides that call
+    /// functions are skipped, as those may vary between calls.
+    fn check_constant(
+        left: &FilterExpr,
+        op: ComparisonOp,
+        right: &FilterExpr,
+    ) -> Option<WirerustError> {
+        if let Some(warning) = Self::check_pattern(op, right) {
+            return Some(warning);
+        }
+        if let (
+            ComparisonOp::In | ComparisonOp::NotIn,
+            FilterExpr::Value(LiteralValue::Array(items)),
+        ) = (op, right)
+        {
+            return items.is_empty().then(|| {
+                let (op, result) = if op == ComparisonOp::In {
+                    ("in", false)
+                } else {
+                    ("not in", true)
+                };
+                WirerustError::Warning(format!("'{op}' an empty set is always {result}"))
+            });
+        }
+        let ordinary = matches!(
+            op,
+            ComparisonOp::Eq
+                | ComparisonOp::EqCaseInsensitive
+                | ComparisonOp::Neq
+                | ComparisonOp::Lt
+                | ComparisonOp::Lte
+                | ComparisonOp::Gt
+                | ComparisonOp::Gte
+        );
+        if !ordinary || calls_function(left) || calls_function(right) {
+            return None;
+        }
+        let what = if left == right {
+            "an expression with itself"
+        } else if matches!((left, right), (FilterExpr::Value(_), FilterExpr::Value(_))) {
+            "two literals"
+        } else {
+            return None;
+        };
+        Some(WirerustError::Warning(format!(
+            "Comparing {what} using {op:?} always gives the same result"
+        )))
+    }
+
+    // A warning for a literal `matches` or `wildcard` pattern that accepts every string,
+    // or (for wildcards) only the empty one
+    fn check_pattern(op: ComparisonOp, right: &FilterExpr) -> Option<WirerustError> {
+        let FilterExpr::Value(LiteralValue::Bytes(pattern)) = right else {
+            return None;
+        };
+        let text = String::from_utf8_lossy(pattern);
+        let (name, matches_all) = match op {
+            ComparisonOp::Matches => {
+                let body = text.strip_prefix('^').unwrap_or(&text);
+                let body = body.strip_suffix('$').unwrap_or(body);
+                ("matches", matches!(body, ".*" | ".*?" | "(?s).*"))
+            }
+            ComparisonOp::Wildcard | ComparisonOp::StrictWildcard => {
+                let name = if op == ComparisonOp::Wildcard {
+                    "wildcard"
+                } else {
+                    "strict wildcard"
+                };
+                if pattern.is_empty() {
+                    return Some(WirerustError::Warning(format!(
+                        "'{name} \"\"' only matches an empty string"
+                    )));
+                }
+                (name, pattern.iter().all(|&b| b == b'*'))
+            }
+            _ => return None,
+        };
+        matches_all
+            .then(|| WirerustError::Warning(format!("'{name} {text:?}' matches every string")))
+    }
+
+    fn check_int_operand(
+        operand: &FilterExpr,
+        what: &str,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+        errors: &mut Vec<WirerustError>,
+    ) {
+        let ty = Self::static_type(operand, schema, functions);
+        if !FunctionSignature::accepts(&FieldType::Int, &ty) {
+            errors.push(WirerustError::TypeError(format!(
+                "{what} expects Int operands, got {ty:?}"
+            )));
+        }
+    }
+
+    /// Validate the `{low high}` bounds of a `between`: two non-null literals of one
+    /// ordered type (Int or DateTime), matching the left operand when that is known.
+    fn check_range(
+        left: &FilterExpr,
+        right: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> Result<(LiteralValue, LiteralValue), WirerustError> {
+        let FilterExpr::Value(LiteralValue::Array(bounds)) = right else {
+            return Err(WirerustError::TypeError(
+                "'between' expects a literal range {low high}".into(),
+            ));
+        };
+        let [low, high] = bounds.as_slice() else {
+            return Err(WirerustError::TypeError(format!(
+                "'between' expects two bounds, got {}",
+                bounds.len()
+            )));
+        };
+        let ty = low.get_type();
+        if !matches!(ty, FieldType::Int | FieldType::DateTime) || high.get_type() != ty {
+            return Err(WirerustError::TypeError(format!(
+                "'between' bounds must both be Int or DateTime, got {ty:?} and {:?}",
+                high.get_type()
+            )));
+        }
+        let left_type = Self::operand_type(left, schema, functions);
+        if !FunctionSignature::accepts(&ty, &left_type) {
+            return Err(WirerustError::TypeError(format!(
+                "Range of {ty:?} does not match the {left_type:?} operand"
+            )));
+        }
+        Ok((low.clone(), high.clone()))
+    }
+
+    /// Best-effort type of an expression before execution; `Unknown` when it can't be known.
+    fn static_type(
+        expr: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> FieldType {
+        match expr {
+            FilterExpr::LogicalOp { .. }
+            | FilterExpr::Comparison { .. }
+            | FilterExpr::CustomComparison { .. }
+            | FilterExpr::Not(_)
+            | FilterExpr::Quantifier { .. }
+            | FilterExpr::Exists(_) => FieldType::Bool,
+            FilterExpr::Placeholder => FieldType::Unknown,
+            FilterExpr::Arithmetic { .. } | FilterExpr::Neg(_) => FieldType::Int,
+            FilterExpr::Field(name) => schema
+                .get_field_type(name)
+                .cloned()
+                .unwrap_or(FieldType::Unknown),
+            FilterExpr::Value(val) => val.get_type(),
+            FilterExpr::List(vals) => LiteralValue::Array(Arc::new(vals.clone())).get_type(),
+            FilterExpr::IntRanges(_) => FieldType::Array(Box::new(FieldType::Int)),
+            FilterExpr::FunctionCall { name, .. } => functions
+                .get(name)
+                .and_then(|f| f.signature())
+                .map_or(FieldType::Unknown, |sig| sig.returns),
+            FilterExpr::Index { target, .. } => {
+                match Self::static_type(target, schema, functions) {
+                    FieldType::Array(inner) | FieldType::Map(inner) => *inner,
+                    _ => FieldType::Unknown,
+                }
+            }
+            FilterExpr::Slice { target, .. } => {
+                match Self::static_type(target, schema, functions) {
+                    array @ FieldType::Array(_) => array,
+                    _ => FieldType::Unknown,
+                }
+            }
+        }
+    }
+
+    /// The type a comparison sees for its left operand: the element type for a
+    /// multi-valued field, which is compared value by value, otherwise the static type.
+    fn operand_type(
+        expr: &FilterExpr,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> FieldType {
+        match (expr, Self::static_type(expr, schema, functions)) {
+            (FilterExpr::Field(name), FieldType::Array(elem)) if schema.is_multi_valued(name) => {
+                *elem
+            }
+            (_, ty) => ty,
+        }
+    }
+
+    /// Rewrite `field op right` on a multi-valued field as `any(field, _ op right)`, so
+    /// the comparison holds if any value satisfies it. `None` for other comparisons.
+    fn expand_multi_value(
+        left: &FilterExpr,
+        op: ComparisonOp,
+        right: &FilterExpr,
+        schema: &FilterSchema,
+    ) -> Result<Option<FilterExpr>, WirerustError> {
+        let FilterExpr::Field(name) = left else {
+            return Ok(None);
+        };
+        if !schema.is_multi_valued(name) {
+            return Ok(None);
+        }
+        if uses_element(right) {
+            return Err(WirerustError::TypeError(format!(
+                "Multi-valued field '{name}' can't be compared against '_'"
+            )));
+        }
+        Ok(Some(FilterExpr::Quantifier {
+            kind: QuantifierKind::Any,
+            array: Box::new(left.clone()),
+            predicate: Box::new(FilterExpr::Comparison {
+                left: Box::new(FilterExpr::Placeholder),
+                op,
+                right: Box::new(right.clone()),
+            }),
+        }))
+    }
+
+    /// Compile the pattern operand of a `matches` comparison up front when it is a
+    /// constant string rather than a field reference. With the `regex` feature, invalid
+    /// patterns are left to the runtime path, which treats them as non-matching; the
+    /// built-in engine rejects syntax outside its subset here instead.
+    fn literal_regex(
+        pattern: &FilterExpr,
+        anchored: bool,
+    ) -> Result<Option<Arc<RegexEngine>>, WirerustError> {
+        let FilterExpr::Value(LiteralValue::Bytes(bytes)) = pattern else {
+            return Ok(None);
+        };
+        let Ok(pat) = std::str::from_utf8(bytes) else {
+            return Ok(None);
+        };
+        match new_regex(pat, anchored) {
+            Ok(re) => Ok(Some(Arc::new(re))),
+            #[cfg(feature = "regex")]
+            Err(_) => Ok(None),
+            #[cfg(not(feature = "regex"))]
+            Err(e) => Err(WirerustError::TypeError(format!(
+                "{e} (enable the 'regex' feature for full regex syntax)"
+            ))),
+        }
+    }
+
+    /// Compile the patterns of a `matches_any` call into one regex set when they are a
+    /// constant list of strings. Invalid patterns are left to the runtime path.
+    #[cfg(feature = "regex")]
+    fn literal_regex_set(patterns: &FilterExpr) -> Option<Arc<regex::RegexSet>> {
+        let FilterExpr::List(items) = patterns else {
+            return None;
+        };
+        let patterns = items
+            .iter()
+            .map(|item| match item {
+                LiteralValue::Bytes(bytes) => std::str::from_utf8(bytes).ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        regex::RegexSet::new(patterns).ok().map(Arc::new)
+    }
+
+    pub fn compile(
+        expr: FilterExpr,
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+    ) -> Result<IrCompiledFilter, WirerustError> {
+        Self::compile_with_options(expr, schema, functions, EngineOptions::default())
+    }
+
+    /// Compile with non-default [`EngineOptions`].
+    pub fn compile_with_options(
+        expr: FilterExpr,
+        schema: Arc<FilterSchema>,
+        functions: Arc<FunctionRegistry>,
+        options: EngineOptions,
+    ) -> Result<IrCompiledFilter, WirerustError> {
+        let mut bytecode: Vec<Instruction> = Vec::new();
+        Self::compile_condition(&expr, &schema, &functions, &options, &mut bytecode)?;
+        fuse_field_literal_eq(&mut bytecode);
+        LiteralInterner::default().intern_code(&mut bytecode);
+        Ok(IrCompiledFilter {
+            max_stack_depth: checked_stack_depth(&bytecode)?,
+            bytecode,
+            schema: Arc::clone(&schema),
+            functions: Arc::clone(&functions),
+            options,
+        })
+    }
+}
+
+// Whether `expr` contains a function call anywhere.
+fn calls_function(expr: &FilterExpr) -> bool {
+    struct Calls(bool);
+    impl ExprVisitor for Calls {
+        fn visit(&mut self, expr: &FilterExpr) {
+            self.0 |= matches!(expr, FilterExpr::FunctionCall { .. });
+        }
+    }
+    let mut calls = Calls(false);
+    expr.accept(&mut calls);
+    calls.0
+}
+
+// Whether `expr` refers to the enclosing quantifier's `_`, outside any nested quantifier
+// predicate that binds its own.
+fn uses_element(expr: &FilterExpr) -> bool {
+    match expr {
+        FilterExpr::Placeholder => true,
+        FilterExpr::LogicalOp { left, right, .. }
+        | FilterExpr::Comparison { left, right, .. }
+        | FilterExpr::CustomComparison { left, right, .. }
+        | FilterExpr::Arithmetic { left, right, .. } => uses_element(left) || uses_element(right),
+        FilterExpr::Not(inner) | FilterExpr::Neg(inner) => uses_element(inner),
+        FilterExpr::FunctionCall { args, .. } => args.iter().any(uses_element),
+        FilterExpr::Quantifier { array, .. } => uses_element(array),
+        FilterExpr::Index { target, key } => uses_element(target) || uses_element(key),
+        FilterExpr::Slice { target, start, end } => {
+            uses_element(target) || [start, end].into_iter().flatten().any(|b| uses_element(b))
+        }
+        FilterExpr::Value(_)
+        | FilterExpr::Field(_)
+        | FilterExpr::List(_)
+        | FilterExpr::IntRanges(_)
+        | FilterExpr::Exists(_) => false,
+    }
+}
+
+// Read a field for a filter: the context's value, else the schema default, else an
+// error, null or `false` depending on the options.
+pub(crate) fn load_field(
+    ctx: &FilterContext,
+    schema: &FilterSchema,
+    options: &EngineOptions,
+    fid: usize,
+) -> Result<LiteralValue, WirerustError> {
+    peek_field(ctx, schema, options, fid).cloned()
+}
+
+// Borrow a field's value (or its default, or the missing-field stand-in) without cloning it
+pub(crate) fn peek_field<'a>(
+    ctx: &'a FilterContext,
+    schema: &'a FilterSchema,
+    options: &EngineOptions,
+    fid: usize,
+) -> Result<&'a LiteralValue, WirerustError> {
+    match ctx.get_by_id(fid).or_else(|| schema.default_by_id(fid)) {
+        Some(val) => Ok(val),
+        None if options.strict_missing_fields => {
+            let name = schema.field_name(fid).unwrap_or("<unknown>");
+            Err(WirerustError::ExecutionError(format!(
+                "Field '{name}' is not set in the context"
+            )))
+        }
+        None if options.three_valued_logic => Ok(&LiteralValue::Null),
+        None => Ok(&LiteralValue::Bool(false)),
+    }
+}
+
+// The result of `EqFieldLiteral`, with the null propagation `CompareEq` would get
+pub(crate) fn eq_field_literal(
+    val: &LiteralValue,
+    lit: &LiteralValue,
+    options: &EngineOptions,
+) -> LiteralValue {
+    if options.three_valued_logic && (*val == LiteralValue::Null || *lit == LiteralValue::Null) {
+        LiteralValue::Null
+    } else {
+        LiteralValue::Bool(cmp_eq(val, lit, options))
+    }
+}
+
+// Peephole pass rewriting `LoadField`, `LoadLiteral`, `CompareEq` into one `EqFieldLiteral`,
+// so the commonest comparison runs without cloning the field or the literal.
+fn fuse_field_literal_eq(code: &mut Vec<Instruction>) {
+    let mut fused = Vec::with_capacity(code.len());
+    for mut instr in std::mem::take(code) {
+        if let Instruction::Quantify { body, .. } = &mut instr {
+            fuse_field_literal_eq(body);
+        }
+        let len = fused.len();
+        if matches!(instr, Instruction::CompareEq) && len >= 2 {
+            if let [Instruction::LoadField(fid), Instruction::LoadLiteral(lit)] = &fused[len - 2..]
+            {
+                let eq = Instruction::EqFieldLiteral(*fid, lit.clone());
+                fused.truncate(len - 2);
+                fused.push(eq);
+                continue;
             }
-            pc += 1;
         }
-        match stack.pop() {
-            Some(LiteralValue::Bool(b)) => Ok(b),
-            Some(other) => Ok(to_bool(&other)),
-            None => Err(WirerustError::ExecutionError(
-                "Empty stack after execution".into(),
-            )),
+        fused.push(instr);
+    }
+    *code = fused;
+}
+
+// Call a registered function, taking the builtin fast path unless a user function
+// replaced the builtin.
+pub(crate) fn call_function(
+    functions: &FunctionRegistry,
+    fid: usize,
+    args: &[LiteralValue],
+    data: &dyn Any,
+) -> Result<LiteralValue, WirerustError> {
+    if let Some(name) = functions.function_name(fid) {
+        if let (Some(builtin_id), false) = (
+            BuiltinFunctionId::from_name(name),
+            functions.is_builtin_overridden(name),
+        ) {
+            return call_builtin(builtin_id, args).ok_or_else(|| {
+                WirerustError::FunctionError(format!("Builtin function call failed for {name}"))
+            });
         }
     }
+    let func = functions
+        .get_by_id(fid)
+        .ok_or_else(|| WirerustError::FunctionError(format!("Function ID {fid} not found")))?;
+    func.call_with_data(args, data)
 }
 
-fn to_bool(val: &LiteralValue) -> bool {
+// Helper for custom operators, by registry ID
+pub(crate) fn call_operator(
+    functions: &FunctionRegistry,
+    id: usize,
+    left: &LiteralValue,
+    right: &LiteralValue,
+) -> Result<bool, WirerustError> {
+    let op = functions
+        .get_operator(id)
+        .ok_or_else(|| WirerustError::FunctionError(format!("Operator ID {id} not found")))?;
+    Ok(op(left, right))
+}
+
+pub(crate) fn negate_int(val: &LiteralValue) -> Result<LiteralValue, WirerustError> {
     match val {
-        LiteralValue::Bool(b) => *b,
-        LiteralValue::Int(i) => *i != 0,
-        LiteralValue::Bytes(_) => true,
-        LiteralValue::Array(arr) => !arr.is_empty(),
-        LiteralValue::Ip(_) => true,
-        LiteralValue::Map(map) => !map.is_empty(),
+        LiteralValue::Int(i) => i
+            .checked_neg()
+            .map(LiteralValue::Int)
+            .ok_or_else(|| WirerustError::ExecutionError(format!("Integer overflow negating {i}"))),
+        other => Err(WirerustError::TypeError(format!(
+            "Negation requires an Int operand, got {:?}",
+            other.get_type()
+        ))),
     }
 }
 
-pub struct DefaultCompiler;
+// Helper for `between` and `strict between` against constant bounds
+pub(crate) fn cmp_between(
+    val: &LiteralValue,
+    low: &LiteralValue,
+    high: &LiteralValue,
+    strict: bool,
+) -> bool {
+    if strict {
+        cmp_ord(low, val, |a, b| a < b) && cmp_ord(val, high, |a, b| a < b)
+    } else {
+        cmp_ord(low, val, |a, b| a <= b) && cmp_ord(val, high, |a, b| a <= b)
+    }
+}
 
-impl DefaultCompiler {
-    /// Compile a filter expression into IR bytecode.
-    pub fn compile_ir(
-        expr: &FilterExpr,
-        schema: &FilterSchema,
-        functions: &FunctionRegistry,
-        code: &mut Vec<Instruction>,
-    ) {
-        match expr {
-            FilterExpr::LogicalOp { op, left, right } => {
-                Self::compile_ir(left, schema, functions, code);
-                Self::compile_ir(right, schema, functions, code);
-                match op {
-                    LogicalOp::And => code.push(Instruction::LogicalAnd),
-                    LogicalOp::Or => code.push(Instruction::LogicalOr),
-                }
-            }
-            FilterExpr::Comparison { left, op, right } => {
-                Self::compile_ir(left, schema, functions, code);
-                Self::compile_ir(right, schema, functions, code);
-                match op {
-                    ComparisonOp::Eq => code.push(Instruction::CompareEq),
-                    ComparisonOp::Neq => code.push(Instruction::CompareNeq),
-                    ComparisonOp::Lt => code.push(Instruction::CompareLt),
-                    ComparisonOp::Lte => code.push(Instruction::CompareLte),
-                    ComparisonOp::Gt => code.push(Instruction::CompareGt),
-                    ComparisonOp::Gte => code.push(Instruction::CompareGte),
-                    ComparisonOp::In => code.push(Instruction::CompareIn),
-                    ComparisonOp::NotIn => code.push(Instruction::CompareNotIn),
-                    ComparisonOp::Matches => code.push(Instruction::CompareMatches),
-                    ComparisonOp::Wildcard => {
-                        code.push(Instruction::CompareWildcard { strict: false })
-                    }
-                    ComparisonOp::StrictWildcard => {
-                        code.push(Instruction::CompareWildcard { strict: true })
-                    }
-                    ComparisonOp::Contains => code.push(Instruction::CompareContains),
-                }
-            }
-            FilterExpr::Not(inner) => {
-                Self::compile_ir(inner, schema, functions, code);
-                code.push(Instruction::LogicalNot);
-            }
-            FilterExpr::Value(val) => {
-                // If this is a field reference (Bytes, and field exists in schema), emit LoadField; else, LoadLiteral
-                if let LiteralValue::Bytes(bytes) = val {
-                    if let Ok(field) = std::str::from_utf8(bytes) {
-                        if let Some(fid) = schema.field_id(field) {
-                            code.push(Instruction::LoadField(fid));
-                            return;
-                        }
-                    }
+pub(crate) fn cmp_matches_compiled(re: &CompiledRegex, val: &LiteralValue) -> bool {
+    match val {
+        LiteralValue::Bytes(bytes) => std::str::from_utf8(bytes)
+            .map(|s| re.0.is_match(s))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+#[cfg(feature = "regex")]
+pub(crate) fn capture_compiled(
+    re: &CompiledRegex,
+    subject: &LiteralValue,
+    group: &LiteralValue,
+) -> Result<LiteralValue, WirerustError> {
+    capture_group(&re.0, subject, group).ok_or_else(|| {
+        WirerustError::FunctionError("Builtin function call failed for regex_capture".into())
+    })
+}
+
+#[cfg(feature = "regex")]
+pub(crate) fn matches_any_compiled(
+    set: &CompiledRegexSet,
+    subject: &LiteralValue,
+) -> Result<LiteralValue, WirerustError> {
+    matches_any_set(&set.0, subject).ok_or_else(|| {
+        WirerustError::FunctionError("Builtin function call failed for matches_any".into())
+    })
+}
+
+// Shares one allocation between identical Bytes and Array literals across a filter's
+// bytecode, so generated filters repeating the same constants don't duplicate them.
+#[derive(Default)]
+struct LiteralInterner {
+    bytes: HashSet<Arc<Vec<u8>>>,
+    arrays: HashSet<Arc<Vec<LiteralValue>>>,
+}
+
+impl LiteralInterner {
+    fn intern_code(&mut self, code: &mut [Instruction]) {
+        for instr in code {
+            match instr {
+                Instruction::LoadLiteral(lit) | Instruction::EqFieldLiteral(_, lit) => {
+                    self.intern(lit)
                 }
-                code.push(Instruction::LoadLiteral(val.clone()));
+                Instruction::Quantify { body, .. } => self.intern_code(body),
+                _ => {}
             }
-            FilterExpr::FunctionCall { name, args } => {
-                for arg in args {
-                    Self::compile_ir(arg, schema, functions, code);
+        }
+    }
+
+    fn intern(&mut self, lit: &mut LiteralValue) {
+        match lit {
+            LiteralValue::Bytes(bytes) => match self.bytes.get(&**bytes) {
+                Some(shared) => *bytes = Arc::clone(shared),
+                None => {
+                    self.bytes.insert(Arc::clone(bytes));
                 }
-                if let Some(fid) = functions.function_id(name) {
-                    code.push(Instruction::CallFunction(fid, args.len() as u8));
-                } else {
-                    // Unknown function: error at runtime
-                    code.push(Instruction::CallFunction(usize::MAX, args.len() as u8));
+            },
+            LiteralValue::Array(items) => match self.arrays.get(&**items) {
+                Some(shared) => *items = Arc::clone(shared),
+                None => {
+                    // Intern the elements first so the stored array shares them too
+                    let mut interned = (**items).clone();
+                    interned.iter_mut().for_each(|item| self.intern(item));
+                    *items = Arc::new(interned);
+                    self.arrays.insert(Arc::clone(items));
                 }
-            }
-            FilterExpr::List(vals) => {
-                code.push(Instruction::LoadLiteral(LiteralValue::Array(Arc::new(
-                    vals.clone(),
-                ))));
-            }
+            },
+            _ => {}
         }
     }
+}
 
-    pub fn compile(
-        expr: FilterExpr,
-        schema: Arc<FilterSchema>,
-        functions: Arc<FunctionRegistry>,
-    ) -> IrCompiledFilter {
-        let mut bytecode: Vec<Instruction> = Vec::new();
-        Self::compile_ir(&expr, &schema, &functions, &mut bytecode);
-        IrCompiledFilter {
-            bytecode,
-            schema: Arc::clone(&schema),
-            functions: Arc::clone(&functions),
-        }
+// Helper for ASCII case-insensitive equality; compares in place without lowercased copies
+pub(crate) fn cmp_eq_ci(a: &LiteralValue, b: &LiteralValue) -> bool {
+    match (a, b) {
+        (LiteralValue::Bytes(a), LiteralValue::Bytes(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
     }
 }
 
-// Helper for ordered comparisons
-fn cmp_ord<F>(a: &LiteralValue, b: &LiteralValue, cmp: F) -> bool
+// Helper for ordered comparisons. Arrays are ordered lexicographically; `cmp` then sees
+// their ordering as -1, 0 or 1 against 0.
+pub(crate) fn cmp_ord<F>(a: &LiteralValue, b: &LiteralValue, cmp: F) -> bool
 where
     F: Fn(&i64, &i64) -> bool,
 {
     match (a, b) {
         (LiteralValue::Int(a), LiteralValue::Int(b)) => cmp(a, b),
+        (LiteralValue::DateTime(a), LiteralValue::DateTime(b)) => cmp(a, b),
+        (LiteralValue::Array(_), LiteralValue::Array(_)) => {
+            value_order(a, b).is_some_and(|order| cmp(&(order as i64), &0))
+        }
         // TODO: Add more type support (e.g., Bytes, Ip)
         _ => false,
     }
 }
 
+// The order of two values `cmp_ord` can compare. Arrays compare element by element: the
+// first unequal pair decides, and if one array is a prefix of the other it comes first.
+// `None` when that first unequal pair can't be ordered, e.g. an Int and Bytes.
+fn value_order(a: &LiteralValue, b: &LiteralValue) -> Option<Ordering> {
+    match (a, b) {
+        (LiteralValue::Int(a), LiteralValue::Int(b))
+        | (LiteralValue::DateTime(a), LiteralValue::DateTime(b)) => Some(a.cmp(b)),
+        (LiteralValue::Array(a), LiteralValue::Array(b)) => {
+            match a.iter().zip(b.iter()).find(|(x, y)| x != y) {
+                Some((x, y)) => value_order(x, y),
+                None => Some(a.len().cmp(&b.len())),
+            }
+        }
+        _ => None,
+    }
+}
+
+// Under `strict_comparisons`, a TypeError for operands `cmp_ord` can't order.
+pub(crate) fn check_orderable(
+    a: &LiteralValue,
+    b: &LiteralValue,
+    op: &str,
+    options: &EngineOptions,
+) -> Result<(), WirerustError> {
+    let orderable = value_order(a, b).is_some();
+    if options.strict_comparisons && !orderable {
+        return Err(WirerustError::TypeError(format!(
+            "Cannot compare {:?} {op} {:?}",
+            a.get_type(),
+            b.get_type()
+        )));
+    }
+    Ok(())
+}
+
+// Helper for ordered comparisons that honours `strict_comparisons`
+pub(crate) fn cmp_ord_checked<F>(
+    a: &LiteralValue,
+    b: &LiteralValue,
+    op: &str,
+    options: &EngineOptions,
+    cmp: F,
+) -> Result<bool, WirerustError>
+where
+    F: Fn(&i64, &i64) -> bool,
+{
+    check_orderable(a, b, op, options)?;
+    Ok(cmp_ord(a, b, cmp))
+}
+
+// Helper for integer arithmetic; errors instead of panicking on overflow or division by zero
+pub(crate) fn eval_arith(
+    instr: &Instruction,
+    a: &LiteralValue,
+    b: &LiteralValue,
+) -> Result<LiteralValue, WirerustError> {
+    let (a, b) = match (a, b) {
+        (LiteralValue::Int(a), LiteralValue::Int(b)) => (*a, *b),
+        _ => {
+            return Err(WirerustError::TypeError(format!(
+                "Arithmetic {instr:?} requires Int operands, got {:?} and {:?}",
+                a.get_type(),
+                b.get_type()
+            )))
+        }
+    };
+    if b == 0 && matches!(instr, Instruction::Div | Instruction::Mod) {
+        return Err(WirerustError::ExecutionError("Division by zero".into()));
+    }
+    let result = match instr {
+        Instruction::Add => a.checked_add(b),
+        Instruction::Sub => a.checked_sub(b),
+        Instruction::Mul => a.checked_mul(b),
+        Instruction::Div => a.checked_div(b),
+        Instruction::Mod => a.checked_rem(b),
+        Instruction::BitAnd => Some(a & b),
+        Instruction::BitOr => Some(a | b),
+        Instruction::BitXor => Some(a ^ b),
+        Instruction::Shl | Instruction::Shr => {
+            let shift = u32::try_from(b)
+                .ok()
+                .filter(|s| *s < i64::BITS)
+                .ok_or_else(|| {
+                    WirerustError::ExecutionError(format!("Shift amount {b} out of range"))
+                })?;
+            Some(if matches!(instr, Instruction::Shl) {
+                a << shift
+            } else {
+                a >> shift
+            })
+        }
+        _ => unreachable!("not an arithmetic instruction"),
+    };
+    result.map(LiteralValue::Int).ok_or_else(|| {
+        WirerustError::ExecutionError(format!("Integer overflow in {instr:?} of {a} and {b}"))
+    })
+}
+
 // Helper for 'in' and 'not in' comparisons
-fn cmp_in(a: &LiteralValue, b: &LiteralValue) -> bool {
+pub(crate) fn cmp_in(a: &LiteralValue, b: &LiteralValue, options: &EngineOptions) -> bool {
     match b {
+        LiteralValue::Array(arr) if options.normalize_ips => {
+            arr.iter().any(|item| cmp_eq(a, item, options))
+        }
         LiteralValue::Array(arr) => arr.contains(a),
+        LiteralValue::Set(set) => set_contains(set, a, options),
         _ => false,
     }
 }
 
+// Helper for membership in ranges sorted and merged by `merge_ranges`: binary search for
+// the first range ending at or after the value.
+pub(crate) fn cmp_in_ranges(a: &LiteralValue, ranges: &[RangeInclusive<i64>]) -> bool {
+    let LiteralValue::Int(n) = a else {
+        return false;
+    };
+    let i = ranges.partition_point(|r| r.end() < n);
+    ranges.get(i).is_some_and(|r| r.contains(n))
+}
+
+// Helper for equality, honouring `normalize_ips`
+pub(crate) fn cmp_eq(a: &LiteralValue, b: &LiteralValue, options: &EngineOptions) -> bool {
+    if options.normalize_ips {
+        canonical_ip(a) == canonical_ip(b)
+    } else {
+        a == b
+    }
+}
+
+// Helper for set membership; under `normalize_ips` an IPv4 address is also looked up in
+// its IPv4-mapped IPv6 form, so sets holding either spelling match.
+pub(crate) fn set_contains(
+    set: &HashSet<LiteralValue>,
+    a: &LiteralValue,
+    options: &EngineOptions,
+) -> bool {
+    if !options.normalize_ips {
+        return set.contains(a);
+    }
+    let a = canonical_ip(a);
+    set.contains(&a)
+        || matches!(&*a, LiteralValue::Ip(IpAddr::V4(v4))
+            if set.contains(&LiteralValue::Ip(IpAddr::V6(v4.to_ipv6_mapped()))))
+}
+
+// Helper for prefix set membership, with the same IPv4-mapped handling as `set_contains`.
+pub(crate) fn trie_contains(trie: &IpTrie, a: &LiteralValue, options: &EngineOptions) -> bool {
+    let LiteralValue::Ip(ip) = a else {
+        return false;
+    };
+    if trie.contains(*ip) {
+        return true;
+    }
+    if !options.normalize_ips {
+        return false;
+    }
+    match ip {
+        IpAddr::V4(v4) => trie.contains(IpAddr::V6(v4.to_ipv6_mapped())),
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .is_some_and(|v4| trie.contains(IpAddr::V4(v4))),
+    }
+}
+
+// An IPv4-mapped IPv6 address as its IPv4 form; other values are returned as they are.
+fn canonical_ip(a: &LiteralValue) -> Cow<'_, LiteralValue> {
+    match a {
+        LiteralValue::Ip(IpAddr::V6(v6)) => match v6.to_ipv4_mapped() {
+            Some(v4) => Cow::Owned(LiteralValue::Ip(IpAddr::V4(v4))),
+            None => Cow::Borrowed(a),
+        },
+        _ => Cow::Borrowed(a),
+    }
+}
+
 // Helper for 'matches' (regex) comparisons
-fn cmp_matches(a: &LiteralValue, b: &LiteralValue) -> bool {
+pub(crate) fn cmp_matches(a: &LiteralValue, b: &LiteralValue, anchored: bool) -> bool {
     match (a, b) {
         (LiteralValue::Bytes(bytes), LiteralValue::Bytes(pattern)) => {
             if let (Ok(s), Ok(pat)) = (std::str::from_utf8(bytes), std::str::from_utf8(pattern)) {
-                // Use regex crate if available, else fallback to substring
-                #[cfg(feature = "regex")]
-                {
-                    if let Ok(re) = regex::Regex::new(pat) {
-                        re.is_match(s)
-                    } else {
-                        false
-                    }
-                }
-                #[cfg(not(feature = "regex"))]
-                {
-                    s.contains(pat)
+                if let Ok(re) = new_regex(pat, anchored) {
+                    re.is_match(s)
+                } else {
+                    false
                 }
             } else {
                 false
@@ -302,72 +2416,148 @@ fn cmp_matches(a: &LiteralValue, b: &LiteralValue) -> bool {
     }
 }
 
-// Helper for wildcard and strict wildcard comparisons
-fn cmp_wildcard(a: &LiteralValue, b: &LiteralValue, case_sensitive: bool) -> bool {
-    match (a, b) {
-        (LiteralValue::Bytes(bytes), LiteralValue::Bytes(pattern)) => {
-            let s = match std::str::from_utf8(bytes) {
-                Ok(s) => s,
-                Err(_) => return false,
-            };
-            let pat = match std::str::from_utf8(pattern) {
-                Ok(p) => p,
-                Err(_) => return false,
-            };
-            wildcard_match(s, pat, case_sensitive)
-        }
-        _ => false,
+// Compile a `matches` pattern, wrapped to match the whole subject when `anchored`.
+#[cfg(feature = "regex")]
+fn new_regex(pattern: &str, anchored: bool) -> Result<RegexEngine, regex::Error> {
+    if anchored {
+        RegexEngine::new(&format!("^(?:{pattern})$"))
+    } else {
+        RegexEngine::new(pattern)
     }
 }
 
-fn wildcard_match(s: &str, pat: &str, case_sensitive: bool) -> bool {
-    let (s, pat) = if case_sensitive {
-        (s.to_string(), pat.to_string())
+// The built-in engine has no groups, so anchoring just adds the anchors around the pattern.
+#[cfg(not(feature = "regex"))]
+fn new_regex(pattern: &str, anchored: bool) -> Result<RegexEngine, crate::pattern::PatternError> {
+    if anchored {
+        RegexEngine::new(&format!("^{pattern}$"))
     } else {
-        (s.to_lowercase(), pat.to_lowercase())
-    };
-    wildcard_match_inner(&s, &pat)
+        RegexEngine::new(pattern)
+    }
+}
+
+// Literal `in` sets at least this large are hashed; smaller ones are scanned linearly.
+const HASH_SET_THRESHOLD: usize = 16;
+
+// All-Int literal `in` sets at least this large are sorted and binary searched instead,
+// which beats hashing an i64 and stays cache-friendly.
+const SORTED_INTS_THRESHOLD: usize = 16;
+
+// Helper for membership in a sorted, deduplicated integer set.
+pub(crate) fn cmp_in_sorted_ints(a: &LiteralValue, values: &[i64]) -> bool {
+    matches!(a, LiteralValue::Int(n) if values.binary_search(n).is_ok())
 }
 
-fn wildcard_match_inner(s: &str, pat: &str) -> bool {
-    let s_bytes = s.as_bytes();
-    let pat_bytes = pat.as_bytes();
-    wildcard_match_bytes(s_bytes, pat_bytes)
+// Helper for wildcard and strict wildcard comparisons. Matching is byte-level, so binary
+// values work; the case-insensitive form compares both sides after `fold_case`.
+pub(crate) fn cmp_wildcard(a: &LiteralValue, b: &LiteralValue, case_sensitive: bool) -> bool {
+    match (a, b) {
+        (LiteralValue::Bytes(bytes), LiteralValue::Bytes(pattern)) => {
+            if case_sensitive {
+                wildcard_match_bytes(bytes, pattern)
+            } else {
+                wildcard_match_bytes(&fold_case(bytes), &fold_case(pattern))
+            }
+        }
+        _ => false,
+    }
 }
 
+// Two-pointer glob matcher: on mismatch, backtrack to the most recent `*` and let it
+// absorb one more byte. Only the last star needs to be remembered, so this runs in
+// O(n*m) worst case instead of exploring every split recursively.
 fn wildcard_match_bytes(s: &[u8], pat: &[u8]) -> bool {
-    if pat.is_empty() {
-        return s.is_empty();
+    let (mut si, mut pi) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // (pattern index after `*`, matched up to)
+    while si < s.len() {
+        if pi < pat.len() && pat[pi] == b'*' {
+            pi += 1;
+            star = Some((pi, si));
+        } else if pi < pat.len() && pat[pi] == s[si] {
+            pi += 1;
+            si += 1;
+        } else if let Some((star_pi, star_si)) = star {
+            pi = star_pi;
+            si = star_si + 1;
+            star = Some((star_pi, si));
+        } else {
+            return false;
+        }
     }
-    if pat[0] == b'*' {
-        for i in 0..=s.len() {
-            if wildcard_match_bytes(&s[i..], &pat[1..]) {
-                return true;
-            }
+    pat[pi..].iter().all(|&c| c == b'*')
+}
+
+// Helper for index expressions: map lookup by string key, array lookup by position
+pub(crate) fn index_value(target: &LiteralValue, key: &LiteralValue) -> Option<LiteralValue> {
+    match (target, key) {
+        (LiteralValue::Map(map), LiteralValue::Bytes(k)) => {
+            map.get(std::str::from_utf8(k).ok()?).cloned()
         }
-        false
-    } else if !s.is_empty() && (pat[0] == s[0]) {
-        wildcard_match_bytes(&s[1..], &pat[1..])
+        (LiteralValue::Array(arr), LiteralValue::Int(i)) => arr
+            .get(usize::try_from(from_end(*i, arr.len())).ok()?)
+            .cloned(),
+        _ => None,
+    }
+}
+
+// Helper for `target[start:end]` on an array. Bounds may be negative and are clamped to
+// the array, so an out-of-range slice is shorter (or empty) rather than missing; a `Null`
+// bound is the start or end of the array.
+pub(crate) fn slice_value(
+    target: &LiteralValue,
+    start: &LiteralValue,
+    end: &LiteralValue,
+) -> Option<LiteralValue> {
+    let LiteralValue::Array(arr) = target else {
+        return None;
+    };
+    let len = arr.len();
+    let bound = |b: &LiteralValue, default: usize| match b {
+        LiteralValue::Null => Some(default),
+        LiteralValue::Int(i) => Some(from_end(*i, len).clamp(0, len as i64) as usize),
+        _ => None,
+    };
+    let (start, end) = (bound(start, 0)?, bound(end, len)?);
+    Some(LiteralValue::Array(Arc::new(
+        arr[start..end.max(start)].to_vec(),
+    )))
+}
+
+// A possibly negative index resolved against `len`: `-1` is the last element.
+fn from_end(i: i64, len: usize) -> i64 {
+    if i < 0 {
+        i.saturating_add(len as i64)
     } else {
-        false
+        i
     }
 }
 
-// Helper for contains comparison
-fn cmp_contains(a: &LiteralValue, b: &LiteralValue) -> bool {
+// Helper for contains comparison. By operand types:
+//   Bytes contains Bytes        substring test
+//   Array contains Array        subset: every right-hand element is in the left array
+//   Array contains scalar       element equality
+//   Set   contains Array/Set    subset
+//   Set   contains scalar       membership
+// Any other combination (including a scalar on the left) is false.
+pub(crate) fn cmp_contains(a: &LiteralValue, b: &LiteralValue) -> bool {
     match (a, b) {
         (LiteralValue::Bytes(haystack), LiteralValue::Bytes(needle)) => {
-            if let (Ok(h), Ok(n)) = (std::str::from_utf8(haystack), std::str::from_utf8(needle)) {
-                h.contains(n)
-            } else {
-                false
-            }
+            contains_bytes(haystack, needle)
         }
+        (LiteralValue::Array(arr), LiteralValue::Array(sub)) => sub.iter().all(|v| arr.contains(v)),
         (LiteralValue::Array(arr), val) => arr.contains(val),
+        (LiteralValue::Set(set), LiteralValue::Array(sub)) => sub.iter().all(|v| set.contains(v)),
+        (LiteralValue::Set(set), LiteralValue::Set(sub)) => sub.is_subset(set),
+        (LiteralValue::Set(set), val) => set.contains(val),
         _ => false,
     }
 }
 
+// Byte substring search; the values needn't be valid UTF-8
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -404,14 +2594,13 @@ mod tests {
     #[test]
     fn test_compile_and_execute_comparison_eq() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Int(42))),
         };
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(filter.execute(&context()).unwrap());
     }
 
@@ -420,16 +2609,12 @@ mod tests {
         let expr = FilterExpr::LogicalOp {
             op: LogicalOp::And,
             left: Box::new(FilterExpr::Comparison {
-                left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                    b"foo".to_vec(),
-                )))),
+                left: Box::new(FilterExpr::Field("foo".into())),
                 op: ComparisonOp::Eq,
                 right: Box::new(FilterExpr::Value(LiteralValue::Int(42))),
             }),
             right: Box::new(FilterExpr::Comparison {
-                left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                    b"bar".to_vec(),
-                )))),
+                left: Box::new(FilterExpr::Field("bar".into())),
                 op: ComparisonOp::Eq,
                 right: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
                     b"baz".to_vec(),
@@ -437,30 +2622,28 @@ mod tests {
             }),
         };
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(filter.execute(&context()).unwrap());
     }
 
     #[test]
     fn test_compile_and_execute_not() {
         let expr = FilterExpr::Not(Box::new(FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Int(0))),
         }));
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(filter.execute(&context()).unwrap());
     }
 
     #[test]
     fn test_compile_and_execute_in() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::In,
             right: Box::new(FilterExpr::Value(LiteralValue::Array(Arc::new(vec![
                 LiteralValue::Int(1),
@@ -468,8 +2651,280 @@ mod tests {
             ])))),
         };
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert!(filter.execute(&context()).unwrap());
+    }
+
+    #[test]
+    fn test_in_set_type_checks() {
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+        };
+        for src in [
+            r#"foo in {80 "ssh" 443}"#,
+            r#"foo not in {"a" "b"}"#,
+            r#"bar in {1 2}"#,
+        ] {
+            assert!(
+                matches!(compile(src), Err(WirerustError::TypeError(_))),
+                "{src}"
+            );
+        }
+        let filter = compile("foo in {42 1 42 1}").unwrap();
+        let set = filter.bytecode.iter().find_map(|i| match i {
+            Instruction::LoadLiteral(LiteralValue::Array(set)) => Some(set.len()),
+            _ => None,
+        });
+        assert_eq!(set, Some(2));
+        assert!(filter.execute(&context()).unwrap());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let expr = FilterParser::parse(r#"foo == 42 && bar == "baz""#, &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert_eq!(
+            filter.disassemble(),
+            "0000 EqFieldLiteral foo 42\n\
+             0001 EqFieldLiteral bar \"baz\"\n\
+             0002 LogicalAnd\n"
+        );
+
+        let expr = FilterParser::parse("any(arr, _ == 1)", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert_eq!(
+            filter.disassemble(),
+            "0000 LoadField arr\n\
+             0001 Quantify any\n  \
+               0000 LoadElement\n  \
+               0001 LoadLiteral 1\n  \
+               0002 CompareEq\n"
+        );
+    }
+
+    #[test]
+    fn test_large_in_set_is_hashed() {
+        let items: Vec<String> = (0..100).map(|i| format!("\"b{}\"", i * 2)).collect();
+        for (op, expected) in [("in", false), ("not in", true)] {
+            let src = format!("bar {op} {{{}}}", items.join(" "));
+            let expr = FilterParser::parse(&src, &schema()).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(schema()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert!(matches!(
+                filter.bytecode.last(),
+                Some(Instruction::CompareInSet { .. })
+            ));
+            assert_eq!(filter.execute(&context()).unwrap(), expected);
+            // Each compile hashes into its own set, but they serialize identically
+            #[cfg(feature = "json")]
+            {
+                let again = DefaultCompiler::compile(
+                    FilterParser::parse(&src, &schema()).unwrap(),
+                    Arc::new(schema()),
+                    Arc::new(FunctionRegistry::new()),
+                )
+                .unwrap();
+                assert_eq!(filter.to_bytes().unwrap(), again.to_bytes().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_int_in_set_is_sorted() {
+        let items: Vec<String> = (0..100).rev().map(|i| (i * 2).to_string()).collect();
+        for (op, expected) in [("in", true), ("not in", false)] {
+            let src = format!("foo {op} {{{} 42 42}}", items.join(" "));
+            let expr = FilterParser::parse(&src, &schema()).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(schema()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            match filter.bytecode.last() {
+                Some(Instruction::CompareInSortedInts { values, .. }) => {
+                    assert_eq!(values.0.len(), 100);
+                    assert!(values.0.windows(2).all(|w| w[0] < w[1]));
+                }
+                other => panic!("Expected CompareInSortedInts, got {other:?}"),
+            }
+            assert_eq!(filter.execute(&context()).unwrap(), expected);
+            #[cfg(feature = "json")]
+            {
+                let loaded = IrCompiledFilter::from_bytes(
+                    &filter.to_bytes().unwrap(),
+                    Arc::new(schema()),
+                    Arc::new(FunctionRegistry::new()),
+                )
+                .unwrap();
+                assert_eq!(loaded.bytecode, filter.bytecode);
+            }
+        }
+        assert!(!cmp_in_sorted_ints(&LiteralValue::Int(3), &[0, 2, 4]));
+        assert!(!cmp_in_sorted_ints(
+            &LiteralValue::Bytes(Arc::new(b"2".to_vec())),
+            &[0, 2, 4]
+        ));
+        // Small sets keep the linear scan
+        let expr = FilterParser::parse("foo in {1 42}", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert_eq!(filter.bytecode.last(), Some(&Instruction::CompareIn));
+    }
+
+    #[test]
+    fn test_cidr_sets_use_a_prefix_trie() {
+        let schema = FilterSchemaBuilder::new()
+            .field("src", FieldType::Ip)
+            .build();
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &schema).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(schema.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+        };
+        let filter = compile(r#"src not in {"10.0.0.0/8" "192.0.2.1" "10.1.0.0/16"}"#).unwrap();
+        match filter.bytecode.last() {
+            Some(Instruction::CompareIpInTrie { trie, negate: true }) => {
+                assert_eq!(trie.0.len(), 3);
+            }
+            other => panic!("Expected CompareIpInTrie, got {other:?}"),
+        }
+        #[cfg(feature = "json")]
+        {
+            let loaded = IrCompiledFilter::from_bytes(
+                &filter.to_bytes().unwrap(),
+                Arc::new(schema.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert_eq!(loaded.bytecode, filter.bytecode);
+        }
+        // Plain addresses keep the hashed set or linear scan
+        let filter = compile(r#"src in {"10.0.0.1" "10.0.0.2"}"#).unwrap();
+        assert_eq!(filter.bytecode.last(), Some(&Instruction::CompareIn));
+        for bad in [r#"src in {"10.0.0.0/33"}"#, r#"src in {"10.0.0.0/8" 5}"#] {
+            assert!(
+                matches!(compile(bad), Err(WirerustError::TypeError(_))),
+                "{bad}"
+            );
+        }
+        let ip = |s: &str| LiteralValue::Ip(s.parse().unwrap());
+        let trie = IpTrie::new([("10.0.0.0".parse().unwrap(), 8)]);
+        let normalize = EngineOptions {
+            normalize_ips: true,
+            ..EngineOptions::default()
+        };
+        assert!(trie_contains(
+            &trie,
+            &ip("10.2.3.4"),
+            &EngineOptions::default()
+        ));
+        assert!(!trie_contains(
+            &trie,
+            &ip("::ffff:10.2.3.4"),
+            &EngineOptions::default()
+        ));
+        assert!(trie_contains(&trie, &ip("::ffff:10.2.3.4"), &normalize));
+        assert!(!trie_contains(&trie, &LiteralValue::Int(1), &normalize));
+    }
+
+    #[test]
+    fn test_int_ranges_are_merged_and_searched() {
+        let expr =
+            FilterParser::parse("foo in {50..60 1..10 40 11..20 41..45}", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert_eq!(
+            filter.bytecode.last(),
+            Some(&Instruction::CompareInRanges {
+                ranges: vec![1..=20, 40..=45, 50..=60],
+                negate: false,
+            })
+        );
+        let ranges = [1..=20, 40..=45, 50..=60];
+        for (n, expected) in [
+            (0, false),
+            (1, true),
+            (20, true),
+            (21, false),
+            (42, true),
+            (61, false),
+        ] {
+            assert_eq!(
+                cmp_in_ranges(&LiteralValue::Int(n), &ranges),
+                expected,
+                "{n}"
+            );
+        }
+        assert!(!cmp_in_ranges(
+            &LiteralValue::Bytes(Arc::new(b"1".to_vec())),
+            &ranges
+        ));
+        assert_eq!(
+            merge_ranges(&[i64::MAX..=i64::MAX, 0..=i64::MAX]),
+            vec![0..=i64::MAX]
+        );
+        // Hand-edited bytecode is merged again on load
+        #[cfg(feature = "json")]
+        {
+            let json = r#"{"CompareInRanges": {"negate": false, "ranges": [
+                {"start": 50, "end": 60}, {"start": 1, "end": 5},
+                {"start": 9, "end": 2}, {"start": 3, "end": 20}
+            ]}}"#;
+            let loaded: Instruction = serde_json::from_str(json).unwrap();
+            assert_eq!(
+                loaded,
+                Instruction::CompareInRanges {
+                    ranges: vec![1..=20, 50..=60],
+                    negate: false,
+                }
+            );
+        }
         assert!(filter.execute(&context()).unwrap());
+        let expr = FilterParser::parse("bar in {1..10}", &schema()).unwrap();
+        assert!(matches!(
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new())),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_in_set_parses_ip_strings() {
+        let sch = FilterSchemaBuilder::new()
+            .field("ip", FieldType::Ip)
+            .build();
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+        };
+        let filter = compile(r#"ip in {"10.0.0.1" "::1"}"#).unwrap();
+        let mut ctx = FilterContext::new();
+        ctx.set("ip", LiteralValue::Ip("::1".parse().unwrap()), &sch)
+            .unwrap();
+        assert!(filter.execute(&ctx).unwrap());
+        assert!(matches!(
+            compile(r#"ip in {"10.0.0.1" "nope"}"#),
+            Err(WirerustError::TypeError(_))
+        ));
     }
 
     #[test]
@@ -483,7 +2938,8 @@ mod tests {
                 LiteralValue::Int(2),
             ])))],
         };
-        let filter = DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(functions));
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(functions)).unwrap();
         // len([1,2]) returns Int(2), which converts to true via to_bool since 2 != 0
         assert!(filter.execute(&context()).unwrap());
     }
@@ -498,23 +2954,35 @@ mod tests {
             right: Box::new(FilterExpr::Value(LiteralValue::Int(1))),
         };
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(!filter.execute(&context()).unwrap());
     }
 
+    #[test]
+    fn test_compile_field_not_in_schema() {
+        let expr = FilterExpr::Comparison {
+            left: Box::new(FilterExpr::Field("missing".into())),
+            op: ComparisonOp::Eq,
+            right: Box::new(FilterExpr::Value(LiteralValue::Int(1))),
+        };
+        let result =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+        assert!(matches!(result, Err(WirerustError::FieldNotFound(name)) if name == "missing"));
+    }
+
     #[test]
     fn test_compile_and_execute_wrong_type() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
                 b"not an int".to_vec(),
             )))),
         };
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(!filter.execute(&context()).unwrap());
     }
 
@@ -522,7 +2990,8 @@ mod tests {
     fn test_compile_and_execute_contains_string() {
         let expr = FilterParser::parse("bar contains \"oba\"", &schema()).unwrap();
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         let mut ctx = context();
         ctx.set(
             "bar",
@@ -561,11 +3030,41 @@ mod tests {
         assert!(filter.execute(&ctx).unwrap());
     }
 
+    #[test]
+    fn test_contains_matrix() {
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        let ints = |v: &[i64]| {
+            LiteralValue::Array(Arc::new(v.iter().map(|i| LiteralValue::Int(*i)).collect()))
+        };
+        let set = |v: &[i64]| {
+            LiteralValue::Set(Arc::new(v.iter().map(|i| LiteralValue::Int(*i)).collect()))
+        };
+        let tags = LiteralValue::Array(Arc::new(vec![bytes("foo"), bytes("bar")]));
+        for (a, b, expected) in [
+            (bytes("foobar"), bytes("oba"), true),
+            (bytes("foobar"), bytes("baz"), false),
+            (tags.clone(), bytes("foo"), true),
+            (tags.clone(), bytes("fo"), false),
+            (ints(&[1, 2, 3]), LiteralValue::Int(2), true),
+            (ints(&[1, 2, 3]), ints(&[3, 1]), true),
+            (ints(&[1, 2, 3]), ints(&[1, 4]), false),
+            (ints(&[1, 2, 3]), ints(&[]), true),
+            (set(&[1, 2, 3]), LiteralValue::Int(3), true),
+            (set(&[1, 2, 3]), ints(&[1, 2]), true),
+            (set(&[1, 2, 3]), set(&[2, 4]), false),
+            (LiteralValue::Int(1), LiteralValue::Int(1), false),
+            (bytes("1"), LiteralValue::Int(1), false),
+        ] {
+            assert_eq!(cmp_contains(&a, &b), expected, "{a:?} contains {b:?}");
+        }
+    }
+
     #[test]
     fn test_compile_and_execute_contains_array() {
         let expr = FilterParser::parse("arr contains 2", &schema()).unwrap();
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         let mut ctx = context();
         ctx.set(
             "arr",
@@ -595,13 +3094,54 @@ mod tests {
         )
         .unwrap();
         assert!(filter.execute(&ctx).unwrap());
+
+        // A list on the right is a subset test
+        let expr = FilterParser::parse("arr contains {2 3}", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert!(!filter.execute(&ctx).unwrap());
+        ctx.set(
+            "arr",
+            LiteralValue::Array(Arc::new(vec![LiteralValue::Int(3), LiteralValue::Int(2)])),
+            &schema(),
+        )
+        .unwrap();
+        assert!(filter.execute(&ctx).unwrap());
+    }
+
+    #[test]
+    fn test_compile_and_execute_eq_case_insensitive() {
+        let mut ctx = context();
+        ctx.set(
+            "bar",
+            LiteralValue::Bytes(Arc::new(b"GeT".to_vec())),
+            &schema(),
+        )
+        .unwrap();
+        for (src, expected) in [
+            ("bar ~= \"get\"", true),
+            ("bar eq_ci \"GET\"", true),
+            ("bar ~= \"post\"", false),
+            ("foo ~= \"42\"", false), // non-Bytes operand
+        ] {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(schema()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert_eq!(filter.execute(&ctx).unwrap(), expected, "{src}");
+        }
     }
 
     #[test]
     fn test_compile_and_execute_wildcard() {
         let expr = FilterParser::parse("bar wildcard \"b*r\"", &schema()).unwrap();
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         let mut ctx = context();
         ctx.set(
             "bar",
@@ -644,7 +3184,8 @@ mod tests {
     fn test_compile_and_execute_strict_wildcard() {
         let expr = FilterParser::parse("bar strict wildcard \"b*r\"", &schema()).unwrap();
         let filter =
-            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         let mut ctx = context();
         ctx.set(
             "bar",
@@ -682,4 +3223,901 @@ mod tests {
         .unwrap();
         assert!(!filter.execute(&ctx).unwrap());
     }
+
+    #[test]
+    fn test_compile_and_execute_arithmetic() {
+        let sch = schema();
+        let ctx = context();
+        for (src, expected) in [
+            ("foo + 1 == 43", true),
+            ("foo - 2 * 10 == 22", true),
+            ("foo / 4 == 10", true),
+            ("foo % 5 == 2", true),
+            ("(foo + 8) / 10 == 5", true),
+            ("foo * 2 > 100", false),
+            ("-foo == -42", true),
+            ("foo == -(-42)", true),
+            ("foo == --42", true),
+            ("foo - -2 == 44", true),
+            ("foo-2 == 40", true),
+            ("-foo + 2 == -40 && foo == 42", true),
+            ("foo & 0x02 != 0", true),
+            ("foo & 0x01 != 0", false),
+            ("foo | 1 == 43", true),
+            ("foo ^ 0xFF == 213", true),
+            ("1 << 4 == 16 && foo >> 1 == 21", true),
+            ("foo & 0x0F | 0x20 == 42", true),
+            ("foo & 2 == 2 && foo & 1 == 0", true),
+        ] {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert_eq!(filter.execute(&ctx).unwrap(), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_division_by_zero() {
+        let sch = schema();
+        for src in [
+            "foo / 0 == 1",
+            "foo % 0 == 1",
+            "foo << 64 == 1",
+            "foo >> -1 == 1",
+        ] {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert!(matches!(
+                filter.execute(&context()),
+                Err(WirerustError::ExecutionError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_negate_errors() {
+        let sch = schema();
+        let mut ctx = context();
+        ctx.set("foo", LiteralValue::Int(i64::MIN), &sch).unwrap();
+        for (src, ctx) in [("-foo == 1", &ctx), ("-bar == 1", &context())] {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            let filter = DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap();
+            assert!(filter.execute(ctx).is_err(), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_non_int_operand() {
+        let expr = FilterParser::parse("bar + 1 == 2", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        assert!(matches!(
+            filter.execute(&context()),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_literal_regex_precompiled() {
+        let sch = schema();
+        let expr = FilterParser::parse("bar matches \"^b.z$\"", &sch).unwrap();
+        let filter = DefaultCompiler::compile(
+            expr,
+            Arc::new(sch.clone()),
+            Arc::new(FunctionRegistry::new()),
+        )
+        .unwrap();
+        assert!(filter
+            .bytecode
+            .iter()
+            .any(|i| matches!(i, Instruction::CompareMatchesCompiled(_))));
+        assert!(!filter.bytecode.contains(&Instruction::CompareMatches));
+        assert!(filter.execute(&context()).unwrap());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_capture() {
+        let sch = FilterSchemaBuilder::new()
+            .field("ua", FieldType::Bytes)
+            .field("pat", FieldType::Bytes)
+            .build();
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(sch.clone()), Arc::clone(&functions)).unwrap()
+        };
+        let mut ctx = FilterContext::new();
+        ctx.set_bytes("ua", "Mozilla/5.0 Firefox/91.0", &sch)
+            .set_bytes("pat", r"Firefox/(\d+)", &sch);
+
+        let literal = compile(r#"regex_capture(ua, "Firefox/(\d+)", 1) == "91""#);
+        assert!(literal
+            .bytecode
+            .iter()
+            .any(|i| matches!(i, Instruction::CaptureCompiled(_))));
+        assert_eq!(literal.referenced_functions(), ["regex_capture"]);
+        assert!(literal.execute(&ctx).unwrap());
+        let dynamic = compile(r#"regex_capture(ua, pat, 0) == "Firefox/91""#);
+        assert!(!dynamic
+            .bytecode
+            .iter()
+            .any(|i| matches!(i, Instruction::CaptureCompiled(_))));
+        assert!(dynamic.execute(&ctx).unwrap());
+
+        // No match, and a group past the end, yield no value
+        for src in [
+            r#"regex_capture(ua, "Chrome/(\d+)", 1) == "91""#,
+            r#"regex_capture(ua, "Firefox/(\d+)", 2) == "91""#,
+            r#"regex_capture(ua, pat, 2) == "91""#,
+        ] {
+            assert!(matches!(
+                compile(src).execute(&ctx),
+                Err(WirerustError::FunctionError(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_matches_any() {
+        let sch = FilterSchemaBuilder::new()
+            .field("ua", FieldType::Bytes)
+            .field("pats", FieldType::Array(Box::new(FieldType::Bytes)))
+            .field("n", FieldType::Int)
+            .build();
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(sch.clone()), Arc::clone(&functions)).unwrap()
+        };
+        let mut ctx = FilterContext::new();
+        ctx.set_bytes("ua", "Googlebot/2.1", &sch)
+            .set_array(
+                "pats",
+                vec![LiteralValue::Bytes(Arc::new(b"(?i)spider".to_vec()))],
+                &sch,
+            )
+            .set_int("n", 1, &sch);
+
+        let literal = compile(r#"matches_any(ua, {"bot" "crawler" "spider"})"#);
+        assert!(matches!(
+            literal.bytecode.as_slice(),
+            [Instruction::LoadField(_), Instruction::MatchesAnyCompiled(set)] if set.0.len() == 3
+        ));
+        assert_eq!(literal.referenced_functions(), ["matches_any"]);
+        assert!(literal.execute(&ctx).unwrap());
+        assert!(!compile(r#"matches_any(ua, {"crawler" "^bot"})"#)
+            .execute(&ctx)
+            .unwrap());
+        // Patterns from a field are compiled per call
+        let dynamic = compile("matches_any(ua, pats)");
+        assert!(!dynamic
+            .bytecode
+            .iter()
+            .any(|i| matches!(i, Instruction::MatchesAnyCompiled(_))));
+        assert!(!dynamic.execute(&ctx).unwrap());
+
+        // An invalid pattern has no value, and a non-bytes subject doesn't compile
+        assert!(matches!(
+            compile(r#"matches_any(ua, {"("})"#).execute(&ctx),
+            Err(WirerustError::FunctionError(_))
+        ));
+        let expr = FilterParser::parse(r#"matches_any(n, {"1"})"#, &sch).unwrap();
+        assert!(matches!(
+            DefaultCompiler::compile(expr, Arc::new(sch.clone()), Arc::clone(&functions)),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_dynamic_regex_compiled_at_runtime() {
+        let sch = FilterSchemaBuilder::new()
+            .field("bar", FieldType::Bytes)
+            .field("pat", FieldType::Bytes)
+            .build();
+        let expr = FilterParser::parse("bar matches pat", &sch).unwrap();
+        let filter = DefaultCompiler::compile(
+            expr,
+            Arc::new(sch.clone()),
+            Arc::new(FunctionRegistry::new()),
+        )
+        .unwrap();
+        assert!(filter.bytecode.contains(&Instruction::CompareMatches));
+        let mut ctx = FilterContext::new();
+        ctx.set_bytes("bar", "baz", &sch)
+            .set_bytes("pat", "a.$", &sch);
+        assert!(filter.execute(&ctx).unwrap());
+    }
+
+    #[test]
+    fn test_wildcard_match_bytes_cases() {
+        assert!(wildcard_match_bytes(b"", b""));
+        assert!(wildcard_match_bytes(b"", b"***"));
+        assert!(!wildcard_match_bytes(b"a", b""));
+        assert!(wildcard_match_bytes(b"abc", b"a*c"));
+        assert!(wildcard_match_bytes(b"abcbc", b"*bc"));
+        assert!(wildcard_match_bytes(b"abc", b"*a*b*c*"));
+        assert!(!wildcard_match_bytes(b"abd", b"a*c"));
+        assert!(!wildcard_match_bytes(b"ab", b"abc*"));
+    }
+
+    #[test]
+    fn test_wildcard_pathological_pattern() {
+        // Exponential with the recursive matcher; must finish promptly now.
+        let bytes = |s: String| LiteralValue::Bytes(Arc::new(s.into_bytes()));
+        let input = "a".repeat(10_000);
+        let pattern = bytes(format!("{}b", "*a".repeat(30)));
+        assert!(!cmp_wildcard(&bytes(input.clone()), &pattern, true));
+        assert!(!cmp_wildcard(&bytes(input.clone()), &pattern, false));
+        assert!(cmp_wildcard(&bytes(format!("{input}b")), &pattern, true));
+    }
+
+    #[test]
+    fn test_wildcard_unicode_case_folding() {
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        for (value, pattern, expected) in [
+            ("STRASSE", "straße", true),
+            ("Straße", "*SS*", true),
+            ("ẞ", "ss", true),
+            ("İSTANBUL", "i̇stanbul", true),
+            ("İSTANBUL", "istanbul", false),
+            ("ΌΣΟΣ", "όσος", true),
+            ("Crème Brûlée", "*BRÛLÉE", true),
+        ] {
+            assert_eq!(
+                cmp_wildcard(&bytes(value), &bytes(pattern), false),
+                expected,
+                "{value} wildcard {pattern}"
+            );
+        }
+        assert!(!cmp_wildcard(&bytes("STRASSE"), &bytes("straße"), true));
+    }
+
+    #[test]
+    fn test_contains_and_wildcard_on_binary_bytes() {
+        let bytes = |b: &[u8]| LiteralValue::Bytes(Arc::new(b.to_vec()));
+        let payload = bytes(b"\xff\xfeGET /\x00\x80");
+        assert!(cmp_contains(&payload, &bytes(b"GET")));
+        assert!(cmp_contains(&payload, &bytes(b"\x00\x80")));
+        assert!(!cmp_contains(&payload, &bytes(b"\x80\x00")));
+        assert!(cmp_wildcard(&payload, &bytes(b"\xff*/*"), true));
+        assert!(!cmp_wildcard(&payload, &bytes(b"*get*"), true));
+        // Case folding falls back to ASCII when the value isn't UTF-8
+        assert!(cmp_wildcard(&payload, &bytes(b"*get /*"), false));
+        assert!(cmp_wildcard(
+            &bytes("ÉTÉ".as_bytes()),
+            &bytes("été".as_bytes()),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_compile_rejects_bad_arity() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        for src in ["len() == 0", "starts_with(bar) "] {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            match DefaultCompiler::compile(expr, Arc::new(schema()), Arc::clone(&functions)) {
+                Err(WirerustError::FunctionError(msg)) => assert!(msg.contains("expects"), "{msg}"),
+                other => panic!("Expected arity error for {src}, got {:?}", other.is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_rejects_bad_argument_type() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let expr = FilterParser::parse("upper(foo) == \"X\"", &schema()).unwrap();
+        let result = DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(functions));
+        assert!(matches!(result, Err(WirerustError::TypeError(_))));
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn test_matches_without_regex_uses_builtin_engine() {
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+        };
+        for (src, expected) in [
+            (r#"bar matches "^baz$""#, true),
+            (r#"bar matches "^ba""#, true),
+            (r#"bar matches "^a""#, false),
+            (r#"bar matches "^b[a-z]+$""#, true),
+            (r#"bar matches "z$""#, true),
+        ] {
+            let filter = compile(src).unwrap();
+            assert!(filter
+                .bytecode
+                .iter()
+                .any(|i| matches!(i, Instruction::CompareMatchesCompiled(_))));
+            assert_eq!(filter.execute(&context()).unwrap(), expected, "{src}");
+        }
+        assert!(matches!(
+            compile(r#"bar matches "^(baz|qux)$""#),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn test_matches_substring_fallback_opt_in() {
+        let options = EngineOptions {
+            substring_matches_fallback: true,
+            ..Default::default()
+        };
+        let expr = FilterParser::parse("bar matches \"a\"", &schema()).unwrap();
+        let filter = DefaultCompiler::compile_with_options(
+            expr,
+            Arc::new(schema()),
+            Arc::new(FunctionRegistry::new()),
+            options,
+        )
+        .unwrap();
+        assert!(filter.execute(&context()).unwrap());
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn test_anchored_matches_substring_fallback_is_equality() {
+        let options = EngineOptions {
+            substring_matches_fallback: true,
+            anchored_matches: true,
+            ..Default::default()
+        };
+        for (src, expected) in [
+            (r#"bar matches "a""#, false),
+            (r#"bar matches "baz""#, true),
+        ] {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            let filter = DefaultCompiler::compile_with_options(
+                expr,
+                Arc::new(schema()),
+                Arc::new(FunctionRegistry::new()),
+                options,
+            )
+            .unwrap();
+            assert_eq!(filter.execute(&context()).unwrap(), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_max_stack_depth() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        for (src, expected) in [
+            ("foo == 42", 1),
+            (r#"foo == 42 && bar == "baz""#, 2),
+            ("foo + (foo * (foo - 1)) == 0", 4),
+            ("any(arr, _ == 1)", 2),
+            ("foo == 1 && any(arr, _ == 1)", 3),
+            (r#"upper(bar) == "BAZ""#, 2),
+            ("foo in {1 2 3}", 2),
+        ] {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            let filter =
+                DefaultCompiler::compile(expr, Arc::new(schema()), Arc::clone(&functions)).unwrap();
+            assert_eq!(filter.max_stack_depth(), expected, "{src}");
+            let mut stack = Vec::new();
+            filter.execute_with_stack(&context(), &mut stack).unwrap();
+            assert!(stack.capacity() >= expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_function_return_types_are_checked() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        functions.register_typed("label", |n: i64| format!("#{n}"));
+        functions.register_fn("untyped", |_| Some(LiteralValue::Int(1)));
+        let functions = Arc::new(functions);
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::clone(&functions))
+        };
+        // A Bytes result can't feed an Array(Int) or Int parameter
+        for src in [
+            "sum(label(foo)) > 0",
+            "substring(bar, len(bar), label(foo)) == \"\"",
+        ] {
+            assert!(
+                matches!(compile(src), Err(WirerustError::TypeError(_))),
+                "{src}"
+            );
+        }
+        assert!(compile("upper(label(foo)) == \"#42\"").is_ok());
+        assert!(compile("sum(untyped()) > 0").is_ok());
+        // Comparisons of results are checked by validation
+        let expr = FilterParser::parse("label(foo) > 1 && len(bar) == 3", &schema()).unwrap();
+        let errors = DefaultCompiler::validate(&expr, &schema(), &functions);
+        assert!(matches!(errors.as_slice(), [WirerustError::TypeError(_)]));
+    }
+
+    #[test]
+    fn test_has_conditional_function_calls() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        functions.register_typed("audit", |n: i64| n > 0);
+        let functions = Arc::new(functions);
+        for (src, expected) in [
+            ("audit(foo)", false),
+            ("audit(foo) && foo == 42", false),
+            ("foo == 42 && audit(foo)", true),
+            ("foo == 42 || (bar == \"x\" && audit(foo))", true),
+            ("foo == 42 || not audit(foo)", true),
+            ("any(arr, audit(_))", true),
+            ("any(arr, _ == 1) && foo == 1", false),
+            // Builtins have no side effects
+            ("foo == 42 && len(bar) == 3", false),
+        ] {
+            let expr = FilterParser::parse(src, &schema()).unwrap();
+            let filter =
+                DefaultCompiler::compile(expr, Arc::new(schema()), Arc::clone(&functions)).unwrap();
+            assert_eq!(filter.has_conditional_function_calls(), expected, "{src}");
+        }
+    }
+
+    #[test]
+    fn test_execute_with_limits() {
+        let expr = FilterParser::parse("foo == 42 && bar == \"baz\"", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        let ctx = context();
+        assert!(filter
+            .execute_with_limits(&ctx, &ExecutionLimits::default())
+            .unwrap());
+        assert!(matches!(
+            filter.execute_with_limits(&ctx, &ExecutionLimits::new(2, 16)),
+            Err(WirerustError::ExecutionError(_))
+        ));
+        assert!(matches!(
+            filter.execute_with_limits(&ctx, &ExecutionLimits::new(100, 1)),
+            Err(WirerustError::ExecutionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_execute_with_stack() {
+        let expr = FilterParser::parse("foo == 42 && bar == \"baz\"", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        // Leftovers from a previous (e.g. failed) run are discarded
+        let mut stack = vec![LiteralValue::Bool(false); 3];
+        assert!(filter.execute_with_stack(&context(), &mut stack).unwrap());
+        assert!(filter.execute_with_stack(&context(), &mut stack).unwrap());
+        assert!(stack.capacity() > 0);
+    }
+
+    #[test]
+    fn test_nested_execute_from_function() {
+        // A custom function that evaluates another filter while the outer one is running
+        struct Inner(IrCompiledFilter);
+        impl crate::functions::FilterFunction for Inner {
+            fn call(&self, _args: &[LiteralValue]) -> Option<LiteralValue> {
+                self.0.execute(&context()).ok().map(LiteralValue::Bool)
+            }
+        }
+        let inner = FilterParser::parse("foo == 42", &schema()).unwrap();
+        let inner =
+            DefaultCompiler::compile(inner, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        let mut functions = FunctionRegistry::new();
+        functions.register("inner", Inner(inner));
+        let expr = FilterParser::parse("bar == \"baz\" && inner()", &schema()).unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(schema()), Arc::new(functions)).unwrap();
+        assert!(filter.execute(&context()).unwrap());
+    }
+
+    #[test]
+    fn test_index_map_and_array() {
+        let sch = FilterSchemaBuilder::new()
+            .field("headers", FieldType::Map(Box::new(FieldType::Bytes)))
+            .field("arr", FieldType::Array(Box::new(FieldType::Int)))
+            .build();
+        let mut ctx = FilterContext::new();
+        let headers = [(
+            "content-type".to_string(),
+            LiteralValue::Bytes(Arc::new(b"application/json".to_vec())),
+        )];
+        ctx.set(
+            "headers",
+            LiteralValue::Map(Arc::new(headers.into_iter().collect())),
+            &sch,
+        )
+        .unwrap();
+        ctx.set(
+            "arr",
+            LiteralValue::Array(Arc::new(vec![LiteralValue::Int(1), LiteralValue::Int(2)])),
+            &sch,
+        )
+        .unwrap();
+        let run = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap()
+            .execute(&ctx)
+            .unwrap()
+        };
+        assert!(run("headers[\"content-type\"] == \"application/json\""));
+        assert!(!run("headers[\"accept\"] == \"application/json\""));
+        assert!(run("arr[1] == 2"));
+        assert!(!run("arr[5] == 2"));
+        assert!(run("arr[-1] == 2 && arr[-2] == 1"));
+        assert!(!run("arr[-3] == 1"));
+        assert!(run("arr[0:1] == {1} && arr[1:] == {2} && arr[:-1] == {1}"));
+        assert!(run("arr[-5:5] == {1 2} && not arr[2:] contains 1"));
+        assert!(!run("any(arr[1:0], _ == 1)"));
+        assert!(!run("arr[\"a\":] == {1 2}"));
+        assert!(!run("headers[0:1] == {1}"));
+    }
+
+    #[test]
+    fn test_array_ordering() {
+        let arr = |vals: &[i64]| {
+            LiteralValue::Array(Arc::new(
+                vals.iter().map(|v| LiteralValue::Int(*v)).collect(),
+            ))
+        };
+        let lt = |a: &LiteralValue, b: &LiteralValue| cmp_ord(a, b, |x, y| x < y);
+        assert!(lt(&arr(&[1, 2]), &arr(&[1, 3])));
+        assert!(!lt(&arr(&[1, 3]), &arr(&[1, 2])));
+        assert!(lt(&arr(&[1, 2]), &arr(&[1, 2, 0])));
+        assert!(lt(&arr(&[]), &arr(&[0])));
+        assert!(!lt(&arr(&[1, 2]), &arr(&[1, 2])));
+        assert!(cmp_ord(&arr(&[1, 2]), &arr(&[1, 2]), |x, y| x <= y));
+        assert!(cmp_ord(&arr(&[2]), &arr(&[1, 9]), |x, y| x > y));
+        assert!(cmp_between(&arr(&[1, 5]), &arr(&[1]), &arr(&[2]), false));
+
+        // Only the first unequal pair needs to be orderable
+        let mixed = |first: i64, second: &str| {
+            LiteralValue::Array(Arc::new(vec![
+                LiteralValue::Int(first),
+                LiteralValue::Bytes(Arc::new(second.as_bytes().to_vec())),
+            ]))
+        };
+        assert!(lt(&mixed(1, "b"), &mixed(2, "a")));
+        assert!(!lt(&mixed(1, "a"), &mixed(1, "b")) && !lt(&mixed(1, "b"), &mixed(1, "a")));
+        assert!(!lt(&mixed(1, "a"), &arr(&[1, 2])));
+        let strict = EngineOptions {
+            strict_comparisons: true,
+            ..Default::default()
+        };
+        assert!(check_orderable(&arr(&[1]), &arr(&[2]), "<", &strict).is_ok());
+        assert!(check_orderable(&mixed(1, "a"), &arr(&[1, 2]), "<", &strict).is_err());
+    }
+
+    #[test]
+    fn test_quantifiers_any_all() {
+        let sch = schema();
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let run = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(sch.clone()), Arc::clone(&functions))
+                .unwrap()
+                .execute(&context())
+                .unwrap()
+        };
+        assert!(run("any(arr, _ == 2)"));
+        assert!(!run("any(arr, _ > 5)"));
+        assert!(run("all(arr, _ > 0)"));
+        assert!(!run("all(arr, _ == 1)"));
+        assert!(run("all(arr, _ > 0) && foo == 42"));
+        // Operands that are neither arrays nor sets never satisfy a quantifier
+        assert!(!run("any(foo, _ == 42)"));
+
+        // Sets are iterated like arrays, on both backends
+        let sch = Arc::new(
+            FilterSchemaBuilder::new()
+                .field("ids", FieldType::Set(Box::new(FieldType::Int)))
+                .build(),
+        );
+        let mut ctx = FilterContext::new();
+        let ids = [1, 2].map(LiteralValue::Int).into_iter().collect();
+        ctx.set("ids", LiteralValue::Set(Arc::new(ids)), &sch)
+            .unwrap();
+        let compilers: [&dyn Compiler; 2] = [&DefaultCompiler, &crate::ClosureCompiler];
+        for compiler in compilers {
+            for (src, expected) in [
+                ("any(ids, _ == 2)", true),
+                ("all(ids, _ > 0)", true),
+                ("all(ids, _ > 1)", false),
+            ] {
+                let expr = FilterParser::parse(src, &sch).unwrap();
+                let filter = compiler
+                    .compile(
+                        expr,
+                        Arc::clone(&sch),
+                        Arc::clone(&functions),
+                        EngineOptions::default(),
+                    )
+                    .unwrap();
+                let matched = filter
+                    .evaluate(&ctx, &ExecutionLimits::default(), &())
+                    .unwrap();
+                assert_eq!(matched, LiteralValue::Bool(expected), "{src}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_variadic_function_arity() {
+        let sch = schema();
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(expr, Arc::new(sch.clone()), Arc::clone(&functions))
+        };
+        let filter = compile("concat(bar, \"-\", bar) == \"baz-baz\"").unwrap();
+        assert!(filter.execute(&context()).unwrap());
+        assert!(matches!(
+            compile("concat(bar) == \"baz\""),
+            Err(WirerustError::FunctionError(_))
+        ));
+        assert!(matches!(
+            compile("concat(bar, foo) == \"baz\""),
+            Err(WirerustError::TypeError(_))
+        ));
+        // The argument count must fit the instruction's operand
+        let call = |n: usize| format!("len(concat({})) == {n}", vec!["\"a\""; n].join(", "));
+        let filter = compile(&call(255)).unwrap();
+        assert!(filter.execute(&context()).unwrap());
+        for n in [256, 257] {
+            match compile(&call(n)).err() {
+                Some(WirerustError::FunctionError(message)) => {
+                    assert!(message.contains("'concat'"), "{message}")
+                }
+                other => panic!("Expected a FunctionError, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_field_exists() {
+        let sch = schema();
+        let run = |src: &str, ctx: &FilterContext| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap()
+            .execute(ctx)
+            .unwrap()
+        };
+        assert!(run("exists foo", &context()));
+        assert!(!run("exists foo", &FilterContext::new()));
+        assert!(run("not exists foo", &FilterContext::new()));
+        let expr = FilterExpr::Exists("nope".into());
+        assert!(matches!(
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new())
+            ),
+            Err(WirerustError::FieldNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_missing_fields() {
+        let sch = schema();
+        let options = EngineOptions {
+            strict_missing_fields: true,
+            ..Default::default()
+        };
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile_with_options(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+                options,
+            )
+            .unwrap()
+        };
+        let missing = FilterContext::new();
+        assert!(matches!(
+            compile("not foo").execute(&missing),
+            Err(WirerustError::ExecutionError(_))
+        ));
+        assert!(!compile("exists foo").execute(&missing).unwrap());
+        assert!(compile("foo == 42").execute(&context()).unwrap());
+    }
+
+    #[test]
+    fn test_between() {
+        let sch = schema();
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+        };
+        // foo is 42
+        for (src, expected) in [
+            ("foo between {1 100}", true),
+            ("foo between {42 42}", true),
+            ("foo strict between {42 100}", false),
+            ("foo strict between {41 43}", true),
+            ("foo between {43 100}", false),
+            ("foo + 1 between {43 43}", true),
+            ("not foo between {0 10}", true),
+        ] {
+            let filter = compile(src).unwrap();
+            assert_eq!(filter.execute(&context()).unwrap(), expected, "{src}");
+        }
+        assert!(compile("foo between {1 100}")
+            .unwrap()
+            .disassemble()
+            .contains("CompareBetween 1 100"));
+        for src in [
+            "foo between {1}",
+            "foo between {1 2 3}",
+            "foo between {1 \"z\"}",
+            "bar between {\"a\" \"z\"}",
+            "foo between {2024-01-01T00:00:00Z 2025-01-01T00:00:00Z}",
+        ] {
+            assert!(
+                matches!(compile(src), Err(WirerustError::TypeError(_))),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_repeated_literals_are_interned() {
+        let sch = schema();
+        let expr = FilterParser::parse(
+            r#"bar == "GET" || bar contains "GET" || (bar == "POST" && bar in {"GET" "PUT"})"#,
+            &sch,
+        )
+        .unwrap();
+        let filter =
+            DefaultCompiler::compile(expr, Arc::new(sch), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        let mut gets = Vec::new();
+        for instr in all_instructions(&filter.bytecode) {
+            match instr {
+                Instruction::LoadLiteral(LiteralValue::Bytes(b))
+                | Instruction::EqFieldLiteral(_, LiteralValue::Bytes(b))
+                    if **b == b"GET" =>
+                {
+                    gets.push(Arc::clone(b))
+                }
+                Instruction::LoadLiteral(LiteralValue::Array(items)) => {
+                    for item in items.iter() {
+                        if let LiteralValue::Bytes(b) = item {
+                            if **b == b"GET" {
+                                gets.push(Arc::clone(b));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        assert!(gets.len() >= 3, "{gets:?}");
+        assert!(gets.iter().all(|b| Arc::ptr_eq(b, &gets[0])));
+    }
+
+    #[test]
+    fn test_field_literal_eq_is_fused() {
+        let sch = schema();
+        let compile = |src: &str| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            DefaultCompiler::compile(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+            )
+            .unwrap()
+        };
+        let filter = compile("any(arr, _ == 1) && foo == 42");
+        assert!(matches!(
+            &filter.bytecode[..],
+            [
+                Instruction::LoadField(_),
+                Instruction::Quantify { body, .. },
+                Instruction::EqFieldLiteral(_, LiteralValue::Int(42)),
+                Instruction::LogicalAnd,
+            ] if body.len() == 3
+        ));
+        assert_eq!(filter.loaded_fields().len(), 2);
+        assert!(filter.execute(&context()).unwrap());
+        // Only a field compared with a literal is fused
+        let filter = compile("foo + 0 == 42");
+        assert!(!filter
+            .bytecode
+            .iter()
+            .any(|i| matches!(i, Instruction::EqFieldLiteral(..))));
+        assert!(filter.execute(&context()).unwrap());
+        // A missing field reads as false, as an unfused comparison would
+        assert!(!compile("bar == \"baz\"")
+            .execute(&FilterContext::new())
+            .unwrap());
+        assert!(compile("bar != \"baz\"")
+            .execute(&FilterContext::new())
+            .unwrap());
+    }
+
+    #[test]
+    fn test_null_two_and_three_valued() {
+        let sch = schema();
+        let compile = |src: &str, three_valued_logic: bool| {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            let options = EngineOptions {
+                three_valued_logic,
+                ..Default::default()
+            };
+            DefaultCompiler::compile_with_options(
+                expr,
+                Arc::new(sch.clone()),
+                Arc::new(FunctionRegistry::new()),
+                options,
+            )
+            .unwrap()
+        };
+        let mut ctx = FilterContext::new();
+        ctx.set("foo", LiteralValue::Null, &sch).unwrap();
+        ctx.set("bar", LiteralValue::Bytes(Arc::new(b"baz".to_vec())), &sch)
+            .unwrap();
+
+        // Two-valued: null is an ordinary value equal only to itself
+        assert!(compile("foo == null", false).execute(&ctx).unwrap());
+        assert!(compile("foo != 1", false).execute(&ctx).unwrap());
+        assert!(!compile("exists foo", false).execute(&ctx).unwrap());
+
+        // Three-valued: unknown never matches, but can be absorbed by and/or
+        for (src, expected) in [
+            ("foo == null", false),
+            ("foo != 1", false),
+            ("not (foo == 1)", false),
+            ("foo + 1 == 2", false),
+            ("foo == 1 or bar == \"baz\"", true),
+            ("foo == 1 and bar == \"nope\"", false),
+            ("not (foo == 1 and bar == \"nope\")", true),
+            ("arr == 1 or not (arr == 1)", false),
+        ] {
+            assert_eq!(compile(src, true).execute(&ctx).unwrap(), expected, "{src}");
+        }
+    }
 }
@@ -0,0 +1,246 @@
+//! Completion module: suggests what can be typed next in a partially written filter.
+//!
+//! This module provides the Completion type returned by
+//! [`WirerustEngine::complete`](crate::WirerustEngine::complete). The analysis is lexical
+//! rather than a full parse: it finds the word being typed at the cursor and whether an
+//! operand or an operator belongs there.
+
+use crate::expr::COMPARISON_OPERATORS;
+use crate::functions::FunctionRegistry;
+use crate::schema::FilterSchema;
+use serde::{Deserialize, Serialize};
+
+/// What kind of token a [`Completion`] inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum CompletionKind {
+    Field,
+    Function,
+    Operator,
+    Keyword,
+}
+
+/// A candidate for the word at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Completion {
+    /// The whole token, to replace the partial word before the cursor with.
+    pub text: String,
+    pub kind: CompletionKind,
+    /// The field's description from its [`FieldMeta`](crate::FieldMeta), if any.
+    pub description: Option<String>,
+}
+
+impl Completion {
+    fn new(text: impl Into<String>, kind: CompletionKind) -> Self {
+        Self {
+            text: text.into(),
+            kind,
+            description: None,
+        }
+    }
+}
+
+// Words after which an operand is expected rather than an operator.
+const OPERAND_KEYWORDS: &[&str] = &["and", "or", "not", "exists", "strict"];
+
+// Keywords that can start an operand.
+const OPERAND_START_KEYWORDS: &[&str] = &["not", "exists", "any", "all"];
+
+const LOGICAL_OPERATORS: &[&str] = &["&&", "||", "and", "or"];
+
+// Identifier characters, as accepted for field and function names by the parser.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Candidates for the word ending at byte offset `cursor` of `partial`: fields, functions
+/// and operand keywords where an operand belongs, comparison (custom ones included) and
+/// logical operators after a complete operand. Nothing is suggested inside a string
+/// literal or a `{...}` list.
+pub(crate) fn complete(
+    schema: &FilterSchema,
+    functions: &FunctionRegistry,
+    partial: &str,
+    cursor: usize,
+) -> Vec<Completion> {
+    let mut cursor = cursor.min(partial.len());
+    while !partial.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+    let before = &partial[..cursor];
+    if in_literal(before) {
+        return Vec::new();
+    }
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    let prefix = &before[start..];
+    let mut out = Vec::new();
+    if expects_operand(schema, functions, before[..start].trim_end()) {
+        let mut fields: Vec<_> = schema
+            .fields()
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        fields.sort();
+        out.extend(fields.into_iter().map(|name| Completion {
+            description: schema.field_meta(name).map(|m| m.description.clone()),
+            ..Completion::new(name.as_str(), CompletionKind::Field)
+        }));
+        let mut names: Vec<_> = (0..functions.num_functions())
+            .filter_map(|id| functions.function_name(id))
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort_unstable();
+        out.extend(
+            names
+                .into_iter()
+                .map(|name| Completion::new(name, CompletionKind::Function)),
+        );
+        out.extend(
+            OPERAND_START_KEYWORDS
+                .iter()
+                .filter(|k| k.starts_with(prefix))
+                .map(|k| Completion::new(*k, CompletionKind::Keyword)),
+        );
+    } else {
+        let operators = COMPARISON_OPERATORS.iter().map(|(op, _)| *op);
+        out.extend(
+            operators
+                .chain(functions.operator_names())
+                .chain(LOGICAL_OPERATORS.iter().copied())
+                .filter(|op| op.starts_with(prefix))
+                .map(|op| Completion::new(op, CompletionKind::Operator)),
+        );
+    }
+    out
+}
+
+// Whether `before` ends inside a quoted or raw string or a `{...}` list, where neither
+// names nor operators apply.
+fn in_literal(before: &str) -> bool {
+    let mut rest = before;
+    let mut braces = 0usize;
+    while let Some(c) = rest.chars().next() {
+        // The closing delimiter of a string starting here, and the length of its opening
+        let raw = rest
+            .strip_prefix('r')
+            .map(|r| r.len() - r.trim_start_matches('#').len())
+            .filter(|&hashes| rest[1 + hashes..].starts_with('"'));
+        let string = match (raw, c) {
+            (Some(hashes), _) => Some((format!("\"{}", "#".repeat(hashes)), hashes + 2)),
+            (None, '"' | '\'') => Some((c.to_string(), 1)),
+            _ => None,
+        };
+        match string {
+            Some((close, open)) => match rest[open..].find(&close) {
+                Some(len) => rest = &rest[open + len + close.len()..],
+                None => return true,
+            },
+            None => {
+                match c {
+                    '{' => braces += 1,
+                    '}' => braces = braces.saturating_sub(1),
+                    _ => {}
+                }
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    braces > 0
+}
+
+// Whether an operand (rather than an operator) comes after `text`, which ends just before
+// the word being completed.
+fn expects_operand(schema: &FilterSchema, functions: &FunctionRegistry, text: &str) -> bool {
+    match text.chars().last() {
+        None => true,
+        Some(')' | ']' | '}' | '"' | '\'') => false,
+        Some(c) if is_word_char(c) => {
+            let word_start = text
+                .char_indices()
+                .rev()
+                .take_while(|(_, c)| is_word_char(*c))
+                .last()
+                .map_or(0, |(i, _)| i);
+            let word = &text[word_start..];
+            schema.field_id(word).is_none()
+                && (OPERAND_KEYWORDS.contains(&word)
+                    || COMPARISON_OPERATORS.iter().any(|(op, _)| *op == word)
+                    || functions.operator_id(word).is_some())
+        }
+        // After `(`, `,`, a symbolic operator or `!`
+        Some(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{FieldMeta, FilterSchemaBuilder};
+    use crate::types::FieldType;
+
+    fn texts(partial: &str) -> Vec<String> {
+        let schema = FilterSchemaBuilder::new()
+            .field("http.method", FieldType::Bytes)
+            .field("http.host", FieldType::Bytes)
+            .field("port", FieldType::Int)
+            .build();
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        complete(&schema, &functions, partial, partial.len())
+            .into_iter()
+            .map(|c| c.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_complete_operands() {
+        assert_eq!(texts("http.m"), ["http.method"]);
+        assert_eq!(texts("port == 1 && http."), ["http.host", "http.method"]);
+        assert_eq!(texts("(po"), ["port"]);
+        assert_eq!(texts("len(http.h"), ["http.host"]);
+        assert_eq!(texts("not upp"), ["upper"]);
+        assert_eq!(texts("port in {80 p"), Vec::<String>::new());
+        assert_eq!(texts("http.host == \"po"), Vec::<String>::new());
+        assert_eq!(texts("http.host == r#\"a \" po"), Vec::<String>::new());
+        assert_eq!(texts("http.host == r#\"a \" b\"# && po"), ["port"]);
+        assert!(texts("").contains(&"lower".to_string()));
+        assert!(texts("a").contains(&"any".to_string()));
+    }
+
+    #[test]
+    fn test_complete_operators() {
+        assert_eq!(texts("http.host con"), ["contains"]);
+        assert_eq!(texts("port "), texts("len(http.host) "));
+        assert!(texts("port ").contains(&"==".to_string()));
+        assert_eq!(texts("port == 80 a"), ["and"]);
+        assert_eq!(texts("http.host == \"x\" o"), ["or"]);
+        assert_eq!(texts("port == 80 and po"), ["port"]);
+    }
+
+    #[test]
+    fn test_completion_descriptions_and_cursor() {
+        let schema = FilterSchemaBuilder::new()
+            .field_with_meta(
+                "method",
+                FieldType::Bytes,
+                FieldMeta {
+                    description: "HTTP method".into(),
+                    examples: vec![],
+                },
+            )
+            .build();
+        let functions = FunctionRegistry::new();
+        let got = complete(&schema, &functions, "me == \"GET\"", 2);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].kind, CompletionKind::Field);
+        assert_eq!(got[0].description.as_deref(), Some("HTTP method"));
+        // A cursor inside a character backs up to its start
+        assert_eq!(complete(&schema, &functions, "mé", 2).len(), 1);
+        assert_eq!(complete(&schema, &functions, "m", 99).len(), 1);
+    }
+}
@@ -3,16 +3,100 @@
 //! This module provides the FilterContext type.
 
 use crate::schema::FilterSchema;
+#[cfg(feature = "json")]
 use crate::types::FieldType;
 use crate::types::LiteralValue;
 use crate::WirerustError;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::IpAddr;
 use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(from = "ContextValues")]
 pub struct FilterContext {
     field_values: Vec<Option<LiteralValue>>, // index = FieldId
+    // Which entries of `field_values` are set, kept in step by `set_by_id`
+    #[serde(skip)]
+    present: FieldBitSet,
+}
+
+// The serialized form of a context; presence is rebuilt from the values on load.
+#[derive(Deserialize)]
+struct ContextValues {
+    field_values: Vec<Option<LiteralValue>>,
+}
+
+impl From<ContextValues> for FilterContext {
+    fn from(values: ContextValues) -> Self {
+        let mut present = FieldBitSet::default();
+        for (fid, value) in values.field_values.iter().enumerate() {
+            if value.is_some() {
+                present.insert(fid);
+            }
+        }
+        Self {
+            field_values: values.field_values,
+            present,
+        }
+    }
+}
+
+/// A set of field IDs stored as a bitmap, one bit per field, for checking which fields a
+/// context has set without looking at their values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldBitSet {
+    words: Vec<u64>,
+}
+
+impl FieldBitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn insert(&mut self, field_id: usize) {
+        let (word, bit) = (field_id / 64, field_id % 64);
+        if self.words.len() <= word {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << bit;
+    }
+    pub fn contains(&self, field_id: usize) -> bool {
+        self.words
+            .get(field_id / 64)
+            .is_some_and(|w| w & (1 << (field_id % 64)) != 0)
+    }
+    /// Whether every field in `other` is also in this set, compared a word at a time.
+    pub fn is_superset(&self, other: &FieldBitSet) -> bool {
+        other
+            .words
+            .iter()
+            .enumerate()
+            .all(|(i, w)| self.words.get(i).copied().unwrap_or(0) & w == *w)
+    }
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|w| *w == 0)
+    }
+    /// The field IDs in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, w)| {
+            (0..64)
+                .filter(move |bit| w & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+}
+
+impl FromIterator<usize> for FieldBitSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for field_id in iter {
+            set.insert(field_id);
+        }
+        set
+    }
 }
 
 pub struct FilterContextBuilder<'a> {
@@ -30,16 +114,32 @@ impl<'a> FilterContextBuilder<'a> {
     pub fn build(self) -> FilterContext {
         self.ctx
     }
+    /// Set a field from any value convertible to a [`LiteralValue`], such as `80`,
+    /// `"GET"` or an `IpAddr`; the typed setters below do the same for one type each.
+    pub fn set(
+        mut self,
+        field: &str,
+        value: impl Into<LiteralValue>,
+    ) -> Result<Self, WirerustError> {
+        self.ctx.set(field, value.into(), self.schema)?;
+        Ok(self)
+    }
     pub fn set_int(mut self, field: &str, value: i64) -> Result<Self, WirerustError> {
-        self.ctx.set_int(field, value, self.schema);
+        self.ctx.set(field, LiteralValue::Int(value), self.schema)?;
         Ok(self)
     }
     pub fn set_bool(mut self, field: &str, value: bool) -> Result<Self, WirerustError> {
-        self.ctx.set_bool(field, value, self.schema);
+        self.ctx
+            .set(field, LiteralValue::Bool(value), self.schema)?;
         Ok(self)
     }
     pub fn set_ip(mut self, field: &str, value: IpAddr) -> Result<Self, WirerustError> {
-        self.ctx.set_ip(field, value, self.schema);
+        self.ctx.set(field, LiteralValue::Ip(value), self.schema)?;
+        Ok(self)
+    }
+    pub fn set_datetime(mut self, field: &str, millis: i64) -> Result<Self, WirerustError> {
+        self.ctx
+            .set(field, LiteralValue::DateTime(millis), self.schema)?;
         Ok(self)
     }
     pub fn set_bytes(
@@ -47,7 +147,8 @@ impl<'a> FilterContextBuilder<'a> {
         field: &str,
         value: impl AsRef<[u8]>,
     ) -> Result<Self, WirerustError> {
-        self.ctx.set_bytes(field, value, self.schema);
+        let value = LiteralValue::Bytes(Arc::new(value.as_ref().to_vec()));
+        self.ctx.set(field, value, self.schema)?;
         Ok(self)
     }
     pub fn set_array(
@@ -55,7 +156,29 @@ impl<'a> FilterContextBuilder<'a> {
         field: &str,
         value: Vec<LiteralValue>,
     ) -> Result<Self, WirerustError> {
-        self.ctx.set_array(field, value, self.schema);
+        self.ctx
+            .set(field, LiteralValue::Array(Arc::new(value)), self.schema)?;
+        Ok(self)
+    }
+    /// Set a `Set` field; duplicate values collapse into one.
+    pub fn set_set(
+        mut self,
+        field: &str,
+        value: impl IntoIterator<Item = LiteralValue>,
+    ) -> Result<Self, WirerustError> {
+        let set = value.into_iter().collect();
+        self.ctx
+            .set(field, LiteralValue::Set(Arc::new(set)), self.schema)?;
+        Ok(self)
+    }
+    /// Set every value of a multi-valued field; fails for fields not declared with
+    /// [`FilterSchemaBuilder::multi_field`](crate::FilterSchemaBuilder::multi_field).
+    pub fn set_multi(
+        mut self,
+        field: &str,
+        values: impl IntoIterator<Item = LiteralValue>,
+    ) -> Result<Self, WirerustError> {
+        self.ctx.set_values(field, values, self.schema)?;
         Ok(self)
     }
 }
@@ -70,11 +193,37 @@ impl FilterContext {
             self.field_values.resize(field_id + 1, None);
         }
         self.field_values[field_id] = Some(value);
+        self.present.insert(field_id);
+    }
+    /// The IDs of the fields set in this context, maintained as values are set.
+    pub fn present_fields(&self) -> &FieldBitSet {
+        &self.present
     }
     /// Get a field value by field ID.
     pub fn get_by_id(&self, field_id: usize) -> Option<&LiteralValue> {
         self.field_values.get(field_id).and_then(|v| v.as_ref())
     }
+    /// The fields set in this context, in field ID order, with names resolved through
+    /// `schema`. IDs the schema doesn't know are skipped.
+    pub fn iter<'a>(
+        &'a self,
+        schema: &'a FilterSchema,
+    ) -> impl Iterator<Item = (&'a str, &'a LiteralValue)> + 'a {
+        self.field_values
+            .iter()
+            .enumerate()
+            .filter_map(|(fid, value)| Some((schema.field_name(fid)?, value.as_ref()?)))
+    }
+    /// Overlay `other` onto this context: every field set in `other` replaces this
+    /// context's value, and fields `other` leaves unset keep theirs. Values are matched by
+    /// field ID, so both contexts must have been populated against the same schema.
+    pub fn merge(&mut self, other: &FilterContext) {
+        for (fid, value) in other.field_values.iter().enumerate() {
+            if let Some(value) = value {
+                self.set_by_id(fid, value.clone());
+            }
+        }
+    }
 
     pub fn set(
         &mut self,
@@ -84,24 +233,14 @@ impl FilterContext {
     ) -> Result<(), WirerustError> {
         match schema.get_field_type(field) {
             Some(expected_type) => {
-                let value_type = value.get_type();
-                // Special case: allow empty arrays for any array type
-                if let (FieldType::Array(_expected_elem), FieldType::Array(value_elem)) =
-                    (expected_type, &value_type)
-                {
-                    if let FieldType::Unknown = **value_elem {
-                        if let Some(fid) = schema.field_id(field) {
-                            self.set_by_id(fid, value.clone());
-                        }
-                        return Ok(());
-                    }
-                }
-                if &value_type == expected_type {
+                // Any field may hold an explicit null
+                if value == LiteralValue::Null || expected_type.accepts_value(&value) {
                     if let Some(fid) = schema.field_id(field) {
-                        self.set_by_id(fid, value.clone());
+                        self.set_by_id(fid, value);
                     }
                     Ok(())
                 } else {
+                    let value_type = value.get_type();
                     Err(WirerustError::TypeError(format!("Type mismatch for field '{field}': expected {expected_type:?}, got {value_type:?}")))
                 }
             }
@@ -125,6 +264,10 @@ impl FilterContext {
         let _ = self.set(field, LiteralValue::Ip(value), schema);
         self
     }
+    pub fn set_datetime(&mut self, field: &str, millis: i64, schema: &FilterSchema) -> &mut Self {
+        let _ = self.set(field, LiteralValue::DateTime(millis), schema);
+        self
+    }
     pub fn set_bytes<T: AsRef<[u8]>>(
         &mut self,
         field: &str,
@@ -147,6 +290,43 @@ impl FilterContext {
         let _ = self.set(field, LiteralValue::Array(Arc::new(value)), schema);
         self
     }
+    /// Set a `Set` field; duplicate values collapse into one.
+    pub fn set_set(
+        &mut self,
+        field: &str,
+        value: impl IntoIterator<Item = LiteralValue>,
+        schema: &FilterSchema,
+    ) -> &mut Self {
+        let set = value.into_iter().collect();
+        let _ = self.set(field, LiteralValue::Set(Arc::new(set)), schema);
+        self
+    }
+    /// Set every value of a multi-valued field, such as each occurrence of a repeated
+    /// header. Comparisons against the field match if any value does.
+    pub fn set_multi(
+        &mut self,
+        field: &str,
+        values: impl IntoIterator<Item = LiteralValue>,
+        schema: &FilterSchema,
+    ) -> &mut Self {
+        let _ = self.set_values(field, values, schema);
+        self
+    }
+
+    fn set_values(
+        &mut self,
+        field: &str,
+        values: impl IntoIterator<Item = LiteralValue>,
+        schema: &FilterSchema,
+    ) -> Result<(), WirerustError> {
+        if schema.get_field_type(field).is_some() && !schema.is_multi_valued(field) {
+            return Err(WirerustError::TypeError(format!(
+                "Field '{field}' is not multi-valued"
+            )));
+        }
+        let values = LiteralValue::Array(Arc::new(values.into_iter().collect()));
+        self.set(field, values, schema)
+    }
     pub fn get_int(&self, field: &str, schema: &FilterSchema) -> Option<i64> {
         match self.get(field, schema) {
             Some(LiteralValue::Int(i)) => Some(*i),
@@ -165,6 +345,12 @@ impl FilterContext {
             _ => None,
         }
     }
+    pub fn get_datetime(&self, field: &str, schema: &FilterSchema) -> Option<i64> {
+        match self.get(field, schema) {
+            Some(LiteralValue::DateTime(ms)) => Some(*ms),
+            _ => None,
+        }
+    }
     pub fn get_bytes(&self, field: &str, schema: &FilterSchema) -> Option<&[u8]> {
         match self.get(field, schema) {
             Some(LiteralValue::Bytes(b)) => Some(&b[..]),
@@ -177,6 +363,97 @@ impl FilterContext {
             _ => None,
         }
     }
+    pub fn get_set(
+        &self,
+        field: &str,
+        schema: &FilterSchema,
+    ) -> Option<Arc<HashSet<LiteralValue>>> {
+        match self.get(field, schema) {
+            Some(LiteralValue::Set(set)) => Some(Arc::clone(set)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl FilterContext {
+    /// Build a context from a JSON object, coercing each value to its field's declared type.
+    ///
+    /// Strings map to `Bytes` (or `Ip` when the field is an IP), integral numbers to `Int`,
+    /// arrays to `Array` (or `Set` for set fields) and objects to `Map`. Keys not present
+    /// in the schema are ignored and top-level `null` values leave the field unset.
+    /// Non-integral numbers are rejected since there is no floating point field type.
+    pub fn from_json(
+        value: &serde_json::Value,
+        schema: &FilterSchema,
+    ) -> Result<FilterContext, WirerustError> {
+        let object = value.as_object().ok_or_else(|| {
+            WirerustError::TypeError(format!("Expected a JSON object, got {value}"))
+        })?;
+        let mut ctx = FilterContext::new();
+        for (field, json) in object {
+            let (Some(ty), Some(fid)) = (schema.get_field_type(field), schema.field_id(field))
+            else {
+                continue;
+            };
+            if json.is_null() {
+                continue;
+            }
+            let value = json_to_literal(json, ty).ok_or_else(|| {
+                WirerustError::TypeError(format!(
+                    "Type mismatch for field '{field}': expected {ty:?}, got JSON {json}"
+                ))
+            })?;
+            ctx.set_by_id(fid, value);
+        }
+        Ok(ctx)
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_to_literal(json: &serde_json::Value, ty: &FieldType) -> Option<LiteralValue> {
+    use serde_json::Value;
+    match (ty, json) {
+        (FieldType::Bytes, Value::String(s)) => {
+            Some(LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec())))
+        }
+        (FieldType::Enum(_), Value::String(s)) if ty.has_variant(s.as_bytes()) => {
+            Some(LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec())))
+        }
+        (FieldType::Int, Value::Number(n)) => n.as_i64().map(LiteralValue::Int),
+        (FieldType::Bool, Value::Bool(b)) => Some(LiteralValue::Bool(*b)),
+        (FieldType::Ip, Value::String(s)) => s.parse().ok().map(LiteralValue::Ip),
+        (FieldType::DateTime, Value::String(s)) => {
+            crate::types::parse_rfc3339(s).map(LiteralValue::DateTime)
+        }
+        (FieldType::DateTime, Value::Number(n)) => n.as_i64().map(LiteralValue::DateTime),
+        (FieldType::Array(elem), Value::Array(items)) => items
+            .iter()
+            .map(|item| json_to_literal(item, elem))
+            .collect::<Option<Vec<_>>>()
+            .map(|vals| LiteralValue::Array(Arc::new(vals))),
+        (FieldType::Set(elem), Value::Array(items)) => items
+            .iter()
+            .map(|item| json_to_literal(item, elem))
+            .collect::<Option<HashSet<_>>>()
+            .map(|set| LiteralValue::Set(Arc::new(set))),
+        (FieldType::Map(elem), Value::Object(entries)) => entries
+            .iter()
+            .map(|(k, v)| json_to_literal(v, elem).map(|v| (k.clone(), v)))
+            .collect::<Option<_>>()
+            .map(|map| LiteralValue::Map(Arc::new(map))),
+        // No declared type to coerce to: infer it from the JSON shape
+        (FieldType::Unknown, Value::String(_)) => json_to_literal(json, &FieldType::Bytes),
+        (FieldType::Unknown, Value::Number(_)) => json_to_literal(json, &FieldType::Int),
+        (FieldType::Unknown, Value::Bool(_)) => json_to_literal(json, &FieldType::Bool),
+        (FieldType::Unknown, Value::Array(_)) => {
+            json_to_literal(json, &FieldType::Array(Box::new(FieldType::Unknown)))
+        }
+        (FieldType::Unknown, Value::Object(_)) => {
+            json_to_literal(json, &FieldType::Map(Box::new(FieldType::Unknown)))
+        }
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -221,6 +498,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_context_builder_generic_set() {
+        let sch = schema();
+        let ip = IpAddr::from_str("10.0.0.1").unwrap();
+        let ctx = FilterContextBuilder::new(&sch)
+            .set("foo", 80)
+            .and_then(|b| b.set("bar", "GET"))
+            .and_then(|b| b.set("flag", true))
+            .and_then(|b| b.set("ip", ip))
+            .and_then(|b| b.set("arr", vec![LiteralValue::Int(1), LiteralValue::Int(2)]))
+            .unwrap()
+            .build();
+        assert_eq!(ctx.get_int("foo", &sch), Some(80));
+        assert_eq!(ctx.get_bytes("bar", &sch), Some(&b"GET"[..]));
+        assert_eq!(ctx.get_bool("flag", &sch), Some(true));
+        assert_eq!(ctx.get_ip("ip", &sch), Some(ip));
+        assert_eq!(ctx.get_array("arr", &sch).map(|a| a.len()), Some(2));
+        assert!(FilterContextBuilder::new(&sch)
+            .set("bar", b"raw".to_vec())
+            .is_ok());
+        assert!(matches!(
+            FilterContextBuilder::new(&sch).set("foo", "80"),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_context_builder_reports_errors() {
+        let sch = schema();
+        assert!(matches!(
+            FilterContextBuilder::new(&sch).set_int("typo", 1),
+            Err(WirerustError::FieldNotFound(_))
+        ));
+        assert!(matches!(
+            FilterContextBuilder::new(&sch).set_bytes("foo", "not an int"),
+            Err(WirerustError::TypeError(_))
+        ));
+        assert!(FilterContextBuilder::new(&sch)
+            .set_set("arr", [LiteralValue::Int(1)])
+            .is_err());
+    }
+
+    #[test]
+    fn test_merge_overrides_set_fields() {
+        let sch = schema();
+        let mut base = FilterContext::new();
+        base.set_int("foo", 1, &sch).set_bool("flag", true, &sch);
+        let mut overrides = FilterContext::new();
+        overrides
+            .set_int("foo", 2, &sch)
+            .set_ip("ip", IpAddr::from_str("10.0.0.1").unwrap(), &sch);
+        base.merge(&overrides);
+        assert_eq!(base.get_int("foo", &sch), Some(2));
+        assert_eq!(base.get_bool("flag", &sch), Some(true));
+        assert_eq!(
+            base.get_ip("ip", &sch),
+            Some(IpAddr::from_str("10.0.0.1").unwrap())
+        );
+        // Merging an empty context changes nothing
+        base.merge(&FilterContext::new());
+        assert_eq!(base.get_int("foo", &sch), Some(2));
+    }
+
+    #[test]
+    fn test_iter_set_fields() {
+        let sch = schema();
+        let mut ctx = FilterContext::new();
+        assert_eq!(ctx.iter(&sch).count(), 0);
+        ctx.set_int("foo", 7, &sch).set_bytes("bar", "x", &sch);
+        let fields: Vec<_> = ctx.iter(&sch).collect();
+        assert_eq!(
+            fields,
+            [
+                ("bar", &LiteralValue::Bytes(Arc::new(b"x".to_vec()))),
+                ("foo", &LiteralValue::Int(7)),
+            ]
+        );
+    }
+
     #[test]
     fn test_typed_setters_and_getters() {
         let sch = schema();
@@ -257,6 +613,59 @@ mod tests {
         assert!(ctx.set("foo", LiteralValue::Int(1), &sch).is_ok());
     }
 
+    #[test]
+    fn test_enum_values_are_checked() {
+        let sch = FilterSchemaBuilder::new()
+            .field("proto", FieldType::Enum(vec!["tcp".into(), "udp".into()]))
+            .build();
+        let mut ctx = FilterContext::new();
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        assert!(ctx.set("proto", bytes("tcp"), &sch).is_ok());
+        assert!(matches!(
+            ctx.set("proto", bytes("sctp"), &sch),
+            Err(WirerustError::TypeError(_))
+        ));
+        #[cfg(feature = "json")]
+        {
+            let ok = FilterContext::from_json(&serde_json::json!({"proto": "udp"}), &sch);
+            assert_eq!(ok.unwrap().get("proto", &sch), Some(&bytes("udp")));
+            assert!(FilterContext::from_json(&serde_json::json!({"proto": "sctp"}), &sch).is_err());
+        }
+    }
+
+    #[test]
+    fn test_present_fields() {
+        let sch = schema();
+        let mut ctx = FilterContext::new();
+        assert!(ctx.present_fields().is_empty());
+        ctx.set_int("foo", 1, &sch).set_bool("flag", true, &sch);
+        let foo = sch.field_id("foo").unwrap();
+        let flag = sch.field_id("flag").unwrap();
+        let bar = sch.field_id("bar").unwrap();
+        assert!(ctx.present_fields().contains(foo));
+        assert!(!ctx.present_fields().contains(bar));
+        assert_eq!(ctx.present_fields().len(), 2);
+        let mut ids = vec![foo, flag];
+        ids.sort();
+        assert_eq!(ctx.present_fields().iter().collect::<Vec<_>>(), ids);
+        assert!(ctx
+            .present_fields()
+            .is_superset(&[foo].into_iter().collect()));
+        assert!(!ctx
+            .present_fields()
+            .is_superset(&[foo, bar].into_iter().collect()));
+        // Beyond the first word, and rebuilt when a context is deserialized
+        let mut wide = FilterContext::new();
+        wide.set_by_id(130, LiteralValue::Int(1));
+        assert!(wide.present_fields().contains(130));
+        assert!(!wide
+            .present_fields()
+            .is_superset(&[3].into_iter().collect()));
+        let json = serde_json::to_string(&wide).unwrap();
+        let loaded: FilterContext = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.present_fields(), wide.present_fields());
+    }
+
     #[test]
     fn test_field_not_found() {
         let mut ctx = FilterContext::new();
@@ -295,4 +704,62 @@ mod tests {
         assert_eq!(ctx.get("foo", &sch), deserialized.get("foo", &sch));
         assert_eq!(ctx.get("bar", &sch), deserialized.get("bar", &sch));
     }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_from_json() {
+        let sch = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("bar", FieldType::Bytes)
+            .field("arr", FieldType::Array(Box::new(FieldType::Int)))
+            .field("ip", FieldType::Ip)
+            .field("headers", FieldType::Map(Box::new(FieldType::Bytes)))
+            .field("ts", FieldType::DateTime)
+            .field("ports", FieldType::Set(Box::new(FieldType::Int)))
+            .build();
+        let json = serde_json::json!({
+            "ts": "2024-01-01T00:00:00Z",
+            "foo": 42,
+            "bar": "baz",
+            "arr": [1, 2],
+            "ip": "10.0.0.1",
+            "headers": {"host": "example.com"},
+            "ports": [80, 443, 80],
+            "ignored": true
+        });
+        let ctx = FilterContext::from_json(&json, &sch).unwrap();
+        assert_eq!(ctx.get_int("foo", &sch), Some(42));
+        assert_eq!(ctx.get_bytes("bar", &sch), Some(&b"baz"[..]));
+        assert_eq!(
+            ctx.get_array("arr", &sch),
+            Some(Arc::new(vec![LiteralValue::Int(1), LiteralValue::Int(2)]))
+        );
+        assert_eq!(ctx.get_ip("ip", &sch), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(ctx.get_datetime("ts", &sch), Some(1_704_067_200_000));
+        assert_eq!(ctx.get_set("ports", &sch).map(|s| s.len()), Some(2));
+        match ctx.get("headers", &sch) {
+            Some(LiteralValue::Map(map)) => assert_eq!(
+                map.get("host"),
+                Some(&LiteralValue::Bytes(Arc::new(b"example.com".to_vec())))
+            ),
+            other => panic!("Expected map, got {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_from_json_type_mismatch() {
+        let sch = schema();
+        for json in [
+            serde_json::json!({"foo": "not an int"}),
+            serde_json::json!({"foo": 1.5}),
+            serde_json::json!({"arr": [1, "two"]}),
+            serde_json::json!([1, 2]),
+        ] {
+            assert!(matches!(
+                FilterContext::from_json(&json, &sch),
+                Err(WirerustError::TypeError(_))
+            ));
+        }
+    }
 }
@@ -2,10 +2,13 @@
 //!
 //! This module provides the FilterExpr type and related AST node types.
 
+use crate::functions::FunctionRegistry;
 use crate::schema::FilterSchema;
-use crate::types::LiteralValue;
+use crate::types::{FieldType, LiteralValue};
 use crate::WirerustError;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::RangeInclusive;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[non_exhaustive]
@@ -21,12 +24,54 @@ pub enum FilterExpr {
         right: Box<FilterExpr>,
     },
     Not(Box<FilterExpr>),
+    /// Unary minus, `-expr`.
+    Neg(Box<FilterExpr>),
+    Arithmetic {
+        left: Box<FilterExpr>,
+        op: ArithOp,
+        right: Box<FilterExpr>,
+    },
     Value(LiteralValue),
+    /// A reference to a schema field, resolved by name while parsing.
+    Field(String),
     FunctionCall {
         name: String,
         args: Vec<FilterExpr>,
     },
     List(Vec<LiteralValue>),
+    /// An integer set containing `start..end` ranges, such as `{1..1024 8080}`, as the
+    /// right operand of `in` / `not in`. Single integers are stored as one-value ranges.
+    IntRanges(Vec<RangeInclusive<i64>>),
+    /// `any(array, predicate)` / `all(array, predicate)`, with `_` bound to each element.
+    Quantifier {
+        kind: QuantifierKind,
+        array: Box<FilterExpr>,
+        predicate: Box<FilterExpr>,
+    },
+    /// The `_` placeholder inside a quantifier predicate.
+    Placeholder,
+    /// `exists field`: whether the field is set in the context.
+    Exists(String),
+    /// Element lookup, `target[key]`, on a map (by string key) or array (by integer index).
+    /// A negative index counts from the end of the array.
+    Index {
+        target: Box<FilterExpr>,
+        key: Box<FilterExpr>,
+    },
+    /// Array slice, `target[start:end]`; either bound may be omitted.
+    Slice {
+        target: Box<FilterExpr>,
+        start: Option<Box<FilterExpr>>,
+        end: Option<Box<FilterExpr>>,
+    },
+    /// A comparison with a custom operator registered with
+    /// [`FunctionRegistry::register_operator`](crate::FunctionRegistry::register_operator),
+    /// `left keyword right`.
+    CustomComparison {
+        left: Box<FilterExpr>,
+        op: String,
+        right: Box<FilterExpr>,
+    },
     // TODO: Add more as needed
 }
 
@@ -37,10 +82,18 @@ pub enum LogicalOp {
     Or,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum QuantifierKind {
+    Any,
+    All,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum ComparisonOp {
     Eq,
+    EqCaseInsensitive, // ASCII case-insensitive bytes equality
     Neq,
     Lt,
     Lte,
@@ -52,6 +105,42 @@ pub enum ComparisonOp {
     Wildcard,       // case-insensitive wildcard
     StrictWildcard, // case-sensitive wildcard
     Contains,       // substring or element containment
+    Between,        // `x between {lo hi}`: lo <= x <= hi
+    StrictBetween,  // `x strict between {lo hi}`: lo < x < hi
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+/// A byte range in the filter source, used to locate parse errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
 }
 
 // Visitor trait for traversing the AST
@@ -59,90 +148,480 @@ pub trait ExprVisitor {
     fn visit(&mut self, expr: &FilterExpr);
 }
 
+impl FilterExpr {
+    /// Walk the tree in pre-order, calling `visitor.visit` on this node and then on every
+    /// descendant (operands, branches, function arguments, quantifier and index parts).
+    pub fn accept(&self, visitor: &mut dyn ExprVisitor) {
+        visitor.visit(self);
+        for child in self.children() {
+            child.accept(visitor);
+        }
+    }
+
+    /// Maximum nesting of the tree: 1 for a lone field or literal, one more for each level
+    /// of operator, call, quantifier or index around it.
+    pub fn depth(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(FilterExpr::depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Total number of nodes in the tree, this one included.
+    pub fn node_count(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(FilterExpr::node_count)
+            .sum::<usize>()
+    }
+
+    /// Serialize the tree as JSON, e.g. for a visual editor. Schema fields (`Field`),
+    /// literals (`Value`) and function calls (`FunctionCall`) are distinct nodes, so a
+    /// string literal never reads as a field name.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<String, WirerustError> {
+        serde_json::to_string(self)
+            .map_err(|e| WirerustError::Other(format!("Failed to serialize expression: {e}")))
+    }
+
+    /// Load a tree serialized with [`FilterExpr::to_json`]. Field and function names are
+    /// checked when the expression is compiled or validated, not here.
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &str) -> Result<Self, WirerustError> {
+        serde_json::from_str(json)
+            .map_err(|e| WirerustError::Other(format!("Failed to deserialize expression: {e}")))
+    }
+
+    /// The leaf conditions the filter's `and`, `or` and `not` combine, left to right: its
+    /// comparisons, `exists` checks, quantifiers and any other operands of those three.
+    /// A filter without them is its own only condition.
+    pub fn conditions(&self) -> Vec<&FilterExpr> {
+        match self {
+            FilterExpr::LogicalOp { left, right, .. } => {
+                let mut out = left.conditions();
+                out.extend(right.conditions());
+                out
+            }
+            FilterExpr::Not(inner) => inner.conditions(),
+            leaf => vec![leaf],
+        }
+    }
+
+    // Direct subexpressions, left to right.
+    fn children(&self) -> Vec<&FilterExpr> {
+        match self {
+            FilterExpr::LogicalOp { left, right, .. }
+            | FilterExpr::Comparison { left, right, .. }
+            | FilterExpr::CustomComparison { left, right, .. }
+            | FilterExpr::Arithmetic { left, right, .. } => vec![left, right],
+            FilterExpr::Not(inner) | FilterExpr::Neg(inner) => vec![inner],
+            FilterExpr::FunctionCall { args, .. } => args.iter().collect(),
+            FilterExpr::Quantifier {
+                array, predicate, ..
+            } => vec![array, predicate],
+            FilterExpr::Index { target, key } => vec![target, key],
+            FilterExpr::Slice { target, start, end } => std::iter::once(&**target)
+                .chain(start.as_deref())
+                .chain(end.as_deref())
+                .collect(),
+            FilterExpr::Value(_)
+            | FilterExpr::Field(_)
+            | FilterExpr::List(_)
+            | FilterExpr::IntRanges(_)
+            | FilterExpr::Placeholder
+            | FilterExpr::Exists(_) => Vec::new(),
+        }
+    }
+}
+
+/// Renders the expression as filter source, e.g. `http.method == "POST"`. Nested operators
+/// are parenthesized, so the text reparses to the same tree. Map and set values, which
+/// filter source can't spell, are the exception and render in their `Debug` form.
+impl fmt::Display for FilterExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // An operand, in parentheses unless it is a single term
+        let operand = |expr: &FilterExpr| match expr {
+            FilterExpr::LogicalOp { .. }
+            | FilterExpr::Comparison { .. }
+            | FilterExpr::CustomComparison { .. }
+            | FilterExpr::Arithmetic { .. }
+            | FilterExpr::Not(_)
+            | FilterExpr::Neg(_) => format!("({expr})"),
+            other => other.to_string(),
+        };
+        match self {
+            FilterExpr::LogicalOp { op, left, right } => {
+                // Operands joined by the same operator need no parentheses
+                let side = |expr: &FilterExpr| match expr {
+                    FilterExpr::LogicalOp { op: inner, .. } if inner != op => format!("({expr})"),
+                    other => other.to_string(),
+                };
+                let token = match op {
+                    LogicalOp::And => "and",
+                    LogicalOp::Or => "or",
+                };
+                write!(f, "{} {token} {}", side(left), side(right))
+            }
+            FilterExpr::Comparison { left, op, right } => {
+                let token = match op {
+                    ComparisonOp::NotIn => "not in",
+                    op => COMPARISON_OPERATORS
+                        .iter()
+                        .find(|(_, candidate)| candidate == op)
+                        .map_or("==", |(token, _)| token),
+                };
+                write!(f, "{} {token} {}", operand(left), operand(right))
+            }
+            FilterExpr::CustomComparison { left, op, right } => {
+                write!(f, "{} {op} {}", operand(left), operand(right))
+            }
+            FilterExpr::Not(inner) => write!(f, "not {}", operand(inner)),
+            FilterExpr::Neg(inner) => write!(f, "-{}", operand(inner)),
+            FilterExpr::Arithmetic { left, op, right } => {
+                let token = match op {
+                    ArithOp::Add => "+",
+                    ArithOp::Sub => "-",
+                    ArithOp::Mul => "*",
+                    ArithOp::Div => "/",
+                    ArithOp::Mod => "%",
+                    ArithOp::BitAnd => "&",
+                    ArithOp::BitOr => "|",
+                    ArithOp::BitXor => "^",
+                    ArithOp::Shl => "<<",
+                    ArithOp::Shr => ">>",
+                };
+                write!(f, "{} {token} {}", operand(left), operand(right))
+            }
+            FilterExpr::Value(val) => write!(f, "{}", literal_source(val)),
+            FilterExpr::Field(name) => write!(f, "{name}"),
+            FilterExpr::Exists(name) => write!(f, "exists {name}"),
+            FilterExpr::FunctionCall { name, args } => {
+                let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{name}({})", args.join(", "))
+            }
+            FilterExpr::List(items) => {
+                let items: Vec<_> = items.iter().map(literal_source).collect();
+                write!(f, "{{{}}}", items.join(" "))
+            }
+            FilterExpr::IntRanges(ranges) => {
+                let ranges: Vec<_> = ranges
+                    .iter()
+                    .map(|r| match (r.start(), r.end()) {
+                        (start, end) if start == end => start.to_string(),
+                        (start, end) => format!("{start}..{end}"),
+                    })
+                    .collect();
+                write!(f, "{{{}}}", ranges.join(" "))
+            }
+            FilterExpr::Quantifier {
+                kind,
+                array,
+                predicate,
+            } => {
+                let kind = match kind {
+                    QuantifierKind::Any => "any",
+                    QuantifierKind::All => "all",
+                };
+                write!(f, "{kind}({array}, {predicate})")
+            }
+            FilterExpr::Placeholder => write!(f, "_"),
+            FilterExpr::Index { target, key } => write!(f, "{}[{key}]", operand(target)),
+            FilterExpr::Slice { target, start, end } => {
+                let bound = |b: &Option<Box<FilterExpr>>| b.as_ref().map(|b| b.to_string());
+                write!(
+                    f,
+                    "{}[{}:{}]",
+                    operand(target),
+                    bound(start).unwrap_or_default(),
+                    bound(end).unwrap_or_default()
+                )
+            }
+        }
+    }
+}
+
+// A literal as filter source: strings quoted (raw when they hold a double quote, since
+// strings have no escapes), timestamps in RFC 3339, arrays as `{...}` lists
+fn literal_source(val: &LiteralValue) -> String {
+    match val {
+        LiteralValue::Bytes(bytes) => {
+            let text = String::from_utf8_lossy(bytes);
+            if !text.contains('"') {
+                format!("\"{text}\"")
+            } else if !text.contains('\'') {
+                format!("'{text}'")
+            } else {
+                let hashes = "#".repeat(text.matches('#').count() + 1);
+                format!("r{hashes}\"{text}\"{hashes}")
+            }
+        }
+        LiteralValue::Int(i) => i.to_string(),
+        LiteralValue::Bool(b) => b.to_string(),
+        LiteralValue::Ip(ip) => ip.to_string(),
+        LiteralValue::DateTime(millis) => crate::types::format_rfc3339(*millis),
+        LiteralValue::Null => "null".into(),
+        LiteralValue::Array(items) => {
+            let items: Vec<_> = items.iter().map(literal_source).collect();
+            format!("{{{}}}", items.join(" "))
+        }
+        other => format!("{other:?}"),
+    }
+}
+
+/// Bounds on the input a [`FilterParser`] accepts, so hostile filters fail with a parse
+/// error instead of exhausting memory or overflowing the stack while parsing or compiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum filter length in bytes.
+    pub max_input_len: usize,
+    /// Maximum nesting depth. Each parenthesized group, `not` or unary minus nests a few
+    /// levels deeper, and each `and`, `or` or arithmetic operator in a chain one more,
+    /// since it nests everything to its left in the tree.
+    pub max_depth: usize,
+}
+
+impl ParseLimits {
+    /// Create limits with the given input length and nesting depth.
+    pub fn new(max_input_len: usize, max_depth: usize) -> Self {
+        Self {
+            max_input_len,
+            max_depth,
+        }
+    }
+}
+
+impl Default for ParseLimits {
+    /// 1 MiB of input and over 150 levels of parentheses.
+    fn default() -> Self {
+        Self::new(1 << 20, 500)
+    }
+}
+
+// Comparison operators in the order the parser tries them, so a longer spelling comes
+// before any operator that is its prefix.
+pub(crate) const COMPARISON_OPERATORS: &[(&str, ComparisonOp)] = &[
+    ("==", ComparisonOp::Eq),
+    ("~=", ComparisonOp::EqCaseInsensitive),
+    ("eq_ci", ComparisonOp::EqCaseInsensitive),
+    ("eq", ComparisonOp::Eq),
+    ("!=", ComparisonOp::Neq),
+    ("ne", ComparisonOp::Neq),
+    ("<=", ComparisonOp::Lte),
+    ("le", ComparisonOp::Lte),
+    (">=", ComparisonOp::Gte),
+    ("ge", ComparisonOp::Gte),
+    ("<", ComparisonOp::Lt),
+    ("lt", ComparisonOp::Lt),
+    (">", ComparisonOp::Gt),
+    ("gt", ComparisonOp::Gt),
+    ("in", ComparisonOp::In),
+    ("matches", ComparisonOp::Matches),
+    ("wildcard", ComparisonOp::Wildcard),
+    ("strict wildcard", ComparisonOp::StrictWildcard),
+    ("contains", ComparisonOp::Contains),
+    ("between", ComparisonOp::Between),
+    ("strict between", ComparisonOp::StrictBetween),
+];
+
+// The operators that can follow `not`, with the op they parse to; `not in` has its own.
+const NEGATABLE_OPERATORS: &[(&str, ComparisonOp)] = &[
+    ("in", ComparisonOp::NotIn),
+    ("matches", ComparisonOp::Matches),
+    ("wildcard", ComparisonOp::Wildcard),
+    ("strict wildcard", ComparisonOp::StrictWildcard),
+    ("contains", ComparisonOp::Contains),
+];
+
+// Whether `c` can appear in an identifier; keywords must not be followed by one.
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+// An arithmetic node, for `FilterParser::parse_chain`
+fn arithmetic(left: Box<FilterExpr>, op: ArithOp, right: Box<FilterExpr>) -> FilterExpr {
+    FilterExpr::Arithmetic { left, op, right }
+}
+
 // Hand-written recursive descent parser for filter expressions
 pub struct FilterParser<'a> {
     input: &'a str,
+    schema: &'a FilterSchema,
+    // Source of custom operator keywords, if any
+    functions: Option<&'a FunctionRegistry>,
     pos: usize,
+    // Number of enclosing any/all calls; `_` is only a placeholder inside one.
+    quantifier_depth: usize,
+    // Current recursion depth, checked against `limits.max_depth`.
+    depth: usize,
+    limits: ParseLimits,
+}
+
+// What a `[...]` suffix holds: a key, or the optional bounds of a slice.
+enum Subscript {
+    Key(Box<FilterExpr>),
+    Slice(Option<Box<FilterExpr>>, Option<Box<FilterExpr>>),
 }
 
 impl<'a> FilterParser<'a> {
-    pub fn new(input: &'a str, _schema: &'a FilterSchema) -> Self {
-        Self { input, pos: 0 }
+    pub fn new(input: &'a str, schema: &'a FilterSchema) -> Self {
+        Self {
+            input,
+            schema,
+            functions: None,
+            pos: 0,
+            quantifier_depth: 0,
+            depth: 0,
+            limits: ParseLimits::default(),
+        }
     }
 
     pub fn parse(input: &str, schema: &FilterSchema) -> Result<FilterExpr, WirerustError> {
+        Self::parse_with_limits(input, schema, &ParseLimits::default())
+    }
+
+    /// Parse with non-default [`ParseLimits`].
+    pub fn parse_with_limits(
+        input: &str,
+        schema: &FilterSchema,
+        limits: &ParseLimits,
+    ) -> Result<FilterExpr, WirerustError> {
+        Self::parse_inner(FilterParser::new(input, schema), limits)
+    }
+
+    /// Parse recognising the custom operators registered in `functions` (see
+    /// [`FunctionRegistry::register_operator`]).
+    pub fn parse_with_functions(
+        input: &str,
+        schema: &FilterSchema,
+        functions: &FunctionRegistry,
+    ) -> Result<FilterExpr, WirerustError> {
         let mut parser = FilterParser::new(input, schema);
-        let expr = parser.parse_expr().map_err(|e| {
-            WirerustError::ParseError(format!(
-                "Failed to parse expression at position {}: {e}",
-                parser.pos
-            ))
-        })?;
+        parser.functions = Some(functions);
+        Self::parse_inner(parser, &ParseLimits::default())
+    }
+
+    fn parse_inner(
+        mut parser: FilterParser<'_>,
+        limits: &ParseLimits,
+    ) -> Result<FilterExpr, WirerustError> {
+        let input = parser.input;
+        parser.limits = *limits;
+        if input.len() > limits.max_input_len {
+            return Err(parser.error_span(
+                limits.max_input_len,
+                input.len(),
+                &format!("Filter exceeds {} bytes", limits.max_input_len),
+            ));
+        }
+        let expr = parser.parse_expr()?;
         parser.skip_whitespace();
         if parser.pos < parser.input.len() {
-            return Err(WirerustError::ParseError(format!(
-                "Unexpected input at position {}",
-                parser.pos
-            )));
+            return Err(parser.error_span(parser.pos, parser.input.len(), "Unexpected input"));
         }
         Ok(expr)
     }
 
+    // Precedence, loosest first: or, and, not, comparison, additive, multiplicative,
+    // unary minus. `not` applies to a whole comparison (`not a == b` is `not (a == b)`) but
+    // not to a following `and`/`or` (`not a && b` is `(not a) && b`).
     fn parse_expr(&mut self) -> Result<FilterExpr, WirerustError> {
         self.parse_or()
     }
 
+    // Run one recursive step one level deeper, failing once `max_depth` is exceeded.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, WirerustError>,
+    ) -> Result<T, WirerustError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(self.error(self.pos, "Expression nested too deeply"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     fn parse_or(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.nested(Self::parse_or_inner)
+    }
+
+    fn parse_or_inner(&mut self) -> Result<FilterExpr, WirerustError> {
         self.skip_whitespace();
-        let mut left = self.parse_and()?;
-        loop {
-            self.skip_whitespace();
-            if self.consume("||") || self.consume("or") {
-                self.skip_whitespace();
-                let right = {
-                    self.skip_whitespace();
-                    self.parse_and()?
-                };
-                left = FilterExpr::LogicalOp {
-                    op: LogicalOp::Or,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                };
-            } else {
-                break;
-            }
-        }
-        Ok(left)
+        let first = self.parse_and()?;
+        self.parse_chain(
+            first,
+            |p| (p.consume("||") || p.consume_keyword("or")).then_some(LogicalOp::Or),
+            |p| {
+                p.skip_whitespace();
+                p.parse_and()
+            },
+            |left, op, right| FilterExpr::LogicalOp { op, left, right },
+        )
     }
 
     fn parse_and(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.nested(Self::parse_and_inner)
+    }
+
+    fn parse_and_inner(&mut self) -> Result<FilterExpr, WirerustError> {
         self.skip_whitespace();
-        let mut left = self.parse_not()?;
-        loop {
+        let first = self.parse_not()?;
+        self.parse_chain(
+            first,
+            |p| (p.consume("&&") || p.consume_keyword("and")).then_some(LogicalOp::And),
+            |p| {
+                p.skip_whitespace();
+                p.parse_not()
+            },
+            |left, op, right| FilterExpr::LogicalOp { op, left, right },
+        )
+    }
+
+    // A left-associative chain `first op operand op operand ...`. Each operator nests the
+    // tree built so far one level deeper, so it counts toward `max_depth` like a nested
+    // group would; a long flat `a or b or ...` can't build a tree too deep to compile.
+    fn parse_chain<Op>(
+        &mut self,
+        mut left: FilterExpr,
+        operator: impl Fn(&mut Self) -> Option<Op>,
+        operand: impl Fn(&mut Self) -> Result<FilterExpr, WirerustError>,
+        combine: impl Fn(Box<FilterExpr>, Op, Box<FilterExpr>) -> FilterExpr,
+    ) -> Result<FilterExpr, WirerustError> {
+        let depth = self.depth;
+        let result = loop {
             self.skip_whitespace();
-            if self.consume("&&") || self.consume("and") {
-                self.skip_whitespace();
-                let right = {
-                    self.skip_whitespace();
-                    self.parse_not()?
-                };
-                left = FilterExpr::LogicalOp {
-                    op: LogicalOp::And,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                };
-            } else {
-                break;
+            let Some(op) = operator(self) else {
+                break Ok(left);
+            };
+            if self.depth >= self.limits.max_depth {
+                break Err(self.error(self.pos, "Expression nested too deeply"));
             }
-        }
-        Ok(left)
+            self.depth += 1;
+            match operand(self) {
+                Ok(right) => left = combine(Box::new(left), op, Box::new(right)),
+                Err(e) => break Err(e),
+            }
+        };
+        self.depth = depth;
+        result
     }
 
     fn parse_not(&mut self) -> Result<FilterExpr, WirerustError> {
         self.skip_whitespace();
-        if self.consume("not") {
-            let expr = self.parse_not()?;
+        if self.consume_keyword("not") {
+            let expr = self.nested(Self::parse_not)?;
             Ok(FilterExpr::Not(Box::new(expr)))
+        } else if self.consume_keyword("exists") {
+            self.skip_whitespace();
+            let start = self.pos;
+            let field = self
+                .parse_identifier()
+                .map_err(|_| self.error(start, "Expected field name after 'exists'"))?;
+            Ok(FilterExpr::Exists(field))
         } else {
             self.parse_comparison()
         }
@@ -153,17 +632,27 @@ impl<'a> FilterParser<'a> {
         // Try to parse as a literal first, then as an identifier, then as a full expression
         let start_pos = self.pos;
 
+        // A quote can only start a string (and `YYYY-MM-DDT` a timestamp, a digit a number),
+        // so report its error rather than backtracking
+        if self.at_string() || self.at_datetime() || self.at_number() {
+            return self.parse_literal().map(FilterExpr::Value);
+        }
+
         // Try literal first (most specific)
         if let Ok(lit) = self.parse_literal() {
             return Ok(FilterExpr::Value(lit));
         }
         self.pos = start_pos;
 
+        // A negated operand rather than a literal, e.g. `-foo` or `-(1)`
+        if self.peek() == Some('-') {
+            return self.parse_unary();
+        }
+
         // Try identifier (field reference)
         if let Ok(ident) = self.parse_identifier() {
-            return Ok(FilterExpr::Value(LiteralValue::Bytes(
-                ident.into_bytes().into(),
-            )));
+            let field = self.ident_expr(ident);
+            return self.parse_index(field);
         }
         self.pos = start_pos;
 
@@ -172,26 +661,198 @@ impl<'a> FilterParser<'a> {
             return Ok(expr);
         }
 
-        Err(WirerustError::ParseError(format!(
-            "Expected expression or value at position {}",
-            self.pos
-        )))
+        Err(self.error(self.pos, "Expected expression or value"))
     }
 
     fn parse_comparison(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.nested(Self::parse_comparison_inner)
+    }
+
+    fn parse_comparison_inner(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.skip_whitespace();
+        // Numbers, `true`/`false` and `null` already parse as operands; a string or
+        // timestamp literal on the left needs its own case
+        let primary = if self.at_string() || self.at_datetime() {
+            FilterExpr::Value(self.parse_literal()?)
+        } else {
+            self.parse_unary()?
+        };
+        let left = self.parse_bitwise(primary)?;
+        self.skip_whitespace();
+        if let Some(op) = self.parse_custom_operator() {
+            let value = self.parse_expr_or_value()?;
+            let right = self.parse_bitwise(value)?;
+            return Ok(FilterExpr::CustomComparison {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            });
+        }
+        // Check for comparison operator
+        if let Ok((op, negated)) = self.parse_operator() {
+            self.skip_whitespace();
+            let right = if self.peek() == Some('{') {
+                // List/set literal as value
+                let right = self.parse_set_operand(op)?;
+                self.skip_whitespace();
+                right
+            } else {
+                // Try to parse as a full expression or value
+                let value = self.parse_expr_or_value()?;
+                self.parse_bitwise(value)?
+            };
+            let comparison = FilterExpr::Comparison {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+            Ok(if negated {
+                FilterExpr::Not(Box::new(comparison))
+            } else {
+                comparison
+            })
+        } else {
+            Ok(left)
+        }
+    }
+
+    // Bitwise levels between comparisons and arithmetic, loosest first as in C: `|`, `^`,
+    // `&`, then the shifts `<<` and `>>`. Unlike C, all of them bind tighter than
+    // comparisons, so `flags & 0x02 != 0` is `(flags & 0x02) != 0`.
+    fn parse_bitwise(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        self.parse_binary_level(first, &[("|", ArithOp::BitOr)], Self::parse_bit_xor)
+    }
+
+    fn parse_bit_xor(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        self.parse_binary_level(first, &[("^", ArithOp::BitXor)], Self::parse_bit_and)
+    }
+
+    fn parse_bit_and(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        self.parse_binary_level(first, &[("&", ArithOp::BitAnd)], Self::parse_shift)
+    }
+
+    fn parse_shift(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        self.parse_binary_level(
+            first,
+            &[("<<", ArithOp::Shl), (">>", ArithOp::Shr)],
+            Self::parse_arithmetic,
+        )
+    }
+
+    // One left-associative level of binary operators, with `next` parsing the tighter level
+    // on each side. A doubled token (`&&`, `||`) is a logical operator, not this one.
+    fn parse_binary_level(
+        &mut self,
+        first: FilterExpr,
+        ops: &[(&str, ArithOp)],
+        next: fn(&mut Self, FilterExpr) -> Result<FilterExpr, WirerustError>,
+    ) -> Result<FilterExpr, WirerustError> {
+        let first = next(self, first)?;
+        self.parse_chain(
+            first,
+            |p| {
+                let rest = &p.input[p.pos..];
+                let (token, op) = ops.iter().find(|(token, _)| {
+                    rest.starts_with(token) && !rest[token.len()..].starts_with(token)
+                })?;
+                p.pos += token.len();
+                Some(*op)
+            },
+            |p| {
+                let operand = p.parse_arith_operand()?;
+                next(p, operand)
+            },
+            arithmetic,
+        )
+    }
+
+    // Additive level: `+` and `-`, binding tighter than the bitwise operators
+    fn parse_arithmetic(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        let first = self.parse_term(first)?;
+        self.parse_chain(
+            first,
+            |p| {
+                let op = match p.peek()? {
+                    '+' => ArithOp::Add,
+                    '-' => ArithOp::Sub,
+                    _ => return None,
+                };
+                p.consume_char();
+                Some(op)
+            },
+            |p| {
+                let operand = p.parse_arith_operand()?;
+                p.parse_term(operand)
+            },
+            arithmetic,
+        )
+    }
+
+    // Multiplicative level: `*`, `/` and `%`
+    fn parse_term(&mut self, first: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        self.parse_chain(
+            first,
+            |p| {
+                let op = match p.peek()? {
+                    '*' => ArithOp::Mul,
+                    '/' => ArithOp::Div,
+                    '%' => ArithOp::Mod,
+                    _ => return None,
+                };
+                p.consume_char();
+                Some(op)
+            },
+            Self::parse_arith_operand,
+            arithmetic,
+        )
+    }
+
+    fn parse_arith_operand(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.skip_whitespace();
+        let start_pos = self.pos;
+        if let Ok(lit) = self.parse_literal() {
+            return Ok(FilterExpr::Value(lit));
+        }
+        self.pos = start_pos;
+        self.parse_unary()
+    }
+
+    // Unary minus: `-5` stays an integer literal, anything else (`-foo`, `-(1)`, `--2`)
+    // becomes a negation of the operand that follows.
+    fn parse_unary(&mut self) -> Result<FilterExpr, WirerustError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.at_number() {
+            // A number, unless it runs into identifier characters (digits are valid in
+            // field names)
+            let lit = self.parse_int_literal()?;
+            if !matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_' || c == '.') {
+                return Ok(FilterExpr::Value(lit));
+            }
+            self.pos = start;
+        }
+        if self.peek() != Some('-') {
+            return self.parse_primary();
+        }
+        self.consume_char();
+        let operand = self.nested(Self::parse_unary)?;
+        Ok(FilterExpr::Neg(Box::new(operand)))
+    }
+
+    fn parse_primary(&mut self) -> Result<FilterExpr, WirerustError> {
         self.skip_whitespace();
         // Parse primary expression: identifier, function call, or parenthesized expression
-        let left = if self.peek() == Some('(') {
+        let expr = if self.peek() == Some('(') {
             self.consume_char();
             let inner = self.parse_expr()?;
             self.skip_whitespace();
             if !self.consume(")") {
-                return Err(WirerustError::ParseError(format!(
-                    "Expected ')' at position {}",
-                    self.pos
-                )));
+                return Err(self.error(self.pos, "Expected ')'"));
             }
             inner
+        } else if self.peek() == Some('{') {
+            // A list literal as an operand, e.g. a function argument
+            FilterExpr::List(self.parse_list_literal()?)
         } else {
             // Parse identifier or function call
             let ident = self.parse_identifier()?;
@@ -199,66 +860,180 @@ impl<'a> FilterParser<'a> {
             if self.peek() == Some('(') {
                 // Function call
                 self.consume_char();
-                let mut args = Vec::new();
-                self.skip_whitespace();
-                if self.peek() != Some(')') {
-                    loop {
-                        // Try to parse as a simple field reference first, then as a full expression
-                        let start_pos = self.pos;
-                        let arg = if let Ok(ident) = self.parse_identifier() {
-                            // Simple field reference
-                            FilterExpr::Value(LiteralValue::Bytes(ident.into_bytes().into()))
-                        } else {
-                            // Reset and try as full expression
-                            self.pos = start_pos;
-                            self.parse_expr_or_value()?
-                        };
-                        args.push(arg);
-                        self.skip_whitespace();
-                        if self.peek() == Some(',') {
-                            self.consume_char();
-                            self.skip_whitespace();
-                        } else {
-                            break;
-                        }
-                    }
+                let quantifier = match ident.as_str() {
+                    "any" => Some(QuantifierKind::Any),
+                    "all" => Some(QuantifierKind::All),
+                    _ => None,
+                };
+                if quantifier.is_some() {
+                    self.quantifier_depth += 1;
                 }
+                let args = self.parse_call_args();
+                if quantifier.is_some() {
+                    self.quantifier_depth -= 1;
+                }
+                let mut args = args?;
                 if !self.consume(")") {
-                    return Err(WirerustError::ParseError(format!(
-                        "Expected ')' after function call at position {}",
-                        self.pos
-                    )));
+                    return Err(self.error(self.pos, "Expected ')' after function call"));
+                }
+                if let Some(kind) = quantifier {
+                    if args.len() != 2 {
+                        return Err(
+                            self.error(self.pos - 1, "Quantifier expects an array and a predicate")
+                        );
+                    }
+                    let predicate = args.pop().unwrap();
+                    let array = args.pop().unwrap();
+                    FilterExpr::Quantifier {
+                        kind,
+                        array: Box::new(array),
+                        predicate: Box::new(predicate),
+                    }
+                } else {
+                    FilterExpr::FunctionCall { name: ident, args }
                 }
-                FilterExpr::FunctionCall { name: ident, args }
-            } else if ident == "{" {
-                let list = self.parse_list_literal()?;
-                FilterExpr::List(list)
             } else {
                 // Just an identifier (field reference)
-                FilterExpr::Value(LiteralValue::Bytes(ident.into_bytes().into()))
+                self.ident_expr(ident)
             }
         };
+        self.parse_index(expr)
+    }
+
+    // Parse comma-separated call arguments up to (not including) the closing ')'.
+    fn parse_call_args(&mut self) -> Result<Vec<FilterExpr>, WirerustError> {
+        let mut args = Vec::new();
         self.skip_whitespace();
-        // Check for comparison operator
-        if let Ok((op, _op_str)) = self.parse_operator() {
-            self.skip_whitespace();
-            let right = if self.peek() == Some('{') {
-                // List/set literal as value
-                let list = self.parse_list_literal()?;
+        if self.peek() != Some(')') {
+            loop {
+                // Try a simple operand first, then a full expression (nested calls,
+                // comparisons) when the operand isn't followed by ',' or ')'
+                let start_pos = self.pos;
+                // Numbers are literals, not identifiers, even though digits are identifier chars
+                let numeric = matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '-');
+                let simple = if numeric {
+                    self.parse_expr_or_value()
+                } else if let Ok(ident) = self.parse_identifier() {
+                    // Simple field reference
+                    let field = self.ident_expr(ident);
+                    self.parse_index(field)
+                } else {
+                    // Reset and try as full expression
+                    self.pos = start_pos;
+                    self.parse_expr_or_value()
+                }
+                .and_then(|arg| self.parse_bitwise(arg));
                 self.skip_whitespace();
-                FilterExpr::Value(LiteralValue::Array(list.into()))
-            } else {
-                // Try to parse as a full expression or value
-                self.parse_expr_or_value()?
-            };
-            Ok(FilterExpr::Comparison {
-                left: Box::new(left),
-                op,
-                right: Box::new(right),
-            })
+                let arg = match simple {
+                    Ok(arg) if matches!(self.peek(), Some(',') | Some(')')) => arg,
+                    _ => {
+                        self.pos = start_pos;
+                        self.parse_expr()?
+                    }
+                };
+                args.push(arg);
+                self.skip_whitespace();
+                if self.peek() == Some(',') {
+                    self.consume_char();
+                    self.skip_whitespace();
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    // A bare identifier: the `_` placeholder inside any/all, the `null`/`true`/`false`
+    // literals, a field when the schema declares one by that name, a lookup into a map
+    // field (see `nested_field`), and otherwise a bytes literal.
+    fn ident_expr(&self, ident: String) -> FilterExpr {
+        if ident == "_" && self.quantifier_depth > 0 {
+            FilterExpr::Placeholder
+        } else if ident == "null" {
+            FilterExpr::Value(LiteralValue::Null)
+        } else if ident == "true" || ident == "false" {
+            FilterExpr::Value(LiteralValue::Bool(ident == "true"))
+        } else if self.schema.field_id(&ident).is_some() {
+            FilterExpr::Field(ident)
+        } else if let Some(nested) = self.nested_field(&ident) {
+            nested
         } else {
-            Ok(left)
+            FilterExpr::Value(LiteralValue::Bytes(ident.into_bytes().into()))
+        }
+    }
+
+    // A dotted name that isn't a field itself, read as keys into a map field: with `http`
+    // a map field, `http.request.method` is `http["request"]["method"]`. The longest
+    // leading part that names a map field is taken, so `http.request` wins over `http`
+    // when both are declared.
+    fn nested_field(&self, ident: &str) -> Option<FilterExpr> {
+        if ident.split('.').any(str::is_empty) {
+            return None;
         }
+        let (field, keys) = ident.rmatch_indices('.').find_map(|(dot, _)| {
+            let field = &ident[..dot];
+            matches!(self.schema.get_field_type(field), Some(FieldType::Map(_)))
+                .then(|| (field, &ident[dot + 1..]))
+        })?;
+        Some(
+            keys.split('.')
+                .fold(FilterExpr::Field(field.to_string()), |target, key| {
+                    FilterExpr::Index {
+                        target: Box::new(target),
+                        key: Box::new(FilterExpr::Value(LiteralValue::from(key))),
+                    }
+                }),
+        )
+    }
+
+    // Parse any trailing `[key]` or `[start:end]` suffixes, e.g. `headers["host"]`,
+    // `tags[-1]` or `tags[1:]`.
+    fn parse_index(&mut self, mut target: FilterExpr) -> Result<FilterExpr, WirerustError> {
+        loop {
+            self.skip_whitespace();
+            if !self.consume("[") {
+                return Ok(target);
+            }
+            target = match self.nested(Self::parse_subscript)? {
+                Subscript::Key(key) => FilterExpr::Index {
+                    target: Box::new(target),
+                    key,
+                },
+                Subscript::Slice(start, end) => FilterExpr::Slice {
+                    target: Box::new(target),
+                    start,
+                    end,
+                },
+            };
+            self.skip_whitespace();
+            if !self.consume("]") {
+                return Err(self.error(self.pos, "Expected ']'"));
+            }
+        }
+    }
+
+    // The inside of `[...]`, up to the closing bracket.
+    fn parse_subscript(&mut self) -> Result<Subscript, WirerustError> {
+        let start = self.parse_slice_bound(':')?;
+        self.skip_whitespace();
+        match start {
+            Some(key) if !self.consume(":") => Ok(Subscript::Key(key)),
+            start => {
+                self.consume(":");
+                Ok(Subscript::Slice(start, self.parse_slice_bound(']')?))
+            }
+        }
+    }
+
+    // One bound of a slice, or `None` when it is omitted and `stop` comes next.
+    fn parse_slice_bound(&mut self, stop: char) -> Result<Option<Box<FilterExpr>>, WirerustError> {
+        self.skip_whitespace();
+        if self.peek() == Some(stop) {
+            return Ok(None);
+        }
+        let operand = self.nested(Self::parse_arith_operand)?;
+        Ok(Some(Box::new(self.parse_bitwise(operand)?)))
     }
 
     fn parse_identifier(&mut self) -> Result<String, WirerustError> {
@@ -266,7 +1041,7 @@ impl<'a> FilterParser<'a> {
         let start = self.pos;
         let mut end = self.pos;
         for (i, c) in self.input[self.pos..].char_indices() {
-            if c.is_alphanumeric() || c == '_' || c == '.' {
+            if is_ident_char(c) {
                 end = self.pos + i + c.len_utf8();
             } else {
                 break;
@@ -277,133 +1052,245 @@ impl<'a> FilterParser<'a> {
             self.pos = end;
             Ok(ident.to_string())
         } else {
-            Err(WirerustError::ParseError(format!(
-                "Expected identifier at position {}",
-                self.pos
-            )))
-        }
-    }
-
-    fn parse_operator(&mut self) -> Result<(ComparisonOp, &'static str), WirerustError> {
-        let ops = [
-            ("==", ComparisonOp::Eq),
-            ("eq", ComparisonOp::Eq),
-            ("!=", ComparisonOp::Neq),
-            ("ne", ComparisonOp::Neq),
-            ("<=", ComparisonOp::Lte),
-            ("le", ComparisonOp::Lte),
-            (">=", ComparisonOp::Gte),
-            ("ge", ComparisonOp::Gte),
-            ("<", ComparisonOp::Lt),
-            ("lt", ComparisonOp::Lt),
-            (">", ComparisonOp::Gt),
-            ("gt", ComparisonOp::Gt),
-            ("in", ComparisonOp::In),
-            ("not in", ComparisonOp::NotIn),
-            ("matches", ComparisonOp::Matches),
-            ("wildcard", ComparisonOp::Wildcard),
-            ("strict wildcard", ComparisonOp::StrictWildcard),
-            ("contains", ComparisonOp::Contains),
-        ];
+            Err(self.error(self.pos, "Expected identifier"))
+        }
+    }
+
+    // A registered custom operator keyword at the current position
+    fn parse_custom_operator(&mut self) -> Option<String> {
+        let keyword = self
+            .functions?
+            .operator_names()
+            .find(|k| self.consume_keyword(k))?;
         self.skip_whitespace();
-        for (s, op) in ops.iter() {
-            if self.input[self.pos..].starts_with(s) {
-                self.pos += s.len();
-                return Ok((*op, *s));
+        Some(keyword.to_string())
+    }
+
+    // The operator and whether it was negated with a `not` prefix, as in
+    // `x not contains y`. `not in` has its own op; other negations wrap the comparison in
+    // `Not`.
+    fn parse_operator(&mut self) -> Result<(ComparisonOp, bool), WirerustError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.consume_keyword("not") {
+            self.skip_whitespace();
+            if let Some(op) = self.consume_operator(NEGATABLE_OPERATORS) {
+                return Ok((op, op != ComparisonOp::NotIn));
             }
+            self.pos = start;
+            return Err(self.error(self.pos, "Expected operator after 'not'"));
         }
-        Err(WirerustError::ParseError(format!(
-            "Expected operator at position {}",
-            self.pos
-        )))
+        self.consume_operator(COMPARISON_OPERATORS)
+            .map(|op| (op, false))
+            .ok_or_else(|| self.error(self.pos, "Expected operator"))
+    }
+
+    // The longest operator in `table` at the current position, so the result doesn't
+    // depend on the table's order. Word operators must end at a word boundary: `in` is
+    // not the start of `integer`.
+    fn consume_operator(&mut self, table: &[(&str, ComparisonOp)]) -> Option<ComparisonOp> {
+        let rest = &self.input[self.pos..];
+        let (token, op) = table
+            .iter()
+            .filter(|(token, _)| {
+                rest.strip_prefix(token).is_some_and(|after| {
+                    !token.starts_with(char::is_alphabetic) || !after.starts_with(is_ident_char)
+                })
+            })
+            .max_by_key(|(token, _)| token.len())?;
+        self.pos += token.len();
+        Some(*op)
     }
 
     fn parse_literal(&mut self) -> Result<LiteralValue, WirerustError> {
         self.skip_whitespace();
         if let Some(c) = self.peek() {
-            if c == '"' {
+            if self.at_string() {
                 return self.parse_string_literal();
+            } else if self.at_datetime() {
+                return self.parse_datetime_literal();
             } else if c.is_ascii_digit() || c == '-' {
                 return self.parse_int_literal();
-            } else if self.input[self.pos..].starts_with("true") {
-                self.pos += 4;
+            } else if self.consume_keyword("true") {
                 return Ok(LiteralValue::Bool(true));
-            } else if self.input[self.pos..].starts_with("false") {
-                self.pos += 5;
+            } else if self.consume_keyword("false") {
                 return Ok(LiteralValue::Bool(false));
+            } else if self.consume_keyword("null") {
+                return Ok(LiteralValue::Null);
             }
         }
-        Err(WirerustError::ParseError(format!(
-            "Expected literal at position {}",
-            self.pos
-        )))
+        Err(self.error(self.pos, "Expected literal"))
     }
 
+    // A `"..."` or `'...'` string; it ends at the next occurrence of its opening quote, so
+    // the other quote character can appear inside unescaped.
     fn parse_string_literal(&mut self) -> Result<LiteralValue, WirerustError> {
         self.skip_whitespace();
-        if self.peek() != Some('"') {
-            return Err(WirerustError::ParseError(format!(
-                "Expected \" at position {}",
-                self.pos
-            )));
+        if let Some(hashes) = self.raw_string_hashes() {
+            return self.parse_raw_string(hashes);
         }
+        let Some(quote @ ('"' | '\'')) = self.peek() else {
+            return Err(self.error(self.pos, "Expected \""));
+        };
         self.consume_char(); // consume opening quote
         let start = self.pos;
         let mut end = self.pos;
         while let Some(c) = self.peek() {
-            if c == '"' {
+            if c == quote {
                 break;
             }
             self.consume_char();
             end = self.pos;
         }
-        if self.peek() != Some('"') {
-            return Err(WirerustError::ParseError(format!(
-                "Unterminated string literal at position {}",
-                self.pos
-            )));
+        if self.peek() != Some(quote) {
+            return Err(self.error(start - 1, "Unterminated string literal"));
         }
         let s = &self.input[start..end];
         self.consume_char(); // consume closing quote
         Ok(LiteralValue::Bytes(s.as_bytes().to_vec().into()))
     }
 
+    // Whether a string literal, quoted or raw, starts here
+    fn at_string(&self) -> bool {
+        matches!(self.peek(), Some('"' | '\'')) || self.raw_string_hashes().is_some()
+    }
+
+    // The number of `#`s of a raw string `r"..."` / `r#"..."#` starting here
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let rest = self.input[self.pos..].strip_prefix('r')?;
+        let hashes = rest.len() - rest.trim_start_matches('#').len();
+        rest[hashes..].starts_with('"').then_some(hashes)
+    }
+
+    // A raw string: `r"` and any number of `#`s, closed by `"` and as many `#`s, with the
+    // contents taken as written, so `r"\d+"` is a backslash, `d` and `+` and
+    // `r#"say "hi""#` holds quotes.
+    fn parse_raw_string(&mut self, hashes: usize) -> Result<LiteralValue, WirerustError> {
+        let start = self.pos;
+        let open = hashes + 2; // `r`, the hashes and `"`
+        let close = format!("\"{}", "#".repeat(hashes));
+        let body = &self.input[start + open..];
+        let Some(len) = body.find(&close) else {
+            return Err(self.error(start, "Unterminated raw string literal"));
+        };
+        self.pos = start + open + len + close.len();
+        Ok(LiteralValue::Bytes(body.as_bytes()[..len].to_vec().into()))
+    }
+
+    // An unquoted RFC 3339 timestamp starts with `YYYY-MM-DDT`.
+    fn at_datetime(&self) -> bool {
+        let b = self.input.as_bytes().get(self.pos..self.pos + 11);
+        b.is_some_and(|b| {
+            b[..4].iter().all(u8::is_ascii_digit)
+                && b[4] == b'-'
+                && b[5..7].iter().all(u8::is_ascii_digit)
+                && b[7] == b'-'
+                && b[8..10].iter().all(u8::is_ascii_digit)
+                && matches!(b[10], b'T' | b't')
+        })
+    }
+
+    fn parse_datetime_literal(&mut self) -> Result<LiteralValue, WirerustError> {
+        let start = self.pos;
+        let len = self.input[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | ',' | '}' | ']'))
+            .unwrap_or(self.input.len() - start);
+        self.pos = start + len;
+        crate::types::parse_rfc3339(&self.input[start..self.pos])
+            .map(LiteralValue::DateTime)
+            .ok_or_else(|| self.error(start, "Invalid RFC 3339 timestamp"))
+    }
+
+    // Decimal, `0x` hex, `0o` octal or `0b` binary, optionally negative, with `_` allowed
+    // between digits (`1_000_000`, `0xFF_FF`).
     fn parse_int_literal(&mut self) -> Result<LiteralValue, WirerustError> {
+        self.parse_int().map(LiteralValue::Int)
+    }
+
+    fn parse_int(&mut self) -> Result<i64, WirerustError> {
         self.skip_whitespace();
         let start = self.pos;
-        if self.peek() == Some('-') {
-            self.consume_char();
+        let negative = self.consume("-");
+        let radix = match self.input[self.pos..].get(..2) {
+            Some("0x" | "0X") => 16,
+            Some("0o" | "0O") => 8,
+            Some("0b" | "0B") => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.pos += 2;
         }
+        let digits_start = self.pos;
         while let Some(c) = self.peek() {
-            if c.is_ascii_digit() {
+            if c.is_digit(radix) || (c == '_' && self.pos > digits_start) {
                 self.consume_char();
             } else {
                 break;
             }
         }
-        if self.pos > start {
-            let s = &self.input[start..self.pos];
-            match s.parse::<i64>() {
-                Ok(n) => Ok(LiteralValue::Int(n)),
-                Err(_) => Err(WirerustError::ParseError(format!(
-                    "Invalid integer literal at position {start}"
-                ))),
-            }
+        let digits: String = self.input[digits_start..self.pos]
+            .chars()
+            .filter(|c| *c != '_')
+            .collect();
+        if digits.is_empty() {
+            return Err(self.error(start, "Expected integer literal"));
+        }
+        let signed = if negative {
+            format!("-{digits}")
         } else {
-            Err(WirerustError::ParseError(format!(
-                "Expected integer literal at position {}",
-                self.pos
-            )))
+            digits
+        };
+        i64::from_str_radix(&signed, radix)
+            .map_err(|_| self.error_span(start, self.pos, "Integer literal out of range"))
+    }
+
+    // At an integer literal: a digit, or a minus sign directly followed by one.
+    fn at_number(&self) -> bool {
+        let mut chars = self.input[self.pos..].chars();
+        match chars.next() {
+            Some('-') => chars.next().is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
         }
     }
 
     fn parse_list_literal(&mut self) -> Result<Vec<LiteralValue>, WirerustError> {
+        self.parse_list_items(None)
+    }
+
+    // The `{...}` right operand of a comparison: a list literal, or an integer range set
+    // when it has any `start..end` items, which only `in` / `not in` accept.
+    fn parse_set_operand(&mut self, op: ComparisonOp) -> Result<FilterExpr, WirerustError> {
+        let start = self.pos;
+        let mut ranges = Vec::new();
+        let items = self.parse_list_items(Some(&mut ranges))?;
+        if ranges.is_empty() {
+            return Ok(FilterExpr::Value(LiteralValue::Array(items.into())));
+        }
+        if !matches!(op, ComparisonOp::In | ComparisonOp::NotIn) {
+            return Err(self.error(start, "Ranges are only allowed in 'in' sets"));
+        }
+        for item in items {
+            match item {
+                LiteralValue::Int(n) => ranges.push(n..=n),
+                _ => return Err(self.error(start, "A set with ranges may only contain integers")),
+            }
+        }
+        Ok(FilterExpr::IntRanges(ranges))
+    }
+
+    // Items of a `{...}` list. With `ranges`, `start..end` integer items are collected
+    // there instead of being rejected.
+    fn parse_list_items(
+        &mut self,
+        mut ranges: Option<&mut Vec<RangeInclusive<i64>>>,
+    ) -> Result<Vec<LiteralValue>, WirerustError> {
         if !self.consume("{") {
-            return Err(WirerustError::ParseError(format!(
-                "Expected '{{' at position {}",
-                self.pos
-            )));
+            return Err(self.error(self.pos, "Expected '{'"));
         }
+        // Items are separated by whitespace, a comma, or both, and the two styles may be
+        // mixed (`{1, 2 3}`). A comma must sit between two items, so leading, doubled and
+        // trailing commas are rejected.
         let mut items = Vec::new();
         loop {
             self.skip_whitespace();
@@ -411,15 +1298,58 @@ impl<'a> FilterParser<'a> {
                 self.consume_char();
                 break;
             }
+            if self.peek() == Some(',') {
+                return Err(self.error(self.pos, "Expected list item before ','"));
+            }
+            let item_start = self.pos;
             let item = self.parse_literal()?;
-            items.push(item);
+            match (item, ranges.as_deref_mut()) {
+                (LiteralValue::Int(low), Some(ranges)) if self.consume("..") => {
+                    if !self.at_number() {
+                        return Err(self.error(self.pos, "Expected integer after '..'"));
+                    }
+                    let high = self.parse_int()?;
+                    if low > high {
+                        return Err(self.error(item_start, "Range start exceeds its end"));
+                    }
+                    ranges.push(low..=high);
+                }
+                (item, _) => items.push(item),
+            }
             self.skip_whitespace();
-            // Accept either whitespace or comma as separator, but do not require comma
-            // If next is '}', break, else continue
+            if self.peek() == Some(',') {
+                let comma = self.pos;
+                self.consume_char();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    return Err(self.error(comma, "Trailing ',' in list"));
+                }
+            }
         }
         Ok(items)
     }
 
+    // Error spanning from `start` to the current position, or over the next character
+    // when nothing has been consumed yet.
+    fn error(&self, start: usize, message: &str) -> WirerustError {
+        let end = if self.pos > start {
+            self.pos
+        } else {
+            self.input[start..]
+                .chars()
+                .next()
+                .map_or(start, |c| start + c.len_utf8())
+        };
+        self.error_span(start, end, message)
+    }
+
+    fn error_span(&self, start: usize, end: usize, message: &str) -> WirerustError {
+        WirerustError::ParseError {
+            message: message.to_string(),
+            span: Span::new(start, end),
+        }
+    }
+
     fn skip_whitespace(&mut self) {
         while let Some(c) = self.peek() {
             if c.is_whitespace() {
@@ -439,6 +1369,18 @@ impl<'a> FilterParser<'a> {
         }
     }
 
+    // Like `consume`, but only when the word isn't the prefix of a longer identifier.
+    fn consume_keyword(&mut self, word: &str) -> bool {
+        let Some(after) = self.input[self.pos..].strip_prefix(word) else {
+            return false;
+        };
+        if after.starts_with(is_ident_char) {
+            return false;
+        }
+        self.pos += word.len();
+        true
+    }
+
     fn consume_char(&mut self) -> Option<char> {
         let mut iter = self.input[self.pos..].char_indices();
         let (offset, ch) = iter.next()?;
@@ -469,10 +1411,7 @@ mod tests {
         let expr = FilterParser::parse("foo == 42", &schema()).unwrap();
         match expr {
             FilterExpr::Comparison { left, op, right } => {
-                assert_eq!(
-                    *left,
-                    FilterExpr::Value(LiteralValue::Bytes(b"foo".to_vec().into()))
-                );
+                assert_eq!(*left, FilterExpr::Field("foo".into()));
                 assert_eq!(op, ComparisonOp::Eq);
                 assert_eq!(*right, FilterExpr::Value(LiteralValue::Int(42)));
             }
@@ -480,6 +1419,99 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_custom_operator() {
+        let sch = schema();
+        let mut functions = FunctionRegistry::new();
+        functions.register_operator("divides", |_, _| true).unwrap();
+        let expr = FilterParser::parse_with_functions(
+            "3 divides foo + 1 && bar == \"x\"",
+            &sch,
+            &functions,
+        )
+        .unwrap();
+        match expr {
+            FilterExpr::LogicalOp { left, .. } => match *left {
+                FilterExpr::CustomComparison { left, op, right } => {
+                    assert_eq!(*left, FilterExpr::Value(LiteralValue::Int(3)));
+                    assert_eq!(op, "divides");
+                    assert!(matches!(*right, FilterExpr::Arithmetic { .. }));
+                }
+                other => panic!("Expected custom comparison, got {other:?}"),
+            },
+            other => panic!("Expected logical op, got {other:?}"),
+        }
+        // Only a whole word is the keyword, and only when the registry is supplied
+        assert!(FilterParser::parse_with_functions("foo dividesx 3", &sch, &functions).is_err());
+        assert!(FilterParser::parse("3 divides foo", &sch).is_err());
+    }
+
+    #[test]
+    fn test_parse_raw_strings() {
+        let sch = schema();
+        let bytes = |s: &str| LiteralValue::Bytes(s.as_bytes().to_vec().into());
+        for (src, expected) in [
+            (r#"bar matches r"\d+\.\d+""#, r"\d+\.\d+"),
+            (r##"bar == r#"say "hi""#"##, r#"say "hi""#),
+            (r###"bar == r##"a "# b"##"###, r##"a "# b"##),
+            (r#"bar == r"""#, ""),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { right, .. } => {
+                    assert_eq!(*right, FilterExpr::Value(bytes(expected)), "{src}")
+                }
+                other => panic!("Expected comparison for {src}, got {other:?}"),
+            }
+        }
+        match FilterParser::parse(r##"bar in {r"a" r#"b"c"# "d"}"##, &sch).unwrap() {
+            FilterExpr::Comparison { right, .. } => assert_eq!(
+                *right,
+                FilterExpr::Value(LiteralValue::Array(
+                    vec![bytes("a"), bytes("b\"c"), bytes("d")].into()
+                ))
+            ),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+        assert!(matches!(
+            FilterParser::parse(r#"r"x" == bar"#, &sch).unwrap(),
+            FilterExpr::Comparison { .. }
+        ));
+        for bad in [r#"bar == r"abc"#, r##"bar == r#"abc""##] {
+            let err = FilterParser::parse(bad, &sch).unwrap_err();
+            assert!(
+                err.to_string().contains("Unterminated raw string"),
+                "{bad}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_literal_on_left() {
+        let sch = schema();
+        for (src, left) in [
+            ("42 == foo", LiteralValue::Int(42)),
+            ("-1 < foo", LiteralValue::Int(-1)),
+            ("true == foo", LiteralValue::Bool(true)),
+            ("\"x\" == bar", LiteralValue::Bytes(b"x".to_vec().into())),
+            ("'x' == bar", LiteralValue::Bytes(b"x".to_vec().into())),
+            (
+                "2024-01-01T00:00:00Z <= foo",
+                crate::types::parse_rfc3339("2024-01-01T00:00:00Z")
+                    .map(LiteralValue::DateTime)
+                    .unwrap(),
+            ),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { left: l, .. } => {
+                    assert_eq!(*l, FilterExpr::Value(left), "{src}")
+                }
+                other => panic!("Expected comparison for {src}, got {other:?}"),
+            }
+        }
+        let err = FilterParser::parse("\"x == bar", &sch).unwrap_err();
+        assert!(err.to_string().contains("Unterminated string literal"));
+    }
+
     #[test]
     fn test_parse_comparison_word_operators() {
         let sch = schema();
@@ -515,6 +1547,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_case_insensitive_eq() {
+        let sch = schema();
+        for src in ["bar ~= \"GET\"", "bar eq_ci \"GET\""] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { op, right, .. } => {
+                    assert_eq!(op, ComparisonOp::EqCaseInsensitive);
+                    assert_eq!(
+                        *right,
+                        FilterExpr::Value(LiteralValue::Bytes(b"GET".to_vec().into()))
+                    );
+                }
+                _ => panic!("Expected case-insensitive comparison for {src}"),
+            }
+        }
+    }
+
     #[test]
     fn test_parse_logical_and() {
         let expr = FilterParser::parse("foo == 1 && bar == \"baz\"", &schema()).unwrap();
@@ -620,4 +1669,820 @@ mod tests {
             _ => panic!("Expected contains comparison"),
         }
     }
+
+    #[test]
+    fn test_parse_between_operators() {
+        let sch = schema();
+        for (src, expected) in [
+            ("foo between {1 10}", ComparisonOp::Between),
+            ("foo strict between {1, 10}", ComparisonOp::StrictBetween),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { op, right, .. } => {
+                    assert_eq!(op, expected);
+                    assert_eq!(
+                        *right,
+                        FilterExpr::Value(LiteralValue::Array(
+                            vec![LiteralValue::Int(1), LiteralValue::Int(10)].into()
+                        ))
+                    );
+                }
+                other => panic!("Expected comparison, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_negated_operators() {
+        let sch = schema();
+        for (src, expected) in [
+            (r#"bar not matches "^a""#, ComparisonOp::Matches),
+            (r#"bar not contains "a""#, ComparisonOp::Contains),
+            (r#"bar not wildcard "a*""#, ComparisonOp::Wildcard),
+            (
+                r#"bar not  strict wildcard "a*""#,
+                ComparisonOp::StrictWildcard,
+            ),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Not(inner) => match *inner {
+                    FilterExpr::Comparison { op, .. } => assert_eq!(op, expected, "{src}"),
+                    other => panic!("Expected comparison, got {other:?}"),
+                },
+                other => panic!("Expected not, got {other:?}"),
+            }
+        }
+        match FilterParser::parse("foo not in {1 2}", &sch).unwrap() {
+            FilterExpr::Comparison { op, .. } => assert_eq!(op, ComparisonOp::NotIn),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+        assert!(FilterParser::parse("foo not == 1", &sch).is_err());
+        assert!(FilterParser::parse("bar notcontains \"a\"", &sch).is_err());
+    }
+
+    #[test]
+    fn test_parse_keywords_at_word_boundaries() {
+        let sch = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("order", FieldType::Int)
+            .field("android", FieldType::Int)
+            .field("nothing", FieldType::Int)
+            .field("trueish", FieldType::Bool)
+            .build();
+        let field = |name: &str| Box::new(FilterExpr::Field(name.into()));
+        let eq = |left, right| FilterExpr::Comparison {
+            left: field(left),
+            op: ComparisonOp::Eq,
+            right: field(right),
+        };
+        for name in ["order", "android", "nothing"] {
+            assert_eq!(
+                FilterParser::parse(&format!("{name} == foo"), &sch).unwrap(),
+                eq(name, "foo")
+            );
+            assert_eq!(
+                FilterParser::parse(&format!("foo == {name}"), &sch).unwrap(),
+                eq("foo", name)
+            );
+        }
+        assert_eq!(
+            FilterParser::parse("foo == trueish", &sch).unwrap(),
+            eq("foo", "trueish")
+        );
+        assert_eq!(
+            FilterParser::parse("foo == order or android == nothing", &sch).unwrap(),
+            FilterExpr::LogicalOp {
+                op: LogicalOp::Or,
+                left: Box::new(eq("foo", "order")),
+                right: Box::new(eq("android", "nothing")),
+            }
+        );
+        // A keyword running into the next word is not a keyword
+        for src in [
+            "foo == 1 order == 2",
+            "foo == 1 android == 2",
+            "foo == 1 andfoo == 2",
+        ] {
+            assert!(FilterParser::parse(src, &sch).is_err(), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_parse_word_operators_at_word_boundaries() {
+        let sch = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("index", FieldType::Int)
+            .field("ports", FieldType::Array(Box::new(FieldType::Int)))
+            .build();
+        let parse = |src: &str| match FilterParser::parse(src, &sch).unwrap() {
+            FilterExpr::Comparison { left, op, right } => (*left, op, *right),
+            other => panic!("Expected comparison, got {other:?}"),
+        };
+        let field = |name: &str| FilterExpr::Field(name.into());
+        assert_eq!(
+            parse("foo in ports"),
+            (field("foo"), ComparisonOp::In, field("ports"))
+        );
+        assert_eq!(
+            parse("foo not in ports"),
+            (field("foo"), ComparisonOp::NotIn, field("ports"))
+        );
+        assert_eq!(parse("foo in{1}").1, ComparisonOp::In);
+        // A field whose name starts with an operator word is not split
+        assert_eq!(
+            parse("index in ports"),
+            (field("index"), ComparisonOp::In, field("ports"))
+        );
+        assert_eq!(
+            parse("foo == index"),
+            (field("foo"), ComparisonOp::Eq, field("index"))
+        );
+        assert_eq!(parse("foo eq_ci index").1, ComparisonOp::EqCaseInsensitive);
+        assert_eq!(parse("foo==index").1, ComparisonOp::Eq);
+        for src in ["foo index", "foo integer {1}", "foo not index", "foo eqq 1"] {
+            assert!(FilterParser::parse(src, &sch).is_err(), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_display_reparses() {
+        let sch = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("bar", FieldType::Bytes)
+            .field("arr", FieldType::Array(Box::new(FieldType::Int)))
+            .field("ts", FieldType::DateTime)
+            .build();
+        for src in [
+            "foo == 42",
+            "ts > 2024-01-01T00:00:00Z and ts < 2024-02-29T12:00:00.250+02:00",
+            r#"bar == "a" and (foo > 1 or not exists bar) and foo in {1..10 20}"#,
+            r#"(foo == 1 and foo == 2) or foo != 3"#,
+            "-(foo + 2) * 3 % 4 << 1 == foo & 7",
+            r#"len(bar, 2) ~= "x" or bar not in {"a" "b"}"#,
+            r#"any(arr, _ >= 2) and arr[-1] == 3 and len(arr[1:]) == 1"#,
+            r##"bar == 'say "hi"' or bar == r#"'""#"##,
+            "not (foo strict between {1 10}) and bar not contains \"z\"",
+        ] {
+            let expr = FilterParser::parse(src, &sch).unwrap();
+            let text = expr.to_string();
+            assert_eq!(
+                FilterParser::parse(&text, &sch).unwrap(),
+                expr,
+                "{src} -> {text}"
+            );
+        }
+        let expr = FilterParser::parse(r#"foo == 1 and (bar == "x" or foo == 2)"#, &sch).unwrap();
+        assert_eq!(expr.to_string(), r#"foo == 1 and (bar == "x" or foo == 2)"#);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let sch = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("arr", FieldType::Array(Box::new(FieldType::Int)))
+            .build();
+        let expr = FilterParser::parse(
+            "foo == 1 and not (exists arr or any(arr, _ == 1 or _ == 2))",
+            &sch,
+        )
+        .unwrap();
+        let conditions: Vec<_> = expr.conditions().iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            conditions,
+            ["foo == 1", "exists arr", "any(arr, _ == 1 or _ == 2)"]
+        );
+        let leaf = FilterParser::parse("foo == 1", &sch).unwrap();
+        assert_eq!(leaf.conditions(), [&leaf]);
+    }
+
+    #[test]
+    fn test_depth_and_node_count() {
+        let field = || Box::new(FilterExpr::Field("foo".into()));
+        let int = |i| Box::new(FilterExpr::Value(LiteralValue::Int(i)));
+        let leaf = FilterExpr::Field("foo".into());
+        assert_eq!((leaf.depth(), leaf.node_count()), (1, 1));
+
+        // foo == 1
+        let cmp = FilterExpr::Comparison {
+            left: field(),
+            op: ComparisonOp::Eq,
+            right: int(1),
+        };
+        assert_eq!((cmp.depth(), cmp.node_count()), (2, 3));
+
+        // not (foo == 1 and len(foo, 2) > -foo)
+        let call = FilterExpr::FunctionCall {
+            name: "len".into(),
+            args: vec![*field(), *int(2)],
+        };
+        let tree = FilterExpr::Not(Box::new(FilterExpr::LogicalOp {
+            op: LogicalOp::And,
+            left: Box::new(cmp),
+            right: Box::new(FilterExpr::Comparison {
+                left: Box::new(call),
+                op: ComparisonOp::Gt,
+                right: Box::new(FilterExpr::Neg(field())),
+            }),
+        }));
+        assert_eq!(tree.depth(), 5);
+        assert_eq!(tree.node_count(), 11);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_expr_json_roundtrip() {
+        let expr = FilterParser::parse(
+            r#"foo in {1..10 20} && upper(bar) == "foo" && not exists bar"#,
+            &schema(),
+        )
+        .unwrap();
+        let json = expr.to_json().unwrap();
+        assert_eq!(FilterExpr::from_json(&json).unwrap(), expr);
+
+        let json = FilterParser::parse(r#"bar == "foo""#, &schema())
+            .unwrap()
+            .to_json()
+            .unwrap();
+        assert_eq!(
+            json,
+            r#"{"Comparison":{"left":{"Field":"bar"},"op":"Eq","right":{"Value":{"Bytes":[102,111,111]}}}}"#
+        );
+        assert!(matches!(
+            FilterExpr::from_json(r#"{"Field": 1}"#),
+            Err(WirerustError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_arithmetic_precedence() {
+        let expr = FilterParser::parse("foo + 2 * 3 == 7", &schema()).unwrap();
+        match expr {
+            FilterExpr::Comparison { left, op, right } => {
+                assert_eq!(op, ComparisonOp::Eq);
+                assert_eq!(*right, FilterExpr::Value(LiteralValue::Int(7)));
+                match *left {
+                    FilterExpr::Arithmetic {
+                        op: ArithOp::Add,
+                        right,
+                        ..
+                    } => assert!(matches!(
+                        *right,
+                        FilterExpr::Arithmetic {
+                            op: ArithOp::Mul,
+                            ..
+                        }
+                    )),
+                    _ => panic!("Expected addition on the left"),
+                }
+            }
+            _ => panic!("Expected comparison expr"),
+        }
+    }
+
+    #[test]
+    fn test_parse_bitwise_precedence() {
+        let expr =
+            FilterParser::parse("foo & 0x02 != 0 && foo | 1 << 2 + 1 == 9", &schema()).unwrap();
+        let FilterExpr::LogicalOp { left, right, .. } = expr else {
+            panic!("Expected logical and");
+        };
+        // `&` binds tighter than the comparison
+        match *left {
+            FilterExpr::Comparison { left, op, .. } => {
+                assert_eq!(op, ComparisonOp::Neq);
+                assert!(matches!(
+                    *left,
+                    FilterExpr::Arithmetic {
+                        op: ArithOp::BitAnd,
+                        ..
+                    }
+                ));
+            }
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+        // `|` is looser than `<<`, which is looser than `+`
+        match *right {
+            FilterExpr::Comparison { left, .. } => match *left {
+                FilterExpr::Arithmetic {
+                    op: ArithOp::BitOr,
+                    right,
+                    ..
+                } => match *right {
+                    FilterExpr::Arithmetic {
+                        op: ArithOp::Shl,
+                        right,
+                        ..
+                    } => assert!(matches!(
+                        *right,
+                        FilterExpr::Arithmetic {
+                            op: ArithOp::Add,
+                            ..
+                        }
+                    )),
+                    other => panic!("Expected shift, got {other:?}"),
+                },
+                other => panic!("Expected bitwise or, got {other:?}"),
+            },
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_arithmetic_right_operand() {
+        let expr = FilterParser::parse("foo == 10 % 4 - 1", &schema()).unwrap();
+        match expr {
+            FilterExpr::Comparison { right, .. } => match *right {
+                FilterExpr::Arithmetic {
+                    op: ArithOp::Sub,
+                    left,
+                    ..
+                } => assert!(matches!(
+                    *left,
+                    FilterExpr::Arithmetic {
+                        op: ArithOp::Mod,
+                        ..
+                    }
+                )),
+                _ => panic!("Expected subtraction on the right"),
+            },
+            _ => panic!("Expected comparison expr"),
+        }
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        let sch = schema();
+        let neg = |e: FilterExpr| FilterExpr::Neg(Box::new(e));
+        let foo = FilterExpr::Field("foo".into());
+        let int = |i| FilterExpr::Value(LiteralValue::Int(i));
+        for (src, left, right) in [
+            ("-foo == 1", neg(foo.clone()), int(1)),
+            ("foo == -(1)", foo.clone(), neg(int(1))),
+            ("foo == --2", foo.clone(), neg(int(-2))),
+            ("foo == -2", foo.clone(), int(-2)),
+        ] {
+            assert_eq!(
+                FilterParser::parse(src, &sch).unwrap(),
+                FilterExpr::Comparison {
+                    left: Box::new(left),
+                    op: ComparisonOp::Eq,
+                    right: Box::new(right),
+                },
+                "{src}"
+            );
+        }
+        // Binary minus followed by a negative literal
+        match FilterParser::parse("foo == 3 - -2", &sch).unwrap() {
+            FilterExpr::Comparison { right, .. } => assert_eq!(
+                *right,
+                FilterExpr::Arithmetic {
+                    left: Box::new(int(3)),
+                    op: ArithOp::Sub,
+                    right: Box::new(int(-2)),
+                }
+            ),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_single_quoted_strings() {
+        let sch = schema();
+        let bytes = |s: &str| FilterExpr::Value(LiteralValue::Bytes(s.as_bytes().to_vec().into()));
+        for (src, expected) in [
+            ("bar == 'application/json'", "application/json"),
+            (r#"bar == "application/json""#, "application/json"),
+            (r#"bar == 'say "hi"'"#, r#"say "hi""#),
+            (r#"bar == "it's""#, "it's"),
+            ("bar == ''", ""),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { right, .. } => {
+                    assert_eq!(*right, bytes(expected), "{src}")
+                }
+                other => panic!("Expected comparison, got {other:?}"),
+            }
+        }
+        match FilterParser::parse(r#"bar in {'a' "b"}"#, &sch).unwrap() {
+            FilterExpr::Comparison { right, .. } => assert_eq!(
+                *right,
+                FilterExpr::Value(LiteralValue::Array(
+                    vec![
+                        LiteralValue::Bytes(b"a".to_vec().into()),
+                        LiteralValue::Bytes(b"b".to_vec().into())
+                    ]
+                    .into()
+                ))
+            ),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+        for src in [r#"bar == 'abc""#, r#"bar == "abc'"#] {
+            let err = FilterParser::parse(src, &sch).unwrap_err();
+            assert!(
+                err.to_string().contains("Unterminated string literal"),
+                "{src}"
+            );
+            assert_eq!(err.span(), Some(Span::new(7, 12)), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_parse_error_spans() {
+        let sch = schema();
+        let err = FilterParser::parse("foo == 42 )", &sch).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(10, 11)));
+        let err = FilterParser::parse("bar == \"abc", &sch).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(7, 11)));
+        assert!(err.to_string().contains("Unterminated string literal"));
+        let err = FilterParser::parse("(foo == 1", &sch).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(9, 9)));
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let expr = FilterParser::parse("bar[\"k\"][0] == 1", &schema()).unwrap();
+        match expr {
+            FilterExpr::Comparison { left, .. } => assert_eq!(
+                *left,
+                FilterExpr::Index {
+                    target: Box::new(FilterExpr::Index {
+                        target: Box::new(FilterExpr::Field("bar".into())),
+                        key: Box::new(FilterExpr::Value(LiteralValue::Bytes(b"k".to_vec().into()))),
+                    }),
+                    key: Box::new(FilterExpr::Value(LiteralValue::Int(0))),
+                }
+            ),
+            _ => panic!("Expected comparison expr"),
+        }
+        assert!(FilterParser::parse("bar[\"k\" == 1", &schema()).is_err());
+        let int = |n| Some(Box::new(FilterExpr::Value(LiteralValue::Int(n))));
+        for (src, start, end) in [
+            ("bar[1:-1]", int(1), int(-1)),
+            ("bar[ : 2 ]", None, int(2)),
+            ("bar[-1:]", int(-1), None),
+            ("bar[:]", None, None),
+        ] {
+            let expr = FilterParser::parse(&format!("len({src}) == 1"), &schema()).unwrap();
+            let FilterExpr::Comparison { left, .. } = expr else {
+                panic!("Expected comparison expr");
+            };
+            let FilterExpr::FunctionCall { args, .. } = *left else {
+                panic!("Expected function call");
+            };
+            assert_eq!(
+                args[0],
+                FilterExpr::Slice {
+                    target: Box::new(FilterExpr::Field("bar".into())),
+                    start,
+                    end,
+                },
+                "{src}"
+            );
+        }
+        assert!(FilterParser::parse("bar[1:2:3] == 1", &schema()).is_err());
+    }
+
+    #[test]
+    fn test_parse_quantifier_placeholder() {
+        let expr = FilterParser::parse("any(bar, _ == \"x\")", &schema()).unwrap();
+        match expr {
+            FilterExpr::Quantifier {
+                kind, predicate, ..
+            } => {
+                assert_eq!(kind, QuantifierKind::Any);
+                match *predicate {
+                    FilterExpr::Comparison { left, .. } => {
+                        assert_eq!(*left, FilterExpr::Placeholder)
+                    }
+                    _ => panic!("Expected comparison predicate"),
+                }
+            }
+            _ => panic!("Expected quantifier expr"),
+        }
+        // Outside a quantifier `_` is an ordinary identifier
+        assert!(matches!(
+            FilterParser::parse("_", &schema()).unwrap(),
+            FilterExpr::Value(_)
+        ));
+        assert!(FilterParser::parse("all(bar)", &schema()).is_err());
+    }
+
+    #[test]
+    fn test_parse_nested_field() {
+        let sch = FilterSchemaBuilder::new()
+            .field("http", FieldType::Map(Box::new(FieldType::Bytes)))
+            .field("http.method", FieldType::Bytes)
+            .field("tls", FieldType::Map(Box::new(FieldType::Bytes)))
+            .field("tls.client", FieldType::Map(Box::new(FieldType::Bytes)))
+            .build();
+        let left = |src: &str| match FilterParser::parse(src, &sch).unwrap() {
+            FilterExpr::Comparison { left, .. } => *left,
+            other => panic!("Expected comparison, got {other:?}"),
+        };
+        let index = |target: FilterExpr, key: &str| FilterExpr::Index {
+            target: Box::new(target),
+            key: Box::new(FilterExpr::Value(LiteralValue::from(key))),
+        };
+        // A declared flat field wins over indexing the map
+        assert_eq!(
+            left("http.method == 1"),
+            FilterExpr::Field("http.method".into())
+        );
+        assert_eq!(
+            left("http.host == 1"),
+            index(FilterExpr::Field("http".into()), "host")
+        );
+        // The longest map field prefix is indexed
+        assert_eq!(
+            left("tls.client.sni == 1"),
+            index(FilterExpr::Field("tls.client".into()), "sni")
+        );
+        assert_eq!(
+            left("tls.server.sni == 1"),
+            index(index(FilterExpr::Field("tls".into()), "server"), "sni")
+        );
+        assert_eq!(
+            left("http.host[0] == 1"),
+            FilterExpr::Index {
+                target: Box::new(index(FilterExpr::Field("http".into()), "host")),
+                key: Box::new(FilterExpr::Value(LiteralValue::Int(0))),
+            }
+        );
+        // Only map fields are indexed; anything else stays a bytes literal
+        for src in ["http..host == 1", "http. == 1", "nope.host == 1"] {
+            assert!(
+                matches!(left(src), FilterExpr::Value(LiteralValue::Bytes(_))),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_exists() {
+        let sch = schema();
+        assert_eq!(
+            FilterParser::parse("exists foo", &sch).unwrap(),
+            FilterExpr::Exists("foo".into())
+        );
+        match FilterParser::parse("not exists foo && bar == \"x\"", &sch).unwrap() {
+            FilterExpr::LogicalOp { op, left, .. } => {
+                assert_eq!(op, LogicalOp::And);
+                assert_eq!(
+                    *left,
+                    FilterExpr::Not(Box::new(FilterExpr::Exists("foo".into())))
+                );
+            }
+            _ => panic!("Expected logical expr"),
+        }
+        // A field that merely starts with the keyword is still a field
+        assert!(matches!(
+            FilterParser::parse("existsfoo == 1", &sch).unwrap(),
+            FilterExpr::Comparison { .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_datetime_literal() {
+        let expr = FilterParser::parse("foo > 2024-01-01T00:00:00Z", &schema()).unwrap();
+        match expr {
+            FilterExpr::Comparison { right, .. } => assert_eq!(
+                *right,
+                FilterExpr::Value(LiteralValue::DateTime(1_704_067_200_000))
+            ),
+            _ => panic!("Expected comparison expr"),
+        }
+        let err = FilterParser::parse("foo > 2024-13-01T00:00:00Z", &schema()).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(6, 26)));
+    }
+
+    #[test]
+    fn test_visitor_walks_whole_tree() {
+        #[derive(Default)]
+        struct Census {
+            comparisons: usize,
+            fields: Vec<String>,
+            literals: Vec<LiteralValue>,
+        }
+        impl ExprVisitor for Census {
+            fn visit(&mut self, expr: &FilterExpr) {
+                match expr {
+                    FilterExpr::Comparison { .. } => self.comparisons += 1,
+                    FilterExpr::Field(name) => self.fields.push(name.clone()),
+                    FilterExpr::Value(val) => self.literals.push(val.clone()),
+                    _ => {}
+                }
+            }
+        }
+        let expr = FilterParser::parse(
+            "foo == 1 && not (bar == \"x\" || upper(bar) == \"Y\")",
+            &schema(),
+        )
+        .unwrap();
+        let mut census = Census::default();
+        expr.accept(&mut census);
+        assert_eq!(census.comparisons, 3);
+        assert_eq!(census.fields, vec!["foo", "bar", "bar"]);
+        assert_eq!(census.literals.len(), 3);
+        assert!(census
+            .literals
+            .contains(&LiteralValue::Bytes(b"Y".to_vec().into())));
+    }
+
+    #[test]
+    fn test_parse_list_separators() {
+        let sch = schema();
+        let expected = FilterExpr::Value(LiteralValue::Array(
+            vec![
+                LiteralValue::Int(1),
+                LiteralValue::Int(2),
+                LiteralValue::Int(3),
+            ]
+            .into(),
+        ));
+        for src in [
+            "foo in {1 2 3}",
+            "foo in {1, 2, 3}",
+            "foo in {1,2,3}",
+            "foo in {1, 2 3}",
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { right, .. } => assert_eq!(*right, expected, "{src}"),
+                _ => panic!("Expected comparison expr"),
+            }
+        }
+        let err = FilterParser::parse("foo in {1, 2,}", &sch).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(12, 13)));
+        assert!(FilterParser::parse("foo in {1,, 2}", &sch).is_err());
+        assert!(FilterParser::parse("foo in {, 1}", &sch).is_err());
+    }
+
+    #[test]
+    fn test_parse_int_ranges() {
+        let sch = schema();
+        match FilterParser::parse("foo not in {1..1024, 8080 -5..-1}", &sch).unwrap() {
+            FilterExpr::Comparison { op, right, .. } => {
+                assert_eq!(op, ComparisonOp::NotIn);
+                assert_eq!(
+                    *right,
+                    FilterExpr::IntRanges(vec![1..=1024, -5..=-1, 8080..=8080])
+                );
+            }
+            _ => panic!("Expected comparison expr"),
+        }
+        let err = FilterParser::parse("foo in {10..1}", &sch).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(8, 13)));
+        for src in [
+            "foo in {1..}",
+            "foo in {1.. 5}",
+            r#"foo in {1..5 "a"}"#,
+            "foo == {1..5}",
+            "foo between {1..5}",
+        ] {
+            assert!(FilterParser::parse(src, &sch).is_err(), "{src}");
+        }
+    }
+
+    #[test]
+    fn test_parse_resolves_fields() {
+        assert_eq!(
+            FilterParser::parse("foo == bar", &schema()).unwrap(),
+            FilterExpr::Comparison {
+                left: Box::new(FilterExpr::Field("foo".into())),
+                op: ComparisonOp::Eq,
+                right: Box::new(FilterExpr::Field("bar".into())),
+            }
+        );
+        // Identifiers the schema doesn't declare stay bytes literals
+        match FilterParser::parse("bar == GET", &schema()).unwrap() {
+            FilterExpr::Comparison { right, .. } => assert_eq!(
+                *right,
+                FilterExpr::Value(LiteralValue::Bytes(b"GET".to_vec().into()))
+            ),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_int_literal_radixes() {
+        let sch = schema();
+        for (src, expected) in [
+            ("foo == 0x1F4", 500),
+            ("foo == 0o777", 511),
+            ("foo == 0b1010", 10),
+            ("foo == 1_000_000", 1_000_000),
+            ("foo == 0xFF_FF", 0xFFFF),
+            ("foo == -0x10", -16),
+            ("foo == -9223372036854775808", i64::MIN),
+        ] {
+            match FilterParser::parse(src, &sch).unwrap() {
+                FilterExpr::Comparison { right, .. } => {
+                    assert_eq!(
+                        *right,
+                        FilterExpr::Value(LiteralValue::Int(expected)),
+                        "{src}"
+                    )
+                }
+                other => panic!("Expected comparison, got {other:?}"),
+            }
+        }
+        assert_eq!(
+            FilterParser::parse("foo in {0x50 443}", &sch).unwrap(),
+            FilterParser::parse("foo in {80 443}", &sch).unwrap()
+        );
+        for src in [
+            "foo == 9223372036854775808",
+            "foo == 0x1_0000_0000_0000_0000",
+            "foo == 0x",
+            "foo in {99999999999999999999}",
+        ] {
+            assert!(
+                matches!(
+                    FilterParser::parse(src, &sch),
+                    Err(WirerustError::ParseError { .. })
+                ),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_not_precedence() {
+        let sch = FilterSchemaBuilder::new()
+            .field("a", FieldType::Bool)
+            .field("b", FieldType::Bool)
+            .field("nothing", FieldType::Bool)
+            .build();
+        let field = |name: &str| Box::new(FilterExpr::Field(name.into()));
+        let a_eq_b = FilterExpr::Comparison {
+            left: field("a"),
+            op: ComparisonOp::Eq,
+            right: field("b"),
+        };
+        let not_a_eq_b = FilterExpr::Not(Box::new(a_eq_b));
+        assert_eq!(FilterParser::parse("not a == b", &sch).unwrap(), not_a_eq_b);
+        assert_eq!(
+            FilterParser::parse("not (a == b)", &sch).unwrap(),
+            not_a_eq_b
+        );
+        assert_eq!(
+            FilterParser::parse("not a && b", &sch).unwrap(),
+            FilterExpr::LogicalOp {
+                op: LogicalOp::And,
+                left: Box::new(FilterExpr::Not(field("a"))),
+                right: field("b"),
+            }
+        );
+        // `not` is only a keyword as a whole word
+        assert_eq!(
+            FilterParser::parse("nothing", &sch).unwrap(),
+            *field("nothing")
+        );
+    }
+
+    #[test]
+    fn test_parse_null_literal() {
+        let null = Box::new(FilterExpr::Value(LiteralValue::Null));
+        for src in ["foo == null", "null == foo"] {
+            match FilterParser::parse(src, &schema()).unwrap() {
+                FilterExpr::Comparison { left, right, .. } => {
+                    assert!(left == null || right == null, "{src}")
+                }
+                other => panic!("Expected comparison, got {other:?}"),
+            }
+        }
+        // Only the whole word is the keyword
+        match FilterParser::parse("foo == nullable", &schema()).unwrap() {
+            FilterExpr::Comparison { right, .. } => assert_ne!(right, null),
+            other => panic!("Expected comparison, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let sch = schema();
+        let deep =
+            |open: &str, close: &str, n: usize| format!("{}foo{}", open.repeat(n), close.repeat(n));
+        for src in [
+            deep("(", ")", 10_000),
+            deep("not ", "", 10_000),
+            deep("-", "", 10_000),
+            deep("foo[", "]", 10_000),
+            deep("len(", ")", 10_000),
+        ] {
+            match FilterParser::parse(&src, &sch) {
+                Err(WirerustError::ParseError { message, .. }) => {
+                    assert_eq!(message, "Expression nested too deeply")
+                }
+                other => panic!("Expected a depth error, got {other:?}"),
+            }
+        }
+        assert!(FilterParser::parse(&deep("(", ")", 160), &sch).is_ok());
+
+        let limits = ParseLimits::new(16, 20);
+        assert!(FilterParser::parse_with_limits("foo == 1", &sch, &limits).is_ok());
+        let err =
+            FilterParser::parse_with_limits("foo == 1 && bar == 2", &sch, &limits).unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(16, 20)));
+        assert!(FilterParser::parse_with_limits(&deep("(", ")", 10), &sch, &limits).is_err());
+    }
 }
@@ -2,37 +2,419 @@
 //!
 //! This module provides the CompiledFilter type.
 
-use crate::compiler::IrCompiledFilter;
+use crate::compiler::{Compiler, DefaultCompiler, ExecutableFilter, ExecutionLimits};
+use std::sync::Arc;
 
 /// A compiled filter, ready for execution.
 pub struct CompiledFilter {
-    ir: IrCompiledFilter,
+    inner: Box<dyn ExecutableFilter>,
+    schema: Arc<crate::schema::FilterSchema>,
+    functions: Arc<crate::functions::FunctionRegistry>,
+    // Fields the filter reads that have no schema default
+    required: crate::context::FieldBitSet,
+    // Each leaf condition compiled on its own, when `EngineOptions::explain` is set
+    explain: Option<Explain>,
+}
+
+// What `CompiledFilter::execute_explain` needs: the filter's `and`/`or`/`not` structure and
+// its leaf conditions, compiled separately in the same order as `expr.conditions()`.
+struct Explain {
+    expr: crate::expr::FilterExpr,
+    conditions: Vec<Box<dyn ExecutableFilter>>,
+    three_valued_logic: bool,
+}
+
+impl Explain {
+    // The value of `expr` given its leaf conditions' values, combined the way the backends
+    // combine them, so nothing is evaluated twice.
+    fn combine(
+        &self,
+        expr: &crate::expr::FilterExpr,
+        values: &mut std::slice::Iter<'_, crate::types::LiteralValue>,
+    ) -> crate::types::LiteralValue {
+        use crate::compiler::{kleene, to_bool};
+        use crate::expr::{FilterExpr, LogicalOp};
+        use crate::types::LiteralValue;
+        match expr {
+            FilterExpr::LogicalOp { op, left, right } => {
+                let (left, right) = (self.combine(left, values), self.combine(right, values));
+                let or = *op == LogicalOp::Or;
+                if self.three_valued_logic {
+                    kleene(&left, &right, or)
+                } else if or {
+                    LiteralValue::Bool(to_bool(&left) || to_bool(&right))
+                } else {
+                    LiteralValue::Bool(to_bool(&left) && to_bool(&right))
+                }
+            }
+            FilterExpr::Not(inner) => match self.combine(inner, values) {
+                LiteralValue::Null if self.three_valued_logic => LiteralValue::Null,
+                val => LiteralValue::Bool(!to_bool(&val)),
+            },
+            _ => values.next().cloned().unwrap_or(LiteralValue::Null),
+        }
+    }
+}
+
+/// What each leaf condition of a filter evaluated to, from
+/// [`CompiledFilter::execute_explain`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// The conditions in filter order.
+    pub conditions: Vec<ConditionResult>,
+}
+
+/// One leaf condition of a filter and its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionResult {
+    /// The condition as filter source, e.g. `http.method == "POST"`.
+    pub condition: String,
+    pub expr: crate::expr::FilterExpr,
+    /// Whether the condition held; `None` when it evaluated to null (unknown, under
+    /// three-valued logic).
+    pub matched: Option<bool>,
+}
+
+impl Explanation {
+    /// The conditions that held, e.g. to report why a request was blocked.
+    pub fn matched(&self) -> impl Iterator<Item = &ConditionResult> {
+        self.conditions.iter().filter(|c| c.matched == Some(true))
+    }
 }
 
 impl CompiledFilter {
     /// Create a new compiled filter from an expression, schema, and function registry.
     pub fn new(
         expr: crate::expr::FilterExpr,
-        schema: std::sync::Arc<crate::schema::FilterSchema>,
-        functions: std::sync::Arc<crate::functions::FunctionRegistry>,
-    ) -> Self {
-        let ir = crate::compiler::DefaultCompiler::compile(expr, schema, functions);
-        Self { ir }
+        schema: Arc<crate::schema::FilterSchema>,
+        functions: Arc<crate::functions::FunctionRegistry>,
+    ) -> Result<Self, crate::WirerustError> {
+        Self::with_options(expr, schema, functions, Default::default())
+    }
+    /// Compile a filter that shares `schema` and `functions` with others. Pass clones of
+    /// the same `Arc`s for every filter so they all point at one schema and registry
+    /// instead of each holding a copy of their maps:
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use wirerust::*;
+    /// let schema = Arc::new(FilterSchemaBuilder::new().field("port", FieldType::Int).build());
+    /// let functions = Arc::new(FunctionRegistry::new());
+    /// let filters = ["port == 80", "port == 443"]
+    ///     .into_iter()
+    ///     .map(|src| {
+    ///         let expr = FilterParser::parse(src, &schema)?;
+    ///         CompiledFilter::new_shared(expr, Arc::clone(&schema), Arc::clone(&functions))
+    ///     })
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(filters.len(), 2);
+    /// # Ok::<(), WirerustError>(())
+    /// ```
+    pub fn new_shared(
+        expr: crate::expr::FilterExpr,
+        schema: Arc<crate::schema::FilterSchema>,
+        functions: Arc<crate::functions::FunctionRegistry>,
+    ) -> Result<Self, crate::WirerustError> {
+        Self::new(expr, schema, functions)
+    }
+    /// Parse and compile `filter` into a self-contained filter that owns its schema and
+    /// function registry, so executing it needs nothing but a context. Handy for FFI
+    /// handles and other embeddings that keep a single filter around; use
+    /// [`CompiledFilter::new_shared`] when many filters should share one schema.
+    ///
+    /// ```
+    /// # use wirerust::*;
+    /// let schema = FilterSchemaBuilder::new().field("port", FieldType::Int).build();
+    /// let filter = CompiledFilter::standalone("port == 80", schema, FunctionRegistry::new())?;
+    /// let mut ctx = FilterContext::new();
+    /// ctx.set_int("port", 80, filter.schema());
+    /// assert!(filter.execute(&ctx)?);
+    /// # Ok::<(), WirerustError>(())
+    /// ```
+    pub fn standalone(
+        filter: &str,
+        schema: crate::schema::FilterSchema,
+        functions: crate::functions::FunctionRegistry,
+    ) -> Result<Self, crate::WirerustError> {
+        let expr = crate::expr::FilterParser::parse_with_functions(filter, &schema, &functions)?;
+        Self::new(expr, Arc::new(schema), Arc::new(functions))
+    }
+    /// Create a new compiled filter using non-default engine options.
+    pub fn with_options(
+        expr: crate::expr::FilterExpr,
+        schema: Arc<crate::schema::FilterSchema>,
+        functions: Arc<crate::functions::FunctionRegistry>,
+        options: crate::compiler::EngineOptions,
+    ) -> Result<Self, crate::WirerustError> {
+        Self::with_compiler(expr, schema, functions, options, &DefaultCompiler)
+    }
+    /// Create a new compiled filter using the given compiler backend.
+    pub fn with_compiler(
+        expr: crate::expr::FilterExpr,
+        schema: Arc<crate::schema::FilterSchema>,
+        functions: Arc<crate::functions::FunctionRegistry>,
+        options: crate::compiler::EngineOptions,
+        compiler: &dyn Compiler,
+    ) -> Result<Self, crate::WirerustError> {
+        let explain = if options.explain {
+            let compile = |cond: &crate::expr::FilterExpr| {
+                compiler.compile(
+                    cond.clone(),
+                    Arc::clone(&schema),
+                    Arc::clone(&functions),
+                    options,
+                )
+            };
+            Some(Explain {
+                conditions: expr
+                    .conditions()
+                    .into_iter()
+                    .map(compile)
+                    .collect::<Result<Vec<_>, crate::WirerustError>>()?,
+                expr: expr.clone(),
+                three_valued_logic: options.three_valued_logic,
+            })
+        } else {
+            None
+        };
+        let inner = compiler.compile(expr, Arc::clone(&schema), Arc::clone(&functions), options)?;
+        Ok(Self {
+            required: required_fields(&inner.loaded_fields(), &schema),
+            inner,
+            schema,
+            functions,
+            explain,
+        })
     }
     /// Execute the filter against a context.
     pub fn execute(
         &self,
         context: &crate::context::FilterContext,
     ) -> Result<bool, crate::WirerustError> {
-        self.ir.execute(context)
+        self.execute_with_limits(context, &ExecutionLimits::default())
+    }
+    /// Execute the filter, or return `Ok(None)` without running it when a field it reads
+    /// is neither set in `context` nor defaulted by the schema, to tell "inputs were
+    /// missing" apart from "evaluated to false". Fields only tested with `exists` don't
+    /// count. The check covers every field the filter reads, before evaluation, so it
+    /// doesn't short-circuit: `port == 80 || host == "a"` is `None` without `host` even
+    /// when the port alone would decide it. Backends without bytecode that don't report
+    /// their fields never give `None`.
+    pub fn execute_checked(
+        &self,
+        context: &crate::context::FilterContext,
+    ) -> Result<Option<bool>, crate::WirerustError> {
+        if !self.required_fields_present(context) {
+            return Ok(None);
+        }
+        self.execute(context).map(Some)
+    }
+    /// Whether `context` sets every field this filter reads that has no schema default,
+    /// a bitmap comparison against [`FilterContext::present_fields`](crate::FilterContext::present_fields).
+    /// Callers can skip execution when it is false; [`CompiledFilter::execute_checked`]
+    /// does exactly that.
+    pub fn required_fields_present(&self, context: &crate::context::FilterContext) -> bool {
+        context.present_fields().is_superset(&self.required)
+    }
+    /// Evaluate the expression and return its final value instead of coercing it to a
+    /// boolean, for computed fields such as `upper(http.method)`.
+    pub fn evaluate(
+        &self,
+        context: &crate::context::FilterContext,
+    ) -> Result<crate::types::LiteralValue, crate::WirerustError> {
+        self.inner
+            .evaluate(context, &ExecutionLimits::default(), &())
+    }
+    /// Execute the filter, failing if it exceeds the given instruction or stack limits.
+    pub fn execute_with_limits(
+        &self,
+        context: &crate::context::FilterContext,
+        limits: &ExecutionLimits,
+    ) -> Result<bool, crate::WirerustError> {
+        self.inner
+            .evaluate(context, limits, &())
+            .map(|v| crate::compiler::to_bool(&v))
+    }
+    /// Execute the filter on a caller-owned stack buffer; see
+    /// [`IrCompiledFilter::execute_with_stack`](crate::IrCompiledFilter::execute_with_stack).
+    /// Backends without bytecode ignore the buffer.
+    pub fn execute_with_stack(
+        &self,
+        context: &crate::context::FilterContext,
+        stack: &mut crate::ir::IrStack,
+    ) -> Result<bool, crate::WirerustError> {
+        match self.inner.as_ir() {
+            Some(ir) => ir.execute_with_stack(context, stack),
+            None => self.execute(context),
+        }
+    }
+    /// Execute the filter, passing `data` through to custom functions.
+    pub fn execute_with_data(
+        &self,
+        context: &crate::context::FilterContext,
+        data: &dyn std::any::Any,
+    ) -> Result<bool, crate::WirerustError> {
+        self.inner
+            .evaluate(context, &ExecutionLimits::default(), data)
+            .map(|v| crate::compiler::to_bool(&v))
+    }
+    /// Serialize the compiled bytecode so it can be cached and reloaded with
+    /// [`CompiledFilter::from_bytes`]. Fails for filters compiled by a backend without
+    /// bytecode.
+    #[cfg(feature = "json")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, crate::WirerustError> {
+        self.ir()?.to_bytes()
+    }
+    /// Load a filter serialized with [`CompiledFilter::to_bytes`], validating its field and
+    /// function references against the given schema and registry.
+    #[cfg(feature = "json")]
+    pub fn from_bytes(
+        bytes: &[u8],
+        schema: Arc<crate::schema::FilterSchema>,
+        functions: Arc<crate::functions::FunctionRegistry>,
+    ) -> Result<Self, crate::WirerustError> {
+        let ir = crate::compiler::IrCompiledFilter::from_bytes(
+            bytes,
+            Arc::clone(&schema),
+            Arc::clone(&functions),
+        )?;
+        Ok(Self {
+            required: required_fields(&ir.loaded_fields(), &schema),
+            inner: Box::new(ir),
+            schema,
+            functions,
+            explain: None,
+        })
+    }
+    /// Names of the fields this filter reads, `exists` checks included, so callers can
+    /// populate only those. `None` for backends that don't report them.
+    pub fn referenced_fields(&self) -> Option<Vec<&str>> {
+        self.inner.referenced_fields()
+    }
+    /// Names of the registered functions this filter calls. `None` for backends that don't
+    /// report them.
+    pub fn referenced_functions(&self) -> Option<Vec<&str>> {
+        self.inner.referenced_functions()
+    }
+    /// The most values the evaluation stack holds at once while running this filter; see
+    /// [`IrCompiledFilter::max_stack_depth`](crate::IrCompiledFilter::max_stack_depth).
+    /// `None` for backends without bytecode.
+    pub fn max_stack_depth(&self) -> Option<usize> {
+        self.inner.as_ir().map(|ir| ir.max_stack_depth())
+    }
+    /// Whether a custom function call might be skipped on some paths; see
+    /// [`IrCompiledFilter::has_conditional_function_calls`](crate::IrCompiledFilter::has_conditional_function_calls).
+    /// `None` for backends without bytecode.
+    pub fn has_conditional_function_calls(&self) -> Option<bool> {
+        self.inner
+            .as_ir()
+            .map(|ir| ir.has_conditional_function_calls())
+    }
+    /// Human-readable listing of the compiled bytecode, for debugging. Empty for backends
+    /// without bytecode.
+    pub fn disassemble(&self) -> String {
+        self.inner
+            .as_ir()
+            .map(|ir| ir.disassemble())
+            .unwrap_or_default()
+    }
+    /// Execute the filter and report what each of its leaf conditions evaluated to, to tell
+    /// why it matched: for `method == "POST" and path contains "/admin"`, both conditions
+    /// show up in [`Explanation::matched`]. Each condition runs once, within `limits` and
+    /// with `data` passed to custom functions as in
+    /// [`CompiledFilter::execute_with_data`], and the result combines their values, so
+    /// stateful functions advance as they would in `execute`. Needs
+    /// [`EngineOptions::explain`](crate::EngineOptions::explain); filters compiled without
+    /// it, or loaded from bytes, return an error.
+    pub fn execute_explain(
+        &self,
+        context: &crate::context::FilterContext,
+        limits: &ExecutionLimits,
+        data: &dyn std::any::Any,
+    ) -> Result<(bool, Explanation), crate::WirerustError> {
+        let explain = self.explain.as_ref().ok_or_else(|| {
+            crate::WirerustError::Other("Filter was compiled without EngineOptions::explain".into())
+        })?;
+        let values = explain
+            .conditions
+            .iter()
+            .map(|filter| filter.evaluate(context, limits, data))
+            .collect::<Result<Vec<_>, _>>()?;
+        let matched = crate::compiler::to_bool(&explain.combine(&explain.expr, &mut values.iter()));
+        let conditions = explain
+            .expr
+            .conditions()
+            .into_iter()
+            .zip(&values)
+            .map(|(expr, val)| ConditionResult {
+                condition: expr.to_string(),
+                expr: expr.clone(),
+                matched: match val {
+                    crate::types::LiteralValue::Null => None,
+                    val => Some(crate::compiler::to_bool(val)),
+                },
+            })
+            .collect();
+        Ok((matched, Explanation { conditions }))
+    }
+    /// Execute the filter against each context in turn, reusing one evaluation stack.
+    pub fn execute_batch(
+        &self,
+        contexts: &[crate::context::FilterContext],
+    ) -> Vec<Result<bool, crate::WirerustError>> {
+        let mut evaluator = Evaluator::new();
+        contexts
+            .iter()
+            .map(|ctx| evaluator.eval(self, ctx))
+            .collect()
     }
     /// Get a reference to the schema used by this filter.
     pub fn schema(&self) -> &crate::schema::FilterSchema {
-        &self.ir.schema
+        &self.schema
     }
     /// Get a reference to the function registry used by this filter.
     pub fn functions(&self) -> &crate::functions::FunctionRegistry {
-        &self.ir.functions
+        &self.functions
+    }
+
+    #[cfg(feature = "json")]
+    fn ir(&self) -> Result<&crate::compiler::IrCompiledFilter, crate::WirerustError> {
+        self.inner.as_ir().ok_or_else(|| {
+            crate::WirerustError::Other("This filter's backend does not produce bytecode".into())
+        })
+    }
+}
+
+// The loaded fields a context has to set itself, as opposed to falling back to a default.
+fn required_fields(
+    loaded: &[crate::ir::FieldId],
+    schema: &crate::schema::FilterSchema,
+) -> crate::context::FieldBitSet {
+    loaded
+        .iter()
+        .copied()
+        .filter(|&fid| schema.default_by_id(fid).is_none())
+        .collect()
+}
+
+/// Reusable execution state for running filters in a hot loop without allocating a new
+/// evaluation stack per call. Not shared between threads; keep one per worker.
+#[derive(Debug, Default)]
+pub struct Evaluator {
+    stack: crate::ir::IrStack,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Execute `filter` against `context`, reusing this evaluator's stack buffer.
+    pub fn eval(
+        &mut self,
+        filter: &CompiledFilter,
+        context: &crate::context::FilterContext,
+    ) -> Result<bool, crate::WirerustError> {
+        filter.execute_with_stack(context, &mut self.stack)
     }
 }
 
@@ -66,43 +448,300 @@ mod tests {
     #[test]
     fn test_compiled_filter_execute_true() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Int(42))),
         };
         let filter =
-            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(filter.execute(&context()).unwrap());
     }
 
     #[test]
     fn test_compiled_filter_execute_false() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Int(0))),
         };
         let filter =
-            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         assert!(!filter.execute(&context()).unwrap());
     }
 
+    #[test]
+    fn test_compiled_filter_evaluate_returns_value() {
+        let filter = CompiledFilter::new(
+            FilterExpr::Field("foo".into()),
+            Arc::new(schema()),
+            Arc::new(FunctionRegistry::new()),
+        )
+        .unwrap();
+        assert_eq!(filter.evaluate(&context()).unwrap(), LiteralValue::Int(42));
+        assert!(filter.execute(&context()).unwrap());
+        // Missing fields load as false unless three-valued logic is enabled
+        assert_eq!(
+            filter.evaluate(&FilterContext::new()).unwrap(),
+            LiteralValue::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_compiled_filter_standalone() {
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let filter =
+            CompiledFilter::standalone(r#"foo == 42 && upper(bar) == "BAZ""#, schema(), functions)
+                .unwrap();
+        assert!(filter.execute(&context()).unwrap());
+        assert!(matches!(
+            CompiledFilter::standalone("foo ==", schema(), FunctionRegistry::new()),
+            Err(crate::WirerustError::ParseError { .. })
+        ));
+        // The handle is self-contained and can move to another thread on its own
+        let handle = std::thread::spawn(move || filter.execute(&context()).unwrap());
+        assert!(handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_compiled_filter_execute_checked() {
+        let sch = Arc::new(
+            FilterSchemaBuilder::new()
+                .field("foo", FieldType::Int)
+                .field("bar", FieldType::Bytes)
+                .field_with_default("port", FieldType::Int, LiteralValue::Int(80))
+                .unwrap()
+                .build(),
+        );
+        let compile = |src: &str| {
+            let expr = crate::expr::FilterParser::parse(src, &sch).unwrap();
+            CompiledFilter::new(expr, Arc::clone(&sch), Arc::new(FunctionRegistry::new())).unwrap()
+        };
+        let mut ctx = FilterContext::new();
+        ctx.set("foo", LiteralValue::Int(42), &sch).unwrap();
+        assert_eq!(
+            compile("foo == 42").execute_checked(&ctx).unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            compile("foo == 1").execute_checked(&ctx).unwrap(),
+            Some(false)
+        );
+        // No short-circuit: the missing field counts even though `foo` decides the result
+        let either = compile(r#"foo == 42 || bar == "x""#);
+        assert!(either.execute(&ctx).unwrap());
+        assert_eq!(either.execute_checked(&ctx).unwrap(), None);
+        assert!(!either.required_fields_present(&ctx));
+        assert!(compile("foo == 42 && port == 80").required_fields_present(&ctx));
+        // Defaults and `exists` checks don't make a field missing
+        assert_eq!(
+            compile("port == 80 && not exists bar")
+                .execute_checked(&ctx)
+                .unwrap(),
+            Some(true)
+        );
+    }
+
     #[test]
     fn test_compiled_filter_schema_access() {
         let expr = FilterExpr::Comparison {
-            left: Box::new(FilterExpr::Value(LiteralValue::Bytes(Arc::new(
-                b"foo".to_vec(),
-            )))),
+            left: Box::new(FilterExpr::Field("foo".into())),
             op: ComparisonOp::Eq,
             right: Box::new(FilterExpr::Value(LiteralValue::Int(42))),
         };
         let filter =
-            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()));
+            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
         let sch = filter.schema();
         assert_eq!(sch.get_field_type("foo"), Some(&FieldType::Int));
     }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_compiled_filter_bytes_roundtrip() {
+        let sch = Arc::new(schema());
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let functions = Arc::new(functions);
+        let expr =
+            crate::expr::FilterParser::parse("foo == 42 && upper(bar) == \"BAZ\"", &sch).unwrap();
+        let filter = CompiledFilter::new(expr, Arc::clone(&sch), Arc::clone(&functions)).unwrap();
+        let bytes = filter.to_bytes().unwrap();
+        let restored = CompiledFilter::from_bytes(&bytes, sch, functions).unwrap();
+        assert_eq!(
+            filter.ir().unwrap().bytecode,
+            restored.ir().unwrap().bytecode
+        );
+        assert!(restored.execute(&context()).unwrap());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_compiled_filter_from_bytes_rejects_changed_schema() {
+        let expr = crate::expr::FilterParser::parse("foo == 42", &schema()).unwrap();
+        let filter =
+            CompiledFilter::new(expr, Arc::new(schema()), Arc::new(FunctionRegistry::new()))
+                .unwrap();
+        let bytes = filter.to_bytes().unwrap();
+        // Without "bar", "foo" is reassigned ID 0 and the serialized ID no longer resolves
+        let changed = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .build();
+        let result = CompiledFilter::from_bytes(
+            &bytes,
+            Arc::new(changed),
+            Arc::new(FunctionRegistry::new()),
+        );
+        assert!(matches!(
+            result,
+            Err(crate::WirerustError::FieldNotFound(_))
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_compiled_filter_from_bytes_rejects_invalid_bytecode() {
+        let sch = Arc::new(schema());
+        let expr = crate::expr::FilterParser::parse("foo == 42", &sch).unwrap();
+        let filter =
+            CompiledFilter::new(expr, Arc::clone(&sch), Arc::new(FunctionRegistry::new())).unwrap();
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&filter.to_bytes().unwrap()).unwrap();
+        for bytecode in [
+            r#"["CompareEq"]"#,
+            r#"[]"#,
+            r#"["LoadElement", "LoadElement"]"#,
+            r#"["LoadElement", {"Quantify": {"all": true, "body": ["LogicalNot"]}}]"#,
+            r#"["LoadElement", {"Quantify": {"all": true, "body": []}}]"#,
+        ] {
+            json["bytecode"] = serde_json::from_str(bytecode).unwrap();
+            let result = CompiledFilter::from_bytes(
+                &serde_json::to_vec(&json).unwrap(),
+                Arc::clone(&sch),
+                Arc::new(FunctionRegistry::new()),
+            );
+            assert!(
+                matches!(result, Err(crate::WirerustError::Other(_))),
+                "{bytecode}"
+            );
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_compiled_filter_from_bytes_with_pinned_ids() {
+        let pinned = || {
+            FilterSchemaBuilder::new()
+                .field_with_id("foo", FieldType::Int, 0)
+                .unwrap()
+        };
+        let sch = pinned().build();
+        let expr = crate::expr::FilterParser::parse("foo == 42", &sch).unwrap();
+        let filter =
+            CompiledFilter::new(expr, Arc::new(sch), Arc::new(FunctionRegistry::new())).unwrap();
+        let bytes = filter.to_bytes().unwrap();
+        // "alpha" sorts before "foo" but can't take its pinned ID
+        let edited = Arc::new(pinned().field("alpha", FieldType::Bytes).build());
+        let restored = CompiledFilter::from_bytes(
+            &bytes,
+            Arc::clone(&edited),
+            Arc::new(FunctionRegistry::new()),
+        )
+        .unwrap();
+        let mut ctx = FilterContext::new();
+        ctx.set("foo", LiteralValue::Int(42), &edited).unwrap();
+        assert!(restored.execute(&ctx).unwrap());
+    }
+
+    #[test]
+    fn test_compiled_filter_referenced_fields() {
+        let sch = Arc::new(schema());
+        let mut functions = FunctionRegistry::new();
+        crate::functions::register_builtins(&mut functions);
+        let expr = crate::expr::FilterParser::parse(
+            "upper(bar) == \"BAZ\" && (foo > 1 || exists bar) && lower(bar) == \"baz\"",
+            &sch,
+        )
+        .unwrap();
+        let filter = CompiledFilter::new(expr, sch, Arc::new(functions)).unwrap();
+        assert_eq!(filter.referenced_fields(), Some(vec!["bar", "foo"]));
+        assert_eq!(filter.referenced_functions(), Some(vec!["upper", "lower"]));
+        assert_eq!(filter.has_conditional_function_calls(), Some(false));
+    }
+
+    #[test]
+    fn test_compiled_filter_execute_batch() {
+        let sch = Arc::new(schema());
+        let expr = crate::expr::FilterParser::parse("10 / foo == 0 && foo > 40", &sch).unwrap();
+        let filter =
+            CompiledFilter::new(expr, sch.clone(), Arc::new(FunctionRegistry::new())).unwrap();
+        let mut low = FilterContext::new();
+        low.set("foo", LiteralValue::Int(0), &sch).unwrap();
+        let results = filter.execute_batch(&[context(), low, context()]);
+        assert!(matches!(results[0], Ok(true)));
+        // A division by zero mid-run must not leave stale values behind for the next context
+        assert!(results[1].is_err());
+        assert!(matches!(results[2], Ok(true)));
+
+        let mut evaluator = Evaluator::new();
+        assert!(evaluator.eval(&filter, &context()).unwrap());
+    }
+
+    #[test]
+    fn test_compiled_filter_execute_explain() {
+        let sch = Arc::new(schema());
+        let compile = |src: &str, explain: bool| {
+            let expr = crate::expr::FilterParser::parse(src, &sch).unwrap();
+            let options = crate::compiler::EngineOptions {
+                explain,
+                ..Default::default()
+            };
+            CompiledFilter::with_options(
+                expr,
+                sch.clone(),
+                Arc::new(FunctionRegistry::new()),
+                options,
+            )
+            .unwrap()
+        };
+        let filter = compile(
+            r#"foo == 42 and (bar == "x" or not bar contains "z")"#,
+            true,
+        );
+        let (matched, explanation) = filter
+            .execute_explain(&context(), &ExecutionLimits::default(), &())
+            .unwrap();
+        assert!(!matched);
+        let results: Vec<_> = explanation
+            .conditions
+            .iter()
+            .map(|c| (c.condition.as_str(), c.matched))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("foo == 42", Some(true)),
+                (r#"bar == "x""#, Some(false)),
+                (r#"bar contains "z""#, Some(true)),
+            ]
+        );
+        let matched: Vec<_> = explanation
+            .matched()
+            .map(|c| c.condition.as_str())
+            .collect();
+        assert_eq!(matched, ["foo == 42", r#"bar contains "z""#]);
+
+        // Conditions that don't decide the result are still reported
+        let (matched, explanation) = compile("foo == 1 and bar == \"baz\"", true)
+            .execute_explain(&context(), &ExecutionLimits::default(), &())
+            .unwrap();
+        assert!(!matched);
+        assert_eq!(explanation.matched().count(), 1);
+
+        assert!(compile("foo == 42", false)
+            .execute_explain(&context(), &ExecutionLimits::default(), &())
+            .is_err());
+    }
 }
@@ -2,31 +2,181 @@
 //!
 //! This module provides traits and registries for filter functions.
 
-use crate::types::LiteralValue;
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::expr::COMPARISON_OPERATORS;
+use crate::types::{FieldType, LiteralValue};
+use crate::WirerustError;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 
 pub trait FilterFunction: Send + Sync {
     fn call(&self, args: &[LiteralValue]) -> Option<LiteralValue>;
+    /// Fallible call used by the engine. Override to report why a call failed; errors are
+    /// returned from `execute` unchanged. The default wraps `call`, turning `None` into a
+    /// generic `FunctionError`.
+    fn try_call(&self, args: &[LiteralValue]) -> Result<LiteralValue, WirerustError> {
+        self.call(args)
+            .ok_or_else(|| WirerustError::FunctionError("Function call returned no value".into()))
+    }
+    /// Call with the request-scoped data passed to `execute_with_data` (or `&()` for a
+    /// plain `execute`). Downcast it with `data.downcast_ref::<T>()`. Defaults to `try_call`.
+    fn call_with_data(
+        &self,
+        args: &[LiteralValue],
+        _data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        self.try_call(args)
+    }
+    /// Declared argument and return types, checked by the compiler. `None` skips validation.
+    fn signature(&self) -> Option<FunctionSignature> {
+        None
+    }
+}
+
+/// A filter function that keeps state between calls, such as the counters behind a rate
+/// limit like `rate(ip.src) > 100`. Register it with
+/// [`FunctionRegistry::register_stateful`].
+///
+/// The registry puts each stateful function behind its own mutex, so filters calling it
+/// can run on any number of threads: calls to one function are serialized and never
+/// overlap, while different functions lock independently. Keep calls short, since
+/// concurrent executions wait on the lock. If a call panics, the lock is poisoned and
+/// later calls fail with a `FunctionError` rather than see half-updated state.
+///
+/// `and`/`or` don't short-circuit, so every call in the filter runs, and changes the
+/// state, on every execution; only the body of an `any`/`all` stops at the first element
+/// that decides it.
+pub trait StatefulFunction: Send {
+    /// Call the function, with the request-scoped data passed to `execute_with_data` (or
+    /// `&()` for a plain `execute`).
+    fn call_mut(
+        &mut self,
+        args: &[LiteralValue],
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError>;
+    /// Declared argument and return types, checked by the compiler. `None` skips validation.
+    fn signature(&self) -> Option<FunctionSignature> {
+        None
+    }
 }
 
+// Adapts a stateful function to the shared `FilterFunction` interface
+struct Stateful<F>(Mutex<F>);
+
+impl<F: StatefulFunction> FilterFunction for Stateful<F> {
+    fn call(&self, args: &[LiteralValue]) -> Option<LiteralValue> {
+        self.try_call(args).ok()
+    }
+    fn try_call(&self, args: &[LiteralValue]) -> Result<LiteralValue, WirerustError> {
+        self.call_with_data(args, &())
+    }
+    fn call_with_data(
+        &self,
+        args: &[LiteralValue],
+        data: &dyn Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        let mut func = self.0.lock().map_err(|_| {
+            WirerustError::FunctionError("Stateful function panicked in an earlier call".into())
+        })?;
+        func.call_mut(args, data)
+    }
+    fn signature(&self) -> Option<FunctionSignature> {
+        self.0.lock().ok()?.signature()
+    }
+}
+
+/// Expected arguments of a filter function, and the type it returns. `FieldType::Unknown`
+/// accepts any argument type, and as the return type leaves results unchecked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FunctionSignature {
+    pub args: Vec<FieldType>,
+    /// Type of any further arguments after `args`; `None` means the arity is exact.
+    pub variadic: Option<FieldType>,
+    /// Declared type of the result, checked against where the call is used.
+    pub returns: FieldType,
+}
+
+impl FunctionSignature {
+    pub fn new(args: Vec<FieldType>) -> Self {
+        Self {
+            args,
+            variadic: None,
+            returns: FieldType::Unknown,
+        }
+    }
+    /// Declare the type of the function's result.
+    pub fn with_returns(mut self, ty: FieldType) -> Self {
+        self.returns = ty;
+        self
+    }
+    /// Accept any number of extra arguments of type `rest` after the fixed ones.
+    pub fn with_variadic(mut self, rest: FieldType) -> Self {
+        self.variadic = Some(rest);
+        self
+    }
+    /// Whether a value of type `actual` may be passed where `expected` is declared.
+    /// Unknown on either side (e.g. an untyped function result) is accepted, and `Enum`
+    /// values pass as bytes.
+    pub fn accepts(expected: &FieldType, actual: &FieldType) -> bool {
+        match (expected, actual) {
+            (FieldType::Unknown, _) | (_, FieldType::Unknown) => true,
+            (FieldType::Bytes, FieldType::Enum(_)) | (FieldType::Enum(_), FieldType::Bytes) => true,
+            (FieldType::Array(e), FieldType::Array(a))
+            | (FieldType::Map(e), FieldType::Map(a))
+            | (FieldType::Set(e), FieldType::Set(a)) => Self::accepts(e, a),
+            _ => expected == actual,
+        }
+    }
+}
+
+/// A custom infix comparison registered with [`FunctionRegistry::register_operator`].
+pub type OperatorFn = Arc<dyn Fn(&LiteralValue, &LiteralValue) -> bool + Send + Sync>;
+
+// Words the parser already gives a meaning between or before operands, which a custom
+// operator can't take
+const RESERVED_WORDS: &[&str] = &[
+    "and", "or", "not", "strict", "exists", "any", "all", "true", "false", "null",
+];
+
 #[derive(Default)]
 pub struct FunctionRegistry {
     functions: HashMap<String, Arc<dyn FilterFunction>>,
     function_names: Vec<String>,          // index = FunctionId
     function_ids: HashMap<String, usize>, // name -> id
+    // Names currently bound to the stock builtin, which the engine may run on its fast path
+    builtins: HashSet<String>,
+    operators: Vec<(String, OperatorFn)>, // index = OperatorId
 }
 
 impl FunctionRegistry {
     pub fn new() -> Self {
         Self::default()
     }
-    /// Register a function and assign it a unique ID if not already present.
+    /// Register a function and assign it a unique ID if not already present. Registering
+    /// under a builtin's name (such as `len`) overrides the builtin.
     pub fn register<F>(&mut self, name: impl Into<String>, func: F)
     where
         F: FilterFunction + 'static,
     {
         let name = name.into();
+        self.builtins.remove(&name);
+        self.insert(name, func);
+    }
+    // Register one of the stock builtins, which the engine may then call directly.
+    fn register_builtin<F>(&mut self, name: &str, func: F)
+    where
+        F: FilterFunction + 'static,
+    {
+        self.builtins.insert(name.to_string());
+        self.insert(name.to_string(), func);
+    }
+    fn insert<F>(&mut self, name: String, func: F)
+    where
+        F: FilterFunction + 'static,
+    {
         if !self.function_ids.contains_key(&name) {
             self.function_ids
                 .insert(name.clone(), self.function_names.len());
@@ -34,6 +184,14 @@ impl FunctionRegistry {
         }
         self.functions.insert(name, Arc::new(func));
     }
+    /// Register a function that keeps state between calls; see [`StatefulFunction`] for
+    /// how calls are locked.
+    pub fn register_stateful<F>(&mut self, name: impl Into<String>, func: F)
+    where
+        F: StatefulFunction + 'static,
+    {
+        self.register(name, Stateful(Mutex::new(func)));
+    }
     /// Register a closure as a filter function.
     pub fn register_fn<F>(&mut self, name: impl Into<String>, func: F)
     where
@@ -50,6 +208,35 @@ impl FunctionRegistry {
         }
         self.register(name, ClosureFn(func));
     }
+    /// Register a closure with typed arguments, e.g. `|a: i64, b: i64| a > b`.
+    ///
+    /// Arguments are converted from `LiteralValue` via [`FromLiteral`] and the call yields
+    /// `None` when the count or any type doesn't match. The declared argument types become
+    /// the function's [`FunctionSignature`] along with the return type, so mismatches are
+    /// also caught at compile time.
+    /// Closures of up to four arguments are supported. Argument and return types are
+    /// `i64`, `bool`, `Vec<u8>`, `String`, `IpAddr` and `LiteralValue` (any type); there is
+    /// no float filter type, so `f64` is not available.
+    pub fn register_typed<Args, F>(&mut self, name: impl Into<String>, func: F)
+    where
+        Args: 'static,
+        F: TypedFunction<Args>,
+    {
+        struct TypedFn<F, Args>(F, PhantomData<fn() -> Args>);
+        impl<F, Args> FilterFunction for TypedFn<F, Args>
+        where
+            Args: 'static,
+            F: TypedFunction<Args>,
+        {
+            fn call(&self, args: &[LiteralValue]) -> Option<LiteralValue> {
+                self.0.call_typed(args)
+            }
+            fn signature(&self) -> Option<FunctionSignature> {
+                Some(FunctionSignature::new(F::arg_types()).with_returns(F::return_type()))
+            }
+        }
+        self.register(name, TypedFn(func, PhantomData));
+    }
     /// Get the function ID for a given function name, if it exists.
     pub fn function_id(&self, name: &str) -> Option<usize> {
         self.function_ids.get(name).copied()
@@ -58,10 +245,69 @@ impl FunctionRegistry {
     pub fn function_name(&self, id: usize) -> Option<&str> {
         self.function_names.get(id).map(|s| s.as_str())
     }
+    /// Whether `name` is a builtin's name bound to something other than that builtin, i.e.
+    /// a user function registered under it. Calls to it then run the user's function.
+    pub fn is_builtin_overridden(&self, name: &str) -> bool {
+        BuiltinFunctionId::from_name(name).is_some() && !self.builtins.contains(name)
+    }
     /// Get the total number of functions.
     pub fn num_functions(&self) -> usize {
         self.function_names.len()
     }
+    /// Register a custom infix comparison operator, written `left keyword right` in
+    /// filters, such as `version semver_satisfies ">=1.2"`. The keyword must be an
+    /// identifier and may not be a built-in operator or keyword (`==`, `in`, `contains`,
+    /// `and`, `not`, ...). Registering a keyword again replaces its operator.
+    pub fn register_operator<F>(
+        &mut self,
+        keyword: impl Into<String>,
+        op: F,
+    ) -> Result<(), WirerustError>
+    where
+        F: Fn(&LiteralValue, &LiteralValue) -> bool + Send + Sync + 'static,
+    {
+        let keyword = keyword.into();
+        let mut chars = keyword.chars();
+        let is_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_');
+        if !is_identifier {
+            return Err(WirerustError::Other(format!(
+                "Operator keyword '{keyword}' is not an identifier"
+            )));
+        }
+        let builtin = COMPARISON_OPERATORS
+            .iter()
+            .flat_map(|(token, _)| token.split(' '))
+            .chain(RESERVED_WORDS.iter().copied())
+            .any(|word| word == keyword);
+        if builtin {
+            return Err(WirerustError::Other(format!(
+                "Operator keyword '{keyword}' collides with a built-in operator"
+            )));
+        }
+        let op: OperatorFn = Arc::new(op);
+        match self.operator_id(&keyword) {
+            Some(id) => self.operators[id].1 = op,
+            None => self.operators.push((keyword, op)),
+        }
+        Ok(())
+    }
+    /// Get the ID of the custom operator registered under `keyword`, if any.
+    pub fn operator_id(&self, keyword: &str) -> Option<usize> {
+        self.operators.iter().position(|(k, _)| k == keyword)
+    }
+    /// Get the keyword of a custom operator by ID.
+    pub fn operator_name(&self, id: usize) -> Option<&str> {
+        self.operators.get(id).map(|(k, _)| k.as_str())
+    }
+    /// Get a custom operator by ID.
+    pub fn get_operator(&self, id: usize) -> Option<&OperatorFn> {
+        self.operators.get(id).map(|(_, op)| op)
+    }
+    /// The keywords of all custom operators, in registration order.
+    pub fn operator_names(&self) -> impl Iterator<Item = &str> {
+        self.operators.iter().map(|(k, _)| k.as_str())
+    }
     pub fn get(&self, name: &str) -> Option<&Arc<dyn FilterFunction>> {
         self.functions.get(name)
     }
@@ -73,55 +319,164 @@ impl FunctionRegistry {
     }
 }
 
+/// A typed argument of a function registered with [`FunctionRegistry::register_typed`].
+pub trait FromLiteral: Sized {
+    /// The filter type this argument is declared as.
+    fn field_type() -> FieldType;
+    fn from_literal(value: &LiteralValue) -> Option<Self>;
+}
+
+/// A return value of a function registered with [`FunctionRegistry::register_typed`].
+/// Returning `Option<T>` lets the function signal failure with `None`.
+pub trait IntoFunctionResult {
+    /// The filter type of the result, declared as the function's return type. Defaults
+    /// to `Unknown`, which leaves it unchecked.
+    fn field_type() -> FieldType {
+        FieldType::Unknown
+    }
+    fn into_function_result(self) -> Option<LiteralValue>;
+}
+
+macro_rules! typed_values {
+    ($( $ty:ty, $field_ty:expr, |$v:ident| $from:expr, |$o:ident| $into:expr; )*) => {
+        $(
+            impl FromLiteral for $ty {
+                fn field_type() -> FieldType {
+                    $field_ty
+                }
+                fn from_literal($v: &LiteralValue) -> Option<Self> {
+                    $from
+                }
+            }
+            impl IntoFunctionResult for $ty {
+                fn field_type() -> FieldType {
+                    $field_ty
+                }
+                fn into_function_result(self) -> Option<LiteralValue> {
+                    let $o = self;
+                    Some($into)
+                }
+            }
+            impl IntoFunctionResult for Option<$ty> {
+                fn field_type() -> FieldType {
+                    $field_ty
+                }
+                fn into_function_result(self) -> Option<LiteralValue> {
+                    self.and_then(IntoFunctionResult::into_function_result)
+                }
+            }
+        )*
+    };
+}
+
+typed_values! {
+    i64, FieldType::Int,
+        |v| match v { LiteralValue::Int(i) => Some(*i), _ => None },
+        |o| LiteralValue::Int(o);
+    bool, FieldType::Bool,
+        |v| match v { LiteralValue::Bool(b) => Some(*b), _ => None },
+        |o| LiteralValue::Bool(o);
+    Vec<u8>, FieldType::Bytes,
+        |v| match v { LiteralValue::Bytes(b) => Some(b.to_vec()), _ => None },
+        |o| LiteralValue::Bytes(Arc::new(o));
+    String, FieldType::Bytes,
+        |v| match v { LiteralValue::Bytes(b) => String::from_utf8(b.to_vec()).ok(), _ => None },
+        |o| LiteralValue::Bytes(Arc::new(o.into_bytes()));
+    IpAddr, FieldType::Ip,
+        |v| match v { LiteralValue::Ip(ip) => Some(*ip), _ => None },
+        |o| LiteralValue::Ip(o);
+    LiteralValue, FieldType::Unknown,
+        |v| Some(v.clone()),
+        |o| o;
+}
+
+/// Closures usable with [`FunctionRegistry::register_typed`]; implemented for
+/// `Fn(A, ..) -> R` of up to four [`FromLiteral`] arguments.
+pub trait TypedFunction<Args>: Send + Sync + 'static {
+    fn call_typed(&self, args: &[LiteralValue]) -> Option<LiteralValue>;
+    fn arg_types() -> Vec<FieldType>;
+    fn return_type() -> FieldType;
+}
+
+macro_rules! typed_function_arity {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> TypedFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: IntoFunctionResult,
+            $($arg: FromLiteral,)*
+        {
+            #[allow(non_snake_case)]
+            fn call_typed(&self, args: &[LiteralValue]) -> Option<LiteralValue> {
+                let [$($arg),*] = args else {
+                    return None;
+                };
+                $(let $arg = $arg::from_literal($arg)?;)*
+                self($($arg),*).into_function_result()
+            }
+            fn arg_types() -> Vec<FieldType> {
+                vec![$($arg::field_type()),*]
+            }
+            fn return_type() -> FieldType {
+                R::field_type()
+            }
+        }
+    };
+}
+
+typed_function_arity!();
+typed_function_arity!(A);
+typed_function_arity!(A, B);
+typed_function_arity!(A, B, C);
+typed_function_arity!(A, B, C, D);
+
 impl Clone for FunctionRegistry {
     fn clone(&self) -> Self {
         Self {
             functions: self.functions.clone(),
             function_names: self.function_names.clone(),
             function_ids: self.function_ids.clone(),
+            builtins: self.builtins.clone(),
+            operators: self.operators.clone(),
         }
     }
 }
 
+// Argument types are listed in parentheses, followed by the return type; a trailing `; Ty`
+// makes the function variadic with any further arguments of type `Ty`. Attributes such as
+// `#[cfg(..)]` apply to the function's struct, impl and registration.
 macro_rules! builtin_functions {
-    ($( $name:ident: $func_name:expr, $args:ident => $body:block ),* $(,)?) => {
+    ($( $(#[$attr:meta])* $name:ident: $func_name:expr, ($($arg_ty:expr),* $(; $rest_ty:expr)?) -> $ret_ty:expr, $args:ident => $body:block ),* $(,)?) => {
         $(
+            $(#[$attr])*
             pub struct $name;
+            $(#[$attr])*
             impl FilterFunction for $name {
                 fn call(&self, $args: &[LiteralValue]) -> Option<LiteralValue> $body
+                fn signature(&self) -> Option<FunctionSignature> {
+                    let sig = FunctionSignature::new(vec![$($arg_ty),*]).with_returns($ret_ty);
+                    $(let sig = sig.with_variadic($rest_ty);)?
+                    Some(sig)
+                }
             }
         )*
         pub fn register_builtins(reg: &mut FunctionRegistry) {
-            $(reg.register($func_name, $name);)*
+            $($(#[$attr])* reg.register_builtin($func_name, $name);)*
         }
     };
 }
 
 builtin_functions! {
-    LenFunction: "len", args => {
-        if let Some(LiteralValue::Array(arr)) = args.first() {
-            Some(LiteralValue::Int(arr.len() as i64))
-        } else {
-            None
-        }
+    LenFunction: "len", (FieldType::Unknown) -> FieldType::Int, args => {
+        len_value(args)
     },
-    UpperFunction: "upper", args => {
-        if let Some(LiteralValue::Bytes(bytes)) = args.first() {
-            let s = String::from_utf8_lossy(bytes).to_uppercase();
-            Some(LiteralValue::Bytes(Arc::new(s.into_bytes())))
-        } else {
-            None
-        }
+    UpperFunction: "upper", (FieldType::Bytes) -> FieldType::Bytes, args => {
+        change_case(args, Case::Upper)
     },
-    LowerFunction: "lower", args => {
-        if let Some(LiteralValue::Bytes(bytes)) = args.first() {
-            let s = String::from_utf8_lossy(bytes).to_lowercase();
-            Some(LiteralValue::Bytes(Arc::new(s.into_bytes())))
-        } else {
-            None
-        }
+    LowerFunction: "lower", (FieldType::Bytes) -> FieldType::Bytes, args => {
+        change_case(args, Case::Lower)
     },
-    SumFunction: "sum", args => {
+    SumFunction: "sum", (FieldType::Array(Box::new(FieldType::Int))) -> FieldType::Int, args => {
         if let Some(LiteralValue::Array(arr)) = args.first() {
             let sum: i64 = arr.iter().filter_map(|v| if let LiteralValue::Int(i) = v { Some(*i) } else { None }).sum();
             Some(LiteralValue::Int(sum))
@@ -129,24 +484,375 @@ builtin_functions! {
             None
         }
     },
-    StartsWithFunction: "starts_with", args => {
-        if let (Some(LiteralValue::Bytes(haystack)), Some(LiteralValue::Bytes(prefix))) = (args.first(), args.get(1)) {
-            let h = String::from_utf8_lossy(haystack);
-            let p = String::from_utf8_lossy(prefix);
-            Some(LiteralValue::Bool(h.starts_with(&*p)))
-        } else {
-            None
-        }
+    StartsWithFunction: "starts_with", (FieldType::Bytes, FieldType::Unknown) -> FieldType::Bool, args => {
+        affix_match(args, Affix::Prefix, false)
+    },
+    EndsWithFunction: "ends_with", (FieldType::Bytes, FieldType::Unknown) -> FieldType::Bool, args => {
+        affix_match(args, Affix::Suffix, false)
+    },
+    StartsWithCiFunction: "starts_with_ci", (FieldType::Bytes, FieldType::Unknown) -> FieldType::Bool, args => {
+        affix_match(args, Affix::Prefix, true)
+    },
+    EndsWithCiFunction: "ends_with_ci", (FieldType::Bytes, FieldType::Unknown) -> FieldType::Bool, args => {
+        affix_match(args, Affix::Suffix, true)
+    },
+    MinFunction: "min", (FieldType::Array(Box::new(FieldType::Int))) -> FieldType::Int, args => {
+        int_elements(args)?.min().map(LiteralValue::Int)
+    },
+    MaxFunction: "max", (FieldType::Array(Box::new(FieldType::Int))) -> FieldType::Int, args => {
+        int_elements(args)?.max().map(LiteralValue::Int)
+    },
+    AvgFunction: "avg", (FieldType::Array(Box::new(FieldType::Int))) -> FieldType::Int, args => {
+        avg_ints(args)
+    },
+    ConcatFunction: "concat", (FieldType::Bytes, FieldType::Bytes; FieldType::Bytes) -> FieldType::Bytes, args => {
+        concat_bytes(args)
+    },
+    SubstringFunction: "substring", (FieldType::Bytes, FieldType::Int, FieldType::Int) -> FieldType::Bytes, args => {
+        substring_bytes(args)
+    },
+    TrimFunction: "trim", (FieldType::Bytes) -> FieldType::Bytes, args => {
+        trim_bytes(args)
+    },
+    ReplaceFunction: "replace", (FieldType::Bytes, FieldType::Bytes, FieldType::Bytes) -> FieldType::Bytes, args => {
+        replace_bytes(args)
+    },
+    IndexOfFunction: "index_of", (FieldType::Bytes, FieldType::Bytes) -> FieldType::Int, args => {
+        index_of_bytes(args)
+    },
+    CharAtFunction: "char_at", (FieldType::Bytes, FieldType::Int) -> FieldType::Bytes, args => {
+        char_at_bytes(args)
+    },
+    ToIntFunction: "to_int", (FieldType::Bytes) -> FieldType::Int, args => {
+        parse_int_bytes(args)
+    },
+    ToBytesFunction: "to_bytes", (FieldType::Int) -> FieldType::Bytes, args => {
+        render_int(args)
     },
-    EndsWithFunction: "ends_with", args => {
-        if let (Some(LiteralValue::Bytes(haystack)), Some(LiteralValue::Bytes(suffix))) = (args.first(), args.get(1)) {
-            let h = String::from_utf8_lossy(haystack);
-            let s = String::from_utf8_lossy(suffix);
-            Some(LiteralValue::Bool(h.ends_with(&*s)))
+    ToBoolFunction: "to_bool", (FieldType::Unknown) -> FieldType::Bool, args => {
+        coerce_bool(args)
+    },
+    #[cfg(feature = "regex")]
+    RegexCaptureFunction: "regex_capture", (FieldType::Bytes, FieldType::Bytes, FieldType::Int) -> FieldType::Bytes, args => {
+        regex_capture(args)
+    },
+    #[cfg(feature = "regex")]
+    MatchesAnyFunction: "matches_any", (FieldType::Bytes, FieldType::Array(Box::new(FieldType::Bytes))) -> FieldType::Bool, args => {
+        matches_any(args)
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+// `upper`/`lower`: the full Unicode mapping for UTF-8 text (so `ß` uppercases to `SS`),
+// and ASCII-only for other bytes, which are otherwise kept as they are.
+fn change_case(args: &[LiteralValue], case: Case) -> Option<LiteralValue> {
+    let Some(LiteralValue::Bytes(bytes)) = args.first() else {
+        return None;
+    };
+    let out = match (std::str::from_utf8(bytes), case) {
+        (Ok(s), Case::Upper) => s.to_uppercase().into_bytes(),
+        (Ok(s), Case::Lower) => s.to_lowercase().into_bytes(),
+        (Err(_), Case::Upper) => bytes.to_ascii_uppercase(),
+        (Err(_), Case::Lower) => bytes.to_ascii_lowercase(),
+    };
+    Some(LiteralValue::Bytes(Arc::new(out)))
+}
+
+/// Fold case for case-insensitive matching. UTF-8 text is lowercased, uppercased and
+/// lowercased again, approximating Unicode full case folding: `ß`, `ẞ` and `SS` all fold
+/// to `ss`, and `İ` to `i̇` (so it doesn't match a plain `i`). Other bytes fold ASCII
+/// letters only.
+pub(crate) fn fold_case(bytes: &[u8]) -> Vec<u8> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_lowercase().to_uppercase().to_lowercase().into_bytes(),
+        Err(_) => bytes.to_ascii_lowercase(),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Affix {
+    Prefix,
+    Suffix,
+}
+
+// `starts_with`/`ends_with` and their `_ci` forms. The second argument is one Bytes
+// candidate or an array of them, matching when any does; `ci` folds ASCII case.
+fn affix_match(args: &[LiteralValue], affix: Affix, ci: bool) -> Option<LiteralValue> {
+    let Some(LiteralValue::Bytes(haystack)) = args.first() else {
+        return None;
+    };
+    let matches = |candidate: &[u8]| {
+        let Some(start) = haystack.len().checked_sub(candidate.len()) else {
+            return false;
+        };
+        let part = match affix {
+            Affix::Prefix => &haystack[..candidate.len()],
+            Affix::Suffix => &haystack[start..],
+        };
+        if ci {
+            part.eq_ignore_ascii_case(candidate)
         } else {
-            None
+            part == candidate
         }
-    },
+    };
+    match args.get(1)? {
+        LiteralValue::Bytes(candidate) => Some(LiteralValue::Bool(matches(candidate))),
+        LiteralValue::Array(candidates) => {
+            let mut any = false;
+            for candidate in candidates.iter() {
+                let LiteralValue::Bytes(candidate) = candidate else {
+                    return None;
+                };
+                any = any || matches(candidate);
+            }
+            Some(LiteralValue::Bool(any))
+        }
+        _ => None,
+    }
+}
+
+// Byte count of a Bytes value, or element/entry count of an Array, Set or Map.
+fn len_value(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let len = match args.first()? {
+        LiteralValue::Bytes(b) => b.len(),
+        LiteralValue::Array(arr) => arr.len(),
+        LiteralValue::Set(set) => set.len(),
+        LiteralValue::Map(map) => map.len(),
+        _ => return None,
+    };
+    Some(LiteralValue::Int(len as i64))
+}
+
+// The Int elements of an array argument, skipping other types as `sum` does.
+fn int_elements(args: &[LiteralValue]) -> Option<impl Iterator<Item = i64> + '_> {
+    if let Some(LiteralValue::Array(arr)) = args.first() {
+        Some(arr.iter().filter_map(|v| {
+            if let LiteralValue::Int(i) = v {
+                Some(*i)
+            } else {
+                None
+            }
+        }))
+    } else {
+        None
+    }
+}
+
+// Mean of the Int elements. There is no float type, so the result is rounded to the
+// nearest integer with halves rounded away from zero; `None` for an empty array.
+fn avg_ints(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let (sum, count) = int_elements(args)?.fold((0i128, 0i128), |(s, c), i| (s + i as i128, c + 1));
+    if count == 0 {
+        return None;
+    }
+    let rounded = (2 * sum + sum.signum() * count) / (2 * count);
+    Some(LiteralValue::Int(rounded as i64))
+}
+
+// Join all arguments, which must all be Bytes.
+fn concat_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let mut out = Vec::new();
+    for arg in args {
+        match arg {
+            LiteralValue::Bytes(bytes) => out.extend_from_slice(bytes),
+            _ => return None,
+        }
+    }
+    Some(LiteralValue::Bytes(Arc::new(out)))
+}
+
+// `len` bytes starting at byte offset `start`, clamped to the end of the input.
+fn substring_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    if let (
+        Some(LiteralValue::Bytes(bytes)),
+        Some(LiteralValue::Int(start)),
+        Some(LiteralValue::Int(len)),
+    ) = (args.first(), args.get(1), args.get(2))
+    {
+        let start = usize::try_from(*start).ok()?.min(bytes.len());
+        let len = usize::try_from(*len).ok()?;
+        let end = start.saturating_add(len).min(bytes.len());
+        Some(LiteralValue::Bytes(Arc::new(bytes[start..end].to_vec())))
+    } else {
+        None
+    }
+}
+
+// Strip leading and trailing ASCII whitespace.
+fn trim_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    if let Some(LiteralValue::Bytes(bytes)) = args.first() {
+        Some(LiteralValue::Bytes(Arc::new(bytes.trim_ascii().to_vec())))
+    } else {
+        None
+    }
+}
+
+// Replace every non-overlapping occurrence of `from` with `to`; an empty `from` is a no-op.
+fn replace_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    if let (
+        Some(LiteralValue::Bytes(haystack)),
+        Some(LiteralValue::Bytes(from)),
+        Some(LiteralValue::Bytes(to)),
+    ) = (args.first(), args.get(1), args.get(2))
+    {
+        if from.is_empty() {
+            return Some(LiteralValue::Bytes(Arc::clone(haystack)));
+        }
+        let mut out = Vec::with_capacity(haystack.len());
+        let mut i = 0;
+        while i < haystack.len() {
+            if haystack[i..].starts_with(from) {
+                out.extend_from_slice(to);
+                i += from.len();
+            } else {
+                out.push(haystack[i]);
+                i += 1;
+            }
+        }
+        Some(LiteralValue::Bytes(Arc::new(out)))
+    } else {
+        None
+    }
+}
+
+// Byte offset of the first occurrence of `needle` in `haystack`, or -1 if there is none.
+// An empty needle is found at offset 0.
+fn index_of_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    if let (Some(LiteralValue::Bytes(haystack)), Some(LiteralValue::Bytes(needle))) =
+        (args.first(), args.get(1))
+    {
+        let offset = if needle.is_empty() {
+            Some(0)
+        } else {
+            haystack
+                .windows(needle.len())
+                .position(|w| w == needle.as_slice())
+        };
+        Some(LiteralValue::Int(offset.map_or(-1, |i| i as i64)))
+    } else {
+        None
+    }
+}
+
+// The single byte at byte offset `index`, or empty bytes when it is out of range.
+fn char_at_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    if let (Some(LiteralValue::Bytes(bytes)), Some(LiteralValue::Int(index))) =
+        (args.first(), args.get(1))
+    {
+        let byte = usize::try_from(*index).ok().and_then(|i| bytes.get(i));
+        Some(LiteralValue::Bytes(Arc::new(
+            byte.into_iter().copied().collect(),
+        )))
+    } else {
+        None
+    }
+}
+
+// The decimal integer spelled by ASCII digits with an optional leading sign, or `None`
+// for anything else, including surrounding whitespace and out-of-range values.
+fn parse_int_bytes(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let [LiteralValue::Bytes(bytes)] = args else {
+        return None;
+    };
+    std::str::from_utf8(bytes)
+        .ok()?
+        .parse()
+        .ok()
+        .map(LiteralValue::Int)
+}
+
+// An integer rendered in decimal.
+fn render_int(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let [LiteralValue::Int(n)] = args else {
+        return None;
+    };
+    Some(LiteralValue::Bytes(Arc::new(n.to_string().into_bytes())))
+}
+
+// Bools as they are, integers as whether they're nonzero, and the strings `true`/`false`
+// (in any case) or `1`/`0`; `None` for anything else.
+fn coerce_bool(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let value = match args {
+        [LiteralValue::Bool(b)] => *b,
+        [LiteralValue::Int(n)] => *n != 0,
+        [LiteralValue::Bytes(bytes)] => match bytes.as_slice() {
+            b"1" => true,
+            b"0" => false,
+            s if s.eq_ignore_ascii_case(b"true") => true,
+            s if s.eq_ignore_ascii_case(b"false") => false,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(LiteralValue::Bool(value))
+}
+
+// `regex_capture(subject, pattern, group)`: the text of capture group `group` (0 is the
+// whole match) in the first match, or `None` when the pattern is invalid, nothing matches,
+// or the group doesn't exist or didn't participate.
+#[cfg(feature = "regex")]
+fn regex_capture(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let (Some(subject), Some(LiteralValue::Bytes(pattern)), Some(group)) =
+        (args.first(), args.get(1), args.get(2))
+    else {
+        return None;
+    };
+    let re = regex::Regex::new(std::str::from_utf8(pattern).ok()?).ok()?;
+    capture_group(&re, subject, group)
+}
+
+// `matches_any(subject, {patterns})`: whether any of the regexes matches the subject, or
+// `None` when the subject isn't bytes or a pattern is invalid.
+#[cfg(feature = "regex")]
+fn matches_any(args: &[LiteralValue]) -> Option<LiteralValue> {
+    let [subject, LiteralValue::Array(patterns)] = args else {
+        return None;
+    };
+    let patterns = patterns
+        .iter()
+        .map(|p| match p {
+            LiteralValue::Bytes(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    matches_any_set(&regex::RegexSet::new(patterns).ok()?, subject)
+}
+
+/// Whether any pattern of `set` matches `subject`, shared by `matches_any` and its
+/// precompiled form; `None` unless the subject is UTF-8 bytes.
+#[cfg(feature = "regex")]
+pub(crate) fn matches_any_set(
+    set: &regex::RegexSet,
+    subject: &LiteralValue,
+) -> Option<LiteralValue> {
+    let LiteralValue::Bytes(bytes) = subject else {
+        return None;
+    };
+    let text = std::str::from_utf8(bytes).ok()?;
+    Some(LiteralValue::Bool(set.is_match(text)))
+}
+
+/// Capture group `group` of `re`'s first match in `subject`, shared by `regex_capture`
+/// and its precompiled form.
+#[cfg(feature = "regex")]
+pub(crate) fn capture_group(
+    re: &regex::Regex,
+    subject: &LiteralValue,
+    group: &LiteralValue,
+) -> Option<LiteralValue> {
+    let (LiteralValue::Bytes(bytes), LiteralValue::Int(group)) = (subject, group) else {
+        return None;
+    };
+    let text = std::str::from_utf8(bytes).ok()?;
+    let captured = re.captures(text)?.get(usize::try_from(*group).ok()?)?;
+    Some(LiteralValue::Bytes(Arc::new(
+        captured.as_str().as_bytes().to_vec(),
+    )))
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -157,6 +863,24 @@ pub enum BuiltinFunctionId {
     Sum,
     StartsWith,
     EndsWith,
+    StartsWithCi,
+    EndsWithCi,
+    Min,
+    Max,
+    Avg,
+    Concat,
+    Substring,
+    Trim,
+    Replace,
+    IndexOf,
+    CharAt,
+    ToInt,
+    ToBytes,
+    ToBool,
+    #[cfg(feature = "regex")]
+    RegexCapture,
+    #[cfg(feature = "regex")]
+    MatchesAny,
 }
 
 impl BuiltinFunctionId {
@@ -168,6 +892,24 @@ impl BuiltinFunctionId {
             "sum" => Some(Self::Sum),
             "starts_with" => Some(Self::StartsWith),
             "ends_with" => Some(Self::EndsWith),
+            "starts_with_ci" => Some(Self::StartsWithCi),
+            "ends_with_ci" => Some(Self::EndsWithCi),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "avg" => Some(Self::Avg),
+            "concat" => Some(Self::Concat),
+            "substring" => Some(Self::Substring),
+            "trim" => Some(Self::Trim),
+            "replace" => Some(Self::Replace),
+            "index_of" => Some(Self::IndexOf),
+            "char_at" => Some(Self::CharAt),
+            "to_int" => Some(Self::ToInt),
+            "to_bytes" => Some(Self::ToBytes),
+            "to_bool" => Some(Self::ToBool),
+            #[cfg(feature = "regex")]
+            "regex_capture" => Some(Self::RegexCapture),
+            #[cfg(feature = "regex")]
+            "matches_any" => Some(Self::MatchesAny),
             _ => None,
         }
     }
@@ -175,29 +917,9 @@ impl BuiltinFunctionId {
 
 pub fn call_builtin(id: BuiltinFunctionId, args: &[LiteralValue]) -> Option<LiteralValue> {
     match id {
-        BuiltinFunctionId::Len => {
-            if let Some(LiteralValue::Array(arr)) = args.first() {
-                Some(LiteralValue::Int(arr.len() as i64))
-            } else {
-                None
-            }
-        }
-        BuiltinFunctionId::Upper => {
-            if let Some(LiteralValue::Bytes(bytes)) = args.first() {
-                let s = String::from_utf8_lossy(bytes).to_uppercase();
-                Some(LiteralValue::Bytes(Arc::new(s.into_bytes())))
-            } else {
-                None
-            }
-        }
-        BuiltinFunctionId::Lower => {
-            if let Some(LiteralValue::Bytes(bytes)) = args.first() {
-                let s = String::from_utf8_lossy(bytes).to_lowercase();
-                Some(LiteralValue::Bytes(Arc::new(s.into_bytes())))
-            } else {
-                None
-            }
-        }
+        BuiltinFunctionId::Len => len_value(args),
+        BuiltinFunctionId::Upper => change_case(args, Case::Upper),
+        BuiltinFunctionId::Lower => change_case(args, Case::Lower),
         BuiltinFunctionId::Sum => {
             if let Some(LiteralValue::Array(arr)) = args.first() {
                 let sum: i64 = arr
@@ -215,28 +937,26 @@ pub fn call_builtin(id: BuiltinFunctionId, args: &[LiteralValue]) -> Option<Lite
                 None
             }
         }
-        BuiltinFunctionId::StartsWith => {
-            if let (Some(LiteralValue::Bytes(haystack)), Some(LiteralValue::Bytes(prefix))) =
-                (args.first(), args.get(1))
-            {
-                let h = String::from_utf8_lossy(haystack);
-                let p = String::from_utf8_lossy(prefix);
-                Some(LiteralValue::Bool(h.starts_with(&*p)))
-            } else {
-                None
-            }
-        }
-        BuiltinFunctionId::EndsWith => {
-            if let (Some(LiteralValue::Bytes(haystack)), Some(LiteralValue::Bytes(suffix))) =
-                (args.first(), args.get(1))
-            {
-                let h = String::from_utf8_lossy(haystack);
-                let s = String::from_utf8_lossy(suffix);
-                Some(LiteralValue::Bool(h.ends_with(&*s)))
-            } else {
-                None
-            }
-        }
+        BuiltinFunctionId::StartsWith => affix_match(args, Affix::Prefix, false),
+        BuiltinFunctionId::EndsWith => affix_match(args, Affix::Suffix, false),
+        BuiltinFunctionId::StartsWithCi => affix_match(args, Affix::Prefix, true),
+        BuiltinFunctionId::EndsWithCi => affix_match(args, Affix::Suffix, true),
+        BuiltinFunctionId::Min => int_elements(args)?.min().map(LiteralValue::Int),
+        BuiltinFunctionId::Max => int_elements(args)?.max().map(LiteralValue::Int),
+        BuiltinFunctionId::Avg => avg_ints(args),
+        BuiltinFunctionId::Concat => concat_bytes(args),
+        BuiltinFunctionId::Substring => substring_bytes(args),
+        BuiltinFunctionId::Trim => trim_bytes(args),
+        BuiltinFunctionId::Replace => replace_bytes(args),
+        BuiltinFunctionId::IndexOf => index_of_bytes(args),
+        BuiltinFunctionId::CharAt => char_at_bytes(args),
+        BuiltinFunctionId::ToInt => parse_int_bytes(args),
+        BuiltinFunctionId::ToBytes => render_int(args),
+        BuiltinFunctionId::ToBool => coerce_bool(args),
+        #[cfg(feature = "regex")]
+        BuiltinFunctionId::RegexCapture => regex_capture(args),
+        #[cfg(feature = "regex")]
+        BuiltinFunctionId::MatchesAny => matches_any(args),
     }
 }
 
@@ -252,6 +972,33 @@ mod tests {
         assert_eq!(result, Some(LiteralValue::Int(2)));
     }
     #[test]
+    fn test_len_bytes_and_maps() {
+        let bytes = LiteralValue::Bytes(Arc::new("héllo".as_bytes().to_vec()));
+        let map = LiteralValue::Map(Arc::new(
+            [("a".to_string(), LiteralValue::Int(1))]
+                .into_iter()
+                .collect(),
+        ));
+        let set = LiteralValue::Set(Arc::new([LiteralValue::Int(1)].into_iter().collect()));
+        for (value, expected) in [(bytes, 6), (map, 1), (set, 1)] {
+            assert_eq!(
+                LenFunction.call(std::slice::from_ref(&value)),
+                Some(LiteralValue::Int(expected))
+            );
+            assert_eq!(
+                call_builtin(BuiltinFunctionId::Len, &[value]),
+                Some(LiteralValue::Int(expected))
+            );
+        }
+        for value in [
+            LiteralValue::Int(3),
+            LiteralValue::Bool(true),
+            LiteralValue::Null,
+        ] {
+            assert_eq!(call_builtin(BuiltinFunctionId::Len, &[value]), None);
+        }
+    }
+    #[test]
     fn test_upper_function() {
         let mut reg = FunctionRegistry::new();
         reg.register("upper", UpperFunction);
@@ -263,6 +1010,32 @@ mod tests {
         );
     }
     #[test]
+    fn test_upper_lower_unicode() {
+        let bytes = |s: &[u8]| LiteralValue::Bytes(Arc::new(s.to_vec()));
+        for (input, upper, lower) in [
+            (
+                "straße".as_bytes(),
+                "STRASSE".as_bytes(),
+                "straße".as_bytes(),
+            ),
+            ("İi".as_bytes(), "İI".as_bytes(), "i̇i".as_bytes()),
+            (
+                "Ünïcödé".as_bytes(),
+                "ÜNÏCÖDÉ".as_bytes(),
+                "ünïcödé".as_bytes(),
+            ),
+            // Not UTF-8: ASCII letters change, other bytes are kept
+            (b"Ab\xff", b"AB\xff", b"ab\xff"),
+        ] {
+            for (name, expected) in [("upper", upper), ("lower", lower)] {
+                let id = BuiltinFunctionId::from_name(name).unwrap();
+                assert_eq!(call_builtin(id, &[bytes(input)]), Some(bytes(expected)));
+            }
+            assert_eq!(UpperFunction.call(&[bytes(input)]), Some(bytes(upper)));
+            assert_eq!(LowerFunction.call(&[bytes(input)]), Some(bytes(lower)));
+        }
+    }
+    #[test]
     fn test_sum_function() {
         let mut reg = FunctionRegistry::new();
         reg.register("sum", SumFunction);
@@ -327,10 +1100,391 @@ mod tests {
         );
     }
     #[test]
+    fn test_affix_functions_ci_and_arrays() {
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        let path = bytes("/API/users.JSON");
+        let prefixes = LiteralValue::Array(Arc::new(vec![bytes("/v1"), bytes("/api")]));
+        for (name, arg, expected) in [
+            ("starts_with", bytes("/api"), false),
+            ("starts_with_ci", bytes("/api"), true),
+            ("starts_with", prefixes.clone(), false),
+            ("starts_with_ci", prefixes.clone(), true),
+            (
+                "starts_with_ci",
+                LiteralValue::Array(Arc::new(vec![])),
+                false,
+            ),
+            ("ends_with", bytes(".json"), false),
+            ("ends_with_ci", bytes(".json"), true),
+            ("ends_with_ci", bytes("/API/users.JSON!"), false),
+        ] {
+            let args = [path.clone(), arg];
+            let id = BuiltinFunctionId::from_name(name).unwrap();
+            assert_eq!(
+                call_builtin(id, &args),
+                Some(LiteralValue::Bool(expected)),
+                "{name}"
+            );
+        }
+        let mixed = LiteralValue::Array(Arc::new(vec![bytes("/v1"), LiteralValue::Int(1)]));
+        assert_eq!(StartsWithFunction.call(&[path, mixed]), None);
+    }
+    #[test]
+    fn test_builtin_override_tracking() {
+        let mut reg = FunctionRegistry::new();
+        register_builtins(&mut reg);
+        assert!(!reg.is_builtin_overridden("len"));
+        assert!(!reg.is_builtin_overridden("my_fn"));
+        reg.register_fn("len", |_args| Some(LiteralValue::Int(0)));
+        assert!(reg.is_builtin_overridden("len"));
+        assert!(reg.clone().is_builtin_overridden("len"));
+        // Re-registering the builtins restores them
+        register_builtins(&mut reg);
+        assert!(!reg.is_builtin_overridden("len"));
+    }
+    #[test]
+    fn test_register_stateful() {
+        // Counts calls per key; a key of "boom" panics mid-call
+        #[derive(Default)]
+        struct Counter(HashMap<LiteralValue, i64>);
+        impl StatefulFunction for Counter {
+            fn call_mut(
+                &mut self,
+                args: &[LiteralValue],
+                _data: &dyn Any,
+            ) -> Result<LiteralValue, WirerustError> {
+                let key = args.first().cloned().unwrap_or(LiteralValue::Null);
+                assert!(key != LiteralValue::from("boom"), "boom");
+                let count = self.0.entry(key).or_default();
+                *count += 1;
+                Ok(LiteralValue::Int(*count))
+            }
+            fn signature(&self) -> Option<FunctionSignature> {
+                Some(FunctionSignature::new(vec![FieldType::Unknown]).with_returns(FieldType::Int))
+            }
+        }
+        let mut reg = FunctionRegistry::new();
+        reg.register_stateful("count", Counter::default());
+        let count = reg.get("count").unwrap();
+        assert_eq!(count.signature().map(|s| s.returns), Some(FieldType::Int));
+        let a = [LiteralValue::from("a")];
+        assert_eq!(count.call(&a), Some(LiteralValue::Int(1)));
+        assert_eq!(count.try_call(&a).unwrap(), LiteralValue::Int(2));
+        assert_eq!(
+            count.call(&[LiteralValue::from("b")]),
+            Some(LiteralValue::Int(1))
+        );
+        // The state is shared by every clone of the registry
+        let cloned = reg.clone();
+        assert_eq!(
+            cloned.get("count").unwrap().call(&a),
+            Some(LiteralValue::Int(3))
+        );
+        // A panic poisons the function for later calls
+        let boom = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            count.call(&[LiteralValue::from("boom")])
+        }));
+        assert!(boom.is_err());
+        assert!(matches!(
+            count.try_call(&a),
+            Err(WirerustError::FunctionError(_))
+        ));
+    }
+    #[test]
     fn test_register_closure() {
         let mut reg = FunctionRegistry::new();
         reg.register_fn("always_true", |_args| Some(LiteralValue::Bool(true)));
         let result = reg.get("always_true").unwrap().call(&[]);
         assert_eq!(result, Some(LiteralValue::Bool(true)));
     }
+    #[test]
+    fn test_register_operator() {
+        let mut reg = FunctionRegistry::new();
+        reg.register_operator("divides", |a, b| match (a, b) {
+            (LiteralValue::Int(a), LiteralValue::Int(b)) => *a != 0 && b % a == 0,
+            _ => false,
+        })
+        .unwrap();
+        let id = reg.operator_id("divides").unwrap();
+        assert_eq!(reg.operator_name(id), Some("divides"));
+        let op = reg.get_operator(id).unwrap();
+        assert!(op(&LiteralValue::Int(3), &LiteralValue::Int(12)));
+        assert!(!op(&LiteralValue::Int(5), &LiteralValue::Int(12)));
+        // Re-registering replaces the operator and keeps its ID
+        reg.register_operator("divides", |_, _| true).unwrap();
+        assert_eq!(reg.operator_id("divides"), Some(id));
+        assert!(reg.get_operator(id).unwrap()(
+            &LiteralValue::Int(5),
+            &LiteralValue::Int(12)
+        ));
+        for keyword in [
+            "in", "contains", "eq", "between", "strict", "and", "not", "null",
+        ] {
+            let err = reg.register_operator(keyword, |_, _| true).unwrap_err();
+            assert!(err.to_string().contains("built-in operator"), "{keyword}");
+        }
+        for keyword in ["", "==", "1st", "semver satisfies", "a.b"] {
+            assert!(
+                reg.register_operator(keyword, |_, _| true).is_err(),
+                "{keyword}"
+            );
+        }
+        assert_eq!(reg.operator_names().collect::<Vec<_>>(), ["divides"]);
+    }
+
+    #[test]
+    fn test_builtin_signatures() {
+        assert_eq!(
+            StartsWithFunction.signature(),
+            Some(
+                FunctionSignature::new(vec![FieldType::Bytes, FieldType::Unknown])
+                    .with_returns(FieldType::Bool)
+            )
+        );
+        let mut reg = FunctionRegistry::new();
+        reg.register_fn("untyped", |_args| None);
+        assert_eq!(reg.get("untyped").unwrap().signature(), None);
+    }
+    #[test]
+    fn test_concat_function() {
+        let mut reg = FunctionRegistry::new();
+        reg.register("concat", ConcatFunction);
+        let a = LiteralValue::Bytes(Arc::new(b"foo".to_vec()));
+        let b = LiteralValue::Bytes(Arc::new(b"bar".to_vec()));
+        assert_eq!(
+            reg.get("concat")
+                .unwrap()
+                .call(&[a.clone(), b.clone(), a.clone()]),
+            Some(LiteralValue::Bytes(Arc::new(b"foobarfoo".to_vec())))
+        );
+        assert_eq!(
+            reg.get("concat")
+                .unwrap()
+                .call(&[a.clone(), LiteralValue::Int(1)]),
+            None
+        );
+    }
+    #[test]
+    fn test_substring_function() {
+        let mut reg = FunctionRegistry::new();
+        reg.register("substring", SubstringFunction);
+        let val = LiteralValue::Bytes(Arc::new(b"foobar".to_vec()));
+        assert_eq!(
+            reg.get("substring").unwrap().call(&[
+                val.clone(),
+                LiteralValue::Int(1),
+                LiteralValue::Int(3)
+            ]),
+            Some(LiteralValue::Bytes(Arc::new(b"oob".to_vec())))
+        );
+        assert_eq!(
+            reg.get("substring").unwrap().call(&[
+                val.clone(),
+                LiteralValue::Int(4),
+                LiteralValue::Int(100)
+            ]),
+            Some(LiteralValue::Bytes(Arc::new(b"ar".to_vec())))
+        );
+        assert_eq!(
+            reg.get("substring").unwrap().call(&[
+                val.clone(),
+                LiteralValue::Int(-1),
+                LiteralValue::Int(2)
+            ]),
+            None
+        );
+    }
+    #[test]
+    fn test_trim_function() {
+        let mut reg = FunctionRegistry::new();
+        reg.register("trim", TrimFunction);
+        let val = LiteralValue::Bytes(Arc::new(b" \tfoo bar\n".to_vec()));
+        assert_eq!(
+            reg.get("trim").unwrap().call(&[val]),
+            Some(LiteralValue::Bytes(Arc::new(b"foo bar".to_vec())))
+        );
+        assert_eq!(reg.get("trim").unwrap().call(&[LiteralValue::Int(1)]), None);
+    }
+    #[test]
+    fn test_index_of_and_char_at() {
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        let path = bytes("/café/admin");
+        assert_eq!(
+            IndexOfFunction.call(&[path.clone(), bytes("admin")]),
+            Some(LiteralValue::Int(7))
+        );
+        assert_eq!(
+            IndexOfFunction.call(&[path.clone(), bytes("root")]),
+            Some(LiteralValue::Int(-1))
+        );
+        assert_eq!(
+            IndexOfFunction.call(&[path.clone(), bytes("")]),
+            Some(LiteralValue::Int(0))
+        );
+        assert_eq!(
+            IndexOfFunction.call(&[path.clone(), LiteralValue::Int(1)]),
+            None
+        );
+        assert_eq!(
+            CharAtFunction.call(&[path.clone(), LiteralValue::Int(0)]),
+            Some(bytes("/"))
+        );
+        assert_eq!(
+            CharAtFunction.call(&[path.clone(), LiteralValue::Int(4)]),
+            Some(LiteralValue::Bytes(Arc::new(vec![0xC3])))
+        );
+        for index in [-1, 100] {
+            assert_eq!(
+                CharAtFunction.call(&[path.clone(), LiteralValue::Int(index)]),
+                Some(bytes(""))
+            );
+        }
+        assert_eq!(CharAtFunction.call(&[path, bytes("0")]), None);
+    }
+
+    #[test]
+    fn test_coercions() {
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        for (input, expected) in [("100", Some(100)), ("-7", Some(-7)), ("+3", Some(3))] {
+            assert_eq!(
+                ToIntFunction.call(&[bytes(input)]),
+                expected.map(LiteralValue::Int)
+            );
+        }
+        for input in ["", "12a", " 1", "1.5", "99999999999999999999"] {
+            assert_eq!(ToIntFunction.call(&[bytes(input)]), None, "{input}");
+        }
+        assert_eq!(ToIntFunction.call(&[LiteralValue::Int(1)]), None);
+        assert_eq!(
+            ToBytesFunction.call(&[LiteralValue::Int(-42)]),
+            Some(bytes("-42"))
+        );
+        assert_eq!(ToBytesFunction.call(&[bytes("42")]), None);
+        for (input, expected) in [
+            (bytes("TRUE"), true),
+            (bytes("0"), false),
+            (LiteralValue::Int(5), true),
+            (LiteralValue::Bool(false), false),
+        ] {
+            assert_eq!(
+                ToBoolFunction.call(&[input]),
+                Some(LiteralValue::Bool(expected))
+            );
+        }
+        assert_eq!(ToBoolFunction.call(&[bytes("yes")]), None);
+    }
+    #[test]
+    fn test_replace_function() {
+        let mut reg = FunctionRegistry::new();
+        reg.register("replace", ReplaceFunction);
+        let val = LiteralValue::Bytes(Arc::new(b"a-b-c".to_vec()));
+        let from = LiteralValue::Bytes(Arc::new(b"-".to_vec()));
+        let to = LiteralValue::Bytes(Arc::new(b"::".to_vec()));
+        assert_eq!(
+            reg.get("replace")
+                .unwrap()
+                .call(&[val.clone(), from.clone(), to.clone()]),
+            Some(LiteralValue::Bytes(Arc::new(b"a::b::c".to_vec())))
+        );
+        let empty = LiteralValue::Bytes(Arc::new(Vec::new()));
+        assert_eq!(
+            reg.get("replace")
+                .unwrap()
+                .call(&[val.clone(), empty, to.clone()]),
+            Some(val.clone())
+        );
+    }
+    #[test]
+    fn test_min_max_avg_functions() {
+        let mut reg = FunctionRegistry::new();
+        register_builtins(&mut reg);
+        let arr = LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(3),
+            LiteralValue::Int(-2),
+            LiteralValue::Int(10),
+        ]));
+        let empty = LiteralValue::Array(Arc::new(Vec::new()));
+        assert_eq!(
+            reg.get("min").unwrap().call(std::slice::from_ref(&arr)),
+            Some(LiteralValue::Int(-2))
+        );
+        assert_eq!(
+            reg.get("max").unwrap().call(std::slice::from_ref(&arr)),
+            Some(LiteralValue::Int(10))
+        );
+        // 11 / 3 rounds to 4
+        assert_eq!(
+            reg.get("avg").unwrap().call(std::slice::from_ref(&arr)),
+            Some(LiteralValue::Int(4))
+        );
+        let halves =
+            LiteralValue::Array(Arc::new(vec![LiteralValue::Int(-1), LiteralValue::Int(-2)]));
+        assert_eq!(
+            reg.get("avg").unwrap().call(&[halves]),
+            Some(LiteralValue::Int(-2))
+        );
+        for name in ["min", "max", "avg"] {
+            assert_eq!(
+                reg.get(name).unwrap().call(std::slice::from_ref(&empty)),
+                None
+            );
+            assert_eq!(
+                call_builtin(
+                    BuiltinFunctionId::from_name(name).unwrap(),
+                    std::slice::from_ref(&empty)
+                ),
+                None
+            );
+        }
+    }
+    #[test]
+    fn test_register_typed() {
+        let mut reg = FunctionRegistry::new();
+        reg.register_typed("greater", |a: i64, b: i64| a > b);
+        reg.register_typed("shout", |s: String| format!("{}!", s.to_uppercase()));
+        reg.register_typed("safe_div", |a: i64, b: i64| a.checked_div(b));
+        let greater = reg.get("greater").unwrap();
+        assert_eq!(
+            greater.call(&[LiteralValue::Int(3), LiteralValue::Int(2)]),
+            Some(LiteralValue::Bool(true))
+        );
+        assert_eq!(
+            greater.call(&[LiteralValue::Int(3), LiteralValue::Bool(true)]),
+            None
+        );
+        assert_eq!(greater.call(&[LiteralValue::Int(3)]), None);
+        assert_eq!(
+            greater.signature(),
+            Some(
+                FunctionSignature::new(vec![FieldType::Int, FieldType::Int])
+                    .with_returns(FieldType::Bool)
+            )
+        );
+        assert_eq!(
+            reg.get("shout")
+                .unwrap()
+                .call(&[LiteralValue::Bytes(Arc::new(b"hi".to_vec()))]),
+            Some(LiteralValue::Bytes(Arc::new(b"HI!".to_vec())))
+        );
+        assert_eq!(
+            reg.get("safe_div")
+                .unwrap()
+                .call(&[LiteralValue::Int(1), LiteralValue::Int(0)]),
+            None
+        );
+    }
+    #[test]
+    fn test_try_call_default_bridges_call() {
+        let mut reg = FunctionRegistry::new();
+        reg.register_fn("maybe", |args| args.first().cloned());
+        let func = reg.get("maybe").unwrap();
+        assert_eq!(
+            func.try_call(&[LiteralValue::Int(1)]).unwrap(),
+            LiteralValue::Int(1)
+        );
+        assert!(matches!(
+            func.try_call(&[]),
+            Err(WirerustError::FunctionError(_))
+        ));
+    }
 }
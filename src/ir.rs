@@ -2,24 +2,39 @@
 //!
 //! This module defines the bytecode instructions and supporting types for fast filter execution.
 
-use crate::types::LiteralValue;
+use crate::trie::{parse_prefix, IpTrie};
+use crate::types::{sorted_elements, LiteralValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
 /// Unique identifier for a field in the schema.
 pub type FieldId = usize;
 /// Unique identifier for a function in the registry.
 pub type FunctionId = usize;
+/// Unique identifier for a custom operator in the registry.
+pub type OperatorId = usize;
 
 /// A single instruction in the filter bytecode.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Instruction {
     /// Push the value of a field onto the stack.
     LoadField(FieldId),
+    /// Push whether a field is set in the context.
+    FieldExists(FieldId),
+    /// Push whether a field equals a literal, comparing in place without loading the
+    /// field onto the stack. Fused from `LoadField`, `LoadLiteral`, `CompareEq`.
+    EqFieldLiteral(FieldId, LiteralValue),
     /// Push a literal value onto the stack.
     LoadLiteral(LiteralValue),
     /// Call a function with N arguments (popped from the stack).
     CallFunction(FunctionId, u8),
+    /// Pop two values and push the result of a custom comparison operator on them.
+    CallOperator(OperatorId),
     /// Comparison operations (pop two, push result).
     CompareEq,
+    CompareEqCi,
     CompareNeq,
     CompareLt,
     CompareLte,
@@ -27,15 +42,375 @@ pub enum Instruction {
     CompareGte,
     CompareIn,
     CompareNotIn,
+    /// Pop a value and push whether it is (or, with `negate`, is not) in a constant set.
+    CompareInSet {
+        set: LiteralSet,
+        negate: bool,
+    },
+    /// Pop a value and push whether it is (or, with `negate`, is not) one of a constant set
+    /// of integers, sorted and deduplicated at compile time and binary searched.
+    CompareInSortedInts {
+        values: SortedInts,
+        negate: bool,
+    },
+    /// Pop a value and push whether it is (or, with `negate`, is not) an integer in one of
+    /// a list of sorted, non-overlapping ranges, found by binary search. Ranges are merged
+    /// again on load, like [`SortedInts`].
+    CompareInRanges {
+        #[serde(deserialize_with = "deserialize_ranges")]
+        ranges: Vec<RangeInclusive<i64>>,
+        negate: bool,
+    },
+    /// Pop a value and push whether it is (or, with `negate`, is not) an IP address within
+    /// one of a constant set of CIDR prefixes, found in a prefix trie.
+    CompareIpInTrie {
+        trie: IpPrefixSet,
+        negate: bool,
+    },
+    /// Pop a value and push whether it lies between two constant bounds, inclusive of
+    /// both unless `strict`.
+    CompareBetween {
+        low: LiteralValue,
+        high: LiteralValue,
+        strict: bool,
+    },
     CompareMatches,
+    /// Match the top of the stack against a regex compiled once at compile time.
+    CompareMatchesCompiled(CompiledRegex),
+    /// Pop a group index and a subject, push `regex_capture`'s result for a pattern
+    /// compiled at compile time.
+    #[cfg(feature = "regex")]
+    CaptureCompiled(CompiledRegex),
+    /// Pop a subject and push `matches_any`'s result for patterns compiled at compile time.
+    #[cfg(feature = "regex")]
+    MatchesAnyCompiled(CompiledRegexSet),
     CompareWildcard {
         strict: bool,
     },
     CompareContains,
+    /// Arithmetic and bitwise operations on integers (pop two, push result).
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    /// Negate the integer on top of the stack.
+    Negate,
+    /// Pop a key and a map or array, push the element or `Bool(false)` when absent.
+    Index,
+    /// Pop an end bound, a start bound and an array, push the elements between them.
+    /// `Null` bounds stand for the array's start and end.
+    Slice,
     /// Logical operations.
     LogicalAnd,
     LogicalOr,
     LogicalNot,
+    /// Push the element bound by the innermost enclosing `Quantify`.
+    LoadElement,
+    /// Pop an array or set and run `body` once per element, pushing whether it held for any
+    /// (or, with `all`, every) element.
+    Quantify {
+        all: bool,
+        body: Vec<Instruction>,
+    },
+}
+
+impl Instruction {
+    /// How many stack operands a comparison or arithmetic instruction pops, so nulls can
+    /// be propagated generically; `None` for everything else.
+    pub(crate) fn null_propagating_operands(&self) -> Option<usize> {
+        match self {
+            Instruction::CompareEq
+            | Instruction::CompareEqCi
+            | Instruction::CompareNeq
+            | Instruction::CompareLt
+            | Instruction::CompareLte
+            | Instruction::CompareGt
+            | Instruction::CompareGte
+            | Instruction::CompareIn
+            | Instruction::CompareNotIn
+            | Instruction::CompareMatches
+            | Instruction::CompareWildcard { .. }
+            | Instruction::CompareContains
+            | Instruction::CallOperator(_)
+            | Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Mod
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::Shl
+            | Instruction::Shr => Some(2),
+            Instruction::CompareMatchesCompiled(_) => Some(1),
+            Instruction::CompareInSet { .. }
+            | Instruction::CompareInSortedInts { .. }
+            | Instruction::CompareInRanges { .. }
+            | Instruction::CompareIpInTrie { .. }
+            | Instruction::CompareBetween { .. }
+            | Instruction::Negate => Some(1),
+            _ => None,
+        }
+    }
+
+    /// How many values this instruction pops and then pushes. A quantifier replaces its
+    /// array with the result; its body runs on a stack of its own.
+    pub(crate) fn stack_effect(&self) -> (usize, usize) {
+        match self {
+            Instruction::LoadField(_)
+            | Instruction::FieldExists(_)
+            | Instruction::EqFieldLiteral(..)
+            | Instruction::LoadLiteral(_)
+            | Instruction::LoadElement => (0, 1),
+            Instruction::CallFunction(_, argc) => (*argc as usize, 1),
+            Instruction::CompareEq
+            | Instruction::CompareEqCi
+            | Instruction::CompareNeq
+            | Instruction::CompareLt
+            | Instruction::CompareLte
+            | Instruction::CompareGt
+            | Instruction::CompareGte
+            | Instruction::CompareIn
+            | Instruction::CompareNotIn
+            | Instruction::CompareMatches
+            | Instruction::CompareWildcard { .. }
+            | Instruction::CompareContains
+            | Instruction::CallOperator(_)
+            | Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mul
+            | Instruction::Div
+            | Instruction::Mod
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::Shl
+            | Instruction::Shr
+            | Instruction::Index
+            | Instruction::LogicalAnd
+            | Instruction::LogicalOr => (2, 1),
+            Instruction::Slice => (3, 1),
+            #[cfg(feature = "regex")]
+            Instruction::CaptureCompiled(_) => (2, 1),
+            #[cfg(feature = "regex")]
+            Instruction::MatchesAnyCompiled(_) => (1, 1),
+            Instruction::CompareMatchesCompiled(_)
+            | Instruction::CompareInSet { .. }
+            | Instruction::CompareInSortedInts { .. }
+            | Instruction::CompareInRanges { .. }
+            | Instruction::CompareIpInTrie { .. }
+            | Instruction::CompareBetween { .. }
+            | Instruction::Negate
+            | Instruction::LogicalNot
+            | Instruction::Quantify { .. } => (1, 1),
+        }
+    }
+}
+
+/// The deepest the evaluation stack gets while running `code` from an empty stack,
+/// found by simulating each instruction's stack effect. Bytecode has no jumps, so every
+/// instruction runs in order; a quantifier body starts just above the popped array. An
+/// error when an instruction would pop more values than the stack holds, or when `code`
+/// or a quantifier body doesn't leave exactly one value.
+pub(crate) fn checked_stack_depth(code: &[Instruction]) -> Result<usize, crate::WirerustError> {
+    let invalid =
+        |reason: String| crate::WirerustError::Other(format!("Invalid bytecode: {reason}"));
+    let (mut depth, mut peak) = (0usize, 0usize);
+    for (i, instr) in code.iter().enumerate() {
+        let (pops, pushes) = instr.stack_effect();
+        if pops > depth {
+            return Err(invalid(format!(
+                "instruction {i} pops {pops} value(s) from a stack of {depth}"
+            )));
+        }
+        if let Instruction::Quantify { body, .. } = instr {
+            peak = peak.max(depth - 1 + checked_stack_depth(body)?);
+        }
+        depth = depth - pops + pushes;
+        peak = peak.max(depth);
+    }
+    if depth != 1 {
+        return Err(invalid(format!(
+            "leaves {depth} values on the stack instead of 1"
+        )));
+    }
+    Ok(peak)
+}
+
+/// All instructions in `code`, including those nested in quantifier bodies, depth-first.
+pub(crate) fn all_instructions(code: &[Instruction]) -> Vec<&Instruction> {
+    let mut out = Vec::with_capacity(code.len());
+    for instr in code {
+        out.push(instr);
+        if let Instruction::Quantify { body, .. } = instr {
+            out.extend(all_instructions(body));
+        }
+    }
+    out
+}
+
+/// The engine behind `matches`: the `regex` crate when that feature is enabled,
+/// otherwise the built-in [`Pattern`](crate::pattern::Pattern) subset.
+#[cfg(feature = "regex")]
+pub type RegexEngine = regex::Regex;
+#[cfg(not(feature = "regex"))]
+pub type RegexEngine = crate::pattern::Pattern;
+
+/// A regex pattern compiled once during filter compilation and shared by the bytecode.
+#[derive(Debug, Clone)]
+pub struct CompiledRegex(pub Arc<RegexEngine>);
+
+impl PartialEq for CompiledRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+// Serialized as the pattern source and recompiled on load.
+impl Serialize for CompiledRegex {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CompiledRegex {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        RegexEngine::new(&pattern)
+            .map(|re| CompiledRegex(Arc::new(re)))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The patterns of a `matches_any` call compiled once into a single regex set.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct CompiledRegexSet(pub Arc<regex::RegexSet>);
+
+#[cfg(feature = "regex")]
+impl PartialEq for CompiledRegexSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.patterns() == other.0.patterns()
+    }
+}
+
+// Serialized as the list of pattern sources and recompiled on load.
+#[cfg(feature = "regex")]
+impl Serialize for CompiledRegexSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.patterns().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<'de> Deserialize<'de> for CompiledRegexSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let patterns = Vec::<String>::deserialize(deserializer)?;
+        regex::RegexSet::new(patterns)
+            .map(|set| CompiledRegexSet(Arc::new(set)))
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A constant `in` set hashed once at compile time for constant-time membership tests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiteralSet(pub Arc<HashSet<LiteralValue>>);
+
+// Serialized as a plain list of its elements, sorted so the same set always serializes
+// the same way.
+impl Serialize for LiteralSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(sorted_elements(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for LiteralSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<LiteralValue>::deserialize(deserializer)?;
+        Ok(LiteralSet(Arc::new(items.into_iter().collect())))
+    }
+}
+
+/// A constant integer `in` set, sorted and deduplicated for binary search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortedInts(pub Arc<[i64]>);
+
+impl SortedInts {
+    /// Sort and deduplicate `values`.
+    pub fn new(mut values: Vec<i64>) -> Self {
+        values.sort_unstable();
+        values.dedup();
+        SortedInts(values.into())
+    }
+}
+
+// Serialized as a plain list, and re-sorted on load.
+impl Serialize for SortedInts {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SortedInts {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<i64>::deserialize(deserializer).map(SortedInts::new)
+    }
+}
+
+/// Sort ranges by start, dropping empty ones and merging those that overlap or touch.
+pub(crate) fn merge_ranges(ranges: &[RangeInclusive<i64>]) -> Vec<RangeInclusive<i64>> {
+    let mut sorted: Vec<_> = ranges.iter().filter(|r| !r.is_empty()).cloned().collect();
+    sorted.sort_by_key(|r| *r.start());
+    let mut merged: Vec<RangeInclusive<i64>> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                let end = (*last.end()).max(*range.end());
+                *last = *last.start()..=end;
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn deserialize_ranges<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<RangeInclusive<i64>>, D::Error> {
+    Vec::<RangeInclusive<i64>>::deserialize(deserializer).map(|ranges| merge_ranges(&ranges))
+}
+
+/// A constant set of IP prefixes built into a trie once at compile time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpPrefixSet(pub Arc<IpTrie>);
+
+// Serialized as a list of "addr/len" strings, and rebuilt on load.
+impl Serialize for IpPrefixSet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.0
+                .prefixes()
+                .iter()
+                .map(|(ip, len)| format!("{ip}/{len}")),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for IpPrefixSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let prefixes = Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|p| parse_prefix(p))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(serde::de::Error::custom)?;
+        Ok(IpPrefixSet(Arc::new(IpTrie::new(prefixes))))
+    }
 }
 
 /// The IR stack used during interpretation.
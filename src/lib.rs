@@ -9,33 +9,45 @@
 //! - Compilation to IR (closures or pluggable backends)
 //! - Execution context (runtime values)
 //! - Extensible function/type registry
-//! - Optional FFI/WASM bindings
+//! - Optional WASM bindings (`wasm` feature)
 
 use std::sync::Arc;
 use thiserror::Error;
 
+mod closure;
 mod compiler;
+mod completion;
 mod context;
 mod expr;
 mod filter;
 mod functions;
 mod ir;
+#[cfg(not(feature = "regex"))]
+mod pattern;
 mod schema;
+mod trie;
 mod types;
+#[cfg(feature = "wasm")]
+mod wasm;
 
+pub use closure::*;
 pub use compiler::*;
+pub use completion::*;
 pub use context::*;
 pub use expr::*;
 pub use filter::*;
 pub use functions::*;
+pub use ir::IrStack;
 pub use schema::*;
 pub use types::*;
+#[cfg(feature = "wasm")]
+pub use wasm::*;
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum WirerustError {
-    #[error("Parse error: {0}")]
-    ParseError(String),
+    #[error("Parse error at {span}: {message}")]
+    ParseError { message: String, span: Span },
     #[error("Type error: {0}")]
     TypeError(String),
     #[error("Function error: {0}")]
@@ -46,12 +58,32 @@ pub enum WirerustError {
     ExecutionError(String),
     #[error("Error: {0}")]
     Other(String),
+    /// A non-fatal finding from [`WirerustEngine::validate`], such as a comparison whose
+    /// result never changes. The filter still compiles.
+    #[error("Warning: {0}")]
+    Warning(String),
+}
+
+impl WirerustError {
+    /// The source range a parse error points at, for highlighting in an editor.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            WirerustError::ParseError { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+    /// Whether this is a [`Warning`](WirerustError::Warning) rather than an error.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, WirerustError::Warning(_))
+    }
 }
 
 /// The main engine for parsing, compiling, and executing filters.
 pub struct WirerustEngine {
     schema: Arc<FilterSchema>,
     functions: Arc<FunctionRegistry>,
+    options: EngineOptions,
+    compiler: Arc<dyn Compiler>,
 }
 
 impl WirerustEngine {
@@ -62,6 +94,8 @@ impl WirerustEngine {
         Self {
             schema: Arc::new(schema),
             functions: Arc::new(functions),
+            options: EngineOptions::default(),
+            compiler: Arc::new(DefaultCompiler),
         }
     }
     /// Create a new engine with the given schema and custom function registry.
@@ -69,8 +103,20 @@ impl WirerustEngine {
         Self {
             schema: Arc::new(schema),
             functions: Arc::new(functions),
+            options: EngineOptions::default(),
+            compiler: Arc::new(DefaultCompiler),
         }
     }
+    /// Replace the options used when compiling filters.
+    pub fn with_options(mut self, options: EngineOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Replace the backend used when compiling filters.
+    pub fn with_compiler(mut self, compiler: impl Compiler + 'static) -> Self {
+        self.compiler = Arc::new(compiler);
+        self
+    }
     /// Get a reference to the filter schema.
     pub fn schema(&self) -> &FilterSchema {
         &self.schema
@@ -79,23 +125,51 @@ impl WirerustEngine {
     pub fn functions(&self) -> &FunctionRegistry {
         &self.functions
     }
-    /// Parse a filter expression string into an AST.
+    /// Get the options used when compiling filters.
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
+    /// Parse a filter expression string into an AST, recognising the registry's custom
+    /// operators.
     pub fn parse_filter(&self, expr: &str) -> Result<FilterExpr, WirerustError> {
-        FilterParser::parse(expr, &self.schema)
+        FilterParser::parse_with_functions(expr, &self.schema, &self.functions)
     }
     /// Compile a parsed filter expression into an executable filter.
     pub fn compile_filter(&self, expr: FilterExpr) -> Result<CompiledFilter, WirerustError> {
-        Ok(CompiledFilter::new(
+        CompiledFilter::with_compiler(
             expr,
             Arc::clone(&self.schema),
             Arc::clone(&self.functions),
-        ))
+            self.options,
+            self.compiler.as_ref(),
+        )
+    }
+    /// Check a parsed filter against the schema and functions, returning every problem
+    /// found rather than the first, warnings included; empty when the filter is clean.
+    /// See [`DefaultCompiler::validate`].
+    pub fn validate(&self, expr: &FilterExpr) -> Vec<WirerustError> {
+        DefaultCompiler::validate(expr, &self.schema, &self.functions)
+    }
+    /// Suggest fields, functions and operators for the word ending at byte offset `cursor`
+    /// of a partially typed filter, for editor autocompletion. Each [`Completion`]'s text
+    /// replaces that partial word. The analysis is lexical, so suggestions are not
+    /// type-checked against the rest of the filter.
+    pub fn complete(&self, partial: &str, cursor: usize) -> Vec<Completion> {
+        completion::complete(&self.schema, &self.functions, partial, cursor)
     }
     /// Parse and compile a filter expression string in one step.
     pub fn parse_and_compile(&self, expr: &str) -> Result<CompiledFilter, WirerustError> {
         let parsed = self.parse_filter(expr)?;
         self.compile_filter(parsed)
     }
+    /// Parse and compile several filter strings, stopping at the first that fails. The
+    /// filters share the engine's schema and function registry rather than copying them.
+    pub fn compile_many(&self, exprs: &[&str]) -> Result<Vec<CompiledFilter>, WirerustError> {
+        exprs
+            .iter()
+            .map(|expr| self.parse_and_compile(expr))
+            .collect()
+    }
     /// Execute a compiled filter against a context.
     pub fn execute(
         &self,
@@ -104,6 +178,16 @@ impl WirerustEngine {
     ) -> Result<bool, WirerustError> {
         filter.execute(ctx)
     }
+    /// Execute a compiled filter, making `data` available to custom functions through
+    /// [`FilterFunction::call_with_data`].
+    pub fn execute_with_data(
+        &self,
+        filter: &CompiledFilter,
+        ctx: &FilterContext,
+        data: &dyn std::any::Any,
+    ) -> Result<bool, WirerustError> {
+        filter.execute_with_data(ctx, data)
+    }
 }
 
 /// Builder for WirerustEngine, for ergonomic embedding and configuration.
@@ -111,6 +195,8 @@ pub struct WirerustEngineBuilder {
     schema_builder: FilterSchemaBuilder,
     functions: FunctionRegistry,
     use_builtins: bool,
+    options: EngineOptions,
+    compiler: Arc<dyn Compiler>,
 }
 
 impl Default for WirerustEngineBuilder {
@@ -119,6 +205,8 @@ impl Default for WirerustEngineBuilder {
             schema_builder: FilterSchemaBuilder::new(),
             functions: FunctionRegistry::new(),
             use_builtins: true,
+            options: EngineOptions::default(),
+            compiler: Arc::new(DefaultCompiler),
         }
     }
 }
@@ -142,11 +230,81 @@ impl WirerustEngineBuilder {
         self.functions.register(name, func);
         self
     }
+    /// Register a custom function that keeps state between calls; see
+    /// [`StatefulFunction`].
+    pub fn register_stateful_function<F: StatefulFunction + 'static>(
+        mut self,
+        name: impl Into<String>,
+        func: F,
+    ) -> Self {
+        self.functions.register_stateful(name, func);
+        self
+    }
+    /// Register a custom infix comparison operator; see
+    /// [`FunctionRegistry::register_operator`]. Fails if `keyword` is not an identifier or
+    /// collides with a built-in operator.
+    pub fn register_operator<F>(
+        mut self,
+        keyword: impl Into<String>,
+        op: F,
+    ) -> Result<Self, WirerustError>
+    where
+        F: Fn(&LiteralValue, &LiteralValue) -> bool + Send + Sync + 'static,
+    {
+        self.functions.register_operator(keyword, op)?;
+        Ok(self)
+    }
     /// Disable built-in functions (by default, builtins are registered).
     pub fn no_builtins(mut self) -> Self {
         self.use_builtins = false;
         self
     }
+    /// Without the `regex` feature, evaluate `matches` as a substring test instead of
+    /// with the built-in pattern engine.
+    pub fn substring_matches_fallback(mut self) -> Self {
+        self.options.substring_matches_fallback = true;
+        self
+    }
+    /// Raise an execution error when a filter reads a field missing from the context,
+    /// rather than treating it as `false`.
+    pub fn strict_missing_fields(mut self) -> Self {
+        self.options.strict_missing_fields = true;
+        self
+    }
+    /// Evaluate `null` with SQL-style three-valued logic: comparisons and arithmetic on a
+    /// null (or missing) value yield unknown, which a filter treats as not matching.
+    pub fn three_valued_logic(mut self) -> Self {
+        self.options.three_valued_logic = true;
+        self
+    }
+    /// Compile filters with `compiler` instead of the bytecode [`DefaultCompiler`].
+    pub fn compiler(mut self, compiler: impl Compiler + 'static) -> Self {
+        self.compiler = Arc::new(compiler);
+        self
+    }
+    /// Raise a `TypeError` naming the operator and operand types when an ordered
+    /// comparison meets values that can't be ordered, rather than treating it as `false`.
+    pub fn strict_comparisons(mut self) -> Self {
+        self.options.strict_comparisons = true;
+        self
+    }
+    /// Make `matches` match the whole value (`^(?:pattern)$`) rather than anywhere in it.
+    pub fn anchored_matches(mut self) -> Self {
+        self.options.anchored_matches = true;
+        self
+    }
+    /// Compare IPv4-mapped IPv6 addresses as their IPv4 form in equality and membership
+    /// tests, so `::ffff:10.0.0.1` matches `10.0.0.1`.
+    pub fn normalize_ips(mut self) -> Self {
+        self.options.normalize_ips = true;
+        self
+    }
+    /// Keep filters' leaf conditions compiled separately so
+    /// [`CompiledFilter::execute_explain`] can say which of them matched.
+    pub fn explain(mut self) -> Self {
+        self.options.explain = true;
+        self
+    }
     /// Build the engine.
     pub fn build(self) -> WirerustEngine {
         let schema = self.schema_builder.build();
@@ -154,7 +312,10 @@ impl WirerustEngineBuilder {
         if self.use_builtins {
             register_builtins(&mut functions);
         }
-        WirerustEngine::with_functions(schema, functions)
+        let mut engine =
+            WirerustEngine::with_functions(schema, functions).with_options(self.options);
+        engine.compiler = self.compiler;
+        engine
     }
 }
 
@@ -252,4 +413,400 @@ mod tests {
             "Expected error when executing missing built-in function"
         );
     }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn test_engine_matches_without_regex() {
+        let strict = WirerustEngineBuilder::new()
+            .field("bar", FieldType::Bytes)
+            .build();
+        let filter = strict.parse_and_compile("bar matches \"^a\"").unwrap();
+        let ctx = FilterContextBuilder::new(&strict.schema)
+            .set_bytes("bar", b"abc")
+            .unwrap()
+            .build();
+        assert!(strict.execute(&filter, &ctx).unwrap());
+        assert!(strict.parse_and_compile("bar matches \"a|b\"").is_err());
+        let lenient = WirerustEngineBuilder::new()
+            .field("bar", FieldType::Bytes)
+            .substring_matches_fallback()
+            .build();
+        let filter = lenient.parse_and_compile("bar matches \"b\"").unwrap();
+        let ctx = FilterContextBuilder::new(&lenient.schema)
+            .set_bytes("bar", b"abc")
+            .unwrap()
+            .build();
+        assert!(lenient.execute(&filter, &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_engine_builder_strict_missing_fields() {
+        let lenient = WirerustEngineBuilder::new()
+            .field("enabled", FieldType::Bool)
+            .build();
+        let filter = lenient.parse_and_compile("not enabled").unwrap();
+        assert!(lenient.execute(&filter, &FilterContext::new()).unwrap());
+
+        let strict = WirerustEngineBuilder::new()
+            .field("enabled", FieldType::Bool)
+            .strict_missing_fields()
+            .build();
+        let filter = strict.parse_and_compile("not enabled").unwrap();
+        assert!(strict.execute(&filter, &FilterContext::new()).is_err());
+        let ctx = FilterContextBuilder::new(&strict.schema)
+            .set_bool("enabled", false)
+            .unwrap()
+            .build();
+        assert!(strict.execute(&filter, &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_validate_reports_every_error() {
+        let engine = WirerustEngineBuilder::new()
+            .field("port", FieldType::Int)
+            .field("name", FieldType::Bytes)
+            .field("tags", FieldType::Array(Box::new(FieldType::Bytes)))
+            .build();
+        let clean = engine
+            .parse_filter(r#"port in {80 443} && len(tags) > 1 && tags contains "a""#)
+            .unwrap();
+        assert!(engine.validate(&clean).is_empty());
+
+        let expr = engine
+            .parse_filter(
+                r#"nmae && len(tags, 2) == 1 && port == "80" && lower(port) == "x" && nope(1) && name + 1 > 2"#,
+            )
+            .unwrap();
+        let errors = engine.validate(&expr);
+        let kinds: Vec<&str> = errors
+            .iter()
+            .map(|e| match e {
+                WirerustError::FieldNotFound(_) => "field",
+                WirerustError::FunctionError(_) => "function",
+                WirerustError::TypeError(_) => "type",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["field", "function", "type", "type", "function", "type"],
+            "{errors:?}"
+        );
+        // Compilation accepts the undeclared name and unknown function, but stops at the arity
+        assert!(engine.compile_filter(expr).is_err());
+    }
+
+    #[test]
+    fn test_validate_warns_on_constant_comparisons() {
+        let engine = WirerustEngineBuilder::new()
+            .field("port", FieldType::Int)
+            .field("name", FieldType::Bytes)
+            .build();
+        let warnings = |src: &str| {
+            let expr = engine.parse_filter(src).unwrap();
+            let found = engine.validate(&expr);
+            assert!(found.iter().all(WirerustError::is_warning), "{found:?}");
+            assert!(engine.compile_filter(expr).is_ok());
+            found.len()
+        };
+        for src in [
+            "name == name",
+            "port > port",
+            "port + 1 >= port + 1",
+            "1 == 2",
+            "port in {}",
+            "name not in {}",
+        ] {
+            assert_eq!(warnings(src), 1, "{src}");
+        }
+        assert_eq!(warnings("port == port || name in {}"), 2);
+        for src in ["port == 80", "port > port + 1", r#"name in {"a"}"#] {
+            assert_eq!(warnings(src), 0, "{src}");
+        }
+        // Functions may return something different each call
+        let len = FilterExpr::FunctionCall {
+            name: "len".into(),
+            args: vec![FilterExpr::Field("name".into())],
+        };
+        let expr = FilterExpr::Comparison {
+            left: Box::new(len.clone()),
+            op: ComparisonOp::Eq,
+            right: Box::new(len),
+        };
+        assert!(engine.validate(&expr).is_empty());
+        let expr = engine.parse_filter("port in {}").unwrap();
+        assert_eq!(
+            engine.validate(&expr)[0].to_string(),
+            "Warning: 'in' an empty set is always false"
+        );
+    }
+
+    #[test]
+    fn test_validate_warns_on_match_everything_patterns() {
+        let engine = WirerustEngineBuilder::new()
+            .field("path", FieldType::Bytes)
+            .build();
+        let warnings = |src: &str| {
+            let expr = engine.parse_filter(src).unwrap();
+            let found = engine.validate(&expr);
+            assert!(found.iter().all(WirerustError::is_warning), "{found:?}");
+            found.iter().map(|w| w.to_string()).collect::<Vec<_>>()
+        };
+        for src in [
+            r#"path matches ".*""#,
+            r#"path matches "^.*$""#,
+            r#"path wildcard "*""#,
+            r#"path strict wildcard "***""#,
+            r#"path not wildcard "*""#,
+        ] {
+            assert_eq!(warnings(src).len(), 1, "{src}");
+        }
+        assert_eq!(
+            warnings(r#"path wildcard "*""#),
+            [r#"Warning: 'wildcard "*"' matches every string"#]
+        );
+        assert_eq!(
+            warnings(r#"path wildcard """#),
+            [r#"Warning: 'wildcard ""' only matches an empty string"#]
+        );
+        for src in [
+            r#"path matches ".*admin""#,
+            r#"path matches "^/api/.*$""#,
+            r#"path wildcard "*.php""#,
+            r#"path == "*""#,
+        ] {
+            assert!(warnings(src).is_empty(), "{src}");
+        }
+        // Diagnostics only: the filter still compiles and behaves as written
+        let filter = engine.parse_and_compile(r#"path wildcard "*""#).unwrap();
+        let ctx = FilterContextBuilder::new(engine.schema())
+            .set_bytes("path", "/x")
+            .unwrap()
+            .build();
+        assert!(engine.execute(&filter, &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_engine_builder_three_valued_logic() {
+        let engine = WirerustEngineBuilder::new()
+            .field("enabled", FieldType::Bool)
+            .three_valued_logic()
+            .build();
+        // A missing field is unknown, and so is its negation
+        let filter = engine.parse_and_compile("not enabled").unwrap();
+        assert!(!engine.execute(&filter, &FilterContext::new()).unwrap());
+        let filter = engine.parse_and_compile("enabled or true").unwrap();
+        assert!(engine.execute(&filter, &FilterContext::new()).unwrap());
+    }
+
+    #[test]
+    fn test_engine_builder_strict_comparisons() {
+        // Untyped, so the mismatch only shows up at runtime
+        struct Label;
+        impl FilterFunction for Label {
+            fn call(&self, _args: &[LiteralValue]) -> Option<LiteralValue> {
+                Some(LiteralValue::Bytes(Arc::new(b"web".to_vec())))
+            }
+        }
+        let build = |strict: bool| {
+            let builder = WirerustEngineBuilder::new()
+                .field("port", FieldType::Int)
+                .register_function("label", Label);
+            if strict {
+                builder.strict_comparisons().build()
+            } else {
+                builder.build()
+            }
+        };
+        let ctx = FilterContextBuilder::new(build(true).schema())
+            .set_int("port", 80)
+            .unwrap()
+            .build();
+        for src in ["label() < 5", "label() between {1 10}"] {
+            let lenient = build(false);
+            let filter = lenient.parse_and_compile(src).unwrap();
+            assert!(!lenient.execute(&filter, &ctx).unwrap());
+            for engine in [build(true), build(true).with_compiler(ClosureCompiler)] {
+                let filter = engine.parse_and_compile(src).unwrap();
+                match engine.execute(&filter, &ctx) {
+                    Err(WirerustError::TypeError(msg)) => assert!(msg.contains("Bytes"), "{msg}"),
+                    other => panic!("Expected type error for {src}, got {other:?}"),
+                }
+            }
+        }
+        let engine = build(true);
+        let filter = engine.parse_and_compile("port < 100").unwrap();
+        assert!(engine.execute(&filter, &ctx).unwrap());
+        let filter = engine.parse_and_compile("label() < 5").unwrap();
+        assert_eq!(
+            engine.execute(&filter, &ctx).unwrap_err().to_string(),
+            "Type error: Cannot compare Bytes < Int"
+        );
+    }
+
+    #[test]
+    fn test_custom_function_error_propagates() {
+        struct Lookup;
+        impl FilterFunction for Lookup {
+            fn call(&self, _args: &[LiteralValue]) -> Option<LiteralValue> {
+                None
+            }
+            fn try_call(&self, _args: &[LiteralValue]) -> Result<LiteralValue, WirerustError> {
+                Err(WirerustError::FunctionError("lookup timed out".into()))
+            }
+        }
+        let engine = WirerustEngineBuilder::new()
+            .field("foo", FieldType::Int)
+            .register_function("lookup", Lookup)
+            .build();
+        let filter = engine.parse_and_compile("lookup(foo)").unwrap();
+        let ctx = FilterContextBuilder::new(&engine.schema)
+            .set_int("foo", 1)
+            .unwrap()
+            .build();
+        match engine.execute(&filter, &ctx) {
+            Err(WirerustError::FunctionError(msg)) => assert_eq!(msg, "lookup timed out"),
+            other => panic!("Expected function error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_execute_with_data() {
+        struct Now;
+        impl FilterFunction for Now {
+            fn call(&self, _args: &[LiteralValue]) -> Option<LiteralValue> {
+                None
+            }
+            fn call_with_data(
+                &self,
+                _args: &[LiteralValue],
+                data: &dyn std::any::Any,
+            ) -> Result<LiteralValue, WirerustError> {
+                data.downcast_ref::<i64>()
+                    .map(|now| LiteralValue::Int(*now))
+                    .ok_or_else(|| WirerustError::FunctionError("no clock provided".into()))
+            }
+        }
+        let engine = WirerustEngineBuilder::new()
+            .field("expires", FieldType::Int)
+            .register_function("now", Now)
+            .build();
+        let filter = engine.parse_and_compile("now() < expires").unwrap();
+        let ctx = FilterContextBuilder::new(&engine.schema)
+            .set_int("expires", 100)
+            .unwrap()
+            .build();
+        assert!(engine.execute_with_data(&filter, &ctx, &50i64).unwrap());
+        assert!(!engine.execute_with_data(&filter, &ctx, &150i64).unwrap());
+        assert!(engine.execute(&filter, &ctx).is_err());
+    }
+
+    // A backend that ignores the filter and returns a fixed value
+    struct ConstCompiler(bool);
+    struct ConstFilter(bool);
+
+    impl ExecutableFilter for ConstFilter {
+        fn evaluate(
+            &self,
+            _ctx: &FilterContext,
+            _limits: &ExecutionLimits,
+            _data: &dyn std::any::Any,
+        ) -> Result<LiteralValue, WirerustError> {
+            Ok(LiteralValue::Bool(self.0))
+        }
+    }
+
+    impl Compiler for ConstCompiler {
+        fn compile(
+            &self,
+            _expr: FilterExpr,
+            _schema: Arc<FilterSchema>,
+            _functions: Arc<FunctionRegistry>,
+            _options: EngineOptions,
+        ) -> Result<Box<dyn ExecutableFilter>, WirerustError> {
+            Ok(Box::new(ConstFilter(self.0)))
+        }
+    }
+
+    #[test]
+    fn test_engine_custom_compiler() {
+        let engine = WirerustEngineBuilder::new()
+            .field("foo", FieldType::Int)
+            .compiler(ConstCompiler(true))
+            .build();
+        let filter = engine.parse_and_compile("foo == 1").unwrap();
+        let ctx = FilterContext::new();
+        assert!(engine.execute(&filter, &ctx).unwrap());
+        assert_eq!(filter.referenced_fields(), None);
+        assert_eq!(filter.disassemble(), "");
+        assert_eq!(filter.max_stack_depth(), None);
+        #[cfg(feature = "json")]
+        assert!(filter.to_bytes().is_err());
+
+        let engine = engine.with_compiler(DefaultCompiler);
+        let filter = engine.parse_and_compile("foo == 1").unwrap();
+        assert!(!engine.execute(&filter, &ctx).unwrap());
+        assert_eq!(filter.referenced_fields(), Some(vec!["foo"]));
+        assert_eq!(filter.max_stack_depth(), Some(1));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_engine_builder_anchored_matches() {
+        let build = |anchored: bool| {
+            let builder = WirerustEngineBuilder::new()
+                .field("method", FieldType::Bytes)
+                .field("pattern", FieldType::Bytes);
+            if anchored {
+                builder.anchored_matches().build()
+            } else {
+                builder.build()
+            }
+        };
+        let ctx = FilterContextBuilder::new(build(false).schema())
+            .set_bytes("method", "GETSTUFF")
+            .unwrap()
+            .set_bytes("pattern", "GET")
+            .unwrap()
+            .build();
+        let cases = [
+            (r#"method matches "GET""#, true, false),
+            ("method matches pattern", true, false),
+            (r#"method matches "GET.*""#, true, true),
+            (r#"method matches "STUFF|GETSTUFF""#, true, true),
+            (r#"method matches "^GET$""#, false, false),
+        ];
+        for (src, unanchored, anchored) in cases {
+            for (engine, expected) in [
+                (build(false), unanchored),
+                (build(true), anchored),
+                (build(true).with_compiler(ClosureCompiler), anchored),
+            ] {
+                let filter = engine.parse_and_compile(src).unwrap();
+                assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{src}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_engine_compile_many() {
+        let engine = WirerustEngineBuilder::new()
+            .field("port", FieldType::Int)
+            .build();
+        let filters = engine
+            .compile_many(&["port == 80", "port == 443", "port in {80 443}"])
+            .unwrap();
+        let ctx = FilterContextBuilder::new(engine.schema())
+            .set_int("port", 443)
+            .unwrap()
+            .build();
+        let results: Vec<bool> = filters.iter().map(|f| f.execute(&ctx).unwrap()).collect();
+        assert_eq!(results, vec![false, true, true]);
+        for filter in &filters {
+            assert!(std::ptr::eq(filter.schema(), engine.schema()));
+            assert!(std::ptr::eq(filter.functions(), engine.functions()));
+        }
+        assert!(engine.compile_many(&["port == 80", "port =="]).is_err());
+        assert!(engine.compile_many(&[]).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,294 @@
+//! Pattern module: a small regex engine used by `matches` when the `regex` feature is off.
+//!
+//! This module provides the Pattern type. It supports a subset of regex syntax:
+//! literals, `.`, the `*`, `+` and `?` quantifiers, `^` and `$` anchors, character
+//! classes such as `[a-z0-9_]` and `[^/]`, the `\d`, `\w` and `\s` shorthands (and their
+//! negations `\D`, `\W`, `\S`), and `\`-escaped metacharacters. Groups, alternation and
+//! counted repetition are rejected. Matching simulates an NFA, so it runs in time linear
+//! in the subject for a given pattern and never backtracks.
+
+use std::fmt;
+
+/// A compiled pattern in the supported regex subset.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    items: Vec<Item>,
+    // Anchored to the start (`^`) and end (`$`) of the subject
+    start: bool,
+    end: bool,
+}
+
+// One atom with its quantifier: `optional` for `*` and `?`, `repeat` for `*` and `+`.
+#[derive(Debug, Clone)]
+struct Item {
+    atom: Atom,
+    optional: bool,
+    repeat: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Atom {
+    Char(char),
+    // `.`: anything but a newline
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Char(x) => *x == c,
+            Atom::Any => c != '\n',
+            Atom::Class { ranges, negated } => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+        }
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+/// Why a pattern could not be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid pattern: {}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, PatternError> {
+        let err = |msg: String| Err(PatternError(msg));
+        let mut chars = source.chars().peekable();
+        let mut start = false;
+        while chars.next_if_eq(&'^').is_some() {
+            start = true;
+        }
+        let mut items: Vec<Item> = Vec::new();
+        let mut end = false;
+        while let Some(c) = chars.next() {
+            if end {
+                if c == '$' {
+                    continue;
+                }
+                return err("'$' is only supported at the end".into());
+            }
+            let atom = match c {
+                '$' => {
+                    end = true;
+                    continue;
+                }
+                '*' | '+' | '?' => {
+                    let Some(last) = items.last_mut().filter(|i| !i.optional && !i.repeat) else {
+                        return err(format!("'{c}' has nothing to repeat"));
+                    };
+                    last.optional = c != '+';
+                    last.repeat = c != '?';
+                    continue;
+                }
+                '.' => Atom::Any,
+                '[' => Self::parse_class(&mut chars)?,
+                '\\' => match chars.next() {
+                    Some(e) => Self::escape(e)?,
+                    None => return err("trailing '\\'".into()),
+                },
+                '^' => return err("'^' is only supported at the start".into()),
+                '(' | ')' | '|' | '{' | '}' => {
+                    return err(format!("'{c}' is not supported; escape it to match it"))
+                }
+                c => Atom::Char(c),
+            };
+            items.push(Item {
+                atom,
+                optional: false,
+                repeat: false,
+            });
+        }
+        Ok(Self {
+            source: source.to_string(),
+            items,
+            start,
+            end,
+        })
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches anywhere in `text` (or all of it, when anchored).
+    pub fn is_match(&self, text: &str) -> bool {
+        let n = self.items.len();
+        let mut current = vec![false; n + 1];
+        let mut next = vec![false; n + 1];
+        self.add(&mut current, 0);
+        for c in text.chars() {
+            if current[n] && !self.end {
+                return true;
+            }
+            next.fill(false);
+            for (i, item) in self.items.iter().enumerate() {
+                if current[i] && item.atom.matches(c) {
+                    if item.repeat {
+                        self.add(&mut next, i);
+                    }
+                    self.add(&mut next, i + 1);
+                }
+            }
+            if !self.start {
+                self.add(&mut next, 0);
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        current[n]
+    }
+
+    // Enter state `i`, and the states after it reachable by skipping optional items
+    fn add(&self, states: &mut [bool], mut i: usize) {
+        while !states[i] {
+            states[i] = true;
+            match self.items.get(i) {
+                Some(item) if item.optional => i += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn escape(c: char) -> Result<Atom, PatternError> {
+        let class = |ranges: &[(char, char)], negated| Atom::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        Ok(match c {
+            'd' | 'D' => class(DIGIT, c == 'D'),
+            'w' | 'W' => class(WORD, c == 'W'),
+            's' | 'S' => class(SPACE, c == 'S'),
+            'n' => Atom::Char('\n'),
+            't' => Atom::Char('\t'),
+            'r' => Atom::Char('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                return Err(PatternError(format!("'\\{c}' is not supported")))
+            }
+            c => Atom::Char(c),
+        })
+    }
+
+    // The rest of a `[...]` class after its opening bracket
+    fn parse_class(
+        chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    ) -> Result<Atom, PatternError> {
+        let negated = chars.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let lo = match chars.next() {
+                None => return Err(PatternError("unterminated character class".into())),
+                Some(']') if !first => break,
+                Some('\\') => match chars.next().map(Self::escape).transpose()? {
+                    Some(Atom::Char(c)) => c,
+                    Some(Atom::Class {
+                        ranges: more,
+                        negated: false,
+                    }) => {
+                        ranges.extend(more);
+                        first = false;
+                        continue;
+                    }
+                    Some(_) => {
+                        return Err(PatternError(
+                            "negated shorthands are not supported in a class".into(),
+                        ))
+                    }
+                    None => return Err(PatternError("unterminated character class".into())),
+                },
+                Some(c) => c,
+            };
+            first = false;
+            // `a-z`, unless the `-` is last in the class
+            let hi = match chars.next_if_eq(&'-') {
+                Some(_) if matches!(chars.peek(), Some(']') | None) => {
+                    ranges.push(('-', '-'));
+                    lo
+                }
+                Some(_) => chars.next().unwrap_or(lo),
+                None => lo,
+            };
+            if hi < lo {
+                return Err(PatternError(format!("invalid range '{lo}-{hi}'")));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Atom::Class { ranges, negated })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_literals_and_anchors() {
+        assert!(is_match("GET", "xGETx"));
+        assert!(!is_match("^GET$", "GETX"));
+        assert!(is_match("^GET$", "GET"));
+        assert!(is_match("^/api", "/api/users"));
+        assert!(!is_match("^/api", "/v1/api"));
+        assert!(is_match("json$", "application/json"));
+        assert!(is_match("", "anything"));
+        assert!(is_match("^^a$$", "a"));
+    }
+
+    #[test]
+    fn test_quantifiers_and_dot() {
+        assert!(is_match("^a.c$", "abc"));
+        assert!(!is_match("^a.c$", "a\nc"));
+        assert!(is_match("^ab*c$", "ac"));
+        assert!(is_match("^ab*c$", "abbbc"));
+        assert!(!is_match("^ab+c$", "ac"));
+        assert!(is_match("^ab+c$", "abbc"));
+        assert!(is_match("^colou?r$", "color"));
+        assert!(is_match("^colou?r$", "colour"));
+        assert!(is_match("^.*bot.*$", "Googlebot/2.1"));
+        assert!(is_match("^a*a*a*b$", &format!("{}b", "a".repeat(50))));
+        assert!(!is_match("^a*a*a*b$", &"a".repeat(50)));
+    }
+
+    #[test]
+    fn test_classes_and_escapes() {
+        assert!(is_match(r"^\d+$", "2024"));
+        assert!(!is_match(r"^\d+$", "20x4"));
+        assert!(is_match(r"^[a-z_]+\.[a-z]+$", "my_file.txt"));
+        assert!(is_match("^[^/]+$", "segment"));
+        assert!(!is_match("^[^/]+$", "a/b"));
+        assert!(is_match("^[a-]+$", "a-a"));
+        assert!(is_match(r"^[\d.]+$", "10.0.0.1"));
+        assert!(is_match(r"\s\w", "Mozilla/5.0 (X11; Linux"));
+        assert!(is_match(r"^\(\$\)$", "($)"));
+        assert!(is_match("^[]]$", "]"));
+    }
+
+    #[test]
+    fn test_unsupported_syntax_is_rejected() {
+        for pattern in [
+            "(a)", "a|b", "a{2}", "*a", "a**", "a^", "a$b", "[a", r"\b", "[z-a]", "\\",
+        ] {
+            assert!(Pattern::new(pattern).is_err(), "{pattern}");
+        }
+        assert_eq!(Pattern::new("^a+$").unwrap().as_str(), "^a+$");
+    }
+}
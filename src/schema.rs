@@ -2,16 +2,39 @@
 //!
 //! This module provides the FilterSchema type and builder for defining available fields and types.
 
-use crate::types::FieldType;
+use crate::types::{FieldType, LiteralValue};
+use crate::WirerustError;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// The largest ID [`FilterSchemaBuilder::field_with_id`] accepts. Schemas and contexts keep
+/// a table slot for every ID up to the highest in use.
+pub const MAX_FIELD_ID: usize = u16::MAX as usize;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[non_exhaustive]
 pub struct FilterSchema {
     fields: HashMap<String, FieldType>,
-    field_names: Vec<String>,          // index = FieldId
+    field_names: Vec<Option<String>>, // index = FieldId; None for unused IDs
     field_ids: HashMap<String, usize>, // name -> id
+    #[serde(default)]
+    defaults: Vec<Option<LiteralValue>>, // index = FieldId
+    #[serde(default)]
+    pinned_ids: HashMap<String, usize>,
+    #[serde(default)]
+    multi_valued: HashSet<String>,
+    #[serde(default)]
+    meta: HashMap<String, FieldMeta>,
+}
+
+/// Documentation for a field, for tooling such as autocompletion. It has no effect on
+/// parsing or evaluation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldMeta {
+    pub description: String,
+    /// Example values as they would be written in a filter, e.g. `"GET"` or `443`.
+    #[serde(default)]
+    pub examples: Vec<String>,
 }
 
 impl FilterSchema {
@@ -21,48 +44,265 @@ impl FilterSchema {
     pub fn fields(&self) -> &HashMap<String, FieldType> {
         &self.fields
     }
+    /// The metadata declared with [`FilterSchemaBuilder::field_with_meta`], if any.
+    pub fn field_meta(&self, name: &str) -> Option<&FieldMeta> {
+        self.meta.get(name)
+    }
+    /// Whether the field was declared with [`FilterSchemaBuilder::multi_field`].
+    pub fn is_multi_valued(&self, name: &str) -> bool {
+        self.multi_valued.contains(name)
+    }
     /// Get the field ID for a given field name, if it exists.
     pub fn field_id(&self, name: &str) -> Option<usize> {
         self.field_ids.get(name).copied()
     }
+    /// The name-to-ID mapping assigned by the builder, e.g. for checking that a cached
+    /// filter's IDs still line up with this schema.
+    pub fn field_id_map(&self) -> &HashMap<String, usize> {
+        &self.field_ids
+    }
     /// Get the field name for a given field ID, if it exists.
     pub fn field_name(&self, id: usize) -> Option<&str> {
-        self.field_names.get(id).map(|s| s.as_str())
+        self.field_names.get(id)?.as_deref()
+    }
+    /// The value a filter reads for this field when the context doesn't set it, if one
+    /// was declared with [`FilterSchemaBuilder::field_with_default`].
+    pub fn default_value(&self, name: &str) -> Option<&LiteralValue> {
+        self.field_id(name).and_then(|id| self.default_by_id(id))
+    }
+    /// Get the declared default for a field ID, if any.
+    pub fn default_by_id(&self, id: usize) -> Option<&LiteralValue> {
+        self.defaults.get(id).and_then(|d| d.as_ref())
     }
     /// Get the total number of fields.
     pub fn num_fields(&self) -> usize {
-        self.field_names.len()
+        self.fields.len()
+    }
+    /// Return a copy of this schema without the named field.
+    ///
+    /// Field IDs not pinned with [`FilterSchemaBuilder::field_with_id`] are reassigned, so
+    /// filters and contexts built against this schema must be recompiled and repopulated
+    /// for the new one; stale bytecode may read the wrong field.
+    pub fn without_field(&self, name: &str) -> FilterSchema {
+        self.to_builder().remove(name).build()
+    }
+    /// Union the fields of two schemas. A field defined in both must have the same type,
+    /// otherwise a `TypeError` is returned; its default and metadata come from `self` when
+    /// both declare one. A field must also be multi-valued in both or neither. Unpinned
+    /// field IDs are reassigned in the result, and a pin from `other` is dropped if `self`
+    /// already pins that ID to a different field.
+    pub fn merge(&self, other: &FilterSchema) -> Result<FilterSchema, WirerustError> {
+        let mut builder = self.to_builder();
+        for (name, ty) in &other.fields {
+            match builder.fields.get(name) {
+                Some(existing) if existing != ty => {
+                    return Err(WirerustError::TypeError(format!(
+                        "Conflicting types for field '{name}': {existing:?} vs {ty:?}"
+                    )));
+                }
+                Some(_) if self.is_multi_valued(name) != other.is_multi_valued(name) => {
+                    return Err(WirerustError::TypeError(format!(
+                        "Field '{name}' is multi-valued in only one schema"
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    builder.fields.insert(name.clone(), ty.clone());
+                }
+            }
+        }
+        for (name, default) in other.to_builder().defaults {
+            builder.defaults.entry(name).or_insert(default);
+        }
+        for (name, meta) in &other.meta {
+            builder
+                .meta
+                .entry(name.clone())
+                .or_insert_with(|| meta.clone());
+        }
+        for (name, id) in &other.pinned_ids {
+            if !builder.ids.contains_key(name) && !builder.ids.values().any(|taken| taken == id) {
+                builder.ids.insert(name.clone(), *id);
+            }
+        }
+        builder.multi.extend(other.multi_valued.iter().cloned());
+        Ok(builder.build())
+    }
+
+    fn to_builder(&self) -> FilterSchemaBuilder {
+        let defaults = self
+            .field_names
+            .iter()
+            .zip(&self.defaults)
+            .filter_map(|(name, d)| Some((name.clone()?, d.clone()?)))
+            .collect();
+        FilterSchemaBuilder {
+            fields: self.fields.clone(),
+            defaults,
+            ids: self.pinned_ids.clone(),
+            multi: self.multi_valued.clone(),
+            meta: self.meta.clone(),
+        }
     }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct FilterSchemaBuilder {
     fields: HashMap<String, FieldType>,
+    #[serde(default)]
+    defaults: HashMap<String, LiteralValue>,
+    #[serde(default)]
+    ids: HashMap<String, usize>,
+    #[serde(default)]
+    multi: HashSet<String>,
+    #[serde(default)]
+    meta: HashMap<String, FieldMeta>,
 }
 
 impl FilterSchemaBuilder {
     pub fn new() -> Self {
-        Self {
-            fields: HashMap::new(),
-        }
+        Self::default()
     }
     pub fn field(mut self, name: impl Into<String>, ty: FieldType) -> Self {
-        self.fields.insert(name.into(), ty);
+        let name = name.into();
+        self.defaults.remove(&name);
+        self.ids.remove(&name);
+        self.multi.remove(&name);
+        self.meta.remove(&name);
+        self.fields.insert(name, ty);
+        self
+    }
+    /// Add a field that can hold several values of type `ty` at once, like repeated HTTP
+    /// headers. It is an `Array(ty)` field, set with
+    /// [`FilterContext::set_multi`](crate::FilterContext::set_multi), and a comparison with
+    /// it on the left matches if any of its values does: `header == "x"` is
+    /// `any(header, _ == "x")`. Everywhere else (`len`, indexing, `any`/`all`, function
+    /// arguments) it reads as the array of its values.
+    pub fn multi_field(self, name: impl Into<String>, ty: FieldType) -> Self {
+        let name = name.into();
+        let mut builder = self.field(name.clone(), FieldType::Array(Box::new(ty)));
+        builder.multi.insert(name);
+        builder
+    }
+    /// Add a field with a fixed ID, so that it keeps that ID when other fields are added
+    /// or removed and serialized filters stay valid across schema edits. Fields without a
+    /// fixed ID fill the lowest unused IDs in name order. IDs index a table in the schema
+    /// and in each context, so keep them small. Fails if `id` is above [`MAX_FIELD_ID`] or
+    /// already pinned to another field.
+    pub fn field_with_id(
+        self,
+        name: impl Into<String>,
+        ty: FieldType,
+        id: usize,
+    ) -> Result<Self, WirerustError> {
+        let name = name.into();
+        if id > MAX_FIELD_ID {
+            return Err(WirerustError::Other(format!(
+                "Field ID {id} for '{name}' is above the maximum of {MAX_FIELD_ID}"
+            )));
+        }
+        if let Some((other, _)) = self.ids.iter().find(|(n, i)| **i == id && **n != name) {
+            return Err(WirerustError::Other(format!(
+                "Field ID {id} is already assigned to '{other}'"
+            )));
+        }
+        let mut builder = self.field(name.clone(), ty);
+        builder.ids.insert(name, id);
+        Ok(builder)
+    }
+    /// Add a field that reads as `default` when the context doesn't set it, instead of
+    /// `false`. The default must have the field's type.
+    pub fn field_with_default(
+        self,
+        name: impl Into<String>,
+        ty: FieldType,
+        default: LiteralValue,
+    ) -> Result<Self, WirerustError> {
+        let name = name.into();
+        if !ty.accepts_value(&default) {
+            return Err(WirerustError::TypeError(format!(
+                "Default for field '{name}' must be {ty:?}, got {:?}",
+                default.get_type()
+            )));
+        }
+        let mut builder = self.field(name.clone(), ty);
+        builder.defaults.insert(name, default);
+        Ok(builder)
+    }
+    /// Add a field with a description and example values for tooling; see [`FieldMeta`].
+    pub fn field_with_meta(self, name: impl Into<String>, ty: FieldType, meta: FieldMeta) -> Self {
+        let name = name.into();
+        let mut builder = self.field(name.clone(), ty);
+        builder.meta.insert(name, meta);
+        builder
+    }
+    /// Remove a field added earlier. Unknown names are ignored.
+    pub fn remove(mut self, name: &str) -> Self {
+        self.fields.remove(name);
+        self.defaults.remove(name);
+        self.ids.remove(name);
+        self.multi.remove(name);
+        self.meta.remove(name);
+        self
+    }
+    /// Rename a field, keeping its type. Replaces any existing field called `new`;
+    /// does nothing if `old` is not defined.
+    pub fn rename(mut self, old: &str, new: impl Into<String>) -> Self {
+        if let Some(ty) = self.fields.remove(old) {
+            let new = new.into();
+            self.defaults.remove(&new);
+            self.ids.remove(&new);
+            if let Some(default) = self.defaults.remove(old) {
+                self.defaults.insert(new.clone(), default);
+            }
+            if let Some(id) = self.ids.remove(old) {
+                self.ids.insert(new.clone(), id);
+            }
+            self.multi.remove(&new);
+            if self.multi.remove(old) {
+                self.multi.insert(new.clone());
+            }
+            self.meta.remove(&new);
+            if let Some(meta) = self.meta.remove(old) {
+                self.meta.insert(new.clone(), meta);
+            }
+            self.fields.insert(new, ty);
+        }
         self
     }
     pub fn build(self) -> FilterSchema {
-        let mut field_names = Vec::new();
-        let mut field_ids = HashMap::new();
-        let mut sorted_names: Vec<_> = self.fields.keys().cloned().collect();
-        sorted_names.sort();
-        for name in sorted_names {
-            field_ids.insert(name.clone(), field_names.len());
-            field_names.push(name);
+        let mut field_ids = self.ids.clone();
+        let mut unpinned: Vec<_> = self
+            .fields
+            .keys()
+            .filter(|name| !self.ids.contains_key(*name))
+            .cloned()
+            .collect();
+        unpinned.sort();
+        let taken: HashSet<usize> = self.ids.values().copied().collect();
+        let mut next = 0;
+        for name in unpinned {
+            while taken.contains(&next) {
+                next += 1;
+            }
+            field_ids.insert(name, next);
+            next += 1;
+        }
+        let len = field_ids.values().map(|id| id + 1).max().unwrap_or(0);
+        let mut field_names = vec![None; len];
+        let mut defaults = vec![None; len];
+        for (name, id) in &field_ids {
+            field_names[*id] = Some(name.clone());
+            defaults[*id] = self.defaults.get(name).cloned();
         }
         FilterSchema {
             fields: self.fields,
             field_names,
             field_ids,
+            defaults,
+            pinned_ids: self.ids,
+            multi_valued: self.multi,
+            meta: self.meta,
         }
     }
 }
@@ -115,4 +355,214 @@ mod tests {
         // Last one wins
         assert_eq!(schema.get_field_type("foo"), Some(&FieldType::Bytes));
     }
+
+    #[test]
+    fn test_schema_builder_remove_and_rename() {
+        let schema = FilterSchemaBuilder::new()
+            .field("foo", FieldType::Int)
+            .field("bar", FieldType::Bytes)
+            .field("baz", FieldType::Bool)
+            .remove("bar")
+            .rename("foo", "qux")
+            .rename("missing", "other")
+            .build();
+        assert_eq!(schema.num_fields(), 2);
+        assert_eq!(schema.get_field_type("foo"), None);
+        assert_eq!(schema.get_field_type("qux"), Some(&FieldType::Int));
+        assert_eq!(schema.field_id("baz"), Some(0));
+        assert_eq!(schema.field_id("qux"), Some(1));
+    }
+
+    #[test]
+    fn test_schema_without_field() {
+        let schema = FilterSchemaBuilder::new()
+            .field("a", FieldType::Int)
+            .field("b", FieldType::Bytes)
+            .field("c", FieldType::Bool)
+            .build();
+        let smaller = schema.without_field("a");
+        assert_eq!(schema.num_fields(), 3);
+        assert_eq!(smaller.num_fields(), 2);
+        // IDs stay dense after removal
+        assert_eq!(smaller.field_id("b"), Some(0));
+        assert_eq!(smaller.field_id("c"), Some(1));
+        assert_eq!(smaller.field_name(2), None);
+    }
+
+    #[test]
+    fn test_schema_merge() {
+        let http = FilterSchemaBuilder::new()
+            .field("http.method", FieldType::Bytes)
+            .field("port", FieldType::Int)
+            .build();
+        let net = FilterSchemaBuilder::new()
+            .field("ip", FieldType::Ip)
+            .field("port", FieldType::Int)
+            .build();
+        let merged = http.merge(&net).unwrap();
+        assert_eq!(merged.num_fields(), 3);
+        assert_eq!(merged.field_id("http.method"), Some(0));
+        assert_eq!(merged.field_id("ip"), Some(1));
+        assert_eq!(merged.field_id("port"), Some(2));
+
+        let conflicting = FilterSchemaBuilder::new()
+            .field("port", FieldType::Bytes)
+            .build();
+        assert!(matches!(
+            http.merge(&conflicting),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_field_defaults() {
+        let schema = FilterSchemaBuilder::new()
+            .field_with_default("port", FieldType::Int, LiteralValue::Int(0))
+            .unwrap()
+            .field_with_default(
+                "path",
+                FieldType::Bytes,
+                LiteralValue::Bytes(Default::default()),
+            )
+            .unwrap()
+            .field("ip", FieldType::Ip)
+            .rename("path", "uri")
+            .build();
+        assert_eq!(schema.default_value("port"), Some(&LiteralValue::Int(0)));
+        assert_eq!(
+            schema.default_value("uri"),
+            Some(&LiteralValue::Bytes(Default::default()))
+        );
+        assert_eq!(schema.default_value("ip"), None);
+        assert_eq!(
+            schema.without_field("ip").default_value("port"),
+            Some(&LiteralValue::Int(0))
+        );
+
+        // Redeclaring a field drops its default
+        let schema = FilterSchemaBuilder::new()
+            .field_with_default("port", FieldType::Int, LiteralValue::Int(0))
+            .unwrap()
+            .field("port", FieldType::Int)
+            .build();
+        assert_eq!(schema.default_value("port"), None);
+
+        assert!(matches!(
+            FilterSchemaBuilder::new().field_with_default(
+                "port",
+                FieldType::Int,
+                LiteralValue::Bool(false)
+            ),
+            Err(WirerustError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_pinned_field_ids() {
+        let schema = FilterSchemaBuilder::new()
+            .field_with_id("zeta", FieldType::Int, 0)
+            .unwrap()
+            .field_with_id("alpha", FieldType::Bytes, 3)
+            .unwrap()
+            .field("beta", FieldType::Bool)
+            .field("gamma", FieldType::Bool)
+            .build();
+        // Unpinned fields fill the lowest free IDs in name order
+        assert_eq!(schema.field_id("zeta"), Some(0));
+        assert_eq!(schema.field_id("beta"), Some(1));
+        assert_eq!(schema.field_id("gamma"), Some(2));
+        assert_eq!(schema.field_id("alpha"), Some(3));
+        assert_eq!(schema.field_id_map().len(), 4);
+
+        // Pins survive removal, merging and serialization; the gap reads as no field
+        let smaller = schema.without_field("gamma");
+        assert_eq!(smaller.field_id("alpha"), Some(3));
+        assert_eq!(smaller.field_name(2), None);
+        assert_eq!(smaller.num_fields(), 3);
+        let json = serde_json::to_string(&smaller).unwrap();
+        let restored: FilterSchema = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.field_id_map(), smaller.field_id_map());
+        let other = FilterSchemaBuilder::new()
+            .field_with_id("delta", FieldType::Int, 0)
+            .unwrap()
+            .build();
+        let merged = smaller.merge(&other).unwrap();
+        assert_eq!(merged.field_id("zeta"), Some(0));
+        assert_eq!(merged.field_id("alpha"), Some(3));
+
+        assert!(FilterSchemaBuilder::new()
+            .field_with_id("a", FieldType::Int, 1)
+            .unwrap()
+            .field_with_id("b", FieldType::Int, 1)
+            .is_err());
+        for id in [MAX_FIELD_ID + 1, usize::MAX] {
+            assert!(FilterSchemaBuilder::new()
+                .field_with_id("a", FieldType::Int, id)
+                .is_err());
+        }
+        let highest = FilterSchemaBuilder::new()
+            .field_with_id("a", FieldType::Int, MAX_FIELD_ID)
+            .unwrap()
+            .build();
+        assert_eq!(highest.field_id("a"), Some(MAX_FIELD_ID));
+    }
+
+    #[test]
+    fn test_multi_valued_fields() {
+        let schema = FilterSchemaBuilder::new()
+            .multi_field("cookie", FieldType::Bytes)
+            .multi_field("old", FieldType::Int)
+            .rename("old", "port")
+            .field("host", FieldType::Bytes)
+            .build();
+        assert!(schema.is_multi_valued("cookie") && schema.is_multi_valued("port"));
+        assert!(!schema.is_multi_valued("host") && !schema.is_multi_valued("old"));
+        assert_eq!(
+            schema.get_field_type("cookie"),
+            Some(&FieldType::Array(Box::new(FieldType::Bytes)))
+        );
+        assert!(!schema.without_field("port").is_multi_valued("port"));
+        assert!(schema.without_field("host").is_multi_valued("cookie"));
+
+        // Same type, but multi-valued on one side only
+        let plain = FilterSchemaBuilder::new()
+            .field("cookie", FieldType::Array(Box::new(FieldType::Bytes)))
+            .build();
+        assert!(schema.merge(&plain).is_err());
+        let extra = FilterSchemaBuilder::new()
+            .multi_field("via", FieldType::Bytes)
+            .build();
+        assert!(schema.merge(&extra).unwrap().is_multi_valued("via"));
+    }
+
+    #[test]
+    fn test_field_metadata() {
+        let meta = FieldMeta {
+            description: "HTTP request method".into(),
+            examples: vec!["\"GET\"".into(), "\"POST\"".into()],
+        };
+        let schema = FilterSchemaBuilder::new()
+            .field_with_meta("method", FieldType::Bytes, meta.clone())
+            .field_with_meta("old", FieldType::Int, FieldMeta::default())
+            .rename("old", "port")
+            .field("host", FieldType::Bytes)
+            .build();
+        assert_eq!(schema.field_meta("method"), Some(&meta));
+        assert_eq!(schema.field_meta("port"), Some(&FieldMeta::default()));
+        assert_eq!(schema.field_meta("host"), None);
+        assert_eq!(schema.field_meta("old"), None);
+        assert_eq!(schema.get_field_type("method"), Some(&FieldType::Bytes));
+
+        let json = serde_json::to_string(&schema).unwrap();
+        let restored: FilterSchema = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.field_meta("method"), Some(&meta));
+        let other = FilterSchemaBuilder::new()
+            .field_with_meta("host", FieldType::Bytes, meta.clone())
+            .field_with_meta("method", FieldType::Bytes, FieldMeta::default())
+            .build();
+        let merged = schema.merge(&other).unwrap();
+        assert_eq!(merged.field_meta("host"), Some(&meta));
+        assert_eq!(merged.field_meta("method"), Some(&meta));
+        assert_eq!(schema.without_field("method").field_meta("method"), None);
+    }
 }
@@ -0,0 +1,260 @@
+//! Trie module: longest-prefix matching for constant sets of IP addresses and CIDR blocks.
+//!
+//! This module provides the IpTrie type behind `ip in {"10.0.0.0/8" ...}` comparisons. Each
+//! address family has a binary trie keyed by address bits, so a lookup takes at most 32
+//! (IPv4) or 128 (IPv6) steps however many prefixes the set holds.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A set of IP prefixes, each an address and a prefix length in bits.
+#[derive(Debug, Clone, Default)]
+pub struct IpTrie {
+    // The prefixes as inserted (masked, sorted and deduplicated), kept for display,
+    // serialization and equality
+    prefixes: Vec<(IpAddr, u8)>,
+    v4: Nodes,
+    v6: Nodes,
+}
+
+// A binary trie rooted at index 0; a child index of 0 means no child, since the root is
+// never anyone's child.
+#[derive(Debug, Clone)]
+struct Nodes(Vec<Node>);
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Node {
+    children: [u32; 2],
+    terminal: bool,
+}
+
+impl Default for Nodes {
+    fn default() -> Self {
+        Nodes(vec![Node::default()])
+    }
+}
+
+impl Nodes {
+    fn insert(&mut self, bits: u128, width: u32, len: u8) {
+        let mut node = 0;
+        for i in 0..u32::from(len) {
+            if self.0[node].terminal {
+                // Already covered by a shorter prefix
+                return;
+            }
+            let bit = bit_at(bits, width, i);
+            node = match self.0[node].children[bit] {
+                0 => {
+                    let child = self.0.len();
+                    self.0.push(Node::default());
+                    self.0[node].children[bit] = child as u32;
+                    child
+                }
+                child => child as usize,
+            };
+        }
+        // Anything below is covered by this prefix now
+        self.0[node] = Node {
+            children: [0, 0],
+            terminal: true,
+        };
+    }
+
+    fn contains(&self, bits: u128, width: u32) -> bool {
+        let mut node = 0;
+        for i in 0..width {
+            if self.0[node].terminal {
+                return true;
+            }
+            node = match self.0[node].children[bit_at(bits, width, i)] {
+                0 => return false,
+                child => child as usize,
+            };
+        }
+        self.0[node].terminal
+    }
+}
+
+// Bit `i` of a `width`-bit address, counting from the most significant
+fn bit_at(bits: u128, width: u32, i: u32) -> usize {
+    ((bits >> (width - 1 - i)) & 1) as usize
+}
+
+// An address as its bits and width
+fn address_bits(ip: IpAddr) -> (u128, u32) {
+    match ip {
+        IpAddr::V4(v4) => (u128::from(u32::from(v4)), 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    }
+}
+
+// `ip` with the bits after the first `len` cleared
+fn mask(ip: IpAddr, len: u8) -> IpAddr {
+    let (bits, width) = address_bits(ip);
+    let host_bits = u128::MAX
+        .checked_shr(128 - width + u32::from(len))
+        .unwrap_or(0);
+    let masked = bits & !host_bits;
+    match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(masked as u32)),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(masked)),
+    }
+}
+
+/// Why a string is not an IP address or CIDR prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixError(String);
+
+impl fmt::Display for PrefixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\" is not a valid IP address or CIDR prefix", self.0)
+    }
+}
+
+impl std::error::Error for PrefixError {}
+
+/// Parse `addr` or `addr/len`; a bare address is a prefix of its full width. Host bits
+/// after the prefix are cleared, so `10.1.2.3/8` is `10.0.0.0/8`.
+pub fn parse_prefix(s: &str) -> Result<(IpAddr, u8), PrefixError> {
+    let err = || PrefixError(s.to_string());
+    let (addr, len) = match s.split_once('/') {
+        Some((addr, len)) => (addr, Some(len)),
+        None => (s, None),
+    };
+    let ip: IpAddr = addr.parse().map_err(|_| err())?;
+    let width = if ip.is_ipv4() { 32 } else { 128 };
+    let len = match len {
+        Some(len) if len.bytes().all(|b| b.is_ascii_digit()) => {
+            len.parse::<u8>().map_err(|_| err())?
+        }
+        Some(_) => return Err(err()),
+        None => width,
+    };
+    if len > width {
+        return Err(err());
+    }
+    Ok((mask(ip, len), len))
+}
+
+impl IpTrie {
+    pub fn new(prefixes: impl IntoIterator<Item = (IpAddr, u8)>) -> Self {
+        let mut prefixes: Vec<_> = prefixes
+            .into_iter()
+            .map(|(ip, len)| (mask(ip, len), len))
+            .collect();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        let mut trie = IpTrie::default();
+        for &(ip, len) in &prefixes {
+            let (bits, width) = address_bits(ip);
+            let nodes = if ip.is_ipv4() {
+                &mut trie.v4
+            } else {
+                &mut trie.v6
+            };
+            nodes.insert(bits, width, len);
+        }
+        trie.prefixes = prefixes;
+        trie
+    }
+
+    /// Whether `ip` falls within any of the prefixes.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (bits, width) = address_bits(ip);
+        match ip {
+            IpAddr::V4(_) => self.v4.contains(bits, width),
+            IpAddr::V6(_) => self.v6.contains(bits, width),
+        }
+    }
+
+    /// The prefixes, masked, sorted and deduplicated.
+    pub fn prefixes(&self) -> &[(IpAddr, u8)] {
+        &self.prefixes
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+impl PartialEq for IpTrie {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefixes == other.prefixes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trie(prefixes: &[&str]) -> IpTrie {
+        IpTrie::new(prefixes.iter().map(|p| parse_prefix(p).unwrap()))
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_prefix_membership() {
+        let t = trie(&[
+            "10.0.0.0/8",
+            "192.168.1.0/24",
+            "203.0.113.7",
+            "2001:db8::/32",
+        ]);
+        assert!(t.contains(ip("10.255.0.1")));
+        assert!(t.contains(ip("192.168.1.200")));
+        assert!(!t.contains(ip("192.168.2.1")));
+        assert!(t.contains(ip("203.0.113.7")));
+        assert!(!t.contains(ip("203.0.113.8")));
+        assert!(t.contains(ip("2001:db8:1::1")));
+        assert!(!t.contains(ip("2001:db9::1")));
+        // Families are kept apart: ::a00:1 has the same low bits as 10.0.0.1
+        assert!(!t.contains(ip("::a00:1")));
+        assert!(trie(&["0.0.0.0/0"]).contains(ip("8.8.8.8")));
+        assert!(!trie(&["0.0.0.0/0"]).contains(ip("::1")));
+        assert!(!trie(&[]).contains(ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn test_overlapping_prefixes() {
+        // A longer prefix under a shorter one adds nothing, in either insertion order
+        let a = trie(&["10.0.0.0/8", "10.1.0.0/16"]);
+        let b = trie(&["10.1.0.0/16", "10.0.0.0/8"]);
+        assert_eq!(a, b);
+        for t in [a, b] {
+            assert!(t.contains(ip("10.2.0.1")));
+            assert!(t.contains(ip("10.1.0.1")));
+            assert!(!t.contains(ip("11.0.0.1")));
+        }
+    }
+
+    #[test]
+    fn test_parse_prefix() {
+        assert_eq!(parse_prefix("10.1.2.3/8").unwrap(), (ip("10.0.0.0"), 8));
+        assert_eq!(parse_prefix("::1").unwrap(), (ip("::1"), 128));
+        assert_eq!(
+            parse_prefix("2001:db8::ff/64").unwrap(),
+            (ip("2001:db8::"), 64)
+        );
+        for bad in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0.0/+8",
+            "10.0.0/8",
+            "x/8",
+        ] {
+            assert!(parse_prefix(bad).is_err(), "{bad}");
+        }
+        assert_eq!(
+            parse_prefix("x/8").unwrap_err().to_string(),
+            "\"x/8\" is not a valid IP address or CIDR prefix"
+        );
+    }
+}
@@ -3,7 +3,9 @@
 //! This module provides FieldType and LiteralValue enums, covering all supported types.
 
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::net::IpAddr;
 use std::sync::Arc;
 
@@ -14,8 +16,14 @@ pub enum FieldType {
     Int,
     Bool,
     Ip,
+    DateTime,
     Array(Box<FieldType>),
     Map(Box<FieldType>),
+    /// An unordered set of primitive values; `in` against a set field is a hash lookup.
+    Set(Box<FieldType>),
+    /// Bytes restricted to one of the named variants, checked when values are set and
+    /// when filters compare the field against literals.
+    Enum(Vec<String>),
     Unknown, // Added for type inference failures
 }
 
@@ -30,16 +38,70 @@ pub enum LiteralValue {
     Int(i64),
     Bool(bool),
     Ip(IpAddr),
+    /// A point in time as milliseconds since the Unix epoch (UTC).
+    DateTime(i64),
     #[serde(
         serialize_with = "serialize_arc_vec_lv",
         deserialize_with = "deserialize_arc_vec_lv"
     )]
     Array(Arc<Vec<LiteralValue>>),
+    /// String-keyed entries, serialized with the keys sorted so output is deterministic.
     #[serde(
         serialize_with = "serialize_arc_map_lv",
         deserialize_with = "deserialize_arc_map_lv"
     )]
     Map(Arc<HashMap<String, LiteralValue>>),
+    /// Distinct primitive values, serialized in sorted order so output is deterministic.
+    /// Maps, arrays and sets hash coarsely and should not be used as elements.
+    #[serde(
+        serialize_with = "serialize_arc_set_lv",
+        deserialize_with = "deserialize_arc_set_lv"
+    )]
+    Set(Arc<HashSet<LiteralValue>>),
+    /// The `null` literal: an explicitly absent value, accepted by fields of any type.
+    Null,
+}
+
+impl From<i64> for LiteralValue {
+    fn from(value: i64) -> Self {
+        LiteralValue::Int(value)
+    }
+}
+
+impl From<bool> for LiteralValue {
+    fn from(value: bool) -> Self {
+        LiteralValue::Bool(value)
+    }
+}
+
+impl From<&str> for LiteralValue {
+    fn from(value: &str) -> Self {
+        LiteralValue::Bytes(Arc::new(value.as_bytes().to_vec()))
+    }
+}
+
+impl From<String> for LiteralValue {
+    fn from(value: String) -> Self {
+        LiteralValue::Bytes(Arc::new(value.into_bytes()))
+    }
+}
+
+impl From<Vec<u8>> for LiteralValue {
+    fn from(value: Vec<u8>) -> Self {
+        LiteralValue::Bytes(Arc::new(value))
+    }
+}
+
+impl From<IpAddr> for LiteralValue {
+    fn from(value: IpAddr) -> Self {
+        LiteralValue::Ip(value)
+    }
+}
+
+impl From<Vec<LiteralValue>> for LiteralValue {
+    fn from(value: Vec<LiteralValue>) -> Self {
+        LiteralValue::Array(Arc::new(value))
+    }
 }
 
 impl PartialEq for LiteralValue {
@@ -49,8 +111,11 @@ impl PartialEq for LiteralValue {
             (LiteralValue::Int(a), LiteralValue::Int(b)) => a == b,
             (LiteralValue::Bool(a), LiteralValue::Bool(b)) => a == b,
             (LiteralValue::Ip(a), LiteralValue::Ip(b)) => a == b,
+            (LiteralValue::DateTime(a), LiteralValue::DateTime(b)) => a == b,
             (LiteralValue::Array(a), LiteralValue::Array(b)) => a == b,
             (LiteralValue::Map(a), LiteralValue::Map(b)) => a == b,
+            (LiteralValue::Set(a), LiteralValue::Set(b)) => a == b,
+            (LiteralValue::Null, LiteralValue::Null) => true,
             _ => false,
         }
     }
@@ -58,6 +123,24 @@ impl PartialEq for LiteralValue {
 
 impl Eq for LiteralValue {}
 
+// Consistent with `PartialEq`: bytes hash by content, and maps and sets only by size since
+// their iteration order is unspecified.
+impl Hash for LiteralValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LiteralValue::Bytes(b) => b.as_slice().hash(state),
+            LiteralValue::Int(i) | LiteralValue::DateTime(i) => i.hash(state),
+            LiteralValue::Bool(b) => b.hash(state),
+            LiteralValue::Ip(ip) => ip.hash(state),
+            LiteralValue::Array(arr) => arr.hash(state),
+            LiteralValue::Map(map) => map.len().hash(state),
+            LiteralValue::Set(set) => set.len().hash(state),
+            LiteralValue::Null => {}
+        }
+    }
+}
+
 fn serialize_arc_vec_u8<S>(arc: &Arc<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -92,8 +175,10 @@ fn serialize_arc_map_lv<S>(
 where
     S: Serializer,
 {
-    let map = arc.as_ref();
-    map.serialize(serializer)
+    // Sorted by key, so the same map always serializes the same way
+    let mut entries: Vec<_> = arc.iter().collect();
+    entries.sort_unstable_by_key(|(key, _)| *key);
+    serializer.collect_map(entries)
 }
 fn deserialize_arc_map_lv<'de, D>(
     deserializer: D,
@@ -104,17 +189,126 @@ where
     let m: HashMap<String, LiteralValue> = Deserialize::deserialize(deserializer)?;
     Ok(Arc::new(m))
 }
+fn serialize_arc_set_lv<S>(
+    arc: &Arc<HashSet<LiteralValue>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(sorted_elements(arc))
+}
+// The elements of a set in a fixed order, so the same set always serializes the same way:
+// by variant, then by value. Arrays compare element-wise, while maps and sets, which
+// shouldn't be elements, only by size.
+pub(crate) fn sorted_elements(set: &HashSet<LiteralValue>) -> Vec<&LiteralValue> {
+    fn rank(val: &LiteralValue) -> u8 {
+        match val {
+            LiteralValue::Bytes(_) => 0,
+            LiteralValue::Int(_) => 1,
+            LiteralValue::Bool(_) => 2,
+            LiteralValue::Ip(_) => 3,
+            LiteralValue::DateTime(_) => 4,
+            LiteralValue::Array(_) => 5,
+            LiteralValue::Map(_) => 6,
+            LiteralValue::Set(_) => 7,
+            LiteralValue::Null => 8,
+        }
+    }
+    fn compare(a: &LiteralValue, b: &LiteralValue) -> Ordering {
+        match (a, b) {
+            (LiteralValue::Bytes(x), LiteralValue::Bytes(y)) => x.cmp(y),
+            (LiteralValue::Int(x), LiteralValue::Int(y))
+            | (LiteralValue::DateTime(x), LiteralValue::DateTime(y)) => x.cmp(y),
+            (LiteralValue::Bool(x), LiteralValue::Bool(y)) => x.cmp(y),
+            (LiteralValue::Ip(x), LiteralValue::Ip(y)) => x.cmp(y),
+            (LiteralValue::Array(x), LiteralValue::Array(y)) => x
+                .iter()
+                .zip(y.iter())
+                .map(|(x, y)| compare(x, y))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| x.len().cmp(&y.len())),
+            (LiteralValue::Map(x), LiteralValue::Map(y)) => x.len().cmp(&y.len()),
+            (LiteralValue::Set(x), LiteralValue::Set(y)) => x.len().cmp(&y.len()),
+            _ => rank(a).cmp(&rank(b)),
+        }
+    }
+    let mut elements: Vec<_> = set.iter().collect();
+    elements.sort_by(|a, b| compare(a, b));
+    elements
+}
+fn deserialize_arc_set_lv<'de, D>(deserializer: D) -> Result<Arc<HashSet<LiteralValue>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let v: Vec<LiteralValue> = Deserialize::deserialize(deserializer)?;
+    Ok(Arc::new(v.into_iter().collect()))
+}
 
 impl FieldType {
     pub fn is_primitive(&self) -> bool {
         matches!(
             self,
-            FieldType::Bytes | FieldType::Int | FieldType::Bool | FieldType::Ip
+            FieldType::Bytes
+                | FieldType::Int
+                | FieldType::Bool
+                | FieldType::Ip
+                | FieldType::DateTime
+                | FieldType::Enum(_)
         )
     }
+    /// Whether a (non-null) value can be stored in a field of this type. An empty array
+    /// or set fits any array or set type, since its element type can't be inferred.
+    pub(crate) fn accepts_value(&self, value: &LiteralValue) -> bool {
+        match (self, value) {
+            (FieldType::Enum(_), LiteralValue::Bytes(b)) => return self.has_variant(b),
+            (FieldType::Array(elem), LiteralValue::Array(items)) if elem.has_enum() => {
+                return items.iter().all(|v| elem.accepts_value(v))
+            }
+            (FieldType::Set(elem), LiteralValue::Set(items)) if elem.has_enum() => {
+                return items.iter().all(|v| elem.accepts_value(v))
+            }
+            (FieldType::Map(elem), LiteralValue::Map(entries)) if elem.has_enum() => {
+                return entries.values().all(|v| elem.accepts_value(v))
+            }
+            _ => {}
+        }
+        let value_type = value.get_type();
+        match (self, &value_type) {
+            (FieldType::Array(_), FieldType::Array(elem))
+            | (FieldType::Set(_), FieldType::Set(elem))
+                if **elem == FieldType::Unknown =>
+            {
+                true
+            }
+            _ => *self == value_type,
+        }
+    }
+
+    /// Whether `value` is one of this `Enum` type's variants; always false for other types.
+    pub(crate) fn has_variant(&self, value: &[u8]) -> bool {
+        match self {
+            FieldType::Enum(variants) => variants.iter().any(|v| v.as_bytes() == value),
+            _ => false,
+        }
+    }
+
+    // Whether an `Enum` appears anywhere in this type, so values need checking one by one
+    fn has_enum(&self) -> bool {
+        match self {
+            FieldType::Enum(_) => true,
+            FieldType::Array(elem) | FieldType::Map(elem) | FieldType::Set(elem) => elem.has_enum(),
+            _ => false,
+        }
+    }
 }
 
 impl LiteralValue {
+    /// A `Bytes` value holding a copy of `value`, e.g. `LiteralValue::bytes("GET")`.
+    pub fn bytes(value: impl AsRef<[u8]>) -> Self {
+        LiteralValue::Bytes(Arc::new(value.as_ref().to_vec()))
+    }
+
     /// Infers the type of this literal value.
     /// For arrays/maps, if empty, returns Array(Unknown)/Map(Unknown) unless a hint is provided.
     pub fn get_type(&self) -> FieldType {
@@ -129,6 +323,8 @@ impl LiteralValue {
             LiteralValue::Int(_) => FieldType::Int,
             LiteralValue::Bool(_) => FieldType::Bool,
             LiteralValue::Ip(_) => FieldType::Ip,
+            LiteralValue::DateTime(_) => FieldType::DateTime,
+            LiteralValue::Null => FieldType::Unknown,
             LiteralValue::Array(vals) => {
                 let vals = &**vals;
                 if vals.is_empty() {
@@ -148,6 +344,19 @@ impl LiteralValue {
                     }
                 }
             }
+            LiteralValue::Set(set) => {
+                let mut iter = set.iter();
+                match iter.next().map(|v| v.get_type()) {
+                    Some(first_ty) if iter.all(|v| v.get_type() == first_ty) => {
+                        FieldType::Set(Box::new(first_ty))
+                    }
+                    Some(_) => FieldType::Set(Box::new(FieldType::Unknown)),
+                    None => match hint {
+                        Some(FieldType::Set(elem_ty)) => FieldType::Set(elem_ty.clone()),
+                        _ => FieldType::Set(Box::new(FieldType::Unknown)),
+                    },
+                }
+            }
             LiteralValue::Map(map) => {
                 let map = &**map;
                 if map.is_empty() {
@@ -175,12 +384,142 @@ impl LiteralValue {
     }
 }
 
+/// Parse an RFC 3339 timestamp such as `2024-01-01T00:00:00Z` or
+/// `2024-01-01T12:30:00.250+02:00` into milliseconds since the Unix epoch.
+/// Fractional seconds beyond millisecond precision are truncated.
+pub fn parse_rfc3339(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b't' | b' ') {
+        return None;
+    }
+    if b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        if !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let mut rest = &s[19..];
+    let mut millis = 0;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return None;
+        }
+        let padded = format!("{:0<3}", &frac[..len.min(3)]);
+        millis = padded.parse::<i64>().ok()?;
+        rest = &frac[len..];
+    }
+    let offset_secs = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let ob = rest.as_bytes();
+            if ob.len() != 6 || !matches!(ob[0], b'+' | b'-') || ob[3] != b':' {
+                return None;
+            }
+            let digits = |r: std::ops::Range<usize>| -> Option<i64> {
+                let d = rest.get(r)?;
+                d.bytes()
+                    .all(|c| c.is_ascii_digit())
+                    .then(|| d.parse().ok())?
+            };
+            let (oh, om) = (digits(1..3)?, digits(4..6)?);
+            if oh > 23 || om > 59 {
+                return None;
+            }
+            let sign = if ob[0] == b'-' { -1 } else { 1 };
+            sign * (oh * 3600 + om * 60)
+        }
+    };
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    Some(secs * 1000 + millis)
+}
+
+/// Format milliseconds since the Unix epoch as an RFC 3339 timestamp in UTC, such as
+/// `2024-01-01T00:00:00Z`, with fractional seconds only when there are any. The inverse
+/// of [`parse_rfc3339`] for years 0 through 9999.
+pub fn format_rfc3339(millis: i64) -> String {
+    let (secs, millis) = (millis.div_euclid(1000), millis.rem_euclid(1000));
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let frac = if millis == 0 {
+        String::new()
+    } else {
+        format!(".{millis:03}")
+    };
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}{frac}Z")
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::net::IpAddr;
     use std::str::FromStr;
 
+    #[test]
+    fn test_literal_conversions() {
+        let get = LiteralValue::Bytes(Arc::new(b"GET".to_vec()));
+        assert_eq!(LiteralValue::bytes("GET"), get);
+        assert_eq!(LiteralValue::bytes(b"GET"), get);
+        assert_eq!(LiteralValue::from("GET"), get);
+        assert_eq!(LiteralValue::from("GET".to_string()), get);
+        assert_eq!(LiteralValue::from(b"GET".to_vec()), get);
+        assert_eq!(LiteralValue::from(42), LiteralValue::Int(42));
+        assert_eq!(LiteralValue::from(true), LiteralValue::Bool(true));
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(LiteralValue::from(ip), LiteralValue::Ip(ip));
+    }
+
     #[test]
     fn test_field_type_is_primitive() {
         assert!(FieldType::Int.is_primitive());
@@ -191,6 +530,37 @@ mod tests {
         assert!(!FieldType::Map(Box::new(FieldType::Int)).is_primitive());
     }
 
+    #[test]
+    fn test_enum_accepts_only_its_variants() {
+        let proto = FieldType::Enum(vec!["tcp".into(), "udp".into()]);
+        assert!(proto.is_primitive());
+        let bytes = |s: &str| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()));
+        assert!(proto.accepts_value(&bytes("udp")));
+        assert!(!proto.accepts_value(&bytes("sctp")));
+        assert!(!proto.accepts_value(&LiteralValue::Int(6)));
+        let protos = FieldType::Array(Box::new(proto));
+        assert!(protos.accepts_value(&LiteralValue::Array(Arc::new(vec![bytes("tcp")]))));
+        assert!(!protos.accepts_value(&LiteralValue::Array(Arc::new(vec![
+            bytes("tcp"),
+            bytes("icmp")
+        ]))));
+    }
+
+    #[test]
+    fn test_literal_value_hash_matches_eq() {
+        let set: std::collections::HashSet<LiteralValue> = [
+            LiteralValue::Bytes(Arc::new(b"a".to_vec())),
+            LiteralValue::Bytes(Arc::new(b"a".to_vec())),
+            LiteralValue::Int(1),
+            LiteralValue::DateTime(1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&LiteralValue::Int(1)));
+        assert!(!set.contains(&LiteralValue::Bool(true)));
+    }
+
     #[test]
     fn test_literal_value_get_type() {
         assert_eq!(LiteralValue::Int(1).get_type(), FieldType::Int);
@@ -237,4 +607,94 @@ mod tests {
         let deser: LiteralValue = serde_json::from_str(&json).unwrap();
         assert_eq!(val, deser);
     }
+
+    #[test]
+    fn test_map_serializes_with_sorted_keys() {
+        let entries: Vec<_> = ["b", "a", "c", "aa"]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), LiteralValue::Int(i as i64)))
+            .collect();
+        // Built in opposite orders, so the hash maps may iterate differently
+        let map = LiteralValue::Map(Arc::new(entries.iter().cloned().collect()));
+        let other = LiteralValue::Map(Arc::new(entries.into_iter().rev().collect()));
+        assert_eq!(map, other);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, serde_json::to_string(&other).unwrap());
+        assert_eq!(
+            json,
+            r#"{"Map":{"a":{"Int":1},"aa":{"Int":3},"b":{"Int":0},"c":{"Int":2}}}"#
+        );
+        let deser: LiteralValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(map, deser);
+    }
+
+    #[test]
+    fn test_set_type_and_serde() {
+        let set = LiteralValue::Set(Arc::new(
+            [
+                LiteralValue::Int(1),
+                LiteralValue::Int(2),
+                LiteralValue::Int(1),
+            ]
+            .into_iter()
+            .collect(),
+        ));
+        assert_eq!(set.get_type(), FieldType::Set(Box::new(FieldType::Int)));
+        let json = serde_json::to_string(&set).unwrap();
+        let deser: LiteralValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(set, deser);
+
+        // Elements serialize sorted, whatever order the hash set iterates in
+        let values: Vec<_> = (0..32)
+            .map(LiteralValue::Int)
+            .chain([LiteralValue::from("b"), LiteralValue::from("a")])
+            .collect();
+        let set = LiteralValue::Set(Arc::new(values.iter().cloned().collect()));
+        let other = LiteralValue::Set(Arc::new(values.into_iter().rev().collect()));
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(json, serde_json::to_string(&other).unwrap());
+        assert!(json.starts_with(r#"{"Set":[{"Bytes":[97]},{"Bytes":[98]},{"Int":0},{"Int":1},"#));
+        let empty = LiteralValue::Set(Arc::default());
+        let hint = FieldType::Set(Box::new(FieldType::Ip));
+        assert_eq!(empty.get_type_with_hint(Some(&hint)), hint);
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339("2024-01-01T00:00:00Z"),
+            Some(1_704_067_200_000)
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29T12:00:00.123456+02:00"),
+            Some(1_709_200_800_123)
+        );
+        assert_eq!(parse_rfc3339("1969-12-31T23:59:59.5Z"), Some(-500));
+        assert_eq!(parse_rfc3339("2023-02-29T00:00:00Z"), None);
+        assert_eq!(parse_rfc3339("2024-01-01T00:00:00"), None);
+        assert_eq!(parse_rfc3339("2024-01-01"), None);
+
+        for text in [
+            "1970-01-01T00:00:00Z",
+            "1969-12-31T23:59:59.500Z",
+            "2024-02-29T10:00:00.123Z",
+            "2000-03-01T23:59:59Z",
+            "0001-01-01T00:00:00Z",
+            "9999-12-31T23:59:59.999Z",
+        ] {
+            let millis = parse_rfc3339(text).unwrap();
+            assert_eq!(format_rfc3339(millis), text);
+        }
+    }
+
+    #[test]
+    fn test_datetime_serde_roundtrip() {
+        let val = LiteralValue::DateTime(1_704_067_200_000);
+        assert_eq!(val.get_type(), FieldType::DateTime);
+        let json = serde_json::to_string(&val).unwrap();
+        let deser: LiteralValue = serde_json::from_str(&json).unwrap();
+        assert_eq!(val, deser);
+    }
 }
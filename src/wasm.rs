@@ -0,0 +1,85 @@
+//! WASM module: `wasm-bindgen` exports for running filters in the browser.
+//!
+//! Enabled with the `wasm` feature. Schemas and contexts cross the boundary as JSON
+//! strings and errors as their display strings.
+
+use crate::context::FilterContext;
+use crate::filter::CompiledFilter;
+use crate::schema::FilterSchemaBuilder;
+use crate::types::FieldType;
+use crate::WirerustEngine;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+/// A [`WirerustEngine`] with the built-in functions, for use from JavaScript.
+#[wasm_bindgen]
+pub struct WasmEngine {
+    engine: WirerustEngine,
+}
+
+/// A filter compiled by [`WasmEngine::compile`].
+#[wasm_bindgen]
+pub struct WasmFilter {
+    filter: CompiledFilter,
+}
+
+#[wasm_bindgen]
+impl WasmEngine {
+    /// Create an engine from a JSON object mapping field names to types, e.g.
+    /// `{"port": "Int", "tags": {"Array": "Bytes"}}`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(schema_json: &str) -> Result<WasmEngine, String> {
+        let fields: HashMap<String, FieldType> =
+            serde_json::from_str(schema_json).map_err(|e| format!("Invalid schema: {e}"))?;
+        let schema = fields
+            .into_iter()
+            .fold(FilterSchemaBuilder::new(), |b, (name, ty)| {
+                b.field(name, ty)
+            })
+            .build();
+        Ok(Self {
+            engine: WirerustEngine::new(schema),
+        })
+    }
+
+    /// Parse and compile a filter string.
+    pub fn compile(&self, filter: &str) -> Result<WasmFilter, String> {
+        self.engine
+            .parse_and_compile(filter)
+            .map(|filter| WasmFilter { filter })
+            .map_err(|e| e.to_string())
+    }
+
+    /// Execute a compiled filter against a JSON object of field values.
+    pub fn execute(&self, filter: &WasmFilter, context_json: &str) -> Result<bool, String> {
+        let json: serde_json::Value =
+            serde_json::from_str(context_json).map_err(|e| format!("Invalid context: {e}"))?;
+        let ctx =
+            FilterContext::from_json(&json, self.engine.schema()).map_err(|e| e.to_string())?;
+        self.engine
+            .execute(&filter.filter, &ctx)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasm_engine_round_trip() {
+        let engine = WasmEngine::new(r#"{"port": "Int", "tags": {"Array": "Bytes"}}"#).unwrap();
+        let filter = engine
+            .compile(r#"port == 80 && tags contains "a""#)
+            .unwrap();
+        assert_eq!(
+            engine.execute(&filter, r#"{"port": 80, "tags": ["a"]}"#),
+            Ok(true)
+        );
+        assert_eq!(engine.execute(&filter, r#"{"port": 81}"#), Ok(false));
+        assert!(engine.execute(&filter, r#"{"port": "80"}"#).is_err());
+        assert!(engine.execute(&filter, "not json").is_err());
+        assert!(engine.compile("port ==").is_err());
+        assert!(WasmEngine::new(r#"{"port": "Float"}"#).is_err());
+    }
+}
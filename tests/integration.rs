@@ -34,25 +34,19 @@ fn test_filter_matches() {
     let functions = make_functions();
     let filter_str = r#"http.method == "GET" && port in {80 443} && len(tags) == 2"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
     ctx.set(
         "tags",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Bytes(Arc::new(b"foo".to_vec()).into()),
-                LiteralValue::Bytes(Arc::new(b"bar".to_vec()).into()),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::bytes("foo"),
+            LiteralValue::bytes("bar"),
+        ])),
         &schema,
     )
     .unwrap();
@@ -66,15 +60,12 @@ fn test_filter_does_not_match() {
     let functions = make_functions();
     let filter_str = r#"http.method == "POST" || port == 22"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
     assert!(!filter.execute(&ctx).unwrap());
 }
@@ -85,25 +76,19 @@ fn test_filter_with_function_call() {
     let functions = make_functions();
     let filter_str = r#"upper(http.method) == "GET""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"get".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("get"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
     ctx.set(
         "tags",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Bytes(Arc::new(b"foo".to_vec()).into()),
-                LiteralValue::Bytes(Arc::new(b"bar".to_vec()).into()),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::bytes("foo"),
+            LiteralValue::bytes("bar"),
+        ])),
         &schema,
     )
     .unwrap();
@@ -119,17 +104,13 @@ fn test_regex_matches() {
     let functions = make_functions();
     let filter_str = r#"user_agent matches "Mozilla.*Firefox""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "user_agent",
-        LiteralValue::Bytes(
-            Arc::new(
-                b"Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0".to_vec(),
-            )
-            .into(),
-        ),
+        LiteralValue::bytes("Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0"),
         &schema,
     )
     .unwrap();
@@ -144,17 +125,13 @@ fn test_regex_does_not_match() {
     let functions = make_functions();
     let filter_str = r#"user_agent matches "Chrome.*""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "user_agent",
-        LiteralValue::Bytes(
-            Arc::new(
-                b"Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0".to_vec(),
-            )
-            .into(),
-        ),
+        LiteralValue::bytes("Mozilla/5.0 (Windows NT 10.0; rv:91.0) Gecko/20100101 Firefox/91.0"),
         &schema,
     )
     .unwrap();
@@ -169,15 +146,12 @@ fn test_regex_with_simple_pattern() {
     let functions = make_functions();
     let filter_str = r#"http.method matches "GET|POST""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
 
     assert!(filter.execute(&ctx).unwrap());
 }
@@ -190,7 +164,8 @@ fn test_ip_address_equality() {
     // For now, we'll test IP comparison by setting the IP value directly in context
     let filter_str = r#"ip == "192.168.1.1""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     let ip = IpAddr::from_str("192.168.1.1").unwrap();
@@ -209,17 +184,175 @@ fn test_ip_address_in_set() {
     let functions = make_functions();
     let filter_str = r#"ip in {"192.168.1.1" "10.0.0.1" "172.16.0.1"}"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     let ip = IpAddr::from_str("10.0.0.1").unwrap();
     ctx.set("ip", LiteralValue::Ip(ip), &schema).unwrap();
 
-    // This will fail because the parser doesn't support IP literals yet
-    // For now, we'll just test that the filter compiles and executes without panicking
-    let _result = filter.execute(&ctx);
-    // The result will be false because the comparison won't work as expected
-    // This is expected behavior until IP literal parsing is implemented
+    // Quoted set elements are parsed as addresses when compared against an IP field
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_in_set_field() {
+    let engine = WirerustEngineBuilder::new()
+        .field("ip", FieldType::Ip)
+        .field("blocked", FieldType::Set(Box::new(FieldType::Ip)))
+        .build();
+    let filter = engine.parse_and_compile("ip in blocked").unwrap();
+    let blocked = ["10.0.0.1", "10.0.0.2"]
+        .iter()
+        .map(|ip| LiteralValue::Ip(ip.parse().unwrap()));
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_ip("ip", "10.0.0.2".parse().unwrap())
+        .unwrap()
+        .set_set("blocked", blocked)
+        .unwrap()
+        .build();
+    assert_eq!(ctx.get_set("blocked", engine.schema()).unwrap().len(), 2);
+    assert!(engine.execute(&filter, &ctx).unwrap());
+}
+
+#[test]
+fn test_normalize_ips() {
+    let build = |normalize: bool| {
+        let builder = WirerustEngineBuilder::new()
+            .field("src", FieldType::Ip)
+            .field("dst", FieldType::Ip)
+            .field("allowed", FieldType::Array(Box::new(FieldType::Ip)));
+        if normalize {
+            builder.normalize_ips().build()
+        } else {
+            builder.build()
+        }
+    };
+    let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+    let mut ctx = FilterContextBuilder::new(build(true).schema())
+        .set_ip("src", ip("::ffff:192.168.1.1"))
+        .unwrap()
+        .set_ip("dst", ip("192.168.1.1"))
+        .unwrap()
+        .build();
+    ctx.set(
+        "allowed",
+        LiteralValue::Array(Arc::new(vec![LiteralValue::Ip(ip("192.168.1.1"))])),
+        build(true).schema(),
+    )
+    .unwrap();
+    // Enough addresses to take the hashed set path
+    let many: String = (1..=20).map(|i| format!(r#""10.0.0.{i}" "#)).collect();
+    let cases = [
+        "src == dst".to_string(),
+        "dst == src".to_string(),
+        r#"src in {"192.168.1.1"}"#.to_string(),
+        r#"dst in {"::ffff:192.168.1.1"}"#.to_string(),
+        format!(r#"src in {{{many} "192.168.1.1"}}"#),
+        format!(r#"dst in {{{many} "::ffff:192.168.1.1"}}"#),
+        "src in allowed".to_string(),
+        r#"src in {"192.168.0.0/16"}"#.to_string(),
+        r#"dst in {"::ffff:192.168.0.0/112"}"#.to_string(),
+    ];
+    for src in &cases {
+        let strict = build(false);
+        let filter = strict.parse_and_compile(src).unwrap();
+        assert!(!strict.execute(&filter, &ctx).unwrap(), "{src}");
+        for engine in [build(true), build(true).with_compiler(ClosureCompiler)] {
+            let filter = engine.parse_and_compile(src).unwrap();
+            assert!(engine.execute(&filter, &ctx).unwrap(), "{src}");
+        }
+    }
+    let engine = build(true);
+    for src in [
+        "src != dst",
+        r#"src not in {"192.168.1.1"}"#,
+        r#"src in {"::ffff:192.168.1.2" "::1"}"#,
+    ] {
+        let filter = engine.parse_and_compile(src).unwrap();
+        assert!(!engine.execute(&filter, &ctx).unwrap(), "{src}");
+    }
+}
+
+#[test]
+fn test_cidr_sets() {
+    let ip = |s: &str| -> IpAddr { s.parse().unwrap() };
+    for engine in [
+        WirerustEngineBuilder::new()
+            .field("src", FieldType::Ip)
+            .build(),
+        WirerustEngineBuilder::new()
+            .field("src", FieldType::Ip)
+            .build()
+            .with_compiler(ClosureCompiler),
+    ] {
+        let allow = engine
+            .parse_and_compile(
+                r#"src in {"10.0.0.0/8" "192.168.1.0/24" "2001:db8::/32" "203.0.113.9"}"#,
+            )
+            .unwrap();
+        let deny = engine
+            .parse_and_compile(
+                r#"src not in {"10.0.0.0/8" "192.168.1.0/24" "2001:db8::/32" "203.0.113.9"}"#,
+            )
+            .unwrap();
+        for (addr, expected) in [
+            ("10.20.30.40", true),
+            ("192.168.1.77", true),
+            ("192.168.2.1", false),
+            ("2001:db8:ffff::1", true),
+            ("2001:db9::1", false),
+            ("203.0.113.9", true),
+            ("203.0.113.10", false),
+        ] {
+            let ctx = FilterContextBuilder::new(engine.schema())
+                .set_ip("src", ip(addr))
+                .unwrap()
+                .build();
+            assert_eq!(engine.execute(&allow, &ctx).unwrap(), expected, "{addr}");
+            assert_eq!(engine.execute(&deny, &ctx).unwrap(), !expected, "{addr}");
+        }
+        let valid = engine
+            .parse_filter(r#"src in {"10.0.0.0/8" "10.0.0.1"}"#)
+            .unwrap();
+        assert!(engine.validate(&valid).is_empty());
+        for bad in [r#"src in {"10.0.0.0/40"}"#, r#"src in {"10.0.0.0/8" "x"}"#] {
+            assert!(engine.parse_and_compile(bad).is_err(), "{bad}");
+            let expr = engine.parse_filter(bad).unwrap();
+            assert!(!engine.validate(&expr).is_empty(), "{bad}");
+        }
+    }
+}
+
+#[test]
+fn test_int_range_sets() {
+    let build = || {
+        WirerustEngineBuilder::new()
+            .field("port", FieldType::Int)
+            .field("ports", FieldType::Array(Box::new(FieldType::Int)))
+    };
+    let engines = [build().build(), build().compiler(ClosureCompiler).build()];
+    let eval = |src: &str, port: i64| {
+        let results: Vec<bool> = engines
+            .iter()
+            .map(|engine| {
+                let ctx = FilterContextBuilder::new(engine.schema())
+                    .set_int("port", port)
+                    .unwrap()
+                    .build();
+                let filter = engine.parse_and_compile(src).unwrap();
+                engine.execute(&filter, &ctx).unwrap()
+            })
+            .collect();
+        assert_eq!(results[0], results[1], "{src}");
+        results[0]
+    };
+    assert!(eval("port in {1..65535}", 443));
+    assert!(!eval("port in {1..1023}", 8080));
+    assert!(eval("port in {1..1023 8080 8443}", 8080));
+    assert!(eval("port not in {1..1023, 8000..8999}", 9000));
+    assert!(!eval("port not in {1..1023, 8000..8999}", 8001));
+    assert!(eval("any(ports, _ in {1..10}) || port in {0x50..0x50}", 80));
 }
 
 // Boolean tests
@@ -229,7 +362,8 @@ fn test_boolean_true() {
     let functions = make_functions();
     let filter_str = r#"enabled == true"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("enabled", LiteralValue::Bool(true), &schema)
@@ -244,7 +378,8 @@ fn test_boolean_false() {
     let functions = make_functions();
     let filter_str = r#"enabled == false"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("enabled", LiteralValue::Bool(false), &schema)
@@ -259,7 +394,8 @@ fn test_boolean_not() {
     let functions = make_functions();
     let filter_str = r#"not enabled"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("enabled", LiteralValue::Bool(false), &schema)
@@ -276,15 +412,12 @@ fn test_simple_logical_operations() {
     let filter_str = r#"http.method == "POST" && port == 443"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Simple logical operations parsed expression: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(443), &schema).unwrap();
 
     let result = filter.execute(&ctx);
@@ -304,15 +437,12 @@ fn test_parenthesized_expression() {
     let filter_str = r#"(http.method == "POST") && (port == 443)"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Parenthesized expression parsed: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(443), &schema).unwrap();
 
     let result = filter.execute(&ctx);
@@ -332,15 +462,12 @@ fn test_or_expression() {
     let filter_str = r#"http.method == "GET" || http.method == "POST" || http.method == "PUT""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("OR expression parsed: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
 
     let result = filter.execute(&ctx);
     println!(
@@ -360,15 +487,12 @@ fn test_mixed_and_or_expression() {
         r#"(http.method == "GET" || http.method == "POST") && (port == 80 || port == 443)"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Mixed AND/OR expression parsed: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(443), &schema).unwrap();
 
     let result = filter.execute(&ctx);
@@ -388,7 +512,8 @@ fn test_with_enabled_field() {
     let filter_str = r#"enabled"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Enabled field expression parsed: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("enabled", LiteralValue::Bool(true), &schema)
@@ -411,12 +536,16 @@ fn test_with_len_function() {
     let filter_str = r#"len(headers) > 0"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Len function expression parsed: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "headers",
-        LiteralValue::Array(Arc::new(vec![LiteralValue::Int(100), LiteralValue::Int(200)]).into()),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(100),
+            LiteralValue::Int(200),
+        ])),
         &schema,
     )
     .unwrap();
@@ -438,15 +567,12 @@ fn test_complex_logical_operations() {
     let filter_str = r#"(http.method == "GET" || http.method == "POST") && (port == 80 || port == 443) && enabled"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Parsed expression: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(443), &schema).unwrap();
     ctx.set("enabled", LiteralValue::Bool(true), &schema)
         .unwrap();
@@ -466,15 +592,12 @@ fn test_nested_parentheses() {
     let functions = make_functions();
     let filter_str = r#"((http.method == "GET") && (port in {80 443})) || ((http.method == "POST") && (port == 8080))"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"POST".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("POST"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(8080), &schema).unwrap();
 
     assert!(filter.execute(&ctx).unwrap());
@@ -487,7 +610,8 @@ fn test_numeric_comparisons() {
     let functions = make_functions();
     let filter_str = r#"status_code >= 200 && status_code < 300 && request_size > 1000"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("status_code", LiteralValue::Int(200), &schema)
@@ -504,7 +628,8 @@ fn test_not_in_operator() {
     let functions = make_functions();
     let filter_str = r#"port not in {22 25 110}"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
@@ -519,19 +644,17 @@ fn test_sum_function() {
     let functions = make_functions();
     let filter_str = r#"sum(headers) > 100"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "headers",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Int(50),
-                LiteralValue::Int(60),
-                LiteralValue::Int(70),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(50),
+            LiteralValue::Int(60),
+            LiteralValue::Int(70),
+        ])),
         &schema,
     )
     .unwrap();
@@ -545,32 +668,27 @@ fn test_multiple_function_calls() {
     let functions = make_functions();
     let filter_str = r#"len(tags) == 3 && sum(headers) == 180"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "tags",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Bytes(Arc::new(b"tag1".to_vec()).into()),
-                LiteralValue::Bytes(Arc::new(b"tag2".to_vec()).into()),
-                LiteralValue::Bytes(Arc::new(b"tag3".to_vec()).into()),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::bytes("tag1"),
+            LiteralValue::bytes("tag2"),
+            LiteralValue::bytes("tag3"),
+        ])),
         &schema,
     )
     .unwrap();
     ctx.set(
         "headers",
-        LiteralValue::Array(
-            Arc::new(vec![
-                LiteralValue::Int(60),
-                LiteralValue::Int(60),
-                LiteralValue::Int(60),
-            ])
-            .into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(60),
+            LiteralValue::Int(60),
+            LiteralValue::Int(60),
+        ])),
         &schema,
     )
     .unwrap();
@@ -585,15 +703,12 @@ fn test_empty_array() {
     let functions = make_functions();
     let filter_str = r#"len(tags) == 0"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "tags",
-        LiteralValue::Array(Arc::new(vec![]).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("tags", LiteralValue::Array(Arc::new(vec![])), &schema)
+        .unwrap();
 
     assert!(filter.execute(&ctx).unwrap());
 }
@@ -604,27 +719,41 @@ fn test_missing_field_returns_false() {
     let functions = make_functions();
     let filter_str = r#"http.method == "GET""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let ctx = FilterContext::new(); // Empty context
 
     assert!(!filter.execute(&ctx).unwrap());
 }
 
+#[test]
+fn test_missing_field_is_distinguishable_with_execute_checked() {
+    let build = || WirerustEngineBuilder::new().field("http.method", FieldType::Bytes);
+    for engine in [build().build(), build().compiler(ClosureCompiler).build()] {
+        let filter = engine.parse_and_compile(r#"http.method == "GET""#).unwrap();
+        assert_eq!(filter.execute_checked(&FilterContext::new()).unwrap(), None);
+        let ctx = FilterContextBuilder::new(engine.schema())
+            .set_bytes("http.method", "POST")
+            .unwrap()
+            .build();
+        assert_eq!(filter.execute_checked(&ctx).unwrap(), Some(false));
+    }
+}
+
 #[test]
 fn test_unknown_function_returns_false() {
     let schema = make_schema();
     let functions = make_functions();
     let filter_str = r#"unknown_function(tags)"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set(
         "tags",
-        LiteralValue::Array(
-            Arc::new(vec![LiteralValue::Bytes(Arc::new(b"tag1".to_vec()).into())]).into(),
-        ),
+        LiteralValue::Array(Arc::new(vec![LiteralValue::bytes("tag1")])),
         &schema,
     )
     .unwrap();
@@ -642,15 +771,12 @@ fn test_string_inequality() {
     let functions = make_functions();
     let filter_str = r#"http.method != "DELETE""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
 
     assert!(filter.execute(&ctx).unwrap());
 }
@@ -661,15 +787,12 @@ fn test_case_insensitive_comparison() {
     let functions = make_functions();
     let filter_str = r#"upper(http.method) == "GET" && lower(http.method) == "get""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"get".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("get"), &schema)
+        .unwrap();
 
     assert!(filter.execute(&ctx).unwrap());
 }
@@ -681,7 +804,8 @@ fn test_response_time_threshold() {
     let functions = make_functions();
     let filter_str = r#"response_time > 1000 && response_time <= 5000"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("response_time", LiteralValue::Int(2500), &schema)
@@ -705,15 +829,12 @@ fn test_complex_web_request_filter() {
     "#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
     println!("Parsed expression: {:#?}", expr);
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(443), &schema).unwrap();
     ctx.set("status_code", LiteralValue::Int(200), &schema)
         .unwrap();
@@ -723,7 +844,10 @@ fn test_complex_web_request_filter() {
         .unwrap();
     ctx.set(
         "headers",
-        LiteralValue::Array(Arc::new(vec![LiteralValue::Int(100), LiteralValue::Int(200)]).into()),
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(100),
+            LiteralValue::Int(200),
+        ])),
         &schema,
     )
     .unwrap();
@@ -745,15 +869,12 @@ fn test_invalid_regex_pattern() {
     let functions = make_functions();
     let filter_str = r#"user_agent matches ".*""#; // Valid regex pattern instead of invalid one
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "user_agent",
-        LiteralValue::Bytes(Arc::new(b"test".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("user_agent", LiteralValue::bytes("test"), &schema)
+        .unwrap();
 
     // Should match any string
     assert!(filter.execute(&ctx).unwrap());
@@ -765,7 +886,8 @@ fn test_type_mismatch_in_comparison() {
     let functions = make_functions();
     let filter_str = r#"port == "not_a_number""#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
@@ -781,7 +903,8 @@ fn test_boundary_values() {
     let functions = make_functions();
     let filter_str = r#"status_code >= 0 && status_code <= 999 && port > 0 && port < 65536"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
     ctx.set("status_code", LiteralValue::Int(0), &schema)
@@ -806,15 +929,12 @@ fn test_mixed_operators() {
     let functions = make_functions();
     let filter_str = r#"http.method == "GET" && port != 22 && status_code in {200 201 204} && response_time <= 1000"#;
     let expr = FilterParser::parse(filter_str, &schema).expect("parse");
-    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()));
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
 
     let mut ctx = FilterContext::new();
-    ctx.set(
-        "http.method",
-        LiteralValue::Bytes(Arc::new(b"GET".to_vec()).into()),
-        &schema,
-    )
-    .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
     ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
     ctx.set("status_code", LiteralValue::Int(201), &schema)
         .unwrap();
@@ -830,6 +950,37 @@ proptest! {
         let schema = make_schema();
         let _ = FilterParser::parse(&s, &schema);
     }
+
+    #[test]
+    fn parser_does_not_panic_on_deeply_nested_input(
+        open in prop::sample::select(vec!["(", "not ", "-", "port[", "len(", "!("]),
+        depth in 0usize..20_000,
+        inner in prop_oneof![Just("port == 80".to_string()), ".{0,16}"],
+        link in prop::sample::select(vec![" or enabled", " && port == 1", " + 1", " * port", " << 1"]),
+        links in 0usize..5_000,
+    ) {
+        // Whatever parses must also compile, however deep the tree it builds
+        let schema = Arc::new(make_schema());
+        let functions = Arc::new(make_functions());
+        let src = format!(
+            "{}{}{}{}",
+            open.repeat(depth),
+            inner,
+            link.repeat(links),
+            ")".repeat(depth)
+        );
+        if let Ok(expr) = FilterParser::parse(&src, &schema) {
+            let compiler: [&dyn Compiler; 2] = [&DefaultCompiler, &ClosureCompiler];
+            for compiler in compiler {
+                let _ = compiler.compile(
+                    expr.clone(),
+                    Arc::clone(&schema),
+                    Arc::clone(&functions),
+                    EngineOptions::default(),
+                );
+            }
+        }
+    }
 }
 
 proptest! {
@@ -847,7 +998,7 @@ proptest! {
     #[test]
     fn prop_type_inference_array_infers_type(vals in pvec(0i64..100, 0..10)) {
         use wirerust::{LiteralValue, FieldType};
-        let arr = LiteralValue::Array(Arc::new(vals.iter().map(|&i| LiteralValue::Int(i)).collect::<Vec<_>>()).into());
+        let arr = LiteralValue::Array(Arc::new(vals.iter().map(|&i| LiteralValue::Int(i)).collect::<Vec<_>>()));
         let ty = arr.get_type();
         if vals.is_empty() {
             assert_eq!(ty, FieldType::Array(Box::new(FieldType::Unknown)));
@@ -860,8 +1011,8 @@ proptest! {
     fn prop_type_inference_array_mixed_types(ints in pvec(0i64..100, 0..5), strs in pvec(".*", 0..5)) {
         use wirerust::{LiteralValue, FieldType};
         let mut vals: Vec<LiteralValue> = ints.iter().map(|&i| LiteralValue::Int(i)).collect();
-        vals.extend(strs.iter().map(|s| LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()).into())));
-        let arr = LiteralValue::Array(Arc::new(vals).into());
+        vals.extend(strs.iter().map(LiteralValue::bytes));
+        let arr = LiteralValue::Array(Arc::new(vals));
         let ty = arr.get_type();
         if ints.is_empty() || strs.is_empty() {
             // If only one type, should infer that type
@@ -887,9 +1038,9 @@ proptest! {
             map.insert(format!("k_int_{}", i), LiteralValue::Int(*v));
         }
         for (i, s) in strs.iter().enumerate() {
-            map.insert(format!("k_str_{}", i), LiteralValue::Bytes(Arc::new(s.as_bytes().to_vec()).into()));
+            map.insert(format!("k_str_{}", i), LiteralValue::bytes(s));
         }
-        let val = LiteralValue::Map(Arc::new(map).into());
+        let val = LiteralValue::Map(Arc::new(map));
         let ty = val.get_type();
         if ints.is_empty() || strs.is_empty() {
             if ints.is_empty() && !strs.is_empty() {
@@ -926,8 +1077,8 @@ proptest! {
         let filter = engine.parse_and_compile(&filter_str);
         // Context with both fields
         let mut ctx = FilterContext::new();
-        ctx.set_int("foo", int_val, &engine.schema());
-        ctx.set_bytes("bar", str_val.as_bytes(), &engine.schema());
+        ctx.set_int("foo", int_val, engine.schema());
+        ctx.set_bytes("bar", str_val.as_bytes(), engine.schema());
         if let Ok(filter) = filter {
             let _ = engine.execute(&filter, &ctx);
         }
@@ -945,7 +1096,7 @@ fn test_error_reporting_unknown_function() {
         .build();
     let filter = engine.parse_and_compile("not_a_function(foo)").unwrap();
     let mut ctx = FilterContext::new();
-    ctx.set_int("foo", 1, &engine.schema());
+    ctx.set_int("foo", 1, engine.schema());
     let result = engine.execute(&filter, &ctx);
     assert!(matches!(result, Err(WirerustError::FunctionError(_))));
 }
@@ -961,7 +1112,1144 @@ fn test_error_reporting_type_mismatch() {
         .build();
     let filter = engine.parse_and_compile("foo == \"not_an_int\"").unwrap();
     let mut ctx = FilterContext::new();
-    ctx.set_int("foo", 1, &engine.schema());
+    ctx.set_int("foo", 1, engine.schema());
     let result = engine.execute(&filter, &ctx);
     assert!(matches!(result, Ok(false)));
 }
+
+#[test]
+fn test_arithmetic_on_int_fields() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = r#"request_size / 1024 > 500 && status_code + 1 == 201"#;
+    let expr = FilterParser::parse(filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set("request_size", LiteralValue::Int(600 * 1024), &schema)
+        .unwrap();
+    ctx.set("status_code", LiteralValue::Int(200), &schema)
+        .unwrap();
+
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_execution_limits_reject_oversized_filter() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = (0..200)
+        .map(|i| format!("port == {i}"))
+        .collect::<Vec<_>>()
+        .join(" || ");
+    let expr = FilterParser::parse(&filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set("port", LiteralValue::Int(199), &schema).unwrap();
+
+    assert!(filter.execute(&ctx).unwrap());
+    let result = filter.execute_with_limits(&ctx, &ExecutionLimits::new(100, 64));
+    assert!(matches!(result, Err(WirerustError::ExecutionError(_))));
+}
+
+#[test]
+fn test_index_into_map_field() {
+    let schema = FilterSchemaBuilder::new()
+        .field("http.headers", FieldType::Map(Box::new(FieldType::Bytes)))
+        .field("tags", FieldType::Array(Box::new(FieldType::Bytes)))
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let filter = engine
+        .parse_and_compile(
+            r#"http.headers["content-type"] == "application/json" && tags[0] == "api""#,
+        )
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    let headers = [(
+        "content-type".to_string(),
+        LiteralValue::bytes("application/json"),
+    )];
+    ctx.set(
+        "http.headers",
+        LiteralValue::Map(Arc::new(headers.into_iter().collect())),
+        engine.schema(),
+    )
+    .unwrap();
+    ctx.set(
+        "tags",
+        LiteralValue::Array(Arc::new(vec![LiteralValue::bytes("api")])),
+        engine.schema(),
+    )
+    .unwrap();
+
+    assert!(engine.execute(&filter, &ctx).unwrap());
+}
+
+#[test]
+fn test_any_tag_starts_with() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = r#"any(tags, starts_with(_, "admin")) && not all(tags, _ == "admin")"#;
+    let expr = FilterParser::parse(filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set(
+        "tags",
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::bytes("user"),
+            LiteralValue::bytes("admin-ro"),
+        ])),
+        &schema,
+    )
+    .unwrap();
+
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_string_builtins() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = r#"trim(user_agent) == "curl/8.0" && substring(trim(user_agent), 0, 4) == "curl" && replace(http.method, "T", "") == "GE" && concat(http.method, " ", "/") == "GET /""#;
+    let expr = FilterParser::parse(filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set("user_agent", LiteralValue::bytes("  curl/8.0 "), &schema)
+        .unwrap();
+    ctx.set("http.method", LiteralValue::bytes("GET"), &schema)
+        .unwrap();
+
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_numeric_aggregates() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = r#"max(headers) > 500 && min(headers) == 100 && avg(headers) == 350"#;
+    let expr = FilterParser::parse(filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set(
+        "headers",
+        LiteralValue::Array(Arc::new(vec![
+            LiteralValue::Int(100),
+            LiteralValue::Int(600),
+        ])),
+        &schema,
+    )
+    .unwrap();
+
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_exists_guards_missing_field() {
+    let schema = make_schema();
+    let functions = make_functions();
+    let filter_str = r#"exists user_agent && user_agent contains "bot""#;
+    let expr = FilterParser::parse(filter_str, &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    assert!(!filter.execute(&FilterContext::new()).unwrap());
+    let mut ctx = FilterContext::new();
+    ctx.set("user_agent", LiteralValue::bytes("googlebot"), &schema)
+        .unwrap();
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_datetime_field_comparisons() {
+    let schema = FilterSchemaBuilder::new()
+        .field("ts", FieldType::DateTime)
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let filter = engine
+        .parse_and_compile("ts > 2024-01-01T00:00:00Z && ts <= 2024-06-30T23:59:59.999Z")
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set_datetime("ts", 1_717_200_000_000, engine.schema()); // 2024-06-01
+    assert!(engine.execute(&filter, &ctx).unwrap());
+    ctx.set_datetime("ts", 1_700_000_000_000, engine.schema()); // 2023-11-14
+    assert!(!engine.execute(&filter, &ctx).unwrap());
+}
+
+#[test]
+fn test_register_typed_function() {
+    let schema = make_schema();
+    let mut functions = make_functions();
+    functions.register_typed("in_range", |v: i64, lo: i64, hi: i64| v >= lo && v <= hi);
+    let expr = FilterParser::parse("in_range(port, 1024, 65535)", &schema).expect("parse");
+    let filter = CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions.clone()))
+        .expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set("port", LiteralValue::Int(8080), &schema).unwrap();
+    assert!(filter.execute(&ctx).unwrap());
+
+    // Declared argument types are checked when compiling
+    let expr = FilterParser::parse("in_range(http.method, 1, 2)", &schema).expect("parse");
+    assert!(CompiledFilter::new(expr, Arc::new(schema), Arc::new(functions)).is_err());
+}
+
+#[test]
+fn test_bare_bool_fields_in_logical_ops() {
+    let engine = WirerustEngineBuilder::new()
+        .field("port", FieldType::Int)
+        .field("enabled", FieldType::Bool)
+        .field("other", FieldType::Bool)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("port", 80)
+        .unwrap()
+        .set_bool("enabled", true)
+        .unwrap()
+        .set_bool("other", false)
+        .unwrap()
+        .build();
+    let eval = |src: &str, ctx: &FilterContext| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, ctx).unwrap()
+    };
+
+    assert!(eval("port == 80 && enabled", &ctx));
+    assert!(!eval("port == 80 && other", &ctx));
+    assert!(!eval("enabled == true && other", &ctx));
+    assert!(eval("not other && enabled", &ctx));
+    assert!(eval("other || enabled", &ctx));
+    assert!(!eval("not enabled || other", &ctx));
+    assert!(eval("true && enabled", &ctx));
+
+    // Declared but unset reads as false, and so does an identifier the schema doesn't declare
+    assert!(!eval("port == 80 && other", &FilterContext::new()));
+    assert!(!eval("port == 80 && undeclared", &ctx));
+    assert!(eval("enabled && not undeclared", &ctx));
+
+    let strict = WirerustEngineBuilder::new()
+        .field("enabled", FieldType::Bool)
+        .strict_missing_fields()
+        .build();
+    let filter = strict.parse_and_compile("true && enabled").unwrap();
+    assert!(strict.execute(&filter, &FilterContext::new()).is_err());
+    assert!(matches!(
+        strict.parse_and_compile("enabled && undeclared"),
+        Err(WirerustError::FieldNotFound(_))
+    ));
+}
+
+#[test]
+fn test_in_array_field() {
+    let engine = WirerustEngineBuilder::new()
+        .field("port", FieldType::Int)
+        .field("allowed_ports", FieldType::Array(Box::new(FieldType::Int)))
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("port", 8080)
+        .unwrap()
+        .set_array(
+            "allowed_ports",
+            vec![LiteralValue::Int(80), LiteralValue::Int(443)],
+        )
+        .unwrap()
+        .build();
+    let eval = |src: &str, ctx: &FilterContext| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, ctx).unwrap()
+    };
+
+    assert!(eval("80 in allowed_ports", &ctx));
+    assert!(!eval("8080 in allowed_ports", &ctx));
+    assert!(eval("port not in allowed_ports", &ctx));
+    assert!(eval("port - 7637 in allowed_ports && port in {8080}", &ctx));
+    // An unset array field holds nothing
+    assert!(!eval("80 in allowed_ports", &FilterContext::new()));
+}
+
+#[test]
+fn test_starts_with_any_prefix() {
+    let engine = WirerustEngineBuilder::new()
+        .field("path", FieldType::Bytes)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("path", "/V1/users")
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    assert!(!eval(r#"starts_with(path, {"/api" "/v1"})"#));
+    assert!(eval(r#"starts_with_ci(path, {"/api" "/v1"})"#));
+    assert!(eval(r#"ends_with_ci(path, {".json" "USERS"})"#));
+}
+
+#[test]
+fn test_field_defaults_for_missing_fields() {
+    let schema = FilterSchemaBuilder::new()
+        .field_with_default("status_code", FieldType::Int, LiteralValue::Int(0))
+        .unwrap()
+        .field_with_default("user_agent", FieldType::Bytes, LiteralValue::bytes(""))
+        .unwrap()
+        .field("port", FieldType::Int)
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let eval = |src: &str, ctx: &FilterContext| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, ctx).unwrap()
+    };
+
+    let empty = FilterContext::new();
+    assert!(eval("status_code == 0", &empty));
+    assert!(eval("status_code + 1 == 1", &empty));
+    assert!(eval(r#"user_agent == """#, &empty));
+    assert!(!eval("exists status_code", &empty));
+    assert!(!eval("port == 0", &empty));
+
+    // A set value wins over the default
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("status_code", 404)
+        .unwrap()
+        .build();
+    assert!(eval("status_code == 404", &ctx));
+}
+
+#[test]
+fn test_between_ranges() {
+    let engine = WirerustEngineBuilder::new()
+        .field("status_code", FieldType::Int)
+        .field("ts", FieldType::DateTime)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("status_code", 204)
+        .unwrap()
+        .set_datetime("ts", 1_717_200_000_000) // 2024-06-01
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    assert!(eval(
+        "status_code between {200 299} && not status_code between {300 399}"
+    ));
+    assert!(!eval("status_code strict between {204 299}"));
+    assert!(eval(
+        "ts between {2024-01-01T00:00:00Z 2024-12-31T23:59:59Z}"
+    ));
+    assert!(!eval(
+        "ts between {2023-01-01T00:00:00Z 2023-12-31T23:59:59Z}"
+    ));
+}
+
+#[test]
+fn test_len_on_bytes_and_maps() {
+    let engine = WirerustEngineBuilder::new()
+        .field("user_agent", FieldType::Bytes)
+        .field("labels", FieldType::Map(Box::new(FieldType::Bytes)))
+        .build();
+    let mut ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("user_agent", "curl/8.0")
+        .unwrap()
+        .build();
+    let labels = [("env", "prod"), ("team", "edge")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), LiteralValue::from(v)))
+        .collect();
+    ctx.set(
+        "labels",
+        LiteralValue::Map(Arc::new(labels)),
+        engine.schema(),
+    )
+    .unwrap();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    assert!(eval("len(user_agent) == 8 && len(labels) == 2"));
+    assert!(eval(r#"len("") == 0"#));
+}
+
+#[test]
+fn test_index_of_and_char_at() {
+    let engine = WirerustEngineBuilder::new()
+        .field("path", FieldType::Bytes)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("path", "/v1/admin/users")
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    assert!(eval(r#"index_of(path, "admin") >= 0"#));
+    assert!(eval(r#"index_of(path, "admin") == 4"#));
+    assert!(eval(r#"index_of(path, "root") == -1"#));
+    assert!(eval(
+        r#"char_at(path, 0) == "/" && char_at(path, 1) == "v""#
+    ));
+    assert!(eval(r#"char_at(path, 99) == """#));
+    assert!(engine.parse_and_compile("index_of(path, 1) == 0").is_err());
+}
+
+#[test]
+fn test_coercion_functions() {
+    let engine = WirerustEngineBuilder::new()
+        .field("content_length", FieldType::Bytes)
+        .field("debug", FieldType::Bytes)
+        .field("port", FieldType::Int)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("content_length", "1024")
+        .unwrap()
+        .set_bytes("debug", "true")
+        .unwrap()
+        .set_int("port", 8080)
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx)
+    };
+    assert!(eval("to_int(content_length) > 100").unwrap());
+    assert!(eval(r#"to_bytes(port) == "8080""#).unwrap());
+    assert!(eval("to_bool(debug) && to_bool(port)").unwrap());
+    assert!(matches!(
+        eval("to_int(debug) > 0"),
+        Err(WirerustError::FunctionError(_))
+    ));
+    assert!(engine.parse_and_compile("to_int(port) > 0").is_err());
+}
+
+#[test]
+fn test_single_quoted_strings() {
+    let engine = WirerustEngineBuilder::new()
+        .field("content_type", FieldType::Bytes)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("content_type", "application/json")
+        .unwrap()
+        .build();
+    // As it would appear nested in a double-quoted JSON config value
+    let config =
+        r#"{"rule": "content_type == 'application/json' && starts_with(content_type, 'app')"}"#;
+    let json: serde_json::Value = serde_json::from_str(config).unwrap();
+    let filter = engine
+        .parse_and_compile(json["rule"].as_str().unwrap())
+        .unwrap();
+    assert!(engine.execute(&filter, &ctx).unwrap());
+}
+
+#[test]
+fn test_enum_fields() {
+    let engine = WirerustEngineBuilder::new()
+        .field(
+            "protocol",
+            FieldType::Enum(vec!["tcp".into(), "udp".into(), "icmp".into()]),
+        )
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("protocol", "udp")
+        .unwrap()
+        .build();
+    for (src, expected) in [
+        (r#"protocol == "udp""#, true),
+        (r#"protocol != "tcp""#, true),
+        (r#"protocol eq_ci "UDP""#, true),
+        (r#"protocol in {"tcp" "icmp"}"#, false),
+        (r#"upper(protocol) == "UDP""#, true),
+    ] {
+        let filter = engine.parse_and_compile(src).unwrap();
+        assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{src}");
+    }
+    for src in [r#"protocol == "sctp""#, r#"protocol in {"tcp" "sctp"}"#] {
+        assert!(
+            matches!(
+                engine.parse_and_compile(src),
+                Err(WirerustError::TypeError(_))
+            ),
+            "{src}"
+        );
+        let expr = engine.parse_filter(src).unwrap();
+        assert!(matches!(
+            engine.validate(&expr).as_slice(),
+            [WirerustError::TypeError(_)]
+        ));
+    }
+    assert!(matches!(
+        FilterContextBuilder::new(engine.schema()).set_bytes("protocol", "sctp"),
+        Err(WirerustError::TypeError(_))
+    ));
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn test_matches_any_bot_detection() {
+    let build = || WirerustEngineBuilder::new().field("user_agent", FieldType::Bytes);
+    let engines = [build().build(), build().compiler(ClosureCompiler).build()];
+    for engine in &engines {
+        let filter = engine
+            .parse_and_compile(r#"matches_any(user_agent, {"bot" "crawler" "spider"})"#)
+            .unwrap();
+        for (ua, expected) in [
+            ("Googlebot/2.1", true),
+            ("Baiduspider", true),
+            ("Mozilla/5.0 Firefox/91.0", false),
+        ] {
+            let ctx = FilterContextBuilder::new(engine.schema())
+                .set_bytes("user_agent", ua)
+                .unwrap()
+                .build();
+            assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{ua}");
+        }
+    }
+}
+
+#[test]
+fn test_negated_operators() {
+    let engine = WirerustEngineBuilder::new()
+        .field("path", FieldType::Bytes)
+        .field("port", FieldType::Int)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("path", "/api/users")
+        .unwrap()
+        .set_int("port", 443)
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    assert!(eval(
+        r#"path not contains "admin" && port not in {80 8080}"#
+    ));
+    assert!(!eval(r#"path not wildcard "/API/*""#));
+    assert!(eval(r#"path not strict wildcard "/API/*""#));
+    #[cfg(feature = "regex")]
+    assert!(eval(
+        r#"path not matches "^/admin" and not path not matches "users$""#
+    ));
+}
+
+#[test]
+fn test_closure_backend() {
+    let engine = WirerustEngineBuilder::new()
+        .field("port", FieldType::Int)
+        .field("tags", FieldType::Array(Box::new(FieldType::Bytes)))
+        .compiler(ClosureCompiler)
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_int("port", 443)
+        .unwrap()
+        .set_array("tags", vec![LiteralValue::bytes("tls")])
+        .unwrap()
+        .build();
+    let filter = engine
+        .parse_and_compile(r#"port in {80 443} && any(tags, _ == "tls") && len(tags) == 1"#)
+        .unwrap();
+    assert!(engine.execute(&filter, &ctx).unwrap());
+    assert_eq!(filter.execute_batch(&[ctx, FilterContext::new()]).len(), 2);
+}
+
+#[test]
+fn test_multi_valued_fields() {
+    let schema = FilterSchemaBuilder::new()
+        .multi_field("http.set_cookie", FieldType::Bytes)
+        .multi_field("port", FieldType::Int)
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let cookie = |v: &str| LiteralValue::from(v);
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_multi("http.set_cookie", [cookie("a=1"), cookie("session=xyz")])
+        .unwrap()
+        .set_multi("port", [LiteralValue::Int(80), LiteralValue::Int(8080)])
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        engine.execute(&filter, &ctx).unwrap()
+    };
+    // Comparisons match if any value does
+    assert!(eval(r#"http.set_cookie == "a=1""#));
+    assert!(eval(r#"http.set_cookie wildcard "session=*""#));
+    assert!(eval(r#"http.set_cookie in {"x" "session=xyz"}"#));
+    assert!(eval("port > 1024 && port between {1 100}"));
+    assert!(!eval(r#"http.set_cookie == "b=2""#));
+    // `!=` asks whether some value differs; `not ==` asks whether none is equal
+    assert!(eval(r#"http.set_cookie != "a=1""#));
+    assert!(!eval(r#"not http.set_cookie == "a=1""#));
+    // Elsewhere the field is the array of its values
+    assert!(eval(
+        "len(port) == 2 && port[1] == 8080 && all(port, _ >= 80)"
+    ));
+    // A missing multi-valued field matches nothing
+    let filter = engine
+        .parse_and_compile(r#"http.set_cookie != "a""#)
+        .unwrap();
+    assert!(!engine.execute(&filter, &FilterContext::new()).unwrap());
+
+    assert!(FilterContextBuilder::new(engine.schema())
+        .set_multi("port", [cookie("80")])
+        .is_err());
+}
+
+#[test]
+fn test_evaluate_computed_values() {
+    let engine = WirerustEngineBuilder::new()
+        .field("http.method", FieldType::Bytes)
+        .field("tags", FieldType::Array(Box::new(FieldType::Bytes)))
+        .build();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bytes("http.method", "get")
+        .unwrap()
+        .set_array("tags", vec![LiteralValue::bytes("a")])
+        .unwrap()
+        .build();
+    let eval = |src: &str| {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        filter.evaluate(&ctx).unwrap()
+    };
+    assert_eq!(eval("upper(http.method)"), LiteralValue::bytes("GET"));
+    assert_eq!(eval("len(tags) + 1"), LiteralValue::Int(2));
+    assert_eq!(eval(r#"http.method == "get""#), LiteralValue::Bool(true));
+}
+
+#[test]
+fn test_user_function_overrides_builtin() {
+    let schema = make_schema();
+    let mut functions = make_functions();
+    // Character count of strings, where the builtin counts bytes
+    functions.register_fn("len", |args| match args.first()? {
+        LiteralValue::Bytes(b) => Some(LiteralValue::Int(
+            String::from_utf8_lossy(b).chars().count() as i64,
+        )),
+        LiteralValue::Array(a) => Some(LiteralValue::Int(a.len() as i64)),
+        _ => None,
+    });
+    assert!(functions.is_builtin_overridden("len"));
+    let expr = FilterParser::parse("len(user_agent) == 4 && len(tags) == 1", &schema).unwrap();
+    let filter =
+        CompiledFilter::new(expr, Arc::new(schema.clone()), Arc::new(functions)).expect("compile");
+
+    let mut ctx = FilterContext::new();
+    ctx.set_bytes("user_agent", "café", &schema).set_array(
+        "tags",
+        vec![LiteralValue::bytes("a")],
+        &schema,
+    );
+    assert!(filter.execute(&ctx).unwrap());
+}
+
+#[test]
+fn test_negative_index_and_slice() {
+    let schema = FilterSchemaBuilder::new()
+        .field(
+            "path.segments",
+            FieldType::Array(Box::new(FieldType::Bytes)),
+        )
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let mut ctx = FilterContext::new();
+    ctx.set_array(
+        "path.segments",
+        ["api", "v1", "users", "export"]
+            .iter()
+            .map(|s| LiteralValue::from(*s))
+            .collect(),
+        engine.schema(),
+    );
+    for (src, expected) in [
+        (r#"path.segments[-1] == "export""#, true),
+        (r#"path.segments[0:2] == {"api" "v1"}"#, true),
+        (r#"any(path.segments[1:], _ == "api")"#, false),
+        (r#"len(path.segments[-2:]) == 2"#, true),
+        (r#"path.segments[-9] == "api""#, false),
+    ] {
+        let filter = engine.parse_and_compile(src).expect("compile");
+        assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{src}");
+    }
+}
+
+// A cut-down semver range check: `version semver_satisfies ">=1.2.0"`
+fn semver_satisfies(version: &LiteralValue, range: &LiteralValue) -> bool {
+    let parse = |s: &str| -> Option<Vec<u64>> { s.split('.').map(|p| p.parse().ok()).collect() };
+    let (LiteralValue::Bytes(version), LiteralValue::Bytes(range)) = (version, range) else {
+        return false;
+    };
+    let (Ok(version), Ok(range)) = (std::str::from_utf8(version), std::str::from_utf8(range))
+    else {
+        return false;
+    };
+    let (op, bound) = match range.strip_prefix(">=") {
+        Some(bound) => (">=", bound),
+        None => match range.strip_prefix('^') {
+            Some(bound) => ("^", bound),
+            None => ("=", range),
+        },
+    };
+    let (Some(version), Some(bound)) = (parse(version), parse(bound)) else {
+        return false;
+    };
+    match op {
+        ">=" => version >= bound,
+        "^" => version.first() == bound.first() && version >= bound,
+        _ => version == bound,
+    }
+}
+
+#[test]
+fn test_custom_operators() {
+    let build = || {
+        WirerustEngineBuilder::new()
+            .field("version", FieldType::Bytes)
+            .field("port", FieldType::Int)
+            .register_operator("semver_satisfies", semver_satisfies)
+            .unwrap()
+    };
+    let ctx = |version: &str| {
+        FilterContextBuilder::new(build().build().schema())
+            .set_bytes("version", version)
+            .unwrap()
+            .set_int("port", 443)
+            .unwrap()
+            .build()
+    };
+    for engine in [build().build(), build().compiler(ClosureCompiler).build()] {
+        for (src, version, expected) in [
+            (r#"version semver_satisfies ">=1.2.0""#, "1.10.0", true),
+            (r#"version semver_satisfies ">=1.2.0""#, "1.1.9", false),
+            (
+                r#"version semver_satisfies "^1.2.0" && port == 443"#,
+                "1.4.0",
+                true,
+            ),
+            (
+                r#"version semver_satisfies "^1.2.0" && port == 443"#,
+                "2.0.0",
+                false,
+            ),
+            (
+                r#"not version semver_satisfies "1.0.0" or port == 80"#,
+                "1.0.0",
+                false,
+            ),
+            (r#""1.2.3" semver_satisfies version"#, "1.2.3", true),
+        ] {
+            let filter = engine.parse_and_compile(src).unwrap();
+            assert_eq!(
+                engine.execute(&filter, &ctx(version)).unwrap(),
+                expected,
+                "{src} with {version}"
+            );
+        }
+    }
+
+    let engine = build().build();
+    let filter = engine
+        .parse_and_compile(r#"version semver_satisfies ">=1.0.0""#)
+        .unwrap();
+    assert!(filter
+        .disassemble()
+        .contains("CallOperator semver_satisfies"));
+    // Without the registration the keyword is not an operator
+    let plain = WirerustEngineBuilder::new()
+        .field("version", FieldType::Bytes)
+        .build();
+    assert!(plain
+        .parse_and_compile(r#"version semver_satisfies ">=1.0.0""#)
+        .is_err());
+    // Compiling or validating a tree that names an unregistered operator fails
+    let expr = engine
+        .parse_filter(r#"version semver_satisfies ">=1.0.0""#)
+        .unwrap();
+    assert!(plain.compile_filter(expr.clone()).is_err());
+    assert!(!plain.validate(&expr).is_empty());
+    assert!(engine.validate(&expr).is_empty());
+    assert!(engine
+        .complete("version sem", 11)
+        .iter()
+        .any(|c| c.text == "semver_satisfies"));
+    assert!(WirerustEngineBuilder::new()
+        .register_operator("contains", |_, _| true)
+        .is_err());
+
+    #[cfg(feature = "json")]
+    {
+        let bytes = filter.to_bytes().unwrap();
+        let loaded = CompiledFilter::from_bytes(
+            &bytes,
+            Arc::new(engine.schema().clone()),
+            Arc::new(engine.functions().clone()),
+        )
+        .unwrap();
+        assert!(loaded.execute(&ctx("1.0.1")).unwrap());
+        let mut other = FunctionRegistry::new();
+        other.register_operator("semver_lt", |_, _| true).unwrap();
+        assert!(CompiledFilter::from_bytes(
+            &bytes,
+            Arc::new(engine.schema().clone()),
+            Arc::new(other)
+        )
+        .is_err());
+    }
+}
+
+#[test]
+fn test_engine_completions() {
+    let schema = FilterSchemaBuilder::new()
+        .field_with_meta(
+            "http.host",
+            FieldType::Bytes,
+            FieldMeta {
+                description: "Host header".into(),
+                examples: vec!["\"example.com\"".into()],
+            },
+        )
+        .field("http.method", FieldType::Bytes)
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let partial = "http.ho == \"example.com\" && sta";
+    let fields = engine.complete(partial, 7);
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].text, "http.host");
+    assert_eq!(fields[0].kind, CompletionKind::Field);
+    assert_eq!(fields[0].description.as_deref(), Some("Host header"));
+
+    let functions = engine.complete(partial, partial.len());
+    assert!(functions
+        .iter()
+        .all(|c| c.kind == CompletionKind::Function && c.text.starts_with("sta")));
+    assert!(functions.iter().any(|c| c.text == "starts_with"));
+
+    let operators = engine.complete("http.method ", 12);
+    assert!(operators.iter().all(|c| c.kind == CompletionKind::Operator));
+    assert!(operators.iter().any(|c| c.text == "matches"));
+}
+
+#[test]
+fn test_array_ordering_comparisons() {
+    let schema = FilterSchemaBuilder::new()
+        .field("version", FieldType::Array(Box::new(FieldType::Int)))
+        .build();
+    let mut ctx = FilterContext::new();
+    ctx.set_array(
+        "version",
+        vec![LiteralValue::Int(1), LiteralValue::Int(2)],
+        &schema,
+    );
+    for (src, expected) in [
+        ("version < {1 3}", true),
+        ("version < {1 2 0}", true),
+        ("version > {1}", true),
+        ("version >= {1 2} && version <= {1 2}", true),
+        ("version > {1 10}", false),
+    ] {
+        for engine in [
+            WirerustEngine::new(schema.clone()),
+            WirerustEngine::new(schema.clone()).with_compiler(ClosureCompiler),
+        ] {
+            let filter = engine.parse_and_compile(src).expect("compile");
+            assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{src}");
+        }
+    }
+}
+
+#[test]
+fn test_literal_on_left_is_symmetric() {
+    let schema = FilterSchemaBuilder::new()
+        .field("enabled", FieldType::Bool)
+        .field("port", FieldType::Int)
+        .field("method", FieldType::Bytes)
+        .build();
+    let engine = WirerustEngine::new(schema);
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_bool("enabled", true)
+        .unwrap()
+        .set_int("port", 443)
+        .unwrap()
+        .set_bytes("method", b"GET")
+        .unwrap()
+        .build();
+    for (left_literal, field_first) in [
+        ("true == enabled", "enabled == true"),
+        ("443 == port", "port == 443"),
+        ("80 != port", "port != 80"),
+        ("1024 > port", "port < 1024"),
+        (r#""GET" == method"#, r#"method == "GET""#),
+        (r#"'POST' == method"#, r#"method == 'POST'"#),
+    ] {
+        let run = |src: &str| {
+            let filter = engine.parse_and_compile(src).expect("compile");
+            engine.execute(&filter, &ctx).unwrap()
+        };
+        assert_eq!(run(left_literal), run(field_first), "{left_literal}");
+    }
+    for src in [r#""GET" == port"#, r#"port == "GET""#] {
+        let expr = engine.parse_filter(src).unwrap();
+        assert!(!engine.validate(&expr).is_empty(), "{src}");
+    }
+}
+
+#[test]
+fn test_raw_string_patterns() {
+    let build = || WirerustEngineBuilder::new().field("ua", FieldType::Bytes);
+    for engine in [build().build(), build().compiler(ClosureCompiler).build()] {
+        let ctx = |ua: &str| {
+            FilterContextBuilder::new(engine.schema())
+                .set_bytes("ua", ua)
+                .unwrap()
+                .build()
+        };
+        for (src, ua, expected) in [
+            (r#"ua matches r"^curl/\d+\.\d+""#, "curl/8.4", true),
+            (r#"ua matches r"^curl/\d+\.\d+""#, "curl/8x4", false),
+            (r##"ua matches r#"^"[^"]*"$"#"##, r#""quoted""#, true),
+            (r##"ua == r#"say "hi""#"##, r#"say "hi""#, true),
+        ] {
+            let filter = engine.parse_and_compile(src).unwrap();
+            assert_eq!(
+                engine.execute(&filter, &ctx(ua)).unwrap(),
+                expected,
+                "{src}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_nested_field_lookups() {
+    let build = || {
+        WirerustEngineBuilder::new()
+            .field("http", FieldType::Map(Box::new(FieldType::Bytes)))
+            .field("http.method", FieldType::Bytes)
+    };
+    for engine in [build().build(), build().compiler(ClosureCompiler).build()] {
+        let http: std::collections::HashMap<String, LiteralValue> =
+            [("host", "example.com"), ("method", "POST")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), LiteralValue::from(v)))
+                .collect();
+        let mut ctx = FilterContext::new();
+        ctx.set("http", LiteralValue::Map(Arc::new(http)), engine.schema())
+            .unwrap();
+        ctx.set("http.method", LiteralValue::from("GET"), engine.schema())
+            .unwrap();
+        for (src, expected) in [
+            (r#"http.host == "example.com""#, true),
+            (r#"http.host == http["host"]"#, true),
+            // The flat field is preferred over the map's "method" key
+            (r#"http.method == "GET""#, true),
+            (r#"http["method"] == "POST""#, true),
+            // A missing key reads as false, like any other map lookup
+            (r#"http.path == "/""#, false),
+        ] {
+            let filter = engine.parse_and_compile(src).unwrap();
+            assert_eq!(engine.execute(&filter, &ctx).unwrap(), expected, "{src}");
+        }
+    }
+}
+
+#[test]
+fn test_execute_explain() {
+    let build = || {
+        WirerustEngineBuilder::new()
+            .field("http.method", FieldType::Bytes)
+            .field("http.path", FieldType::Bytes)
+            .field("port", FieldType::Int)
+            .explain()
+    };
+    for engine in [build().build(), build().compiler(ClosureCompiler).build()] {
+        let filter = engine
+            .parse_and_compile(
+                r#"http.method == "POST" and http.path contains "/admin" and not port in {80 443}"#,
+            )
+            .unwrap();
+        let ctx = FilterContextBuilder::new(engine.schema())
+            .set_bytes("http.method", "POST")
+            .unwrap()
+            .set_bytes("http.path", "/admin/users")
+            .unwrap()
+            .set_int("port", 8080)
+            .unwrap()
+            .build();
+        let (blocked, explanation) = filter
+            .execute_explain(&ctx, &ExecutionLimits::default(), &())
+            .unwrap();
+        assert!(blocked);
+        let reasons: Vec<_> = explanation
+            .matched()
+            .map(|c| c.condition.as_str())
+            .collect();
+        assert_eq!(
+            reasons,
+            [r#"http.method == "POST""#, r#"http.path contains "/admin""#]
+        );
+        assert_eq!(explanation.conditions[2].condition, "port in {80 443}");
+        assert_eq!(explanation.conditions[2].matched, Some(false));
+    }
+    let engine = WirerustEngineBuilder::new()
+        .field("port", FieldType::Int)
+        .build();
+    let filter = engine.parse_and_compile("port == 80").unwrap();
+    assert!(filter
+        .execute_explain(&FilterContext::new(), &ExecutionLimits::default(), &())
+        .is_err());
+}
+
+// Requests per IP within the last `window` milliseconds, timed by the request's
+// timestamp passed as execute data
+struct Rate {
+    window: i64,
+    hits: std::collections::HashMap<IpAddr, std::collections::VecDeque<i64>>,
+}
+
+impl StatefulFunction for Rate {
+    fn call_mut(
+        &mut self,
+        args: &[LiteralValue],
+        data: &dyn std::any::Any,
+    ) -> Result<LiteralValue, WirerustError> {
+        let (Some(LiteralValue::Ip(ip)), Some(&now)) = (args.first(), data.downcast_ref::<i64>())
+        else {
+            return Err(WirerustError::FunctionError(
+                "rate() needs an IP and the request time".into(),
+            ));
+        };
+        let hits = self.hits.entry(*ip).or_default();
+        while hits.front().is_some_and(|&t| t <= now - self.window) {
+            hits.pop_front();
+        }
+        hits.push_back(now);
+        Ok(LiteralValue::Int(hits.len() as i64))
+    }
+}
+
+#[test]
+fn test_stateful_rate_limit() {
+    let rate = || Rate {
+        window: 1000,
+        hits: Default::default(),
+    };
+    let engine = WirerustEngineBuilder::new()
+        .field("ip", FieldType::Ip)
+        .register_stateful_function("rate", rate())
+        .build();
+    let filter = engine.parse_and_compile("rate(ip) > 2").unwrap();
+    let ctx = |ip: &str| {
+        FilterContextBuilder::new(engine.schema())
+            .set_ip("ip", ip.parse().unwrap())
+            .unwrap()
+            .build()
+    };
+    let (a, b) = (ctx("10.0.0.1"), ctx("10.0.0.2"));
+    let run = |ctx: &FilterContext, now: i64| filter.execute_with_data(ctx, &now).unwrap();
+    assert!(!run(&a, 0));
+    assert!(!run(&a, 100));
+    assert!(!run(&b, 200));
+    assert!(run(&a, 300));
+    // The first two requests fall out of the window
+    assert!(!run(&a, 1150));
+
+    // Explaining runs each call once, with the request's data
+    let engine = WirerustEngineBuilder::new()
+        .field("ip", FieldType::Ip)
+        .register_stateful_function("rate", rate())
+        .explain()
+        .build();
+    let filter = engine
+        .parse_and_compile("rate(ip) > 2 or not exists ip")
+        .unwrap();
+    let explain = |now: i64| {
+        filter
+            .execute_explain(&a, &ExecutionLimits::default(), &now)
+            .unwrap()
+    };
+    assert!(!explain(0).0);
+    assert!(!explain(100).0);
+    let (limited, explanation) = explain(200);
+    assert!(limited);
+    let reasons: Vec<_> = explanation
+        .matched()
+        .map(|c| c.condition.as_str())
+        .collect();
+    assert_eq!(reasons, ["rate(ip) > 2", "exists ip"]);
+
+    // One filter shared across threads updates the same counters
+    let engine = WirerustEngineBuilder::new()
+        .field("ip", FieldType::Ip)
+        .register_stateful_function("rate", rate())
+        .build();
+    let filter = engine.parse_and_compile("rate(ip) > 100").unwrap();
+    let ctx = FilterContextBuilder::new(engine.schema())
+        .set_ip("ip", "10.0.0.1".parse().unwrap())
+        .unwrap()
+        .build();
+    let limited = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| {
+                    (0..50)
+                        .filter(|_| filter.execute_with_data(&ctx, &0i64).unwrap())
+                        .count()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .sum::<usize>()
+    });
+    assert_eq!(limited, 100);
+}
+
+#[test]
+fn test_long_operator_chains_hit_the_depth_limit() {
+    let schema = Arc::new(make_schema());
+    let functions = Arc::new(make_functions());
+    for (prefix, link) in [
+        ("", " or enabled"),
+        ("", " and port == 80"),
+        ("", " + 1"),
+        ("not ", ""),
+        ("-", ""),
+    ] {
+        let chain = |n: usize| format!("{}port == 80{}", prefix.repeat(n), link.repeat(n));
+        assert!(
+            FilterParser::parse(&chain(5000), &schema).is_err(),
+            "{prefix}{link}"
+        );
+        // The longest chain the default limits accept compiles and runs on both backends
+        let longest = (0..5000)
+            .map_while(|n| FilterParser::parse(&chain(n), &schema).ok())
+            .last()
+            .unwrap();
+        assert!(longest.depth() > 400, "{prefix}{link}");
+        let mut ctx = FilterContext::new();
+        ctx.set("port", LiteralValue::Int(80), &schema).unwrap();
+        let compilers: [&dyn Compiler; 2] = [&DefaultCompiler, &ClosureCompiler];
+        for compiler in compilers {
+            let filter = CompiledFilter::with_compiler(
+                longest.clone(),
+                Arc::clone(&schema),
+                Arc::clone(&functions),
+                EngineOptions::default(),
+                compiler,
+            )
+            .unwrap();
+            filter.execute(&ctx).unwrap();
+        }
+    }
+}